@@ -0,0 +1,357 @@
+//! Operator-facing endpoints guarded by a bearer token.
+//!
+//! All admin routes are disabled unless `admin_token` is configured. Requests
+//! must carry `Authorization: Bearer <admin_token>`.
+//!
+//! Besides reading the effective configuration, operators can change the
+//! upstream set without a restart: `POST /admin/nodes` adds a node from an
+//! upstream config, `DELETE /admin/nodes/{name}` removes one and
+//! `POST /admin/nodes/{name}/drain` stops routing new requests to one. Requests
+//! already on a removed or drained node finish normally.
+//! `POST /admin/nodes/{name}/reset` closes a node's circuit and clears its
+//! failure counters without waiting for the cooldown.
+//! `POST /admin/nodes/{name}/upgrading` flags a node that is about to restart
+//! for an upgrade, softening its circuit breaker for the node's
+//! `upgrade.window_ms`; `DELETE` on the same path clears the flag early.
+//! Changes are not written back to the configuration file.
+//!
+//! `POST /admin/cache/clear` flushes every cached response, e.g. to get rid of
+//! a bad value after an incident.
+//!
+//! `GET /debug/last-selection` shows why nodes were skipped in the latest node
+//! selection, when selection tracing is enabled.
+
+use crate::AppState;
+use crate::types::UpstreamConfig;
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+/// Checks the bearer token against the configured admin token.
+///
+/// Returns the status and message to send back when the caller is not authorized.
+pub fn authorize(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let Some(expected) = state.config.admin_token.as_deref() else {
+        return Err((StatusCode::NOT_FOUND, "Admin API disabled"));
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if token == expected => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Invalid admin token")),
+    }
+}
+
+/// Returns the fully resolved configuration with secrets masked.
+pub async fn effective_config(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    (StatusCode::OK, Json(state.config.redacted())).into_response()
+}
+
+/// Adds an upstream node described by the JSON body.
+///
+/// The body is parsed only after the token check, so unauthorized callers
+/// learn nothing about the expected format.
+pub async fn add_node(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    let config: UpstreamConfig = match serde_json::from_slice(&body) {
+        Ok(config) => config,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid node config: {}", e),
+            )
+                .into_response();
+        }
+    };
+    if state.load_balancer.node_by_name(&config.name).is_some() {
+        return (StatusCode::CONFLICT, "Node already exists").into_response();
+    }
+    match state.load_balancer.add_node(config) {
+        Ok(_) => (StatusCode::CREATED, nodes_listing(&state)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+}
+
+/// Removes the named upstream node.
+pub async fn remove_node(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    match state.load_balancer.remove_node(&name) {
+        Some(_) => (StatusCode::OK, nodes_listing(&state)).into_response(),
+        None => (StatusCode::NOT_FOUND, "Unknown node").into_response(),
+    }
+}
+
+/// Stops routing new requests to the named upstream node.
+pub async fn drain_node(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    let Some(node) = state.load_balancer.node_by_name(&name) else {
+        return (StatusCode::NOT_FOUND, "Unknown node").into_response();
+    };
+    node.set_drained(true);
+    (StatusCode::OK, nodes_listing(&state)).into_response()
+}
+
+/// Marks a node healthy and clears its failure history.
+pub async fn reset_node(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    let Some(node) = state.load_balancer.node_by_name(&name) else {
+        return (StatusCode::NOT_FOUND, "Unknown node").into_response();
+    };
+    node.reset();
+    (StatusCode::OK, nodes_listing(&state)).into_response()
+}
+
+/// Flags the named node as upgrading, so expected failures are tolerated.
+pub async fn start_upgrade(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    set_upgrading(&state, &name, &headers, true)
+}
+
+/// Clears the named node's upgrading flag.
+pub async fn finish_upgrade(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    set_upgrading(&state, &name, &headers, false)
+}
+
+fn set_upgrading(state: &AppState, name: &str, headers: &HeaderMap, upgrading: bool) -> Response {
+    if let Err(rejection) = authorize(state, headers) {
+        return rejection.into_response();
+    }
+    let Some(node) = state.load_balancer.node_by_name(name) else {
+        return (StatusCode::NOT_FOUND, "Unknown node").into_response();
+    };
+    node.set_upgrading(upgrading);
+    (StatusCode::OK, nodes_listing(state)).into_response()
+}
+
+/// Returns why nodes were skipped in the latest selection.
+///
+/// Needs `selection.record_selection_trace`; answers 404 until a selection
+/// has been recorded.
+pub async fn last_selection(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    match state.load_balancer.last_selection() {
+        Some(trace) => (StatusCode::OK, Json(trace)).into_response(),
+        None => (StatusCode::NOT_FOUND, "No selection recorded").into_response(),
+    }
+}
+
+/// Flushes the response cache, reporting how many entries were dropped.
+pub async fn clear_cache(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Err(rejection) = authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    let cleared = state.cache.clear();
+    tracing::info!(
+        "Cache cleared by admin request, {} entries dropped",
+        cleared
+    );
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "cleared": cleared })),
+    )
+        .into_response()
+}
+
+/// The node list in the same shape as `/status`.
+fn nodes_listing(state: &AppState) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "nodes": state.load_balancer.get_nodes_status() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_state;
+    use crate::cache::CacheSettings;
+    use crate::config::GatewayConfig;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_config_endpoint_requires_token() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+
+        let response = effective_config(State(state), bearer("wrong")).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_config_endpoint_returns_redacted_config() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            cache: CacheSettings {
+                min_ttl_ms: Some(250),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+
+        let response = effective_config(State(state), bearer("s3cret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["admin_token"], "<redacted>");
+        assert_eq!(json["cache"]["min_ttl_ms"], 250);
+        assert!(!String::from_utf8_lossy(&body).contains("s3cret"));
+    }
+
+    #[tokio::test]
+    async fn test_nodes_can_be_added_drained_and_removed() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+        let body = Bytes::from(r#"{ "name": "Extra", "url": "http://127.0.0.1:1" }"#);
+
+        let response = add_node(State(state.clone()), bearer("wrong"), body.clone()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(state.load_balancer.node_by_name("Extra").is_none());
+
+        let response = add_node(State(state.clone()), bearer("s3cret"), body.clone()).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = add_node(State(state.clone()), bearer("s3cret"), body).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = drain_node(
+            State(state.clone()),
+            Path("Extra".to_string()),
+            bearer("s3cret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let node = state.load_balancer.node_by_name("Extra").unwrap();
+        assert!(node.is_drained());
+        assert!(state.load_balancer.choose_healthy_node(&[]).is_none());
+
+        let response = remove_node(
+            State(state.clone()),
+            Path("Extra".to_string()),
+            bearer("s3cret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(state.load_balancer.node_by_name("Extra").is_none());
+        let response = remove_node(State(state), Path("Extra".to_string()), bearer("s3cret")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cache_clear_flushes_every_entry() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+        state
+            .cache
+            .put("eth_blockNumber:[]".to_string(), serde_json::json!("0x10"));
+
+        let response = clear_cache(State(state.clone()), bearer("wrong")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(state.cache.get("eth_blockNumber:[]").is_some());
+
+        let response = clear_cache(State(state.clone()), bearer("s3cret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["cleared"], 1);
+        assert!(state.cache.get("eth_blockNumber:[]").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reset_closes_open_circuit() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let upstream = UpstreamConfig {
+            name: "Node".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream]).unwrap();
+        let node = state.load_balancer.node_by_name("Node").unwrap();
+        for _ in 0..3 {
+            node.force_mark_failure();
+        }
+        assert!(state.load_balancer.choose_healthy_node(&[]).is_none());
+
+        let response = reset_node(
+            State(state.clone()),
+            Path("Missing".to_string()),
+            bearer("s3cret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = reset_node(
+            State(state.clone()),
+            Path("Node".to_string()),
+            bearer("s3cret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(node.get_consecutive_failures(), 0);
+        assert!(node.cooldown_remaining().is_none());
+        let chosen = state.load_balancer.choose_healthy_node(&[]).unwrap();
+        assert_eq!(chosen.get_name(), "Node");
+    }
+}
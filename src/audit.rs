@@ -0,0 +1,234 @@
+//! Per-request audit trail.
+//!
+//! With a `sink` configured, one JSON line is written per JSON-RPC request
+//! (each batch element counts separately) recording who called which method,
+//! when, which node served it and how it ended. Only the fields listed in
+//! `fields` are written; request params are left out unless asked for.
+//!
+//! API keys are never written in clear, only as a short SHA-256 fingerprint
+//! that is stable per key.
+//!
+//! # Non-blocking Writes
+//!
+//! Records go through a bounded channel to a background writer, so the request
+//! path never waits on disk or stdout. If the writer falls `buffer` records
+//! behind, new records are dropped (and counted in the logs) rather than
+//! stalling traffic.
+
+use crate::types::RequestContext;
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+/// Where audit records are written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditSink {
+    /// One JSON line per record on stdout.
+    Stdout,
+
+    /// One JSON line per record, appended to the file at `path`.
+    File { path: String },
+}
+
+/// A field that can be included in audit records.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditField {
+    /// Milliseconds since the Unix epoch when the request finished.
+    TimestampMs,
+    /// Address of the connecting client.
+    ClientIp,
+    /// Fingerprint of the caller's API key.
+    ApiKey,
+    /// W3C trace ID of the request.
+    TraceId,
+    /// JSON-RPC method.
+    Method,
+    /// Name of the node that served the request, if any.
+    Node,
+    /// `ok`, `client_error` or `server_error`.
+    Outcome,
+    /// Time spent serving the request.
+    LatencyMs,
+    /// Request params. Off by default, since they may carry sensitive data.
+    Params,
+}
+
+/// Audit trail configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditSettings {
+    /// Destination of audit records. `None` disables auditing.
+    pub sink: Option<AuditSink>,
+
+    /// Fields written in each record.
+    pub fields: Vec<AuditField>,
+
+    /// Records queued for the writer before new ones are dropped.
+    pub buffer: usize,
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            fields: vec![
+                AuditField::TimestampMs,
+                AuditField::ClientIp,
+                AuditField::ApiKey,
+                AuditField::TraceId,
+                AuditField::Method,
+                AuditField::Node,
+                AuditField::Outcome,
+                AuditField::LatencyMs,
+            ],
+            buffer: 4096,
+        }
+    }
+}
+
+/// What happened to one request, as reported to the audit trail.
+pub struct AuditEntry<'a> {
+    pub ctx: &'a RequestContext,
+    pub method: &'a str,
+    pub params: &'a serde_json::Value,
+    pub node: Option<&'a str>,
+    pub outcome: &'a str,
+    pub latency: Duration,
+}
+
+/// Hands audit records to a background writer.
+pub struct AuditLog {
+    fields: Vec<AuditField>,
+    sender: Option<mpsc::Sender<String>>,
+    dropped: AtomicU64,
+}
+
+impl AuditLog {
+    /// Opens the configured sink and starts its writer task.
+    ///
+    /// Fails if the audit file cannot be opened for appending.
+    pub fn new(settings: &AuditSettings) -> std::io::Result<Self> {
+        let sender = match &settings.sink {
+            None => None,
+            Some(sink) => {
+                let writer: Box<dyn AsyncWrite + Send + Unpin> = match sink {
+                    AuditSink::Stdout => Box::new(tokio::io::stdout()),
+                    AuditSink::File { path } => {
+                        let file = std::fs::OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)?;
+                        Box::new(tokio::fs::File::from_std(file))
+                    }
+                };
+                let (sender, receiver) = mpsc::channel(settings.buffer.max(1));
+                tokio::spawn(write_records(receiver, writer));
+                Some(sender)
+            }
+        };
+
+        Ok(Self {
+            fields: settings.fields.clone(),
+            sender,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// Queues a record for `entry` without waiting for it to be written.
+    pub fn record(&self, entry: &AuditEntry<'_>) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let line = self.build_record(entry).to_string();
+        if sender.try_send(line).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed);
+            if dropped.is_multiple_of(1000) {
+                tracing::warn!(
+                    "Audit writer is behind, dropped {} records so far",
+                    dropped + 1
+                );
+            }
+        }
+    }
+
+    fn build_record(&self, entry: &AuditEntry<'_>) -> serde_json::Value {
+        let caller = &entry.ctx.caller;
+        let mut record = serde_json::Map::new();
+        for field in &self.fields {
+            let (key, value) = match field {
+                AuditField::TimestampMs => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    ("timestamp_ms", (now.as_millis() as u64).into())
+                }
+                AuditField::ClientIp => (
+                    "client_ip",
+                    caller.client_ip.map(|ip| ip.to_string()).into(),
+                ),
+                AuditField::ApiKey => (
+                    "api_key",
+                    caller.api_key.as_deref().map(key_fingerprint).into(),
+                ),
+                AuditField::TraceId => ("trace_id", entry.ctx.trace.trace_id.clone().into()),
+                AuditField::Method => ("method", entry.method.into()),
+                AuditField::Node => ("node", entry.node.into()),
+                AuditField::Outcome => ("outcome", entry.outcome.into()),
+                AuditField::LatencyMs => ("latency_ms", (entry.latency.as_millis() as u64).into()),
+                AuditField::Params => ("params", entry.params.clone()),
+            };
+            record.insert(key.to_string(), value);
+        }
+        serde_json::Value::Object(record)
+    }
+}
+
+/// Identifies an API key in audit records without revealing it.
+fn key_fingerprint(key: &str) -> String {
+    let hash = digest(&SHA256, key.as_bytes());
+    let hex: String = hash.as_ref()[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Writes queued records, flushing whenever the queue runs empty.
+async fn write_records(
+    mut receiver: mpsc::Receiver<String>,
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+) {
+    let mut writer = BufWriter::new(writer);
+    while let Some(first) = receiver.recv().await {
+        let mut next = Some(first);
+        while let Some(line) = next {
+            if let Err(e) = writer.write_all(format!("{}\n", line).as_bytes()).await {
+                tracing::error!("Failed to write audit record: {}", e);
+            }
+            next = receiver.try_recv().ok();
+        }
+        if let Err(e) = writer.flush().await {
+            tracing::error!("Failed to flush audit records: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_is_fingerprinted() {
+        let fingerprint = key_fingerprint("secret-key");
+        assert!(fingerprint.starts_with("sha256:"));
+        assert_eq!(fingerprint.len(), "sha256:".len() + 16);
+        assert!(!fingerprint.contains("secret-key"));
+        assert_eq!(fingerprint, key_fingerprint("secret-key"));
+    }
+}
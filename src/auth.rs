@@ -0,0 +1,423 @@
+//! API-key authentication for the JSON-RPC endpoint.
+//!
+//! Authentication is disabled unless `api_keys` is configured. Clients send
+//! their key in the `X-Api-Key` header, and keys are checked against a
+//! `KeyStore`, which may live in a remote backend.
+//!
+//! # Backend Failures
+//!
+//! When the key store itself cannot answer, `on_backend_error` decides whether
+//! requests are rejected (fail-closed, the default) or let through (fail-open).
+//!
+//! # Key Policies
+//!
+//! `key_policies` adjusts behavior per key. With `verbose_errors`, gateway-side
+//! failures are reported in full (which node failed and how); everyone else
+//! gets a generic "Internal error" so upstream addresses and topology don't leak
+//! to public clients. JSON-RPC errors returned by an upstream are not affected.
+//! With `allowed_methods`, the key may only call the listed methods.
+//!
+//! # Authorization
+//!
+//! Once a request is authenticated, each JSON-RPC call (every element of a
+//! batch) is put to an `Authorizer` before it is forwarded, with the caller,
+//! method and params. The default `KeyPolicyAuthorizer` enforces the
+//! configured key policies; a custom implementation can add rules such as
+//! per-key spending limits or time-of-day restrictions. A denied call is
+//! answered with a JSON-RPC error and never reaches an upstream.
+//!
+//! The default authorizer also applies the gateway-wide `method_filter`, so
+//! HTTP calls, notifications and WebSocket frames all pass the same check
+//! through `check_call`.
+
+use crate::AppState;
+use crate::method_filter::{METHOD_NOT_FOUND_CODE, MethodFilterSettings};
+use crate::metrics::ErrorClass;
+use crate::types::{Caller, RpcRequest, RpcResponse};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Header carrying the client's API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// JSON-RPC error code for calls the authorizer denies.
+pub const NOT_AUTHORIZED_CODE: i32 = -32003;
+
+/// What to do with a request when the key store is unreachable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureMode {
+    /// Reject the request (secure).
+    #[default]
+    FailClosed,
+
+    /// Allow the request (available).
+    FailOpen,
+}
+
+/// API-key authentication settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    /// Accepted API keys. Empty disables authentication.
+    pub api_keys: Vec<String>,
+
+    /// Behavior when the key store returns an error.
+    pub on_backend_error: FailureMode,
+
+    /// Per-key policies, keyed by API key.
+    pub key_policies: HashMap<String, KeyPolicy>,
+}
+
+/// What a particular API key is entitled to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyPolicy {
+    /// Return full details of gateway-side failures instead of a generic message.
+    pub verbose_errors: bool,
+
+    /// Methods the key may call. Empty allows all methods.
+    pub allowed_methods: Vec<String>,
+}
+
+/// Source of truth for valid API keys.
+pub trait KeyStore: Send + Sync {
+    /// Returns whether `key` is valid, or an error if the store can't be reached.
+    fn validate<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, String>>;
+}
+
+/// Key store backed by the keys listed in the configuration.
+struct StaticKeyStore {
+    keys: HashSet<String>,
+}
+
+impl KeyStore for StaticKeyStore {
+    fn validate<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, String>> {
+        Box::pin(async move { Ok(self.keys.contains(key)) })
+    }
+}
+
+/// Validates API keys against a key store, applying the configured failure mode.
+pub struct Authenticator {
+    store: Option<Arc<dyn KeyStore>>,
+    on_backend_error: FailureMode,
+    verbose_error_keys: HashSet<String>,
+}
+
+impl Authenticator {
+    pub fn new(settings: &AuthSettings) -> Self {
+        let store = (!settings.api_keys.is_empty()).then(|| {
+            Arc::new(StaticKeyStore {
+                keys: settings.api_keys.iter().cloned().collect(),
+            }) as Arc<dyn KeyStore>
+        });
+        let verbose_error_keys = settings
+            .key_policies
+            .iter()
+            .filter(|(_, policy)| policy.verbose_errors)
+            .map(|(key, _)| key.clone())
+            .collect();
+        Self {
+            store,
+            on_backend_error: settings.on_backend_error,
+            verbose_error_keys,
+        }
+    }
+
+    /// Whether the client presenting `api_key` gets detailed error messages.
+    pub fn verbose_errors(&self, api_key: Option<&str>) -> bool {
+        api_key.is_some_and(|key| self.verbose_error_keys.contains(key))
+    }
+
+    /// Creates an authenticator that consults a custom key store.
+    #[cfg(test)]
+    pub fn with_store(store: Arc<dyn KeyStore>, on_backend_error: FailureMode) -> Self {
+        Self {
+            store: Some(store),
+            on_backend_error,
+            verbose_error_keys: HashSet::new(),
+        }
+    }
+
+    /// Checks the request's API key.
+    ///
+    /// Returns the status and message to send back when the caller is not authorized.
+    pub async fn check(&self, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+
+        let Some(key) = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()) else {
+            return Err((StatusCode::UNAUTHORIZED, "Missing API key"));
+        };
+
+        match store.validate(key).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err((StatusCode::UNAUTHORIZED, "Invalid API key")),
+            Err(e) => match self.on_backend_error {
+                FailureMode::FailClosed => {
+                    tracing::error!(
+                        "Auth backend unavailable, rejecting request (fail-closed): {}",
+                        e
+                    );
+                    Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Authentication unavailable",
+                    ))
+                }
+                FailureMode::FailOpen => {
+                    tracing::error!(
+                        "Auth backend unavailable, ALLOWING UNAUTHENTICATED request (fail-open): {}",
+                        e
+                    );
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+/// The call being authorized and who is making it.
+pub struct AuthorizationRequest<'a> {
+    pub caller: &'a Caller,
+
+    /// The JSON-RPC call, with its method and params.
+    pub call: &'a RpcRequest,
+}
+
+/// Whether a call may be forwarded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Allow,
+
+    /// Reject the call, telling the client why.
+    Deny(String),
+
+    /// Reject the call as if the method did not exist, so probing cannot tell
+    /// blocked methods from unsupported ones.
+    Hide,
+}
+
+/// Decides whether an authenticated caller may make a particular call.
+pub trait Authorizer: Send + Sync {
+    fn authorize<'a>(&'a self, request: AuthorizationRequest<'a>) -> BoxFuture<'a, Decision>;
+}
+
+/// Puts one call to `authorizer`, returning the JSON-RPC error to answer it
+/// with when it is rejected.
+pub async fn check_call(
+    authorizer: &dyn Authorizer,
+    caller: &Caller,
+    call: &RpcRequest,
+) -> Result<(), RpcResponse> {
+    match authorizer
+        .authorize(AuthorizationRequest { caller, call })
+        .await
+    {
+        Decision::Allow => Ok(()),
+        Decision::Deny(reason) => {
+            tracing::info!("Denied {} call: {}", call.method, reason);
+            Err(RpcResponse::error(
+                call.id.clone(),
+                NOT_AUTHORIZED_CODE,
+                reason,
+            ))
+        }
+        Decision::Hide => {
+            tracing::info!("Blocked {} call by method filter", call.method);
+            Err(RpcResponse::error(
+                call.id.clone(),
+                METHOD_NOT_FOUND_CODE,
+                "Method not found".to_string(),
+            ))
+        }
+    }
+}
+
+/// Authorizer enforcing the gateway-wide method filter and the configured
+/// per-key method allowlists.
+pub struct KeyPolicyAuthorizer {
+    method_filter: MethodFilterSettings,
+    allowed_methods: HashMap<String, HashSet<String>>,
+}
+
+impl KeyPolicyAuthorizer {
+    pub fn new(settings: &AuthSettings, method_filter: &MethodFilterSettings) -> Self {
+        let allowed_methods = settings
+            .key_policies
+            .iter()
+            .filter(|(_, policy)| !policy.allowed_methods.is_empty())
+            .map(|(key, policy)| {
+                (
+                    key.clone(),
+                    policy.allowed_methods.iter().cloned().collect(),
+                )
+            })
+            .collect();
+        Self {
+            method_filter: method_filter.clone(),
+            allowed_methods,
+        }
+    }
+}
+
+impl Authorizer for KeyPolicyAuthorizer {
+    fn authorize<'a>(&'a self, request: AuthorizationRequest<'a>) -> BoxFuture<'a, Decision> {
+        if !self.method_filter.permits(&request.call.method) {
+            return Box::pin(async { Decision::Hide });
+        }
+        let allowed = request
+            .caller
+            .api_key
+            .as_ref()
+            .and_then(|key| self.allowed_methods.get(key))
+            .is_none_or(|methods| methods.contains(&request.call.method));
+        let decision = if allowed {
+            Decision::Allow
+        } else {
+            Decision::Deny(format!(
+                "Method {} not allowed for this key",
+                request.call.method
+            ))
+        };
+        Box::pin(async move { decision })
+    }
+}
+
+/// Middleware rejecting requests that fail API-key authentication.
+pub async fn require_api_key(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match state.auth.check(request.headers()).await {
+        Ok(()) => next.run(request).await,
+        Err(rejection) => {
+            state
+                .request_counters
+                .record(Some(ErrorClass::of_status(rejection.0)));
+            rejection.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct UnreachableStore;
+
+    impl KeyStore for UnreachableStore {
+        fn validate<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, Result<bool, String>> {
+            Box::pin(async { Err("connection refused".to_string()) })
+        }
+    }
+
+    fn with_key(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_static_keys() {
+        let auth = Authenticator::new(&AuthSettings {
+            api_keys: vec!["k1".to_string()],
+            ..Default::default()
+        });
+
+        assert!(auth.check(&with_key("k1")).await.is_ok());
+        assert_eq!(
+            auth.check(&with_key("k2")).await.unwrap_err().0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            auth.check(&HeaderMap::new()).await.unwrap_err().0,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backend_error_honors_failure_mode() {
+        let closed = Authenticator::with_store(Arc::new(UnreachableStore), FailureMode::FailClosed);
+        assert_eq!(
+            closed.check(&with_key("k1")).await.unwrap_err().0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let open = Authenticator::with_store(Arc::new(UnreachableStore), FailureMode::FailOpen);
+        assert!(open.check(&with_key("k1")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_key_policy_restricts_methods() {
+        let authorizer = KeyPolicyAuthorizer::new(
+            &AuthSettings {
+                key_policies: HashMap::from([(
+                    "reader".to_string(),
+                    KeyPolicy {
+                        allowed_methods: vec![
+                            "eth_call".to_string(),
+                            "debug_traceCall".to_string(),
+                        ],
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            },
+            &MethodFilterSettings {
+                deny: vec!["debug_*".to_string()],
+                ..Default::default()
+            },
+        );
+        let decide = |key: Option<&str>, method: &'static str| {
+            let caller = Caller {
+                api_key: key.map(str::to_string),
+                ..Default::default()
+            };
+            let call = RpcRequest {
+                jsonrpc: "2.0".to_string(),
+                method: method.to_string(),
+                params: serde_json::json!([]),
+                id: serde_json::json!(1),
+            };
+            let authorizer = &authorizer;
+            async move {
+                authorizer
+                    .authorize(AuthorizationRequest {
+                        caller: &caller,
+                        call: &call,
+                    })
+                    .await
+            }
+        };
+
+        assert_eq!(decide(Some("reader"), "eth_call").await, Decision::Allow);
+        assert!(matches!(
+            decide(Some("reader"), "eth_sendRawTransaction").await,
+            Decision::Deny(_)
+        ));
+        assert_eq!(
+            decide(Some("other"), "eth_sendRawTransaction").await,
+            Decision::Allow
+        );
+        assert_eq!(
+            decide(None, "eth_sendRawTransaction").await,
+            Decision::Allow
+        );
+        // The method filter applies to every key, even one allowing the method
+        assert_eq!(
+            decide(Some("reader"), "debug_traceCall").await,
+            Decision::Hide
+        );
+        assert_eq!(decide(None, "debug_traceCall").await, Decision::Hide);
+    }
+}
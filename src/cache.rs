@@ -8,52 +8,623 @@
 //! # Cache Strategy
 //!
 //! The cache uses a dual eviction strategy:
-//! 1. **Time-based**: Entries expire after `CACHE_TTL` seconds
+//! 1. **Time-based**: Entries expire after `ttl_ms` (2 seconds by default)
 //! 2. **LRU-based**: When capacity is reached, least recently used entries are evicted
+//!
+//! # Sharding
+//!
+//! A lookup refreshes LRU recency, so even reads take the store's write
+//! lock. To keep concurrent lookups from serializing on one lock, entries are
+//! spread by key hash over `shards` independently locked LRU stores, each with
+//! its share of `capacity`. TTLs behave as before; LRU eviction picks the
+//! least recently used entry of the full shard rather than of the whole cache.
+//! The default is a single shard, which keeps exact LRU order; each shard
+//! holds at least 128 entries, so a small cache is not split into stores that
+//! evict almost at once.
+//!
+//! # Cacheable Methods
+//!
+//! Only methods in `cacheable_methods` or `method_ttl_ms` are cached; everything
+//! else always goes upstream. `method_ttl_ms` gives a method its own TTL, so
+//! immutable results such as `eth_getTransactionReceipt` can live for hours
+//! while `eth_blockNumber` stays short. TTLs are capped at one year.
+//!
+//! Requests that name a block by tag (`latest`, `pending`, `earliest`, `safe`,
+//! `finalized`) are never cached, even for cacheable methods: the tag points at
+//! a different block as the chain advances. The same request for a block number
+//! or hash is cached as usual.
+//!
+//! # Negative Results
+//!
+//! A `null` result, such as the receipt of a transaction not yet mined, is
+//! not cached by default: it will change. Methods in `negative_ttl_ms` cache
+//! it anyway for their own, typically second-long TTL, so clients polling for
+//! a receipt are answered from the cache between refreshes while still seeing
+//! the real result shortly after it appears. This works whether or not the
+//! method's other results are cached. Methods that never answer `null`
+//! legitimately (see `may_answer_null`) still skip it.
+//!
+//! # Upstream TTL Hints
+//!
+//! Some providers say how long a result stays valid. With `ttl_hint_header`
+//! set, a result carrying that header is cached for the hinted number of
+//! seconds instead of its configured TTL, for every cached method or only
+//! those in `ttl_hint_methods`. Results without the header, or with an
+//! unparseable one, keep the configured TTL.
+//!
+//! # TTL Jitter
+//!
+//! Entries written together (e.g. by prewarming) would otherwise all expire in
+//! the same instant and refetch as a stampede. With `ttl_jitter_ms`, each entry
+//! gets its own expiry drawn uniformly from `ttl_ms ± ttl_jitter_ms`.
+//!
+//! # Bypass and Minimum TTL
+//!
+//! Clients may ask to bypass the cache, or cap how old a cached value they
+//! accept (`X-Max-Age`), which refetches entries older than that even within
+//! their TTL. A bypass is simply a maximum age of zero. When `min_ttl_ms` is
+//! configured, either is only honored once the entry is older than that floor,
+//! so aggressive bypassing cannot turn a hot key into an upstream hammer.
+//! The fresh result of a bypassed read replaces the cached entry as usual.
+//!
+//! # Key Normalization
+//!
+//! Keys come from `cache_key`; `normalize_param_types` opts into treating
+//! differently-typed but equivalent params (`1` vs `"0x1"`) as the same entry,
+//! and `key_hashing` bounds key size by storing a digest of the params.
+//!
+//! # Summary Logging
+//!
+//! With `summary_interval_ms` set, a background task logs a one-line summary
+//! every interval: entry count, approximate memory, and the hit ratio and
+//! eviction and expiry rates over the interval since the previous summary.
+//! Memory is estimated from the average serialized size of inserted entries.
+//!
+//! # Reorg Invalidation
+//!
+//! When `reorg` detects a chain reorganization, only the entries of
+//! `reorg_sensitive_methods` (receipts, logs, blocks by number, ...) are
+//! dropped. Results a reorg cannot change, such as `eth_chainId`, stay cached.
+//!
+//! # Result Validation
+//!
+//! A parseable but malformed result would otherwise be served repeatedly for the
+//! whole TTL, so callers check `is_well_formed_result` before caching.
 
-
+use crate::cache_key::{self, KeyHashing};
+use crate::method_class;
 use lru_time_cache::LruCache;
 use parking_lot::RwLock;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Longest TTL any entry may have, in milliseconds (one year).
+const MAX_TTL_MS: u64 = 365 * 24 * 60 * 60 * 1000;
+
+/// Fewest entries a shard is given; `shards` is reduced to keep to it.
+const MIN_SHARD_CAPACITY: usize = 128;
 
-/// Time-to-live for cached entries.
-const CACHE_TTL: Duration = Duration::from_secs(2);
+/// Tunable cache behavior.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// Time-to-live for cached entries of methods without their own TTL.
+    pub ttl_ms: u64,
 
-/// Maximum number of entries the cache can hold.
-const CACHE_CAPACITY: usize = 1000;
+    /// Methods whose results are cached, with `ttl_ms`.
+    pub cacheable_methods: Vec<String>,
 
+    /// Per-method TTLs. Methods listed here are cacheable as well.
+    pub method_ttl_ms: HashMap<String, u64>,
+
+    /// Methods whose `null` results are cached, each for its own TTL, usually
+    /// much shorter than the TTL of their other results.
+    pub negative_ttl_ms: HashMap<String, u64>,
+
+    /// Maximum number of entries the cache can hold.
+    pub capacity: usize,
+
+    /// Independently locked partitions the entries are spread over by key
+    /// hash, each holding an equal share of `capacity`. More shards let
+    /// lookups of different keys proceed in parallel; LRU order is kept per
+    /// shard. Shards are capped so each holds at least 128 entries. Defaults
+    /// to 1.
+    pub shards: usize,
+
+    /// Spread each entry's expiry randomly by up to this much either side of
+    /// `ttl_ms`. Zero gives every entry exactly `ttl_ms`.
+    pub ttl_jitter_ms: u64,
+
+    /// Minimum age an entry must reach before a client bypass refetches it.
+    ///
+    /// `None` means bypass requests always go upstream.
+    pub min_ttl_ms: Option<u64>,
+
+    /// Canonicalize block tags, block numbers and boolean flags in cache keys,
+    /// so equivalent spellings of a request share an entry.
+    pub normalize_param_types: bool,
+
+    /// Replace params in cache keys with a fixed-size digest.
+    pub key_hashing: KeyHashing,
+
+    /// How often to log a cache summary. `None` disables the summary.
+    pub summary_interval_ms: Option<u64>,
+
+    /// Upstream response header carrying how long a result stays valid, in
+    /// whole seconds (e.g. `X-Cache-TTL: 30`). `None` ignores upstream hints.
+    pub ttl_hint_header: Option<String>,
+
+    /// Methods whose TTL follows the upstream hint when one is present. Empty
+    /// honors the hint for every cached method.
+    pub ttl_hint_methods: Vec<String>,
+
+    /// Methods whose cached results a chain reorganization can change. Their
+    /// entries are dropped when a reorg is detected; others are kept.
+    pub reorg_sensitive_methods: Vec<String>,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            ttl_ms: 2000,
+            cacheable_methods: vec!["eth_blockNumber".to_string()],
+            method_ttl_ms: HashMap::new(),
+            negative_ttl_ms: HashMap::new(),
+            capacity: 1000,
+            shards: 1,
+            ttl_jitter_ms: 0,
+            min_ttl_ms: None,
+            normalize_param_types: false,
+            key_hashing: KeyHashing::None,
+            summary_interval_ms: None,
+            ttl_hint_header: None,
+            ttl_hint_methods: Vec::new(),
+            reorg_sensitive_methods: [
+                "eth_blockNumber",
+                "eth_getBlockByNumber",
+                "eth_getBlockReceipts",
+                "eth_getLogs",
+                "eth_getTransactionByHash",
+                "eth_getTransactionReceipt",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+        }
+    }
+}
+
+/// Cache lookup and removal counts since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+
+    /// Entries dropped by the store to make room or past the longest TTL.
+    pub evictions: u64,
+
+    /// Entries found past their own expiry on lookup.
+    pub expirations: u64,
+}
+
+/// A cached value along with the instant it was stored and its own expiry.
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    value: serde_json::Value,
+    inserted_at: Instant,
+    expires_at: Instant,
+}
 
 pub struct Cache {
-    /// Internal LRU cache storage.
-    store: RwLock<LruCache<String, serde_json::Value>>,
+    /// Internal LRU cache storage, split into shards picked by key hash.
+    shards: Vec<RwLock<LruCache<String, CacheEntry>>>,
+
+    /// Hashes keys to their shard.
+    shard_hasher: RandomState,
+
+    /// Behavior knobs such as the bypass floor.
+    settings: CacheSettings,
+
+    /// Lookups answered from the cache.
+    hits: AtomicU64,
+
+    /// Lookups that had to go upstream, including bypasses.
+    misses: AtomicU64,
+
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+
+    /// Entries inserted and their total estimated size, for the memory estimate.
+    /// Only counted with `summary_interval_ms` set.
+    inserts: AtomicU64,
+    inserted_bytes: AtomicU64,
 }
 
 impl Cache {
-    /// Creates a new cache with default TTL and capacity.
-    pub fn new() -> Self {
+    /// Creates a new cache with the configured TTL and capacity.
+    pub fn new(settings: CacheSettings) -> Self {
+        // The store only evicts entries past the longest possible expiry;
+        // each entry's own expiry is checked on read.
+        let longest = match settings.ttl_hint_header {
+            // Hints may ask for anything up to the cap
+            Some(_) => MAX_TTL_MS,
+            None => settings
+                .method_ttl_ms
+                .values()
+                .fold(settings.ttl_ms, |max, &ttl| max.max(ttl)),
+        };
+        let max_ttl = Duration::from_millis(
+            longest
+                .saturating_add(settings.ttl_jitter_ms)
+                .min(MAX_TTL_MS),
+        );
+        let shard_count = settings
+            .shards
+            .clamp(1, (settings.capacity / MIN_SHARD_CAPACITY).max(1));
+        let shard_capacity = settings.capacity.div_ceil(shard_count);
         Self {
-            store: RwLock::new(LruCache::with_expiry_duration_and_capacity(
-                CACHE_TTL,
-                CACHE_CAPACITY,
-            )),
+            shards: (0..shard_count)
+                .map(|_| {
+                    RwLock::new(LruCache::with_expiry_duration_and_capacity(
+                        max_ttl,
+                        shard_capacity,
+                    ))
+                })
+                .collect(),
+            shard_hasher: RandomState::new(),
+            settings,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+            inserts: AtomicU64::new(0),
+            inserted_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// The shard holding `key`.
+    fn shard(&self, key: &str) -> &RwLock<LruCache<String, CacheEntry>> {
+        let index = self.shard_hasher.hash_one(key) % self.shards.len() as u64;
+        &self.shards[index as usize]
+    }
+
+    /// Entries currently held across all shards.
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
+
+    /// Builds the cache key for a request under this cache's settings.
+    pub fn key_for(&self, method: &str, params: &serde_json::Value) -> String {
+        let key = cache_key::cache_key(method, params, self.settings.normalize_param_types);
+        cache_key::hash_key(method, key, self.settings.key_hashing)
+    }
+
+    /// Whether any method is cached at all.
+    pub fn is_enabled(&self) -> bool {
+        self.settings.capacity > 0
+            && !(self.settings.cacheable_methods.is_empty()
+                && self.settings.method_ttl_ms.is_empty()
+                && self.settings.negative_ttl_ms.is_empty())
+    }
+
+    /// Returns the TTL for results of `method`, or `None` if it is not cached.
+    ///
+    /// Writes are never cached, even if configured.
+    pub fn ttl_for(&self, method: &str) -> Option<Duration> {
+        if method_class::is_write(method) {
+            return None;
+        }
+        let ttl_ms = match self.settings.method_ttl_ms.get(method) {
+            Some(&ttl_ms) => ttl_ms,
+            None if self.settings.cacheable_methods.iter().any(|m| m == method) => {
+                self.settings.ttl_ms
+            }
+            None => return None,
+        };
+        Some(Duration::from_millis(ttl_ms.min(MAX_TTL_MS)))
+    }
+
+    /// Returns the TTL for `null` results of `method`, or `None` if they are
+    /// not cached.
+    pub fn negative_ttl_for(&self, method: &str) -> Option<Duration> {
+        if method_class::is_write(method) {
+            return None;
+        }
+        let ttl_ms = *self.settings.negative_ttl_ms.get(method)?;
+        Some(Duration::from_millis(ttl_ms.min(MAX_TTL_MS)))
+    }
+
+    /// TTL requested by the upstream for a `method` result through the
+    /// configured hint header, if the method honors hints and one was sent.
+    pub fn hinted_ttl(&self, method: &str, headers: &[(String, String)]) -> Option<Duration> {
+        let header = self.settings.ttl_hint_header.as_deref()?;
+        let methods = &self.settings.ttl_hint_methods;
+        if !methods.is_empty() && !methods.iter().any(|m| m == method) {
+            return None;
         }
+        let seconds: u64 = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(header))
+            .and_then(|(_, value)| value.trim().parse().ok())?;
+        Some(Duration::from_millis(
+            seconds.saturating_mul(1000).min(MAX_TTL_MS),
+        ))
     }
 
     /// Retrieves a value from the cache if it exists and hasn't expired.
+    #[cfg(test)]
     pub fn get(&self, key: &str) -> Option<serde_json::Value> {
-        let mut store = self.store.write();
-        if let Some(value) = store.get(key) {
-            Some(value.clone())
+        self.live_entry(key).map(|entry| entry.value)
+    }
+
+    /// Returns the entry for `key` unless its own expiry has passed, in which
+    /// case it is dropped.
+    fn live_entry(&self, key: &str) -> Option<CacheEntry> {
+        let mut store = self.shard(key).write();
+        let entry = store.get(key)?.clone();
+        if entry.expires_at <= Instant::now() {
+            store.remove(key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Looks up a value no older than the client's `max_age`, if given.
+    ///
+    /// An older entry normally reports a miss so the caller refetches. With a
+    /// minimum TTL configured, entries younger than the floor are still served.
+    pub fn lookup(&self, key: &str, max_age: Option<Duration>) -> Option<serde_json::Value> {
+        let value = self.lookup_uncounted(key, max_age);
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Returns how many lookups hit and missed so far.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Estimated memory held by the cached entries, in bytes.
+    fn approx_bytes(&self, entries: usize) -> u64 {
+        let inserts = self.inserts.load(Ordering::Relaxed);
+        if inserts == 0 {
+            return 0;
+        }
+        entries as u64 * (self.inserted_bytes.load(Ordering::Relaxed) / inserts)
+    }
+
+    /// Logs the summary for the interval since `previous` was taken, and
+    /// returns the stats to diff the next summary against.
+    fn log_summary(&self, previous: CacheStats, elapsed: Duration) -> CacheStats {
+        let current = self.stats();
+        let entries = self.len();
+        let hits = current.hits - previous.hits;
+        let lookups = hits + current.misses - previous.misses;
+        let hit_ratio = if lookups == 0 {
+            "n/a".to_string()
+        } else {
+            format!("{:.3}", hits as f64 / lookups as f64)
+        };
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        tracing::info!(
+            entries,
+            approx_bytes = self.approx_bytes(entries),
+            lookups,
+            hit_ratio = %hit_ratio,
+            evictions_per_sec = %format!("{:.2}", (current.evictions - previous.evictions) as f64 / seconds),
+            expirations_per_sec = %format!("{:.2}", (current.expirations - previous.expirations) as f64 / seconds),
+            "Cache summary"
+        );
+        current
+    }
+
+    /// Starts logging a summary every `summary_interval_ms`, if configured.
+    pub fn start_summary_logger(self: Arc<Self>) {
+        let Some(interval_ms) = self.settings.summary_interval_ms else {
+            return;
+        };
+        let period = Duration::from_millis(interval_ms.max(1));
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        let mut previous = (self.stats(), Instant::now());
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                let stats = self.log_summary(previous.0, previous.1.elapsed());
+                previous = (stats, Instant::now());
+            }
+        });
+    }
+
+    fn lookup_uncounted(&self, key: &str, max_age: Option<Duration>) -> Option<serde_json::Value> {
+        let entry = self.live_entry(key)?;
+        let Some(max_age) = max_age else {
+            return Some(entry.value);
+        };
+
+        let age = entry.inserted_at.elapsed();
+        if age < max_age {
+            return Some(entry.value);
+        }
+        let min_ttl = Duration::from_millis(self.settings.min_ttl_ms?);
+        if age < min_ttl {
+            tracing::debug!(
+                "Ignoring cache bypass for {}: entry younger than min TTL",
+                key
+            );
+            Some(entry.value)
         } else {
             None
         }
     }
 
-    /// Inserts or updates a value in the cache.
+    /// Inserts or updates a value in the cache with the default TTL.
+    #[cfg(test)]
     pub fn put(&self, key: String, value: serde_json::Value) {
-        let mut store = self.store.write();
-        store.insert(key.clone(), value);
+        self.put_with_ttl(key, value, Duration::from_millis(self.settings.ttl_ms));
+    }
+
+    /// Inserts or updates a value in the cache with the given nominal TTL.
+    pub fn put_with_ttl(&self, key: String, value: serde_json::Value, ttl: Duration) {
+        let now = Instant::now();
+        let ttl = self.entry_ttl(&key, ttl);
+        // Sizing serializes the value, so it is only paid for when summaries are logged
+        if self.settings.summary_interval_ms.is_some() {
+            let size = key.len() + value.to_string().len() + std::mem::size_of::<CacheEntry>();
+            self.inserts.fetch_add(1, Ordering::Relaxed);
+            self.inserted_bytes
+                .fetch_add(size as u64, Ordering::Relaxed);
+        }
+
+        let mut store = self.shard(&key).write();
+        let before = store.len();
+        let replaced = store.insert(
+            key,
+            CacheEntry {
+                value,
+                inserted_at: now,
+                expires_at: now + ttl,
+            },
+        );
+        // The store evicts silently; whatever it lost shows in its length
+        let kept = before + usize::from(replaced.is_none());
+        let evicted = kept.saturating_sub(store.len());
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    /// Drops every entry, returning how many there were.
+    pub fn clear(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                let mut store = shard.write();
+                let entries = store.len();
+                store.clear();
+                entries
+            })
+            .sum()
+    }
+
+    /// Drops the entries of every reorg-sensitive method, returning how many.
+    pub fn invalidate_reorg_sensitive(&self) -> usize {
+        let sensitive = &self.settings.reorg_sensitive_methods;
+        let mut dropped = 0;
+        for shard in &self.shards {
+            let mut store = shard.write();
+            let stale: Vec<String> = store
+                .peek_iter()
+                .map(|(key, _)| key)
+                .filter(|key| {
+                    // Keys start with the method, hashed ones followed by `#`
+                    let method = key.split([':', '#']).next().unwrap_or_default();
+                    sensitive.iter().any(|m| m == method)
+                })
+                .cloned()
+                .collect();
+            for key in &stale {
+                store.remove(key);
+            }
+            dropped += stale.len();
+        }
+        dropped
+    }
+
+    /// Nominal TTL, shifted by a random offset within the jitter band.
+    fn entry_ttl(&self, key: &str, ttl: Duration) -> Duration {
+        let ttl = ttl.as_millis() as u64;
+        let jitter = self.settings.ttl_jitter_ms;
+        if jitter == 0 {
+            return Duration::from_millis(ttl);
+        }
+        let random = RandomState::new().hash_one((key, Instant::now()));
+        let offset = random % (2 * jitter + 1);
+        Duration::from_millis((ttl + offset).saturating_sub(jitter))
+    }
+}
+
+/// Methods whose result is a hex-encoded quantity such as `"0x1a"`.
+const QUANTITY_METHODS: &[&str] = &[
+    "eth_blockNumber",
+    "eth_chainId",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getTransactionCount",
+];
+
+/// Methods whose result is hex-encoded byte data such as `"0x6080"`.
+const DATA_METHODS: &[&str] = &["eth_call", "eth_getCode", "eth_getStorageAt"];
+
+/// Methods whose result is a JSON object when present.
+const OBJECT_METHODS: &[&str] = &[
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getTransactionByHash",
+    "eth_getTransactionReceipt",
+];
+
+/// Basic sanity check a result must pass before it is cached.
+///
+/// `null` never passes, and results of well-known methods must have the
+/// expected shape. Unknown methods only need to be non-null.
+pub fn is_plausible_result(method: &str, result: &serde_json::Value) -> bool {
+    if result.is_null() {
+        return false;
+    }
+    if QUANTITY_METHODS.contains(&method) {
+        return result
+            .as_str()
+            .and_then(|s| s.strip_prefix("0x"))
+            .is_some_and(|digits| !digits.is_empty() && is_hex(digits));
+    }
+    if DATA_METHODS.contains(&method) {
+        return result
+            .as_str()
+            .and_then(|s| s.strip_prefix("0x"))
+            .is_some_and(|digits| digits.len() % 2 == 0 && is_hex(digits));
+    }
+    if OBJECT_METHODS.contains(&method) {
+        return result.is_object();
+    }
+    true
+}
+
+/// Methods whose `null` result is also what a node missing the data returns,
+/// so it cannot be trusted on its shape alone.
+const UNVERIFIED_NULL_METHODS: &[&str] = &["eth_getTransactionReceipt"];
+
+/// Whether `method` may legitimately answer `null`, such as a lookup of an
+/// unknown transaction. Quantity and data methods never do.
+pub fn may_answer_null(method: &str) -> bool {
+    !QUANTITY_METHODS.contains(&method) && !DATA_METHODS.contains(&method)
+}
+
+/// Whether an upstream result has the shape its method calls for.
+///
+/// Looser than `is_plausible_result`: `null` is accepted for methods that
+/// answer it legitimately, except where a node lacking the data would answer
+/// the same, as for a receipt; callers confirm such a `null` separately.
+pub fn is_well_formed_result(method: &str, result: &serde_json::Value) -> bool {
+    if result.is_null() {
+        return may_answer_null(method) && !UNVERIFIED_NULL_METHODS.contains(&method);
     }
+    is_plausible_result(method, result)
+}
+
+fn is_hex(digits: &str) -> bool {
+    digits.bytes().all(|b| b.is_ascii_hexdigit())
 }
 
 #[cfg(test)]
@@ -62,7 +633,7 @@ mod tests {
 
     #[test]
     fn test_cache_put_and_get() {
-        let cache = Cache::new();
+        let cache = Cache::new(CacheSettings::default());
         let key = "test_key".to_string();
         let value = serde_json::json!({"result": "0x1234"});
 
@@ -75,14 +646,14 @@ mod tests {
 
     #[test]
     fn test_cache_miss() {
-        let cache = Cache::new();
+        let cache = Cache::new(CacheSettings::default());
         let result = cache.get("invalid_key");
         assert!(result.is_none());
     }
 
     #[test]
     fn test_cache_expiry() {
-        let cache = Cache::new();
+        let cache = Cache::new(CacheSettings::default());
         let key = "expired_key".to_string();
         let value = serde_json::json!({"result": "0x1234"});
 
@@ -96,12 +667,11 @@ mod tests {
     #[test]
     fn test_cache_lru_eviction() {
         // Create a cache with small capacity for testing
-        let cache = Cache {
-            store: RwLock::new(LruCache::with_expiry_duration_and_capacity(
-                Duration::from_secs(60),
-                2,
-            )),
-        };
+        let cache = Cache::new(CacheSettings {
+            ttl_ms: 60_000,
+            capacity: 2,
+            ..Default::default()
+        });
 
         cache.put("key1".to_string(), serde_json::json!("value1"));
         cache.put("key2".to_string(), serde_json::json!("value2"));
@@ -111,4 +681,321 @@ mod tests {
         assert!(cache.get("key2").is_some());
         assert!(cache.get("key3").is_some());
     }
+
+    #[test]
+    fn test_shards_keep_a_minimum_capacity() {
+        let shards = |capacity, shards| {
+            Cache::new(CacheSettings {
+                capacity,
+                shards,
+                ..Default::default()
+            })
+            .shards
+            .len()
+        };
+        assert_eq!(shards(1000, 1), 1);
+        assert_eq!(shards(1000, 4), 4);
+        assert_eq!(shards(1000, 64), 7);
+        assert_eq!(shards(2, 16), 1);
+    }
+
+    #[test]
+    fn test_lookups_in_other_shards_do_not_wait_for_a_busy_shard() {
+        let cache = Arc::new(Cache::new(CacheSettings {
+            ttl_ms: 60_000,
+            shards: 4,
+            ..Default::default()
+        }));
+        let busy = "key0".to_string();
+        let other = (1..)
+            .map(|i| format!("key{}", i))
+            .find(|key| !std::ptr::eq(cache.shard(key), cache.shard(&busy)))
+            .unwrap();
+        cache.put(busy.clone(), serde_json::json!("0x1"));
+        cache.put(other.clone(), serde_json::json!("0x2"));
+
+        // Hold one shard's lock, as a long burst of writes to it would
+        let guard = cache.shard(&busy).write();
+        let (done, finished) = std::sync::mpsc::channel();
+        let readers: Vec<_> = [other, busy]
+            .into_iter()
+            .map(|key| {
+                let cache = Arc::clone(&cache);
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1_000 {
+                        cache.lookup(&key, None);
+                    }
+                    done.send(key).unwrap();
+                })
+            })
+            .collect();
+
+        let first = finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(first, "key0");
+        assert!(finished.recv_timeout(Duration::from_millis(50)).is_err());
+
+        drop(guard);
+        assert_eq!(
+            finished.recv_timeout(Duration::from_secs(5)).unwrap(),
+            "key0"
+        );
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(cache.stats().hits, 2_000);
+    }
+
+    #[test]
+    fn test_bypass_skips_cache_without_min_ttl() {
+        let cache = Cache::new(CacheSettings::default());
+        cache.put("key".to_string(), serde_json::json!("0x1"));
+
+        assert!(cache.lookup("key", Some(Duration::ZERO)).is_none());
+        assert!(cache.lookup("key", None).is_some());
+    }
+
+    #[test]
+    fn test_min_ttl_serves_cached_value_despite_bypass() {
+        let cache = Cache::new(CacheSettings {
+            min_ttl_ms: Some(500),
+            ..Default::default()
+        });
+        cache.put("key".to_string(), serde_json::json!("0x1"));
+
+        // Rapid bypass requests inside the floor keep hitting the cache.
+        for _ in 0..5 {
+            assert_eq!(
+                cache.lookup("key", Some(Duration::ZERO)),
+                Some(serde_json::json!("0x1"))
+            );
+        }
+
+        std::thread::sleep(Duration::from_millis(600));
+        assert!(cache.lookup("key", Some(Duration::ZERO)).is_none());
+    }
+
+    #[test]
+    fn test_plausible_result_shapes() {
+        assert!(is_plausible_result(
+            "eth_blockNumber",
+            &serde_json::json!("0x1b4")
+        ));
+        assert!(is_plausible_result("eth_call", &serde_json::json!("0x")));
+        assert!(is_plausible_result(
+            "eth_getBlockByNumber",
+            &serde_json::json!({"number": "0x1"})
+        ));
+
+        assert!(!is_plausible_result(
+            "eth_blockNumber",
+            &serde_json::Value::Null
+        ));
+        assert!(!is_plausible_result(
+            "eth_blockNumber",
+            &serde_json::json!("0x")
+        ));
+        assert!(!is_plausible_result(
+            "eth_blockNumber",
+            &serde_json::json!(436)
+        ));
+        assert!(!is_plausible_result(
+            "eth_chainId",
+            &serde_json::json!("latest")
+        ));
+        assert!(!is_plausible_result(
+            "eth_call",
+            &serde_json::json!("0xabc")
+        ));
+        assert!(!is_plausible_result(
+            "eth_getBlockByNumber",
+            &serde_json::json!("0x1")
+        ));
+    }
+
+    #[test]
+    fn test_well_formed_results_allow_null_only_where_meaningful() {
+        let null = serde_json::Value::Null;
+        assert!(!is_well_formed_result("eth_getTransactionReceipt", &null));
+        assert!(may_answer_null("eth_getTransactionReceipt"));
+        assert!(is_well_formed_result("eth_getTransactionByHash", &null));
+        assert!(is_well_formed_result("debug_traceTransaction", &null));
+        assert!(!is_well_formed_result("eth_blockNumber", &null));
+        assert!(!is_well_formed_result("eth_call", &null));
+        assert!(!is_well_formed_result(
+            "eth_getTransactionReceipt",
+            &serde_json::json!("0x1")
+        ));
+    }
+
+    #[test]
+    fn test_ttl_jitter_spreads_expiry_within_band() {
+        let cache = Cache::new(CacheSettings {
+            ttl_ms: 1000,
+            ttl_jitter_ms: 400,
+            ..Default::default()
+        });
+        for i in 0..50 {
+            cache.put(format!("key{}", i), serde_json::json!("0x1"));
+        }
+
+        let ttls: Vec<Duration> = (0..50)
+            .map(|i| {
+                let key = format!("key{}", i);
+                let store = cache.shard(&key).read();
+                let entry = store.peek(&key).unwrap();
+                entry.expires_at - entry.inserted_at
+            })
+            .collect();
+        let (min, max) = (ttls.iter().min().unwrap(), ttls.iter().max().unwrap());
+        assert!(*min >= Duration::from_millis(600), "{:?}", min);
+        assert!(*max <= Duration::from_millis(1400), "{:?}", max);
+        assert!(
+            *max - *min >= Duration::from_millis(200),
+            "{:?}..{:?}",
+            min,
+            max
+        );
+    }
+
+    #[test]
+    fn test_entry_past_its_own_expiry_is_a_miss() {
+        let cache = Cache::new(CacheSettings {
+            ttl_ms: 100,
+            ttl_jitter_ms: 50,
+            ..Default::default()
+        });
+        cache.put("key".to_string(), serde_json::json!("0x1"));
+        assert!(cache.get("key").is_some());
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(cache.get("key").is_none());
+    }
+
+    #[test]
+    fn test_only_policy_methods_are_cacheable_with_their_own_ttl() {
+        let cache = Cache::new(CacheSettings {
+            method_ttl_ms: HashMap::from([
+                ("eth_getTransactionReceipt".to_string(), 3_600_000),
+                ("eth_sendRawTransaction".to_string(), 3_600_000),
+            ]),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            cache.ttl_for("eth_blockNumber"),
+            Some(Duration::from_millis(2000))
+        );
+        assert_eq!(
+            cache.ttl_for("eth_getTransactionReceipt"),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(cache.ttl_for("eth_call"), None);
+        // Writes stay uncacheable even when configured
+        assert_eq!(cache.ttl_for("eth_sendRawTransaction"), None);
+
+        let params = serde_json::json!(["0xabc"]);
+        assert_ne!(
+            cache.key_for("eth_getTransactionReceipt", &params),
+            cache.key_for("eth_getTransactionByHash", &params)
+        );
+    }
+
+    #[test]
+    fn test_lookups_count_hits_and_misses() {
+        let cache = Cache::new(CacheSettings::default());
+        cache.put("key".to_string(), serde_json::json!("0x1"));
+
+        cache.lookup("key", None);
+        cache.lookup("key", Some(Duration::ZERO));
+        cache.lookup("other", None);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_age_refetches_entries_older_than_tolerance() {
+        let cache = Cache::new(CacheSettings {
+            ttl_ms: 10_000,
+            ..Default::default()
+        });
+        cache.put("key".to_string(), serde_json::json!("0x1"));
+        let tolerance = Some(Duration::from_millis(100));
+
+        assert_eq!(
+            cache.lookup("key", tolerance),
+            Some(serde_json::json!("0x1"))
+        );
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(cache.lookup("key", tolerance).is_none());
+        // Still within its TTL for clients without a tolerance
+        assert!(cache.lookup("key", None).is_some());
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<parking_lot::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_is_logged_each_interval_with_interval_counts() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let cache = Arc::new(Cache::new(CacheSettings {
+            capacity: 1,
+            summary_interval_ms: Some(100),
+            ..Default::default()
+        }));
+        Arc::clone(&cache).start_summary_logger();
+        cache.put("a".to_string(), serde_json::json!("0x1"));
+        cache.put("b".to_string(), serde_json::json!("0x2"));
+        cache.lookup("b", None);
+        cache.lookup("a", None);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let output = String::from_utf8(logs.0.lock().clone()).unwrap();
+        let first = output
+            .lines()
+            .find(|l| l.contains("Cache summary"))
+            .unwrap();
+        for field in ["entries=1", "lookups=2", "hit_ratio=0.500"] {
+            assert!(first.contains(field), "missing {:?} in {}", field, first);
+        }
+        assert!(!first.contains("approx_bytes=0 "), "{}", first);
+        assert!(!first.contains("evictions_per_sec=0.00"), "{}", first);
+
+        // Counts start afresh for the next interval
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let output = String::from_utf8(logs.0.lock().clone()).unwrap();
+        let second = output
+            .lines()
+            .filter(|l| l.contains("Cache summary"))
+            .nth(1)
+            .unwrap();
+        assert!(second.contains("lookups=0"), "{}", second);
+        assert!(second.contains("hit_ratio=n/a"), "{}", second);
+    }
 }
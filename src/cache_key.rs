@@ -0,0 +1,378 @@
+//! Cache key construction.
+//!
+//! Keys have the form `method:canonical-params`. Canonicalization makes
+//! semantically identical requests share an entry while keeping every field
+//! that can change the result.
+//!
+//! # `eth_call`
+//!
+//! Only fields that influence execution are kept: `to`, `from`, `data` (or its
+//! alias `input`), `value` and `gas`. `from` matters because contracts can
+//! branch on `msg.sender`. Hex strings are lowercased and a missing block
+//! parameter is treated as `"latest"`.
+//!
+//! # Param Types
+//!
+//! With `normalize_types`, equivalent spellings of common param shapes also
+//! share a key: block parameters accept any-case tags (`"Latest"`), JSON
+//! numbers and zero-padded hex (`1`, `"0x01"` and `"0x1"` are one block), and
+//! `eth_getBlockByNumber`'s `fullTransactions` flag accepts `"true"` or `1`.
+//! Only params at known positions are touched; addresses, hashes and calldata
+//! are never reinterpreted.
+//!
+//! # Hashing
+//!
+//! A large `eth_call` payload or log filter would otherwise be stored verbatim
+//! as its key. With `key_hashing` set, the canonical params are replaced by a
+//! digest, giving keys of bounded length (`method#len:digest`). The length of
+//! the canonical params is kept alongside the digest as a cheap discriminator,
+//! so two inputs only share a key if they are the same size and collide in the
+//! hash as well.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Digest used to shorten cache keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyHashing {
+    /// Keep the canonical params in the key as they are.
+    #[default]
+    None,
+    Sha256,
+}
+
+/// Call-object fields that can affect the result of `eth_call`.
+const ETH_CALL_FIELDS: &[&str] = &["to", "from", "data", "value", "gas"];
+
+/// Named block parameters.
+const BLOCK_TAGS: &[&str] = &["latest", "earliest", "pending", "safe", "finalized"];
+
+/// Position of the block parameter for methods that take one.
+const BLOCK_PARAM_POSITIONS: &[(&str, usize)] = &[
+    ("eth_getBlockByNumber", 0),
+    ("eth_getBlockTransactionCountByNumber", 0),
+    ("eth_getUncleCountByBlockNumber", 0),
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getTransactionCount", 1),
+    ("eth_call", 1),
+    ("eth_getStorageAt", 2),
+];
+
+/// Builds the cache key for a request.
+///
+/// `normalize_types` additionally canonicalizes block parameters and boolean
+/// flags (see the module docs).
+pub fn cache_key(method: &str, params: &Value, normalize_types: bool) -> String {
+    let mut canonical = match method {
+        "eth_call" => canonicalize_eth_call(params),
+        _ => params.clone(),
+    };
+    if normalize_types {
+        normalize_param_types(method, &mut canonical);
+    }
+    format!(
+        "{}:{}",
+        method,
+        serde_json::to_string(&canonical).unwrap_or_default()
+    )
+}
+
+/// Whether `params` name a block by tag anywhere, including inside filter
+/// objects such as `eth_getLogs`' `fromBlock`.
+///
+/// Tags resolve to a different block as the chain moves, so results for them
+/// must not be cached. Block numbers and hashes are fixed and stay cacheable.
+pub fn has_block_tag(params: &Value) -> bool {
+    match params {
+        Value::String(s) => BLOCK_TAGS.iter().any(|tag| s.eq_ignore_ascii_case(tag)),
+        Value::Array(items) => items.iter().any(has_block_tag),
+        Value::Object(fields) => fields.values().any(has_block_tag),
+        _ => false,
+    }
+}
+
+/// Replaces the params part of `key` (as built by `cache_key` for `method`)
+/// with a digest.
+pub fn hash_key(method: &str, key: String, hashing: KeyHashing) -> String {
+    let algorithm = match hashing {
+        KeyHashing::None => return key,
+        KeyHashing::Sha256 => &ring::digest::SHA256,
+    };
+    let params = &key[method.len() + 1..];
+    let digest = ring::digest::digest(algorithm, params.as_bytes());
+    let hex: String = digest
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("{}#{}:{}", method, params.len(), hex)
+}
+
+fn canonicalize_eth_call(params: &Value) -> Value {
+    let Some(items) = params.as_array() else {
+        return params.clone();
+    };
+    let Some(call) = items.first().and_then(Value::as_object) else {
+        return params.clone();
+    };
+
+    let mut canonical = Map::new();
+    for field in ETH_CALL_FIELDS {
+        let value = match *field {
+            "data" => call.get("data").or_else(|| call.get("input")),
+            other => call.get(other),
+        };
+        if let Some(value) = value {
+            canonical.insert(field.to_string(), lowercase_hex(value));
+        }
+    }
+
+    let block = items
+        .get(1)
+        .map(lowercase_hex)
+        .unwrap_or_else(|| Value::String("latest".to_string()));
+
+    let mut rest: Vec<Value> = vec![Value::Object(canonical), block];
+    // State overrides and any further params are kept verbatim.
+    rest.extend(items.iter().skip(2).cloned());
+    Value::Array(rest)
+}
+
+fn normalize_param_types(method: &str, params: &mut Value) {
+    let Some(items) = params.as_array_mut() else {
+        return;
+    };
+    if let Some((_, position)) = BLOCK_PARAM_POSITIONS.iter().find(|(m, _)| *m == method)
+        && let Some(block) = items.get_mut(*position)
+    {
+        *block = normalize_block(block);
+    }
+    if method == "eth_getBlockByNumber"
+        && let Some(full) = items.get_mut(1)
+    {
+        *full = normalize_bool(full);
+    }
+}
+
+/// Canonical block parameter: a lowercase tag or a minimal lowercase hex quantity.
+fn normalize_block(value: &Value) -> Value {
+    match value {
+        Value::Number(n) => match n.as_u64() {
+            Some(n) => Value::String(format!("{:#x}", n)),
+            None => value.clone(),
+        },
+        Value::String(s) => {
+            let lower = s.to_ascii_lowercase();
+            if BLOCK_TAGS.contains(&lower.as_str()) {
+                return Value::String(lower);
+            }
+            match lower
+                .strip_prefix("0x")
+                .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+            {
+                Some(n) => Value::String(format!("{:#x}", n)),
+                None => value.clone(),
+            }
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Canonical boolean flag, accepting `"true"`/`"false"` strings and `0`/`1`.
+fn normalize_bool(value: &Value) -> Value {
+    match value {
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Value::Bool(true),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Value::Bool(false),
+        Value::Number(n) if n.as_u64() == Some(1) => Value::Bool(true),
+        Value::Number(n) if n.as_u64() == Some(0) => Value::Bool(false),
+        _ => value.clone(),
+    }
+}
+
+fn lowercase_hex(value: &Value) -> Value {
+    match value.as_str() {
+        Some(s) if s.starts_with("0x") || s.starts_with("0X") => Value::String(s.to_lowercase()),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TOKEN: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+
+    #[test]
+    fn test_eth_call_key_includes_from() {
+        let alice = json!([{"to": TOKEN, "from": "0x00000000000000000000000000000000000000a1", "data": "0x70a08231"}, "0x10"]);
+        let bob = json!([{"to": TOKEN, "from": "0x00000000000000000000000000000000000000b2", "data": "0x70a08231"}, "0x10"]);
+
+        assert_ne!(
+            cache_key("eth_call", &alice, false),
+            cache_key("eth_call", &bob, false)
+        );
+    }
+
+    #[test]
+    fn test_eth_call_key_ignores_irrelevant_fields_and_case() {
+        let a = json!([{"to": TOKEN, "input": "0xABCD", "type": "0x2", "chainId": "0x1"}]);
+        let b =
+            json!([{"to": TOKEN.to_uppercase().replace("0X", "0x"), "data": "0xabcd"}, "latest"]);
+
+        assert_eq!(
+            cache_key("eth_call", &a, false),
+            cache_key("eth_call", &b, false)
+        );
+    }
+
+    #[test]
+    fn test_eth_call_key_distinguishes_value_and_gas() {
+        let base = json!([{"to": TOKEN, "data": "0x"}, "0x10"]);
+        let with_value = json!([{"to": TOKEN, "data": "0x", "value": "0x1"}, "0x10"]);
+        let with_gas = json!([{"to": TOKEN, "data": "0x", "gas": "0x5208"}, "0x10"]);
+
+        let keys = [
+            cache_key("eth_call", &base, false),
+            cache_key("eth_call", &with_value, false),
+            cache_key("eth_call", &with_gas, false),
+        ];
+        assert_ne!(keys[0], keys[1]);
+        assert_ne!(keys[0], keys[2]);
+        assert_ne!(keys[1], keys[2]);
+    }
+
+    #[test]
+    fn test_other_methods_keep_raw_params() {
+        assert_eq!(
+            cache_key("eth_blockNumber", &json!([]), true),
+            "eth_blockNumber:[]"
+        );
+    }
+
+    fn normalized(method: &str, params: Value) -> String {
+        cache_key(method, &params, true)
+    }
+
+    #[test]
+    fn test_block_tags_ignore_case() {
+        assert_eq!(
+            normalized("eth_getBalance", json!([TOKEN, "Latest"])),
+            normalized("eth_getBalance", json!([TOKEN, "latest"]))
+        );
+        assert_ne!(
+            normalized("eth_getBalance", json!([TOKEN, "latest"])),
+            normalized("eth_getBalance", json!([TOKEN, "pending"]))
+        );
+    }
+
+    #[test]
+    fn test_block_numbers_share_one_spelling() {
+        let canonical = normalized("eth_getBlockByNumber", json!(["0x1", false]));
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!([1, false])),
+            canonical
+        );
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!(["0x01", false])),
+            canonical
+        );
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!(["0X1", false])),
+            canonical
+        );
+        assert_ne!(
+            normalized("eth_getBlockByNumber", json!(["0x2", false])),
+            canonical
+        );
+    }
+
+    #[test]
+    fn test_full_transactions_flag_accepts_strings_and_numbers() {
+        let full = normalized("eth_getBlockByNumber", json!(["latest", true]));
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!(["latest", "true"])),
+            full
+        );
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!(["latest", 1])),
+            full
+        );
+        assert_eq!(
+            normalized("eth_getBlockByNumber", json!(["latest", "FALSE"])),
+            normalized("eth_getBlockByNumber", json!(["latest", 0]))
+        );
+        assert_ne!(
+            normalized("eth_getBlockByNumber", json!(["latest", false])),
+            full
+        );
+    }
+
+    #[test]
+    fn test_eth_call_block_is_normalized_but_calldata_is_not() {
+        let a = json!([{"to": TOKEN, "data": "0x0001"}, 16]);
+        let b = json!([{"to": TOKEN, "data": "0x0001"}, "0x10"]);
+        assert_eq!(normalized("eth_call", a), normalized("eth_call", b));
+
+        let padded = json!([{"to": TOKEN, "data": "0x01"}, "0x10"]);
+        let short = json!([{"to": TOKEN, "data": "0x1"}, "0x10"]);
+        assert_ne!(
+            normalized("eth_call", padded),
+            normalized("eth_call", short)
+        );
+    }
+
+    #[test]
+    fn test_type_normalization_is_opt_in() {
+        assert_ne!(
+            cache_key("eth_getBalance", &json!([TOKEN, 1]), false),
+            cache_key("eth_getBalance", &json!([TOKEN, "0x1"]), false)
+        );
+    }
+
+    #[test]
+    fn test_hashed_keys_have_fixed_size_and_stay_distinct() {
+        let key = |data: String| {
+            let params = json!([{ "to": TOKEN, "data": data }, "latest"]);
+            hash_key(
+                "eth_call",
+                cache_key("eth_call", &params, false),
+                KeyHashing::Sha256,
+            )
+        };
+        let long = key(format!("0x{}", "ab".repeat(50_000)));
+        let other = key(format!("0x{}", "ab".repeat(49_999) + "cd"));
+        let short = key("0x70a08231".to_string());
+
+        let digest_len = |k: &str| k.rsplit(':').next().unwrap().len();
+        assert_eq!(digest_len(&long), 64);
+        assert_eq!(digest_len(&short), 64);
+        assert!(long.len() < 100, "{}", long);
+        assert!(long.starts_with("eth_call#"));
+        assert_ne!(long, other);
+        assert_ne!(long, short);
+
+        let plain = cache_key("eth_chainId", &json!([]), false);
+        assert_eq!(
+            hash_key("eth_chainId", plain.clone(), KeyHashing::None),
+            plain
+        );
+    }
+
+    #[test]
+    fn test_block_tags_are_found_but_numbers_and_hashes_are_not() {
+        assert!(has_block_tag(&json!(["Pending", false])));
+        assert!(has_block_tag(&json!([TOKEN, "finalized"])));
+        assert!(has_block_tag(
+            &json!([{"fromBlock": "0x10", "toBlock": "latest"}])
+        ));
+
+        assert!(!has_block_tag(&json!(["0x10", true])));
+        assert!(!has_block_tag(
+            &json!([{"blockHash": format!("0x{}", "ab".repeat(32))}])
+        ));
+        assert!(!has_block_tag(&json!([])));
+    }
+}
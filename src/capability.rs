@@ -0,0 +1,94 @@
+//! Node capabilities a request needs, such as archive state or tracing.
+//!
+//! Nodes declare capability tags in `capabilities` (e.g. `archive`, `full`,
+//! `trace`). `trace_*` and `debug_trace*` calls need `trace`. State queries
+//! (`eth_getBalance`, `eth_call`, ...) at `earliest` or at a block more than
+//! `FULL_NODE_STATE_BLOCKS` behind the chain head need `archive`, since pruned
+//! full nodes answer those with "missing trie node". Blocks given only by hash
+//! cannot be dated and need nothing special.
+
+use crate::types::RpcRequest;
+
+pub const ARCHIVE: &str = "archive";
+pub const TRACE: &str = "trace";
+
+/// Recent blocks whose state a pruned full node still keeps (geth's default).
+const FULL_NODE_STATE_BLOCKS: u64 = 128;
+
+/// Position of the block parameter for methods that read state.
+const STATE_METHODS: &[(&str, usize)] = &[
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getTransactionCount", 1),
+    ("eth_call", 1),
+    ("eth_getStorageAt", 2),
+];
+
+/// Returns the capability a node needs to serve `request`, given the highest
+/// known chain head.
+pub fn required(request: &RpcRequest, head: Option<u64>) -> Option<&'static str> {
+    let method = request.method.as_str();
+    if method.starts_with("trace_") || method.starts_with("debug_trace") {
+        return Some(TRACE);
+    }
+
+    let (_, position) = STATE_METHODS.iter().find(|(m, _)| *m == method)?;
+    let block = request.params.get(*position)?;
+    // EIP-1898 block objects may carry the number instead of a tag
+    let block = block.get("blockNumber").unwrap_or(block);
+    let is_historical = match block.as_str()? {
+        "earliest" => true,
+        tag => match (quantity(tag), head) {
+            (Some(number), Some(head)) => head.saturating_sub(number) > FULL_NODE_STATE_BLOCKS,
+            _ => false,
+        },
+    };
+    is_historical.then_some(ARCHIVE)
+}
+
+fn quantity(value: &str) -> Option<u64> {
+    let digits = value.strip_prefix("0x")?;
+    u64::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn request(method: &str, params: Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: json!(1),
+        }
+    }
+
+    #[test]
+    fn test_required_capabilities() {
+        let head = Some(1_000);
+        let balance = |block: Value| request("eth_getBalance", json!(["0xabc", block]));
+
+        assert_eq!(
+            required(&request("trace_block", json!(["0x1"])), head),
+            Some(TRACE)
+        );
+        assert_eq!(required(&balance(json!("earliest")), head), Some(ARCHIVE));
+        assert_eq!(required(&balance(json!("0x64")), head), Some(ARCHIVE));
+        assert_eq!(
+            required(&balance(json!({ "blockNumber": "0x64" })), head),
+            Some(ARCHIVE)
+        );
+
+        // Recent or undatable blocks can be served by any node
+        assert_eq!(required(&balance(json!("0x3e0")), head), None);
+        assert_eq!(required(&balance(json!("latest")), head), None);
+        assert_eq!(required(&balance(json!("0x64")), None), None);
+        assert_eq!(
+            required(&balance(json!({ "blockHash": "0xdef" })), head),
+            None
+        );
+        assert_eq!(required(&request("eth_chainId", json!([])), head), None);
+    }
+}
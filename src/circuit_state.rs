@@ -0,0 +1,106 @@
+//! Circuit breaker state carried across restarts.
+//!
+//! A restarted gateway would otherwise put every node straight back into
+//! rotation, including ones that were cooling down or had been flapping. With
+//! `state_file` set, each node's circuit state is written there on shutdown and
+//! applied again at startup: open circuits resume their remaining cooldown and
+//! still need a successful probe to close, flapping nodes keep their longer
+//! backoff until they stay healthy for a full cooldown, and drained nodes stay
+//! drained. Nodes that are no longer configured are ignored, and a missing file
+//! just means a fresh start.
+
+use crate::load_balancer::LoadBalancer;
+use crate::upstream::CircuitSnapshot;
+use std::collections::HashMap;
+
+/// Writes the circuit state of every node to `path`.
+pub fn save(lb: &LoadBalancer, path: &str) -> Result<(), String> {
+    let snapshots: HashMap<String, CircuitSnapshot> = lb
+        .snapshot()
+        .iter()
+        .map(|node| (node.get_name().to_string(), node.circuit_snapshot()))
+        .collect();
+    let contents = serde_json::to_string_pretty(&snapshots)
+        .map_err(|e| format!("Failed to serialize circuit state: {}", e))?;
+    std::fs::write(path, contents)
+        .map_err(|e| format!("Failed to write circuit state to {}: {}", path, e))
+}
+
+/// Applies the circuit state saved in `path`, returning how many nodes it
+/// covered.
+pub fn restore(lb: &LoadBalancer, path: &str) -> Result<usize, String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read circuit state {}: {}", path, e)),
+    };
+    let snapshots: HashMap<String, CircuitSnapshot> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid circuit state {}: {}", path, e))?;
+
+    let mut restored = 0;
+    for (name, snapshot) in &snapshots {
+        match lb.node_by_name(name) {
+            Some(node) => {
+                node.restore_circuit(snapshot);
+                restored += 1;
+            }
+            None => tracing::debug!("Ignoring saved circuit state of unknown node {}", name),
+        }
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UpstreamConfig;
+
+    fn balancer() -> LoadBalancer {
+        let node = |name: &str| UpstreamConfig {
+            name: name.to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        };
+        LoadBalancer::new(&[node("Flappy"), node("Drained"), node("Steady")]).unwrap()
+    }
+
+    #[test]
+    fn test_flapping_and_drained_nodes_survive_restart() {
+        let path = std::env::temp_dir()
+            .join(format!("ha_gateway_circuits_{}.json", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let before = balancer();
+        let flappy = before.node_by_name("Flappy").unwrap();
+        // Open, recover briefly and open again: flapping
+        for _ in 0..3 {
+            flappy.force_mark_failure();
+        }
+        flappy.force_cooldown_expiry();
+        flappy.force_mark_success();
+        for _ in 0..3 {
+            flappy.force_mark_failure();
+        }
+        before.node_by_name("Drained").unwrap().set_drained(true);
+        let saved = flappy.circuit_snapshot();
+        assert!(saved.open);
+        assert_eq!(saved.cooldown_attempts, 1);
+
+        save(&before, &path).unwrap();
+        let after = balancer();
+        assert_eq!(restore(&after, &path).unwrap(), 3);
+
+        let flappy = after.node_by_name("Flappy").unwrap();
+        let restored = flappy.circuit_snapshot();
+        assert!(restored.open);
+        assert_eq!(restored.cooldown_attempts, 1);
+        assert!(restored.cooldown_remaining_ms <= saved.cooldown_remaining_ms);
+        assert!(restored.cooldown_remaining_ms > 0);
+        assert!(after.node_by_name("Drained").unwrap().is_drained());
+        let chosen = after.choose_healthy_node(&[]).unwrap();
+        assert_eq!(chosen.get_name(), "Steady");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restore(&after, &path).unwrap(), 0);
+    }
+}
@@ -0,0 +1,375 @@
+//! Gateway configuration.
+//!
+//! Every tunable lives in a settings struct next to the component that uses it
+//! (e.g. `RetryPolicy` in `load_balancer.rs`); `GatewayConfig` gathers them into
+//! a single serde-friendly tree. Missing sections fall back to their defaults,
+//! which match the gateway's historical hardcoded behavior.
+//!
+//! # Configuration File
+//!
+//! At startup the configuration is read from the JSON file named by
+//! `--config <path>` or, failing that, the `HA_GATEWAY_CONFIG` environment
+//! variable. Only `upstreams` is required; the gateway refuses to start with a
+//! missing or malformed file, or without any upstream node.
+//!
+//! ```json
+//! {
+//!   "server": { "listen_addr": "0.0.0.0:8080" },
+//!   "upstreams": [
+//!     { "name": "Node 1", "url": "http://localhost:8545" },
+//!     { "name": "Node 2", "url": "http://localhost:8546" }
+//!   ]
+//! }
+//! ```
+
+use crate::audit::AuditSettings;
+use crate::auth::AuthSettings;
+use crate::cache::CacheSettings;
+use crate::health_summary::HealthSummarySettings;
+use crate::idempotency::IdempotencySettings;
+use crate::load_balancer::{ChainIdSettings, HealthCheckSettings, RetryPolicy, SelectionSettings};
+use crate::maintenance::MaintenanceWindow;
+use crate::method_filter::MethodFilterSettings;
+use crate::metrics::MetricsSettings;
+use crate::overload::OverloadSettings;
+use crate::prewarm::PrewarmRequest;
+use crate::rate_limit::RateLimitSettings;
+use crate::request_hooks::RequestHookSettings;
+use crate::server::ServerSettings;
+use crate::tx_dedup::TxDedupSettings;
+use crate::types::UpstreamConfig;
+use crate::upstream_override::UpstreamOverrideSettings;
+use crate::ws_proxy::WsSettings;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Environment variable naming the configuration file when `--config` is not given.
+pub const CONFIG_ENV_VAR: &str = "HA_GATEWAY_CONFIG";
+
+/// Top-level gateway configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    /// Inbound HTTP server behavior.
+    pub server: ServerSettings,
+
+    /// Upstream nodes requests are balanced across.
+    pub upstreams: Vec<UpstreamConfig>,
+
+    /// Background health checker behavior.
+    pub health_checks: HealthCheckSettings,
+
+    /// Thresholds behind the severity reported by `/healthz/summary`.
+    pub health_summary: HealthSummarySettings,
+
+    /// Startup and health-check verification of upstream chain ids.
+    pub chain_id: ChainIdSettings,
+
+    /// Retry behavior when forwarding requests across nodes.
+    pub retry: RetryPolicy,
+
+    /// Node selection behavior.
+    pub selection: SelectionSettings,
+
+    /// Response cache behavior.
+    pub cache: CacheSettings,
+
+    /// Send every node's requests through one shared HTTP client instead of a
+    /// client per node. Per-node timeouts still apply; client settings such as
+    /// `proxy` or `http2` must match across nodes, and redirects are not
+    /// followed, so startup fails for nodes that set redirect options.
+    pub shared_http_client: bool,
+
+    /// Upstream response headers copied onto the client response (case-insensitive).
+    ///
+    /// Hop-by-hop headers are never forwarded, even if listed here.
+    pub forward_response_headers: Vec<String>,
+
+    /// Content type of JSON-RPC responses, e.g. `application/json-rpc`.
+    ///
+    /// Defaults to `application/json; charset=utf-8`.
+    pub response_content_type: Option<String>,
+
+    /// Event-loop lag based load shedding.
+    pub overload: OverloadSettings,
+
+    /// Scheduled windows during which specific nodes are drained.
+    pub maintenance_windows: Vec<MaintenanceWindow>,
+
+    /// Methods the gateway refuses, or the only ones it serves.
+    pub method_filter: MethodFilterSettings,
+
+    /// Positional params filled in per method when the client omits them.
+    pub param_defaults: HashMap<String, Vec<serde_json::Value>>,
+
+    /// Built-in hooks adjusting requests before they are forwarded.
+    pub request_hooks: RequestHookSettings,
+
+    /// Result fields removed per method before responding.
+    pub strip_result_fields: HashMap<String, Vec<String>>,
+
+    /// Replay window for repeated `eth_sendRawTransaction` submissions.
+    pub tx_dedup: TxDedupSettings,
+
+    /// Replay of responses for repeated `X-Idempotency-Key` values.
+    pub idempotency: IdempotencySettings,
+
+    /// Metrics collection and export.
+    pub metrics: MetricsSettings,
+
+    /// Requests issued at startup to populate the cache.
+    pub prewarm: Vec<PrewarmRequest>,
+
+    /// Fixed seed for node selection, making routing reproducible (mainly for tests).
+    pub selection_seed: Option<u64>,
+
+    /// Attach debugging headers (e.g. cache provenance) to RPC responses.
+    pub debug_headers: bool,
+
+    /// Enable the admin-only `/debug/profile` CPU profiling endpoint.
+    ///
+    /// Also requires a build with the `profiling` feature.
+    pub debug_profiling: bool,
+
+    /// Path to a WASM module transforming requests and responses
+    /// (requires the `wasm-plugins` feature).
+    pub wasm_plugin: Option<String>,
+
+    /// API-key authentication for the JSON-RPC endpoint.
+    pub auth: AuthSettings,
+
+    /// Per-client-IP rate limiting of the JSON-RPC endpoint.
+    pub rate_limit: RateLimitSettings,
+
+    /// Per-request audit trail.
+    pub audit: AuditSettings,
+
+    /// WebSocket proxying on `/ws`.
+    pub ws: WsSettings,
+
+    /// Verification of signed `X-Upstream` node overrides.
+    pub upstream_override: UpstreamOverrideSettings,
+
+    /// Bearer token required by the admin endpoints. Admin routes are disabled when unset.
+    pub admin_token: Option<String>,
+
+    /// File the nodes' circuit breaker state is saved to on shutdown and
+    /// restored from at startup. Unset starts every node fresh.
+    pub state_file: Option<String>,
+}
+
+/// Keys whose values are masked when the configuration is exposed.
+const SECRET_KEYS: &[&str] = &[
+    "admin_token",
+    "password",
+    "secret",
+    "token",
+    "api_key",
+    "api_keys",
+    // Keyed by API key
+    "key_policies",
+];
+
+/// Keys holding URLs, shown with only their scheme, host and port: node URLs
+/// often embed a provider API key in their path, query or credentials.
+const URL_KEYS: &[&str] = &["url", "ws_url", "proxy"];
+
+impl GatewayConfig {
+    /// Rejects configurations the gateway cannot run with.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.upstreams.is_empty() {
+            return Err("no upstream nodes configured".to_string());
+        }
+        let mut names = HashSet::new();
+        for (index, upstream) in self.upstreams.iter().enumerate() {
+            if upstream.name.is_empty() {
+                return Err(format!("upstream #{} has no name", index + 1));
+            }
+            if upstream.url.is_empty() {
+                return Err(format!("upstream {} has no url", upstream.name));
+            }
+            if !names.insert(upstream.name.as_str()) {
+                return Err(format!("duplicate upstream name {}", upstream.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes the configuration with every secret-bearing field masked and
+    /// every URL reduced to its origin.
+    ///
+    /// Masking is by key name so credentials added to any section later are
+    /// covered without touching this function.
+    pub fn redacted(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        redact(&mut value);
+        value
+    }
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) && !v.is_null() {
+                    *v = serde_json::Value::String("<redacted>".to_string());
+                } else if let (true, Some(url)) = (URL_KEYS.contains(&key.as_str()), v.as_str()) {
+                    *v = serde_json::Value::String(redact_url(url));
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// `url` without credentials, path or query, which are replaced by a marker
+/// when present. Unparseable values are masked whole.
+fn redact_url(url: &str) -> String {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return "<redacted>".to_string();
+    };
+    let origin = parsed.origin().ascii_serialization();
+    let hidden = !parsed.username().is_empty()
+        || parsed.password().is_some()
+        || parsed.path() != "/"
+        || parsed.query().is_some();
+    if hidden {
+        format!("{}/<redacted>", origin)
+    } else {
+        origin
+    }
+}
+
+/// Finds the configuration file named by `--config <path>` (or `--config=<path>`)
+/// in `args`, falling back to `env` (the value of `HA_GATEWAY_CONFIG`).
+pub fn config_path(args: impl IntoIterator<Item = String>, env: Option<String>) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
+        }
+    }
+    env.filter(|path| !path.is_empty())
+}
+
+/// Reads, parses and validates the configuration file at `path`.
+pub fn load(path: &str) -> Result<GatewayConfig, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+    let config: GatewayConfig = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    config
+        .validate()
+        .map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "ha_gateway_config_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_config_path_prefers_flag_over_env() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let env = Some("/etc/env.json".to_string());
+
+        assert_eq!(
+            config_path(args(&["--config", "/etc/a.json"]), env.clone()).as_deref(),
+            Some("/etc/a.json")
+        );
+        assert_eq!(
+            config_path(args(&["--config=/etc/b.json"]), env.clone()).as_deref(),
+            Some("/etc/b.json")
+        );
+        assert_eq!(
+            config_path(args(&[]), env).as_deref(),
+            Some("/etc/env.json")
+        );
+        assert_eq!(config_path(args(&[]), None), None);
+    }
+
+    #[test]
+    fn test_load_reads_upstreams_and_settings() {
+        let path = write_config(
+            "valid",
+            r#"{
+                "server": { "listen_addr": "127.0.0.1:9000" },
+                "health_checks": { "interval_ms": 2500 },
+                "upstreams": [
+                    { "name": "Node 1", "url": "http://localhost:8545" },
+                    { "name": "Node 2", "url": "http://localhost:8546", "labels": { "region": "eu" } }
+                ]
+            }"#,
+        );
+
+        let config = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.server.listen_addr, "127.0.0.1:9000");
+        assert_eq!(config.health_checks.interval_ms, 2500);
+        assert_eq!(config.upstreams.len(), 2);
+        assert_eq!(config.upstreams[1].labels["region"], "eu");
+        assert_eq!(config.cache.ttl_ms, 2000);
+    }
+
+    #[test]
+    fn test_load_rejects_missing_malformed_and_empty_configs() {
+        let missing = load("/nonexistent/ha_gateway.json").unwrap_err();
+        assert!(
+            missing.starts_with("Failed to read config file"),
+            "{}",
+            missing
+        );
+
+        let path = write_config("malformed", r#"{ "upstreams": [ { "name": "A" "#);
+        let malformed = load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(malformed.contains("line 1"), "{}", malformed);
+
+        let path = write_config("empty", r#"{ "upstreams": [] }"#);
+        let empty = load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(empty.contains("no upstream nodes configured"), "{}", empty);
+    }
+
+    #[test]
+    fn test_redacted_reduces_urls_to_their_origin() {
+        let config: GatewayConfig = serde_json::from_str(
+            r#"{
+                "upstreams": [
+                    {
+                        "name": "Provider",
+                        "url": "https://mainnet.example.io/v3/abc123?key=def456",
+                        "ws_url": "wss://user:pw@mainnet.example.io/ws",
+                        "proxy": "http://proxy.internal:3128"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let redacted = config.redacted();
+        let upstream = &redacted["upstreams"][0];
+        assert_eq!(upstream["url"], "https://mainnet.example.io/<redacted>");
+        assert_eq!(upstream["ws_url"], "wss://mainnet.example.io/<redacted>");
+        assert_eq!(upstream["proxy"], "http://proxy.internal:3128");
+        let text = redacted.to_string();
+        assert!(!text.contains("abc123") && !text.contains("def456") && !text.contains("pw@"));
+    }
+}
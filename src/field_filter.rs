@@ -0,0 +1,101 @@
+//! Pruning of result fields for bandwidth-constrained clients.
+//!
+//! Block and receipt objects carry fields many clients never read, such as
+//! `logsBloom` or full transaction lists. `strip_result_fields` maps a method to
+//! fields removed from its result for every client, and a client may send
+//! `X-Fields: number,hash,timestamp` to keep only the fields it names. Both
+//! apply to an object result, or to each object in an array result (as from
+//! `eth_getLogs`); other results pass through. Only `result` is touched, so the
+//! JSON-RPC envelope (`jsonrpc`, `id`, `error`) is always intact. Cached values
+//! are stored in full, so clients asking for different fields share entries.
+//!
+//! ```json
+//! "strip_result_fields": {
+//!   "eth_getBlockByNumber": ["logsBloom", "transactions"]
+//! }
+//! ```
+
+use axum::http::HeaderMap;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Header listing the result fields a client wants, comma-separated.
+pub const FIELDS_HEADER: &str = "x-fields";
+
+/// Field names from the client's `X-Fields` header, if it sent a non-empty one.
+pub fn requested_fields(headers: &HeaderMap) -> Option<Vec<String>> {
+    let fields: Vec<String> = headers
+        .get(FIELDS_HEADER)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!fields.is_empty()).then_some(fields)
+}
+
+/// Removes the fields configured for `method` from `result`, then keeps only
+/// the `wanted` fields if the client listed any.
+pub fn apply(
+    strip: &HashMap<String, Vec<String>>,
+    method: &str,
+    wanted: Option<&[String]>,
+    result: &mut Value,
+) {
+    let stripped = strip.get(method);
+    if stripped.is_none() && wanted.is_none() {
+        return;
+    }
+    let prune = |object: &mut serde_json::Map<String, Value>| {
+        if let Some(stripped) = stripped {
+            object.retain(|key, _| !stripped.contains(key));
+        }
+        if let Some(wanted) = wanted {
+            object.retain(|key, _| wanted.contains(key));
+        }
+    };
+    match result {
+        Value::Object(object) => prune(object),
+        Value::Array(items) => items
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+            .for_each(prune),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_fields_header_selects_fields_of_each_object() {
+        let mut headers = HeaderMap::new();
+        headers.insert(FIELDS_HEADER, " address , data,".parse().unwrap());
+        let wanted = requested_fields(&headers).unwrap();
+        assert_eq!(wanted, ["address", "data"]);
+
+        let mut logs = json!([
+            {"address": "0x1", "data": "0x", "topics": []},
+            {"address": "0x2", "data": "0x", "removed": false}
+        ]);
+        apply(&HashMap::new(), "eth_getLogs", Some(&wanted), &mut logs);
+        assert_eq!(
+            logs,
+            json!([{"address": "0x1", "data": "0x"}, {"address": "0x2", "data": "0x"}])
+        );
+
+        let mut quantity = json!("0x10");
+        apply(
+            &HashMap::new(),
+            "eth_blockNumber",
+            Some(&wanted),
+            &mut quantity,
+        );
+        assert_eq!(quantity, "0x10");
+        assert!(requested_fields(&HeaderMap::new()).is_none());
+    }
+}
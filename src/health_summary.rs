@@ -0,0 +1,243 @@
+//! Alert-friendly health summary served at `GET /healthz/summary`.
+//!
+//! `/status` lists every node in detail; monitoring systems mostly want one
+//! verdict. The summary folds the node list into a single severity plus the
+//! reasons behind it:
+//!
+//! - **CRITICAL**: no node is healthy, so requests cannot be served. Answered
+//!   with `503 Service Unavailable`.
+//! - **WARNING**: service continues but with less headroom: some nodes are
+//!   unhealthy, a healthy node lags more than `max_block_lag` blocks behind the
+//!   highest one, or a node's share of failed requests over the last five
+//!   minutes exceeds `max_error_percent`. Answered with `200 OK`.
+//! - **OK**: none of the above. Answered with `200 OK`.
+
+use crate::AppState;
+use crate::load_balancer::NodeStatus;
+use crate::upstream::NodeCondition;
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+/// Thresholds that downgrade the summary to a warning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthSummarySettings {
+    /// Blocks a healthy node may trail the highest reported block.
+    pub max_block_lag: u64,
+
+    /// Largest acceptable share of failed requests on a node, in percent.
+    pub max_error_percent: f64,
+
+    /// Requests a node must have served in the last five minutes before its
+    /// error rate is judged.
+    pub min_requests: u64,
+}
+
+impl Default for HealthSummarySettings {
+    fn default() -> Self {
+        Self {
+            max_block_lag: 10,
+            max_error_percent: 5.0,
+            min_requests: 20,
+        }
+    }
+}
+
+/// Overall gateway health, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Critical,
+}
+
+/// Body of `/healthz/summary`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSummary {
+    pub severity: Severity,
+    pub healthy_nodes: usize,
+    pub total_nodes: usize,
+
+    /// Human-readable causes of a non-OK severity, e.g. `1 of 3 nodes unhealthy`.
+    pub reasons: Vec<String>,
+}
+
+/// Derives the overall severity from the nodes' status.
+pub fn summarize(nodes: &[NodeStatus], settings: &HealthSummarySettings) -> HealthSummary {
+    let healthy: Vec<&NodeStatus> = nodes
+        .iter()
+        .filter(|n| n.condition == NodeCondition::Healthy)
+        .collect();
+    let mut severity = Severity::Ok;
+    let mut reasons = Vec::new();
+    let mut raise = |to: Severity, reason: String| {
+        severity = severity.max(to);
+        reasons.push(reason);
+    };
+
+    if healthy.is_empty() {
+        raise(
+            Severity::Critical,
+            format!("0 of {} nodes healthy", nodes.len()),
+        );
+    } else if healthy.len() < nodes.len() {
+        raise(
+            Severity::Warning,
+            format!(
+                "{} of {} nodes unhealthy",
+                nodes.len() - healthy.len(),
+                nodes.len()
+            ),
+        );
+    }
+
+    if let Some(head) = healthy.iter().filter_map(|n| n.latest_block).max() {
+        for node in &healthy {
+            if let Some(block) = node.latest_block
+                && head - block > settings.max_block_lag
+            {
+                raise(
+                    Severity::Warning,
+                    format!("node {} is {} blocks behind", node.name, head - block),
+                );
+            }
+        }
+    }
+
+    for node in nodes {
+        let total = node.recent_successes + node.recent_failures;
+        if total < settings.min_requests.max(1) {
+            continue;
+        }
+        let error_percent = node.recent_failures as f64 * 100.0 / total as f64;
+        if error_percent > settings.max_error_percent {
+            raise(
+                Severity::Warning,
+                format!(
+                    "node {} failed {:.1}% of requests",
+                    node.name, error_percent
+                ),
+            );
+        }
+    }
+
+    HealthSummary {
+        severity,
+        healthy_nodes: healthy.len(),
+        total_nodes: nodes.len(),
+        reasons,
+    }
+}
+
+/// Reports the overall severity, with `503` when it is critical.
+pub async fn summary_handler(State(state): State<AppState>) -> Response {
+    let summary = summarize(
+        &state.load_balancer.get_nodes_status(),
+        &state.config.health_summary,
+    );
+    let status = match summary.severity {
+        Severity::Critical => StatusCode::SERVICE_UNAVAILABLE,
+        Severity::Ok | Severity::Warning => StatusCode::OK,
+    };
+    (status, Json(summary)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn node(name: &str, condition: NodeCondition, block: u64) -> NodeStatus {
+        NodeStatus {
+            name: name.to_string(),
+            status: "",
+            condition,
+            labels: HashMap::new(),
+            successes: 100,
+            failures: 0,
+            recent_successes: 100,
+            recent_failures: 0,
+            consecutive_failures: 0,
+            last_failure_unix_ms: None,
+            latest_block: Some(block),
+            avg_latency_ms: None,
+            upgrading: false,
+            tier: 0,
+            recovery_in_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_all_nodes_healthy_is_ok() {
+        let nodes = [
+            node("A", NodeCondition::Healthy, 100),
+            node("B", NodeCondition::Healthy, 98),
+        ];
+
+        let summary = summarize(&nodes, &HealthSummarySettings::default());
+
+        assert_eq!(summary.severity, Severity::Ok);
+        assert_eq!(summary.healthy_nodes, 2);
+        assert!(summary.reasons.is_empty());
+    }
+
+    #[test]
+    fn test_unhealthy_lagging_or_failing_nodes_warn() {
+        let settings = HealthSummarySettings::default();
+
+        let nodes = [
+            node("A", NodeCondition::Healthy, 100),
+            node("B", NodeCondition::Healthy, 100),
+            node("C", NodeCondition::Unhealthy, 100),
+        ];
+        let summary = summarize(&nodes, &settings);
+        assert_eq!(summary.severity, Severity::Warning);
+        assert_eq!(summary.reasons, vec!["1 of 3 nodes unhealthy"]);
+
+        let nodes = [
+            node("A", NodeCondition::Healthy, 100),
+            node("B", NodeCondition::Healthy, 50),
+        ];
+        let summary = summarize(&nodes, &settings);
+        assert_eq!(summary.severity, Severity::Warning);
+        assert_eq!(summary.reasons, vec!["node B is 50 blocks behind"]);
+
+        // Only recent failures count, not those since startup
+        let mut recovered = node("B", NodeCondition::Healthy, 100);
+        recovered.failures = 500;
+        let summary = summarize(
+            &[node("A", NodeCondition::Healthy, 100), recovered],
+            &settings,
+        );
+        assert_eq!(summary.severity, Severity::Ok);
+
+        let mut failing = node("B", NodeCondition::Healthy, 100);
+        failing.recent_failures = 25;
+        let summary = summarize(
+            &[node("A", NodeCondition::Healthy, 100), failing],
+            &settings,
+        );
+        assert_eq!(summary.severity, Severity::Warning);
+        assert_eq!(summary.reasons, vec!["node B failed 20.0% of requests"]);
+    }
+
+    #[test]
+    fn test_no_healthy_node_is_critical() {
+        let nodes = [
+            node("A", NodeCondition::Unhealthy, 100),
+            node("B", NodeCondition::HalfOpen, 100),
+        ];
+
+        let summary = summarize(&nodes, &HealthSummarySettings::default());
+
+        assert_eq!(summary.severity, Severity::Critical);
+        assert_eq!(summary.healthy_nodes, 0);
+        assert_eq!(summary.reasons, vec!["0 of 2 nodes healthy"]);
+    }
+}
@@ -0,0 +1,177 @@
+//! Replay of responses for repeated client idempotency keys.
+//!
+//! Clients with at-least-once delivery can tag a request with
+//! `X-Idempotency-Key`. With `window_ms` set, the first successful response for
+//! a key is remembered and returned for repeats within the window, for any
+//! method, without forwarding again. Unlike the result cache this keys on the
+//! client's token rather than the request contents.
+//!
+//! Tokens are scoped to the caller (API key, else client address), so two
+//! clients picking the same token never see each other's responses. Batch
+//! elements share the header, so entries are keyed by caller, token, method
+//! and JSON-RPC id. Failed requests are not remembered, so the client can
+//! retry them.
+//!
+//! Writes such as `eth_sendRawTransaction` are keyed by caller, token and
+//! method alone: a client retrying a broadcast under the same key gets the
+//! first response back, never a second broadcast, even when the retry uses a
+//! new JSON-RPC id or re-signed transaction bytes. This is stronger than
+//! `tx_dedup`, which only recognizes byte-identical resubmissions. Since one
+//! key stands for one write, a batch carrying several writes under a key is
+//! rejected. A repeat arriving while the first call is still in flight waits
+//! for and shares its response rather than forwarding again.
+//!
+//! At most `capacity` responses are kept; past that the least recently used
+//! key is forgotten first, so a flood of fresh tokens cannot grow memory
+//! without bound.
+
+use crate::method_class;
+use crate::types::{Caller, IDEMPOTENCY_KEY_HEADER, RpcRequest, RpcResponse};
+use axum::http::HeaderMap;
+use lru_time_cache::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Idempotency store configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdempotencySettings {
+    /// How long a key's response is replayed. `None` disables the store.
+    pub window_ms: Option<u64>,
+
+    /// Most responses remembered at once.
+    pub capacity: usize,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        Self {
+            window_ms: None,
+            capacity: 10_000,
+        }
+    }
+}
+
+/// Remembers the first successful response per client idempotency key.
+pub struct IdempotencyStore {
+    window: Option<Duration>,
+
+    /// Responses with when they were first recorded; lookups do not extend
+    /// the window.
+    seen: Mutex<LruCache<String, (Instant, RpcResponse)>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(settings: &IdempotencySettings) -> Self {
+        let window = settings.window_ms.map(Duration::from_millis);
+        Self {
+            window,
+            seen: Mutex::new(LruCache::with_expiry_duration_and_capacity(
+                window.unwrap_or_default().max(Duration::from_millis(1)),
+                settings.capacity.max(1),
+            )),
+        }
+    }
+
+    /// Returns the client's token, if it sent one and the store is enabled.
+    fn token<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        self.window?;
+        headers.get(IDEMPOTENCY_KEY_HEADER)?.to_str().ok()
+    }
+
+    /// Returns the store key for `caller`'s request, if the client sent a token.
+    pub fn key_for(
+        &self,
+        headers: &HeaderMap,
+        caller: &Caller,
+        request: &RpcRequest,
+    ) -> Option<String> {
+        let token = self.token(headers)?;
+        if method_class::is_write(&request.method) {
+            return Some(format!("{}:{}:{}", caller.scope(), token, request.method));
+        }
+        Some(format!(
+            "{}:{}:{}:{}",
+            caller.scope(),
+            token,
+            request.method,
+            request.id
+        ))
+    }
+
+    /// Whether a batch with these methods has more than one write sharing the
+    /// client's token, which would make all but one of them replays.
+    pub fn has_conflicting_writes<'a>(
+        &self,
+        headers: &HeaderMap,
+        methods: impl IntoIterator<Item = &'a str>,
+    ) -> bool {
+        if self.token(headers).is_none() {
+            return false;
+        }
+        // Writes share a key per method
+        let mut writes = HashSet::new();
+        methods
+            .into_iter()
+            .filter(|method| method_class::is_write(method))
+            .any(|method| !writes.insert(method))
+    }
+
+    /// Returns the response recorded for `key` if still within the window.
+    pub fn get(&self, key: &str) -> Option<RpcResponse> {
+        let window = self.window?;
+        let mut seen = self.seen.lock();
+        let (at, response) = seen.get(key)?;
+        (at.elapsed() < window).then(|| response.clone())
+    }
+
+    /// Records the first response for `key`, evicting the least recently
+    /// used key when full.
+    pub fn record(&self, key: String, response: RpcResponse) {
+        if self.window.is_none() {
+            return;
+        }
+        self.seen
+            .lock()
+            .entry(key)
+            .or_insert((Instant::now(), response));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_is_bounded_by_capacity() {
+        let store = IdempotencyStore::new(&IdempotencySettings {
+            window_ms: Some(60_000),
+            capacity: 2,
+        });
+        let response = |result: &str| RpcResponse::success(serde_json::json!(1), result.into());
+        store.record("a".to_string(), response("0xa"));
+        store.record("b".to_string(), response("0xb"));
+        // A lookup makes `a` the most recently used
+        assert!(store.get("a").is_some());
+        store.record("c".to_string(), response("0xc"));
+
+        assert!(store.get("b").is_none());
+        assert_eq!(
+            store.get("a").unwrap().result,
+            Some(serde_json::json!("0xa"))
+        );
+        assert_eq!(
+            store.get("c").unwrap().result,
+            Some(serde_json::json!("0xc"))
+        );
+
+        // The first response for a key is kept
+        store.record("a".to_string(), response("0xother"));
+        assert_eq!(
+            store.get("a").unwrap().result,
+            Some(serde_json::json!("0xa"))
+        );
+    }
+}
@@ -11,132 +11,3785 @@
 //! 2. Skips unhealthy nodes during selection
 //! 3. Distributes load evenly across healthy nodes
 //!
+//! When nodes are given different `weight`s, selection switches to smooth
+//! weighted round-robin: each node receives a share of traffic proportional to
+//! its weight, interleaved rather than in bursts, still skipping unhealthy nodes.
+//!
+//! With `weight_by_peer_count`, each node's weight is further multiplied by
+//! the peer count (`net_peerCount`) the health checker last saw, so poorly
+//! connected nodes get proportionally less traffic. Nodes that have not
+//! reported a count yet weigh as if they had a single peer.
+//!
+//! # Fallback Tiers
+//!
+//! Nodes carry a `tier`, 0 by default. Selection only considers the lowest
+//! tier that still has a selectable node, so higher tiers are backups that
+//! take traffic once every node of the tiers below is unhealthy, drained or
+//! already tried, and hand it back as soon as one recovers. Every selection
+//! path honors the tier, including transaction and session affinity, fresh
+//! reads, gas-price routing, fanout and quorum. `/status` reports the active
+//! tier.
+//!
+//! # Standby Nodes
+//!
+//! Nodes marked `standby` sit outside the tiers: they are skipped while any
+//! other node is healthy, even one that lags, is at capacity or was already
+//! tried for the request, and are promoted only when none is. The switch to
+//! standby is logged as a warning, and traffic returns to the primaries as
+//! soon as one of them recovers.
+//!
+//! # Least Response Time
+//!
+//! With `least_response_time`, round-robin gives way to picking the eligible
+//! node with the lowest moving average latency of its recent requests. Each
+//! average is scaled by a random factor of up to `LATENCY_JITTER` per pick so
+//! nodes with similar latencies share the load instead of one taking it all.
+//! Nodes without samples yet count as fastest, so new nodes get tried.
+//!
+//! # Byte Fairness
+//!
+//! With `byte_fairness`, picks balance egress instead of request counts: each
+//! request goes to the eligible node that returned the fewest response bytes
+//! within the last `RECENT_BYTES_WINDOW`, so a node that just served a few
+//! large responses is passed over until the others catch up. Ties, such as
+//! nodes that served nothing yet, are broken round-robin.
+//!
+//! When only one node is eligible it is picked directly, without running the
+//! latency, byte or weighted strategy, unless `always_run_strategy` is set.
+//!
+//! # Transaction Affinity
+//!
+//! With `tx_affinity_ms` set, the node that accepted an `eth_sendRawTransaction`
+//! is remembered under the returned transaction hash. For that long, receipt
+//! and transaction lookups for the hash go to that node, which certainly has
+//! the transaction, instead of one it may not have propagated to yet. Lookups
+//! fall back to normal selection when that node is unavailable.
+//!
+//! # Session Affinity
+//!
+//! With `session_affinity_ms` set, requests carrying an `X-Session-Id` header
+//! stick to one node, so a client that broadcasts a transaction can query the
+//! same node's mempool next. A new session is placed by rendezvous hashing over
+//! the eligible nodes, which spreads sessions evenly without coordination. The
+//! session stays on its node while the node is eligible; if it is not, the
+//! session is placed again among the remaining nodes and sticks to its new node
+//! from then on, even after the old one recovers. Sessions belong to the caller
+//! (API key or address) that opened them, so clients reusing an id do not share
+//! a node. Sessions idle for longer than `session_affinity_ms` are forgotten,
+//! as are the least recently used once 10,000 are tracked. Requests without the
+//! header use normal selection.
+//!
+//! # Hedging
+//!
+//! With `hedge` set, a read from its method list that the chosen node has not
+//! answered within `delay_ms` is also sent to the next eligible node; the
+//! first success wins and the other call is dropped. Each call that completes
+//! counts toward its node's circuit breaker as usual, while the dropped call
+//! counts as neither, since being slower is not a failure. Methods that change
+//! state are never hedged, whatever the list says.
+//!
+//! # Writes
+//!
+//! Methods with side effects (see `method_class`) take a dedicated path: one
+//! node, one attempt, no hedging, fanout or quorum, regardless of the retry
+//! policy and selection settings. Only the node choice itself (session and
+//! gas-price routing) still applies.
+//!
+//! # Selection Trace
+//!
+//! With `record_selection_trace`, every node selection records why each node
+//! that was not picked got skipped (see `SkipReason`), and the latest record is
+//! served at `/debug/last-selection`. Nodes that were eligible but lost to
+//! round-robin are not listed, except those whose in-flight limit is reached
+//! and that have no queue, which are listed as `AtCapacity`. Selection itself
+//! does not look at capacity: such a node can still be picked, refuses the
+//! request, and the attempt fails over like any other node error.
+//!
+//! # Capabilities
+//!
+//! Once any node declares `capabilities`, requests that need one (tracing, or
+//! historical state needing an archive node, see `capability`) only go to
+//! nodes carrying that tag. If no node has it, the request fails with an error
+//! naming the missing capability instead of reaching a node that cannot
+//! answer it. Deployments without tags route as before.
+//!
+//! # Read Fanout
+//!
+//! With `read_fanout` set to `k`, a read is sent to `k` healthy nodes at once
+//! and the first successful answer wins; the other calls are dropped. Unlike
+//! retries or hedging there is no waiting on a slow node first. Methods that
+//! change state are never fanned out, since sending them `k` times could
+//! broadcast or sign more than once.
+//!
+//! # Block Lag
+//!
+//! With `max_block_lag` set, round-robin skips nodes whose last probed block
+//! height is more than that many blocks behind the highest height reported by
+//! a healthy node, so reads are not answered from stale state. Lagging nodes
+//! stay healthy as far as the circuit breaker is concerned; they rejoin the
+//! rotation as soon as they catch up. Nodes with no known height are not
+//! skipped.
+//!
 //! # Health Monitoring
 //!
 //! A background task periodically checks the health of all nodes:
-//! - Runs every 10 seconds
+//! - Runs every `interval_ms` (10 seconds by default)
 //! - Executes health checks concurrently for all nodes
 //! - Updates node status based on check results
+//!
+//! # Chain Id Verification
+//!
+//! At startup `verify_chain_ids` asks every node for its `eth_chainId` and
+//! compares the answers with `expected` if configured, or else with each
+//! other. On a mismatch the gateway refuses to start, or, per `on_mismatch`,
+//! drains the nodes that differ (`quarantine`) or marks them unhealthy
+//! (`mark_unhealthy`) and starts with the rest. Without `expected`, nodes only
+//! differ from a strict majority; a split with no majority, or no node serving
+//! `expected`, always aborts. With `verify_in_health_checks`, each health check
+//! asks again and marks a node that switched chains unhealthy. A node marked
+//! unhealthy this way stays out of rotation until a health check finds it back
+//! on the right chain, whether or not `verify_in_health_checks` is set.
+//!
+//! Once known, the chain id answers `eth_chainId` without an upstream call.
+//! With `expected` configured it is known from the start, so clients are
+//! answered locally even before verification completes.
 
-use crate::types::{RpcRequest, RpcResponse, UpstreamConfig};
-use crate::upstream::UpstreamNode;
+use crate::capability;
+use crate::method_class::{self, MethodClass};
+use crate::request_hooks::RequestHook;
+use crate::retry_budget::{RetryBudget, RetryBudgetSettings};
+use crate::types::{
+    Consistency, ForwardError, HealthCheckRequest, RequestContext, RpcError, RpcRequest,
+    RpcResponse, UpstreamConfig,
+};
+use crate::upstream::{ClientOptions, NodeCondition, SharedClient, UpstreamNode};
+use lru_time_cache::LruCache;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::time;
 
-/// Interval between health check cycles.
-const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Lookups routed to the node that accepted the transaction they name.
+const TX_LOOKUP_METHODS: &[&str] = &["eth_getTransactionReceipt", "eth_getTransactionByHash"];
+
+/// Largest fraction by which `least_response_time` inflates a node's average
+/// latency at random when comparing nodes.
+const LATENCY_JITTER: f64 = 0.1;
+
+/// Most client retry keys remembered for `avoid_repeat_node_ms`; the least
+/// recently served are forgotten first.
+const REMEMBERED_RETRY_KEYS: usize = 10_000;
+
+/// Most client sessions remembered for `session_affinity_ms`; the least
+/// recently used are forgotten first.
+const REMEMBERED_SESSIONS: usize = 10_000;
+
+/// Background health checker configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckSettings {
+    /// Interval between health check cycles.
+    pub interval_ms: u64,
+
+    /// Probe sent to nodes without their own `health_check`, e.g. `net_version`
+    /// or `web3_clientVersion` for upstreams that are not Ethereum nodes.
+    pub probe: HealthCheckRequest,
+
+    /// Consecutive successful probes an open circuit needs before the node is
+    /// healthy again, for nodes without their own `recovery_probes`. Values
+    /// above 1 keep a flapping node from rejoining on a single lucky probe.
+    pub recovery_probes: usize,
+}
+
+impl Default for HealthCheckSettings {
+    fn default() -> Self {
+        Self {
+            interval_ms: 10_000,
+            probe: HealthCheckRequest::default(),
+            recovery_probes: 1,
+        }
+    }
+}
+
+/// What to do when upstreams disagree about the chain they serve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainIdMismatch {
+    /// Refuse to start.
+    #[default]
+    Abort,
+
+    /// Drain the differing nodes and start with the rest.
+    Quarantine,
+
+    /// Mark the differing nodes unhealthy and start with the rest. They rejoin
+    /// only once a health check finds them serving the right chain.
+    MarkUnhealthy,
+}
+
+/// Chain id verification settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainIdSettings {
+    /// Chain id the upstreams are expected to serve, answered to
+    /// `eth_chainId` from startup on. Unset waits for verification.
+    pub expected: Option<u64>,
+
+    /// Policy for nodes reporting a chain id other than `expected`, or than
+    /// the majority without it, at startup.
+    pub on_mismatch: ChainIdMismatch,
+
+    /// Re-check the chain id on every health check and mark nodes that report
+    /// a different one unhealthy.
+    pub verify_in_health_checks: bool,
+}
+
+/// How long a single forwarding attempt may run before moving on to the next node.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttemptTimeout {
+    /// A fixed number of milliseconds per attempt.
+    FixedMs(u64),
+
+    /// A fraction (0.0..=1.0) of the overall request deadline.
+    FractionOfDeadline(f64),
+}
+
+/// Controls how `forward_request` retries across nodes.
+///
+/// The default makes a single attempt bounded only by the node's own request
+/// timeout, which matches the historical behavior.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Maximum number of distinct nodes tried for one request.
+    pub max_attempts: usize,
+
+    /// Overall time budget for the request across all attempts.
+    pub deadline_ms: Option<u64>,
+
+    /// Per-attempt timeout, always clamped to whatever remains of the deadline.
+    pub attempt_timeout: Option<AttemptTimeout>,
+
+    /// Global cap on retries relative to traffic. `None` leaves retries unbounded.
+    pub budget: Option<RetryBudgetSettings>,
+
+    /// Retry `eth_estimateGas` on another node when the upstream answers with
+    /// a non-deterministic error, since estimation quirks vary by client.
+    /// Reverts, invalid params, insufficient funds and too-low nonces are
+    /// passed through on the first answer. Bounded by `max_attempts`.
+    pub estimate_gas_fallback: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            deadline_ms: None,
+            attempt_timeout: None,
+            budget: None,
+            estimate_gas_fallback: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn deadline(&self) -> Option<Duration> {
+        self.deadline_ms.map(Duration::from_millis)
+    }
+
+    /// Computes the timeout for the next attempt given the absolute deadline.
+    ///
+    /// Returns `None` when neither a deadline nor a per-attempt timeout is set.
+    fn next_attempt_timeout(&self, deadline: Option<Instant>) -> Option<Duration> {
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        let per_attempt = match (self.attempt_timeout, self.deadline()) {
+            (Some(AttemptTimeout::FixedMs(ms)), _) => Some(Duration::from_millis(ms)),
+            (Some(AttemptTimeout::FractionOfDeadline(f)), Some(total)) => {
+                Some(total.mul_f64(f.clamp(0.0, 1.0)))
+            }
+            _ => None,
+        };
+        match (per_attempt, remaining) {
+            (Some(a), Some(r)) => Some(a.min(r)),
+            (a, r) => a.or(r),
+        }
+    }
+}
+
+/// Duplicating slow reads to a second node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HedgeSettings {
+    /// How long the first node may take before the request is also sent to
+    /// the next one, typically around its p95 latency.
+    pub delay_ms: u64,
+
+    /// Idempotent read methods that may be hedged.
+    pub methods: Vec<String>,
+}
+
+impl Default for HedgeSettings {
+    fn default() -> Self {
+        Self {
+            delay_ms: 200,
+            methods: [
+                "eth_blockNumber",
+                "eth_call",
+                "eth_chainId",
+                "eth_getBalance",
+                "eth_getBlockByHash",
+                "eth_getBlockByNumber",
+                "eth_getCode",
+                "eth_getLogs",
+                "eth_getStorageAt",
+                "eth_getTransactionByHash",
+                "eth_getTransactionCount",
+                "eth_getTransactionReceipt",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Node selection behavior.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SelectionSettings {
+    /// When every node is cooling down, try the least unhealthy one (fewest
+    /// consecutive failures, then closest to leaving cooldown) instead of failing
+    /// the request outright. Such responses are flagged as degraded.
+    pub last_resort_when_all_cooling_down: bool,
+
+    /// Send `eth_sendRawTransaction` to the healthy node reporting the lowest
+    /// `eth_gasPrice`. Prices are polled by the health checker.
+    pub route_transactions_by_gas_price: bool,
+
+    /// Prefer nodes carrying all of these labels (e.g. `region = "eu-west"`),
+    /// falling back to the remaining nodes when none of them is available.
+    pub preferred_labels: HashMap<String, String>,
+
+    /// Send a client's retry (a request repeating a recent `X-Idempotency-Key`
+    /// or `X-Request-Id`) to a different node than the one that served it last
+    /// time, when another is available. Keys are remembered for this long;
+    /// `None` disables the behavior.
+    pub avoid_repeat_node_ms: Option<u64>,
+
+    /// Fanout(k): send each `fast` read to this many healthy nodes concurrently
+    /// and return the first success. `None` (or 1) sends reads to one node.
+    /// Writes are never fanned out.
+    pub read_fanout: Option<usize>,
+
+    /// Run the selection strategy even when a single node is eligible. By
+    /// default that node is picked directly, skipping latency or weight
+    /// bookkeeping that cannot change the outcome.
+    pub always_run_strategy: bool,
+
+    /// Skip nodes more than this many blocks behind the highest healthy node.
+    /// Heights come from `eth_blockNumber` health probes. `None` disables it.
+    pub max_block_lag: Option<u64>,
+
+    /// Scale each node's weight by its reported `net_peerCount`, polled by
+    /// the health checker.
+    pub weight_by_peer_count: bool,
+
+    /// Send each request to the eligible node with the lowest moving average
+    /// latency instead of round-robin.
+    pub least_response_time: bool,
+
+    /// Send each request to the eligible node that returned the fewest
+    /// response bytes recently instead of round-robin.
+    pub byte_fairness: bool,
+
+    /// After a node accepts `eth_sendRawTransaction`, send receipt and
+    /// transaction lookups for that hash to it for this long. `None` disables
+    /// the behavior.
+    pub tx_affinity_ms: Option<u64>,
+
+    /// Keep requests with the same `X-Session-Id` on one node, forgetting a
+    /// session after this long without requests. `None` ignores the header.
+    pub session_affinity_ms: Option<u64>,
+
+    /// Record why nodes were skipped in each selection, for
+    /// `/debug/last-selection`.
+    pub record_selection_trace: bool,
+
+    /// Send slow reads to a second node as well. `None` disables hedging.
+    pub hedge: Option<HedgeSettings>,
+}
+
+/// Why a node was passed over by a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Already tried for this request, or avoided as the node that served the
+    /// client's previous attempt.
+    Excluded,
+
+    /// Taken out of rotation by an operator or a maintenance window.
+    Drained,
+
+    /// Circuit open, still cooling down.
+    Cooldown,
+
+    /// Cooldown over, waiting for a successful health probe.
+    HalfOpen,
+
+    /// Too far behind the chain head (`max_block_lag`).
+    Lagging,
+
+    /// Lacks the capability the request needs.
+    CapabilityMismatch,
+
+    /// Every in-flight slot taken and no queue configured.
+    AtCapacity,
+
+    /// In a fallback tier while a lower tier still has a selectable node.
+    StandbyTier,
+
+    /// A `standby` node, held back while some primary node is healthy.
+    Standby,
+}
+
+/// A node skipped by a selection, with the reason.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedNode {
+    pub node: String,
+    pub reason: SkipReason,
+}
+
+/// How the latest selection went, as served by `/debug/last-selection`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectionTrace {
+    pub method: String,
+
+    /// Node picked, or `None` when none was available.
+    pub chosen: Option<String>,
+
+    pub skipped: Vec<SkippedNode>,
+}
+
+/// Point-in-time view of a node, as reported by `/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub name: String,
+    pub status: &'static str,
+
+    /// Circuit state behind `status`.
+    #[serde(skip)]
+    pub condition: NodeCondition,
+
+    pub labels: HashMap<String, String>,
+
+    /// Client requests this node served successfully since startup.
+    pub successes: u64,
+
+    /// Client requests that failed on this node since startup.
+    pub failures: u64,
+
+    /// Client requests this node served successfully in the last five minutes.
+    pub recent_successes: u64,
+
+    /// Client requests that failed on this node in the last five minutes.
+    pub recent_failures: u64,
+
+    /// Failures since the node's last success.
+    pub consecutive_failures: usize,
+
+    /// When a client request last failed on this node, in Unix milliseconds.
+    pub last_failure_unix_ms: Option<u64>,
+
+    /// Block height seen by the node's latest health probe, if known.
+    pub latest_block: Option<u64>,
+
+    /// Moving average latency of the node's successful requests, if any.
+    pub avg_latency_ms: Option<f64>,
+
+    /// Flagged as upgrading by an operator, so failures are expected.
+    pub upgrading: bool,
+
+    /// Fallback tier the node belongs to.
+    pub tier: u8,
+
+    /// For an unhealthy node, estimated seconds until it may serve again: the
+    /// rest of its backed-off cooldown. Zero once it only awaits a probe.
+    pub recovery_in_secs: Option<f64>,
+}
 
 /// Load balancer for distributing requests across multiple upstream RPC nodes.
 pub struct LoadBalancer {
     /// List of upstream nodes wrapped in Arc for shared ownership.
-    nodes: Vec<Arc<UpstreamNode>>,
+    ///
+    /// Nodes can be added and removed at runtime; requests already holding a
+    /// removed node finish on it. Changes replace the list rather than edit
+    /// it, so readers only clone the outer `Arc`.
+    nodes: RwLock<Arc<Vec<Arc<UpstreamNode>>>>,
 
     /// Atomic counter for round-robin node selection.
     next_index: AtomicUsize,
+
+    /// Running per-node scores of smooth weighted round-robin, indexed like `nodes`.
+    current_weights: Mutex<Vec<i64>>,
+
+    /// Retry behavior applied by `forward_request`.
+    retry_policy: RetryPolicy,
+
+    /// Shared retry budget, present when the retry policy configures one.
+    retry_budget: Option<RetryBudget>,
+
+    /// Node selection behavior.
+    selection: SelectionSettings,
+
+    /// Background health checker behavior.
+    health_checks: HealthCheckSettings,
+
+    chain_id_settings: ChainIdSettings,
+
+    /// Chain id the nodes agreed on at startup, once verified.
+    chain_id: Mutex<Option<u64>>,
+
+    /// SplitMix64 state backing any randomized selection decisions.
+    ///
+    /// Seeded from entropy by `new`, or from a fixed value by `new_with_seed`
+    /// so tests can assert exact routing.
+    rng_state: AtomicU64,
+
+    /// Node that last served each client retry key, forgotten once
+    /// `avoid_repeat_node_ms` passes.
+    last_node_by_key: Mutex<LruCache<String, String>>,
+
+    /// Node that accepted each recently broadcast transaction hash, with when.
+    tx_nodes: Mutex<HashMap<String, (Instant, String)>>,
+
+    /// Node each client session sticks to, keyed by caller and session id and
+    /// forgotten once idle for `session_affinity_ms`.
+    session_nodes: Mutex<LruCache<String, String>>,
+
+    /// Latest selection, when `record_selection_trace` is on.
+    last_selection: Mutex<Option<SelectionTrace>>,
+
+    /// Hooks applied in order to every request before it is forwarded.
+    request_hooks: Vec<Box<dyn RequestHook>>,
+
+    /// HTTP client shared by every node, including ones added later, when
+    /// client sharing is on. `None` gives each node its own client.
+    shared_client: Option<SharedClient>,
+
+    /// Whether the last selection fell back to a standby node.
+    on_standby: AtomicBool,
 }
 
 impl LoadBalancer {
     /// Initalizes a new load balancer with the given upstream node configurations.
-    pub fn new(configs: &[UpstreamConfig]) -> Self {
-        let nodes = configs
-            .into_iter()
-            .map(|config| Arc::new(UpstreamNode::new(config.clone())))
-            .collect();
+    /// Fails if any node cannot be built, listing every offending node.
+    pub fn new(configs: &[UpstreamConfig]) -> Result<Self, String> {
+        let mut nodes = Vec::with_capacity(configs.len());
+        let mut errors = Vec::new();
+        for config in configs {
+            match UpstreamNode::new(config.clone()) {
+                Ok(node) => nodes.push(Arc::new(node)),
+                Err(e) => errors.push(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        let current_weights = Mutex::new(vec![0; nodes.len()]);
 
-        Self {
-            nodes,
+        Ok(Self {
+            nodes: RwLock::new(Arc::new(nodes)),
             next_index: AtomicUsize::new(0),
+            current_weights,
+            retry_policy: RetryPolicy::default(),
+            retry_budget: None,
+            selection: SelectionSettings::default(),
+            health_checks: HealthCheckSettings::default(),
+            chain_id_settings: ChainIdSettings::default(),
+            chain_id: Mutex::new(None),
+            rng_state: AtomicU64::new(entropy_seed()),
+            last_node_by_key: Mutex::new(node_memory(None, REMEMBERED_RETRY_KEYS)),
+            tx_nodes: Mutex::new(HashMap::new()),
+            session_nodes: Mutex::new(node_memory(None, REMEMBERED_SESSIONS)),
+            last_selection: Mutex::new(None),
+            request_hooks: Vec::new(),
+            shared_client: None,
+            on_standby: AtomicBool::new(false),
+        })
+    }
+
+    /// Creates a load balancer whose selection order is fully determined by `seed`.
+    ///
+    /// Both the round-robin starting point and any randomized strategy decisions
+    /// derive from the seed, so two balancers built with the same seed and
+    /// configs pick nodes in exactly the same order.
+    pub fn new_with_seed(configs: &[UpstreamConfig], seed: u64) -> Result<Self, String> {
+        let lb = Self::new(configs)?;
+        lb.rng_state.store(seed, Ordering::SeqCst);
+        let count = lb.nodes.read().len();
+        if count > 0 {
+            let start = (lb.next_random() % count as u64) as usize;
+            lb.next_index.store(start, Ordering::SeqCst);
+        }
+        Ok(lb)
+    }
+
+    /// Replaces the retry policy used by `forward_request`.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_budget = retry_policy.budget.clone().map(RetryBudget::new);
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Retries left in the retry budget, if one is configured.
+    pub fn retry_budget_remaining(&self) -> Option<u64> {
+        self.retry_budget.as_ref().map(RetryBudget::remaining)
+    }
+
+    /// Rebuilds the nodes around a single HTTP client shared by all of them,
+    /// so idle connections and memory no longer grow with the node count.
+    ///
+    /// Each node's `request_timeout_ms` still applies per request. The shared
+    /// client follows no redirects and is built with the client-level options
+    /// (`proxy`, `http2`, `accept_gzip`, TCP and pool tuning) of the nodes,
+    /// which must all agree on them; nodes that set redirect options or
+    /// disagree fail the call instead of losing their settings. Meant for
+    /// setup, before any traffic: the rebuilt nodes start with fresh state.
+    pub fn with_shared_client(mut self) -> Result<Self, String> {
+        let nodes = self.snapshot();
+        let options = nodes.first().map_or_else(
+            || ClientOptions::of(&UpstreamConfig::default()),
+            |node| ClientOptions::of(&node.config),
+        );
+        let shared = SharedClient::new(options)?;
+        let nodes = nodes
+            .iter()
+            .map(|node| UpstreamNode::with_shared_client(node.config.clone(), &shared))
+            .map(|node| node.map(Arc::new))
+            .collect::<Result<Vec<_>, _>>()?;
+        *self.nodes.write() = Arc::new(nodes);
+        self.shared_client = Some(shared);
+        Ok(self)
+    }
+
+    /// Replaces the hooks that adjust requests before forwarding.
+    pub fn with_request_hooks(mut self, request_hooks: Vec<Box<dyn RequestHook>>) -> Self {
+        self.request_hooks = request_hooks;
+        self
+    }
+
+    /// Replaces the node selection settings.
+    pub fn with_selection_settings(mut self, selection: SelectionSettings) -> Self {
+        *self.last_node_by_key.get_mut() =
+            node_memory(selection.avoid_repeat_node_ms, REMEMBERED_RETRY_KEYS);
+        *self.session_nodes.get_mut() =
+            node_memory(selection.session_affinity_ms, REMEMBERED_SESSIONS);
+        self.selection = selection;
+        self
+    }
+
+    /// Replaces the health checker settings.
+    pub fn with_health_check_settings(mut self, health_checks: HealthCheckSettings) -> Self {
+        self.health_checks = health_checks;
+        self
+    }
+
+    /// Replaces the chain id verification settings.
+    pub fn with_chain_id_settings(mut self, chain_id_settings: ChainIdSettings) -> Self {
+        *self.chain_id.get_mut() = chain_id_settings.expected;
+        self.chain_id_settings = chain_id_settings;
+        self
+    }
+
+    /// Returns the configured chain id, or else the one the nodes agreed on
+    /// at startup, if verified.
+    pub fn chain_id(&self) -> Option<u64> {
+        *self.chain_id.lock()
+    }
+
+    /// Asks every node for its chain id and checks it against `expected`, or
+    /// that the nodes agree when none is configured.
+    ///
+    /// Nodes that cannot be reached are skipped. On a mismatch this fails with
+    /// a message listing every node's answer, unless `on_mismatch` drains or
+    /// marks unhealthy the differing nodes instead. Without `expected` that
+    /// takes a strict majority to differ from; a split without one always
+    /// fails, as does no node serving `expected`.
+    pub async fn verify_chain_ids(&self) -> Result<(), String> {
+        let nodes = self.snapshot();
+        let answers =
+            futures::future::join_all(nodes.iter().map(|node| node.fetch_chain_id())).await;
+
+        let mut reported = Vec::new();
+        for (node, answer) in nodes.iter().zip(answers) {
+            match answer {
+                Ok(chain_id) => {
+                    tracing::info!("Node {} reports chain id {}", node.get_name(), chain_id);
+                    reported.push((node, chain_id));
+                }
+                Err(e) => tracing::warn!(
+                    "Could not read chain id from node {}: {}",
+                    node.get_name(),
+                    e
+                ),
+            }
         }
+        let listing = || {
+            reported
+                .iter()
+                .map(|(node, chain_id)| format!("{}={}", node.get_name(), chain_id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let reference = match self.chain_id_settings.expected {
+            Some(expected) => expected,
+            None => {
+                let mut votes: HashMap<u64, usize> = HashMap::new();
+                for (_, chain_id) in &reported {
+                    *votes.entry(*chain_id).or_default() += 1;
+                }
+                let mut ranked: Vec<(u64, usize)> = votes.into_iter().collect();
+                ranked.sort_by_key(|&(_, n)| std::cmp::Reverse(n));
+                let Some(&(majority, count)) = ranked.first() else {
+                    tracing::warn!("No node answered eth_chainId, chain id left unverified");
+                    return Ok(());
+                };
+                let strict_majority = ranked.get(1).is_none_or(|&(_, n)| n < count);
+                if !strict_majority {
+                    return Err(format!("Upstreams disagree on chain id: {}", listing()));
+                }
+                majority
+            }
+        };
+
+        let mismatched: Vec<_> = reported
+            .iter()
+            .filter(|(_, chain_id)| *chain_id != reference)
+            .collect();
+        if !reported.is_empty() && mismatched.len() == reported.len() {
+            return Err(format!(
+                "No upstream serves configured chain id {}: {}",
+                reference,
+                listing()
+            ));
+        }
+        if !mismatched.is_empty() {
+            match self.chain_id_settings.on_mismatch {
+                ChainIdMismatch::Abort if self.chain_id_settings.expected.is_some() => {
+                    return Err(format!(
+                        "Upstreams disagree with configured chain id {}: {}",
+                        reference,
+                        listing()
+                    ));
+                }
+                ChainIdMismatch::Abort => {
+                    return Err(format!("Upstreams disagree on chain id: {}", listing()));
+                }
+                ChainIdMismatch::Quarantine => {
+                    for (node, chain_id) in mismatched {
+                        tracing::error!(
+                            "Quarantining node {}: chain id {} differs from {}",
+                            node.get_name(),
+                            chain_id,
+                            reference
+                        );
+                        node.set_drained(true);
+                    }
+                }
+                ChainIdMismatch::MarkUnhealthy => {
+                    for (node, chain_id) in mismatched {
+                        node.mark_wrong_chain(*chain_id, reference);
+                    }
+                }
+            }
+        }
+
+        *self.chain_id.lock() = Some(reference);
+        Ok(())
     }
 
     /// Selects a healthy node using round-robin strategy.
     ///
     /// This method iterates through all nodes starting from the current round-robin
     /// index, returning the first healthy node found. The index is incremented
-    /// atomically to ensure fair distribution across concurrent requests.
-    pub fn choose_healthy_node(&self) -> Option<Arc<UpstreamNode>> {
-        if self.nodes.is_empty() {
+    /// atomically to ensure fair distribution across concurrent requests. With
+    /// differing node weights, smooth weighted round-robin is used instead.
+    ///
+    /// Nodes named in `exclude` (e.g. ones already tried for this request) are skipped.
+    pub fn choose_healthy_node(&self, exclude: &[String]) -> Option<Arc<UpstreamNode>> {
+        let nodes = self.snapshot();
+        if nodes.is_empty() {
             tracing::error!("No Upstream Nodes registered.");
             return None;
         }
 
-        let total_nodes = self.nodes.len();
+        let total_nodes = nodes.len();
         let start_index = self.next_index.fetch_add(1, Ordering::SeqCst) % total_nodes;
+        let selectable = self.eligibility(&nodes, exclude);
+
+        // With preferred labels, a first pass only considers matching nodes
+        let preferred = &self.selection.preferred_labels;
+        let passes: &[bool] = if preferred.is_empty() {
+            &[false]
+        } else {
+            &[true, false]
+        };
 
-        for i in 0..total_nodes {
-            let index = (start_index + i) % total_nodes;
-            let node = &self.nodes[index];
+        for &preferred_only in passes {
+            let eligible = |node: &UpstreamNode| {
+                (!preferred_only || node.has_labels(preferred)) && selectable(node)
+            };
+            let chosen = if !self.selection.always_run_strategy
+                && let Some(only) = self.single_eligible(&nodes, eligible)
+            {
+                Some(only)
+            } else if self.selection.least_response_time {
+                self.choose_fastest(&nodes, eligible)
+            } else if self.selection.byte_fairness {
+                self.choose_fewest_bytes(&nodes, start_index, eligible)
+            } else if self.is_weighted(&nodes) {
+                self.choose_weighted(&nodes, eligible)
+            } else {
+                (0..total_nodes)
+                    .map(|i| &nodes[(start_index + i) % total_nodes])
+                    .find(|node| eligible(node))
+            };
 
-            if node.is_healthy() {
+            if let Some(node) = chosen {
+                self.note_standby(node);
                 tracing::debug!("Selected healthy node: {}", node.get_name());
                 return Some(Arc::clone(node));
             }
         }
 
         tracing::error!("No healthy nodes available!");
+        if self.selection.last_resort_when_all_cooling_down {
+            return self.choose_last_resort_node(exclude);
+        }
         None
     }
 
-    /// Forwards an RPC request to a healthy upstream node.
-    ///
-    /// This is the main entry point for request routing. It selects a healthy
-    /// node and forwards the request to it.
-    /// ```
-    pub async fn forward_request(&self, request: &RpcRequest) -> Result<RpcResponse, String> {
-        let node = self
-            .choose_healthy_node()
-            .ok_or_else(|| "No healthy nodes available".to_string())?;
-        tracing::info!("Forwarding request to Node {}", node.get_name());
-        node.call_rpc(request).await
+    /// Logs when selection switches to or back from standby nodes.
+    fn note_standby(&self, node: &UpstreamNode) {
+        let standby = node.is_standby();
+        if self.on_standby.swap(standby, Ordering::Relaxed) == standby {
+            return;
+        }
+        if standby {
+            tracing::warn!(
+                "No primary node is healthy, failing over to standby node {}",
+                node.get_name()
+            );
+        } else {
+            tracing::info!(
+                "Primary node {} is healthy again, leaving standby",
+                node.get_name()
+            );
+        }
     }
 
-    /// Starts a background task that periodically checks the health of all nodes.
-    ///
-    /// It runs indefinitely, performing health checks on all nodes at regular intervals. 
-    /// Each node's health check runs concurrently in its own task.
-    ///
-    /// # Behavior
-    ///
-    /// - Runs every `HEALTH_CHECK_INTERVAL` (10 seconds)
-    /// - Spawns a separate task for each node's health check
-    /// - Logs the health status of each node
-    /// - Continues running until the program terminates
-    pub fn start_health_checker(self: Arc<Self>) {
-        tokio::spawn(async move {
-            let mut interval = time::interval(HEALTH_CHECK_INTERVAL);
-            tracing::info!("Running health checks on all nodes...");
+    /// Whether `node` is a standby node that must wait: some primary node is
+    /// healthy and not drained.
+    fn standby_held(&self, node: &UpstreamNode) -> bool {
+        node.is_standby()
+            && self
+                .snapshot()
+                .iter()
+                .any(|other| !other.is_standby() && !other.is_drained() && other.is_healthy())
+    }
 
-            loop {
-                interval.tick().await;
+    /// Accepts the nodes any selector may pick among `nodes`: not skipped for
+    /// a reason of their own and in the active tier. Every selection path
+    /// filters through this, so tiers apply to all of them alike.
+    fn eligibility<'a>(
+        &'a self,
+        nodes: &[Arc<UpstreamNode>],
+        exclude: &'a [String],
+    ) -> impl Fn(&UpstreamNode) -> bool + 'a {
+        let min_block = self.min_acceptable_block(nodes);
+        let tier = self.active_tier_among(nodes, exclude, min_block);
+        move |node| {
+            Some(node.tier()) == tier && self.skip_reason(node, exclude, min_block).is_none()
+        }
+    }
 
-                for node in &self.nodes {
-                    let node = Arc::clone(node);
-                    tokio::spawn(async move {
-                        let is_healthy = node.check_health().await;
-                        let status = if is_healthy { "HEALTHY" } else { "UNHEALTHY" };
-                        tracing::info!("Health check status for {}: {}", node.get_name(), status);
-                    });
-                }
-            }
-        });
+    /// Lowest tier with a node that may be picked, if any.
+    fn active_tier_among(
+        &self,
+        nodes: &[Arc<UpstreamNode>],
+        exclude: &[String],
+        min_block: Option<u64>,
+    ) -> Option<u8> {
+        nodes
+            .iter()
+            .filter(|node| self.skip_reason(node, exclude, min_block).is_none())
+            .map(|node| node.tier())
+            .min()
     }
 
-    /// Returns the current health status of all nodes.
+    /// Tier currently taking traffic: the lowest with a selectable node.
+    pub fn active_tier(&self) -> Option<u8> {
+        let nodes = self.snapshot();
+        self.active_tier_among(&nodes, &[], self.min_acceptable_block(&nodes))
+    }
+
+    /// The one node accepted by `eligible`, if exactly one is.
+    fn single_eligible<'a>(
+        &self,
+        nodes: &'a [Arc<UpstreamNode>],
+        eligible: impl Fn(&UpstreamNode) -> bool,
+    ) -> Option<&'a Arc<UpstreamNode>> {
+        let mut candidates = nodes.iter().filter(|node| eligible(node));
+        let only = candidates.next()?;
+        candidates.next().is_none().then_some(only)
+    }
+
+    /// Lowest block height a node may report and still be selected, when
+    /// `max_block_lag` is set and some healthy node has reported a height.
+    fn min_acceptable_block(&self, nodes: &[Arc<UpstreamNode>]) -> Option<u64> {
+        let max_lag = self.selection.max_block_lag?;
+        let head = head_of(nodes)?;
+        Some(head.saturating_sub(max_lag))
+    }
+
+    /// Highest block reported by a healthy, undrained node, if any.
+    pub fn chain_head(&self) -> Option<u64> {
+        head_of(&self.snapshot())
+    }
+
+    /// Why round-robin would pass over `node`, or `None` if it may be picked.
+    fn skip_reason(
+        &self,
+        node: &UpstreamNode,
+        exclude: &[String],
+        min_block: Option<u64>,
+    ) -> Option<SkipReason> {
+        if exclude.iter().any(|name| name == node.get_name()) {
+            return Some(SkipReason::Excluded);
+        }
+        if node.is_drained() {
+            return Some(SkipReason::Drained);
+        }
+        if self.standby_held(node) {
+            return Some(SkipReason::Standby);
+        }
+        match node.get_status() {
+            NodeCondition::Healthy => {}
+            NodeCondition::Unhealthy => return Some(SkipReason::Cooldown),
+            NodeCondition::HalfOpen => return Some(SkipReason::HalfOpen),
+        }
+        if self.lags_behind(node, min_block) {
+            return Some(SkipReason::Lagging);
+        }
+        None
+    }
+
+    fn lags_behind(&self, node: &UpstreamNode, min_block: Option<u64>) -> bool {
+        let lagging = min_block
+            .zip(node.latest_block())
+            .is_some_and(|(min, block)| block < min);
+        if lagging {
+            tracing::debug!(
+                "Skipping node {}: block {:?} is behind the chain head",
+                node.get_name(),
+                node.latest_block()
+            );
+        }
+        lagging
+    }
+
+    /// Whether selection uses weighted round-robin: node weights differ, or
+    /// peer counts scale them.
+    fn is_weighted(&self, nodes: &[Arc<UpstreamNode>]) -> bool {
+        self.selection.weight_by_peer_count
+            || nodes.iter().any(|node| node.weight() != nodes[0].weight())
+    }
+
+    /// Smooth weighted round-robin over the nodes accepted by `eligible`.
     ///
-    /// This method provides a snapshot of the health status of all registered
-    /// nodes, useful for monitoring and debugging.
-    pub fn get_nodes_status(&self) -> Vec<(String, String)> {
-        self.nodes
-            .iter()
-            .map(|node| {
-                let status = match node.get_status() {
-                    crate::upstream::NodeCondition::Healthy => "HEALTHY",
-                    crate::upstream::NodeCondition::Unhealthy => "UNHEALTHY",
-                };
-                (node.get_name().to_string(), status.to_string())
-            })
+    /// Every eligible node's score grows by its weight; the highest scorer is
+    /// picked and pays back the total, which interleaves picks in proportion
+    /// to the weights.
+    fn choose_weighted<'a>(
+        &self,
+        nodes: &'a [Arc<UpstreamNode>],
+        eligible: impl Fn(&UpstreamNode) -> bool,
+    ) -> Option<&'a Arc<UpstreamNode>> {
+        let mut current = self.current_weights.lock();
+        // The node list changed since the scores were reset; start over
+        if current.len() != nodes.len() {
+            *current = vec![0; nodes.len()];
+        }
+        let mut total = 0;
+        let mut best: Option<usize> = None;
+        for (index, node) in nodes.iter().enumerate() {
+            if !eligible(node) {
+                continue;
+            }
+            let weight = self.selection_weight(node);
+            current[index] += weight;
+            total += weight;
+            if best.is_none_or(|best| current[index] > current[best]) {
+                best = Some(index);
+            }
+        }
+
+        let best = best?;
+        current[best] -= total;
+        Some(&nodes[best])
+    }
+
+    /// The node accepted by `eligible` with the lowest jittered average latency.
+    fn choose_fastest<'a>(
+        &self,
+        nodes: &'a [Arc<UpstreamNode>],
+        eligible: impl Fn(&UpstreamNode) -> bool,
+    ) -> Option<&'a Arc<UpstreamNode>> {
+        nodes
+            .iter()
+            .filter(|node| eligible(node))
+            .map(|node| {
+                let jitter = (self.next_random() >> 11) as f64 / (1u64 << 53) as f64;
+                let latency = node.average_latency_ms().unwrap_or(0.0);
+                // The jitter also breaks ties between nodes without samples
+                (latency * (1.0 + LATENCY_JITTER * jitter), jitter, node)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)))
+            .map(|(_, _, node)| node)
+    }
+
+    /// The node accepted by `eligible` with the fewest recent response bytes,
+    /// searching from `start_index` so ties rotate.
+    fn choose_fewest_bytes<'a>(
+        &self,
+        nodes: &'a [Arc<UpstreamNode>],
+        start_index: usize,
+        eligible: impl Fn(&UpstreamNode) -> bool,
+    ) -> Option<&'a Arc<UpstreamNode>> {
+        (0..nodes.len())
+            .map(|i| &nodes[(start_index + i) % nodes.len()])
+            .filter(|node| eligible(node))
+            .min_by_key(|node| node.recent_bytes())
+    }
+
+    /// A node's share in weighted round-robin: its configured weight, scaled by
+    /// its peer count when `weight_by_peer_count` is on.
+    fn selection_weight(&self, node: &UpstreamNode) -> i64 {
+        let weight = i64::from(node.weight());
+        if !self.selection.weight_by_peer_count {
+            return weight;
+        }
+        let peers = node.peer_count().unwrap_or(1).clamp(1, i64::MAX as u64) as i64;
+        weight.saturating_mul(peers)
+    }
+
+    /// The capability a node needs to serve `request`, if capability routing
+    /// is in use (some node declares capabilities).
+    fn required_capability(&self, request: &RpcRequest) -> Option<&'static str> {
+        let nodes = self.snapshot();
+        if nodes.iter().all(|node| node.config.capabilities.is_empty()) {
+            return None;
+        }
+        let head = nodes.iter().filter_map(|node| node.latest_block()).max();
+        capability::required(request, head)
+    }
+
+    /// Picks the node for `request`, recording the selection trace if enabled.
+    fn choose_node_for(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+        exclude: &[String],
+    ) -> Option<Arc<UpstreamNode>> {
+        let chosen = self.pick_node_for(request, ctx, exclude);
+        if self.selection.record_selection_trace {
+            self.record_selection(request, exclude, chosen.as_deref());
+        }
+        chosen
+    }
+
+    /// Records why each node other than `chosen` was skipped for `request`.
+    fn record_selection(
+        &self,
+        request: &RpcRequest,
+        exclude: &[String],
+        chosen: Option<&UpstreamNode>,
+    ) {
+        let nodes = self.snapshot();
+        let required = self.required_capability(request);
+        let min_block = self.min_acceptable_block(&nodes);
+        let tier = self.active_tier_among(&nodes, exclude, min_block);
+        let skipped = nodes
+            .iter()
+            .filter(|node| chosen.is_none_or(|chosen| chosen.get_name() != node.get_name()))
+            .filter_map(|node| {
+                let reason = if required.is_some_and(|required| !node.has_capability(required)) {
+                    SkipReason::CapabilityMismatch
+                } else if let Some(reason) = self.skip_reason(node, exclude, min_block) {
+                    reason
+                } else if tier.is_some_and(|tier| node.tier() > tier) {
+                    SkipReason::StandbyTier
+                } else if node.at_capacity() {
+                    SkipReason::AtCapacity
+                } else {
+                    return None;
+                };
+                Some(SkippedNode {
+                    node: node.get_name().to_string(),
+                    reason,
+                })
+            })
+            .collect();
+        *self.last_selection.lock() = Some(SelectionTrace {
+            method: request.method.clone(),
+            chosen: chosen.map(|node| node.get_name().to_string()),
+            skipped,
+        });
+    }
+
+    /// The latest recorded selection, if `record_selection_trace` is on.
+    pub fn last_selection(&self) -> Option<SelectionTrace> {
+        self.last_selection.lock().clone()
+    }
+
+    /// Picks the node for `request`, applying method-specific routing before round-robin.
+    fn pick_node_for(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+        exclude: &[String],
+    ) -> Option<Arc<UpstreamNode>> {
+        // A signed override names the node outright, whatever its state
+        if let Some(name) = &ctx.pinned_node {
+            return self.node_by_name(name).filter(|_| !exclude.contains(name));
+        }
+        let with_incapable: Vec<String>;
+        let exclude = match self.required_capability(request) {
+            Some(required) => {
+                with_incapable = self
+                    .snapshot()
+                    .iter()
+                    .filter(|node| !node.has_capability(required))
+                    .map(|node| node.get_name().to_string())
+                    .chain(exclude.iter().cloned())
+                    .collect();
+                &with_incapable
+            }
+            None => exclude,
+        };
+        if let Some(node) = self.tx_affinity_node(request, exclude) {
+            return Some(node);
+        }
+        if let Some(node) = self.session_node(ctx, exclude) {
+            return Some(node);
+        }
+        if ctx.consistency == Consistency::Fresh
+            && let Some(node) = self.choose_freshest_node(exclude)
+        {
+            return Some(node);
+        }
+        if self.selection.route_transactions_by_gas_price
+            && request.method == "eth_sendRawTransaction"
+            && let Some(node) = self.choose_cheapest_node(exclude)
+        {
+            return Some(node);
+        }
+        self.choose_healthy_node(exclude)
+    }
+
+    /// The node that accepted the transaction a lookup asks about, if it is
+    /// still within the affinity window and available.
+    fn tx_affinity_node(
+        &self,
+        request: &RpcRequest,
+        exclude: &[String],
+    ) -> Option<Arc<UpstreamNode>> {
+        let window = Duration::from_millis(self.selection.tx_affinity_ms?);
+        if !TX_LOOKUP_METHODS.contains(&request.method.as_str()) {
+            return None;
+        }
+        let hash = request.params.get(0)?.as_str()?.to_ascii_lowercase();
+        let name = {
+            let tx_nodes = self.tx_nodes.lock();
+            let (at, name) = tx_nodes.get(&hash)?;
+            (at.elapsed() < window).then(|| name.clone())?
+        };
+        let selectable = self.eligibility(&self.snapshot(), exclude);
+        let node = self.node_by_name(&name).filter(|node| selectable(node))?;
+        tracing::debug!("Routing lookup of {} to {}, which accepted it", hash, name);
+        Some(node)
+    }
+
+    /// Remembers the node that accepted a broadcast, pruning expired entries.
+    fn remember_tx_node(&self, request: &RpcRequest, response: &RpcResponse, node: &str) {
+        let Some(window) = self.selection.tx_affinity_ms.map(Duration::from_millis) else {
+            return;
+        };
+        if request.method != "eth_sendRawTransaction" {
+            return;
+        }
+        let Some(hash) = response.result.as_ref().and_then(|v| v.as_str()) else {
+            return;
+        };
+        let mut tx_nodes = self.tx_nodes.lock();
+        tx_nodes.retain(|_, (at, _)| at.elapsed() < window);
+        tx_nodes.insert(
+            hash.to_ascii_lowercase(),
+            (Instant::now(), node.to_string()),
+        );
+    }
+
+    /// The node the client's session sticks to, placing the session on an
+    /// eligible node if it has none or its node is not eligible.
+    fn session_node(&self, ctx: &RequestContext, exclude: &[String]) -> Option<Arc<UpstreamNode>> {
+        self.selection.session_affinity_ms?;
+        let session = ctx.session_id.as_deref()?;
+        // Session ids are chosen by clients, so one client cannot steer another's
+        let key = format!("{}:{}", ctx.caller.scope(), session);
+        let nodes = self.snapshot();
+        let eligible = self.eligibility(&nodes, exclude);
+
+        let mut sessions = self.session_nodes.lock();
+        let current = sessions.peek(&key).and_then(|name| {
+            nodes
+                .iter()
+                .find(|node| node.get_name() == name && eligible(node))
+        });
+        let node = match current {
+            Some(node) => node,
+            None => {
+                let node = nodes
+                    .iter()
+                    .filter(|node| eligible(node))
+                    .max_by_key(|node| {
+                        let mut hasher = std::hash::DefaultHasher::new();
+                        (session, node.get_name()).hash(&mut hasher);
+                        hasher.finish()
+                    })?;
+                tracing::debug!("Placing session {} on node {}", session, node.get_name());
+                node
+            }
+        };
+        sessions.insert(key, node.get_name().to_string());
+        Some(Arc::clone(node))
+    }
+
+    /// Picks a healthy node at the highest known block height.
+    fn choose_freshest_node(&self, exclude: &[String]) -> Option<Arc<UpstreamNode>> {
+        let nodes = self.snapshot();
+        let selectable = self.eligibility(&nodes, exclude);
+        let node = nodes
+            .iter()
+            .filter(|node| selectable(node))
+            .filter_map(|node| node.latest_block().map(|block| (block, node)))
+            .max_by_key(|(block, _)| *block)
+            .map(|(_, node)| node)?;
+        Some(Arc::clone(node))
+    }
+
+    /// Picks the healthy node with the lowest known gas price.
+    fn choose_cheapest_node(&self, exclude: &[String]) -> Option<Arc<UpstreamNode>> {
+        let nodes = self.snapshot();
+        let selectable = self.eligibility(&nodes, exclude);
+        let (price, node) = nodes
+            .iter()
+            .filter(|node| selectable(node))
+            .filter_map(|node| node.gas_price().map(|price| (price, node)))
+            .min_by_key(|(price, _)| *price)?;
+        tracing::debug!(
+            "Routing transaction to {} (gas price {} wei)",
+            node.get_name(),
+            price
+        );
+        Some(Arc::clone(node))
+    }
+
+    /// Picks the least unhealthy node, for use when none is healthy.
+    fn choose_last_resort_node(&self, exclude: &[String]) -> Option<Arc<UpstreamNode>> {
+        let nodes = self.snapshot();
+        let node = nodes
+            .iter()
+            .filter(|node| !node.is_drained())
+            .filter(|node| !exclude.iter().any(|name| name == node.get_name()))
+            .min_by_key(|node| {
+                (
+                    node.get_consecutive_failures(),
+                    node.cooldown_remaining().unwrap_or_default(),
+                )
+            })?;
+        tracing::warn!(
+            "All nodes unhealthy, trying {} as a last resort (degraded)",
+            node.get_name()
+        );
+        Some(Arc::clone(node))
+    }
+
+    /// `request` as adjusted by the request hooks, copied only if any are set.
+    fn apply_hooks<'a>(&self, request: &'a RpcRequest) -> Cow<'a, RpcRequest> {
+        if self.request_hooks.is_empty() {
+            return Cow::Borrowed(request);
+        }
+        let mut copy = request.clone();
+        for hook in &self.request_hooks {
+            hook.apply(&mut copy);
+        }
+        Cow::Owned(copy)
+    }
+
+    /// Sends a notification to one healthy node, without retrying, returning
+    /// the name of the node that took it.
+    ///
+    /// The client is not waiting for an answer, so a failure is only logged
+    /// and returned for the caller's records. Request hooks run first, as for
+    /// `forward_request`.
+    pub async fn forward_notification(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<String, String> {
+        let request = &*self.apply_hooks(request);
+        let Some(node) = self.choose_healthy_node(&[]) else {
+            tracing::warn!("Dropping {} notification: no healthy node", request.method);
+            return Err("No healthy nodes available".to_string());
+        };
+        match node.notify(request, ctx).await {
+            Ok(()) => Ok(node.get_name().to_string()),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to forward {} notification to {}: {}",
+                    request.method,
+                    node.get_name(),
+                    e
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// Forwards an RPC request to a healthy upstream node.
+    ///
+    /// This is the main entry point for request routing. It selects a healthy
+    /// node and forwards the request to it, moving on to the next healthy node
+    /// when an attempt fails or exceeds its per-attempt timeout, as long as the
+    /// retry policy allows it. JSON-RPC errors returned by an upstream are
+    /// deterministic and come back without retrying; any other final error says
+    /// how many nodes were tried.
+    ///
+    /// Request hooks run first, so every node sees the adjusted request.
+    pub async fn forward_request(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<RpcResponse, ForwardError> {
+        let request = &*self.apply_hooks(request);
+        if let Some(required) = self.required_capability(request)
+            && !self
+                .snapshot()
+                .iter()
+                .any(|node| node.has_capability(required))
+        {
+            return Err(format!(
+                "No upstream node has the {:?} capability required by {}",
+                required, request.method
+            )
+            .into());
+        }
+        if method_class::classify(&request.method) == MethodClass::Write {
+            return self.forward_write(request, ctx).await;
+        }
+        if ctx.consistency == Consistency::Quorum {
+            return self.forward_quorum(request, ctx).await;
+        }
+        if let Some(k) = self.selection.read_fanout
+            && k > 1
+            && ctx.consistency == Consistency::Fast
+        {
+            return self.forward_fanout(request, ctx, k).await;
+        }
+
+        let deadline = self.retry_policy.deadline().map(|d| Instant::now() + d);
+        let retry_key = self.client_retry_key(request, ctx);
+        let avoid = retry_key.as_deref().and_then(|key| self.previous_node(key));
+        let mut tried: Vec<String> = Vec::new();
+        let mut last_error = ForwardError::NoHealthyUpstream;
+        if let Some(budget) = &self.retry_budget {
+            budget.record_request();
+        }
+
+        while tried.len() < self.retry_policy.max_attempts.max(1) {
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                last_error = format!("Request deadline exceeded: {}", last_error).into();
+                break;
+            }
+
+            if !tried.is_empty()
+                && let Some(budget) = &self.retry_budget
+                && !budget.try_acquire_retry()
+            {
+                tracing::warn!("Retry budget exhausted, failing fast");
+                last_error = format!("Retry budget exhausted: {}", last_error).into();
+                break;
+            }
+
+            // Steer a client retry away from its previous node, unless no
+            // other node is available
+            let node = avoid
+                .as_ref()
+                .filter(|previous| !tried.contains(previous))
+                .and_then(|previous| {
+                    let mut exclude = tried.clone();
+                    exclude.push(previous.clone());
+                    self.choose_node_for(request, ctx, &exclude)
+                })
+                .or_else(|| self.choose_node_for(request, ctx, &tried));
+            let Some(node) = node else {
+                break;
+            };
+            tried.push(node.get_name().to_string());
+            tracing::info!("Forwarding request to Node {}", node.get_name());
+            let degraded = !node.is_healthy();
+
+            let hedge_delay = self.hedge_delay(request);
+            let mut in_flight = vec![Arc::clone(&node)];
+            let call = async {
+                match hedge_delay {
+                    Some(delay) => {
+                        self.call_hedged(&node, request, ctx, delay, &mut tried, &mut in_flight)
+                            .await
+                    }
+                    None => (Arc::clone(&node), node.call_rpc(request, ctx).await),
+                }
+            };
+            let (served_by, result) = match self.retry_policy.next_attempt_timeout(deadline) {
+                Some(timeout) => match time::timeout(timeout, call).await {
+                    Ok(outcome) => outcome,
+                    Err(_) => {
+                        // Charge the nodes whose calls were still running, not
+                        // a primary that already failed over to a hedge
+                        for timed_out in &in_flight {
+                            timed_out.record_failure();
+                        }
+                        let error = format!("Attempt timed out after {:?}", timeout);
+                        let last = in_flight.last().unwrap_or(&node);
+                        (Arc::clone(last), Err(error.into()))
+                    }
+                },
+                None => call.await,
+            };
+
+            match result {
+                Ok(mut response) => {
+                    response.meta.attempts = tried.len();
+                    response.meta.degraded = degraded && Arc::ptr_eq(&served_by, &node);
+                    if let Some(key) = retry_key {
+                        self.remember_node(key, served_by.get_name());
+                    }
+                    self.remember_tx_node(request, &response, served_by.get_name());
+                    return Ok(response);
+                }
+                Err(ForwardError::Rpc { error, node })
+                    if self.retry_policy.estimate_gas_fallback
+                        && request.method == "eth_estimateGas"
+                        && !is_deterministic_error(&error) =>
+                {
+                    tracing::warn!(
+                        "eth_estimateGas failed on node {}, trying another: {}",
+                        node,
+                        error.message
+                    );
+                    last_error = ForwardError::Rpc { error, node };
+                }
+                // The upstream answered; another node would answer the same
+                Err(e @ ForwardError::Rpc { .. }) => return Err(e),
+                Err(e) => {
+                    tracing::warn!("Attempt on node {} failed: {}", served_by.get_name(), e);
+                    last_error = e;
+                }
+            }
+        }
+
+        if !tried.is_empty() {
+            let plural = if tried.len() == 1 { "" } else { "s" };
+            last_error = format!("{} (tried {} node{})", last_error, tried.len(), plural).into();
+        }
+        Err(last_error)
+    }
+
+    /// Sends a write to exactly one node, once.
+    ///
+    /// Retries, hedging, fanout and quorum reads are skipped whatever the
+    /// configuration says: a write that timed out may still have been
+    /// broadcast, so sending it elsewhere could submit it twice.
+    async fn forward_write(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<RpcResponse, ForwardError> {
+        let node = self
+            .choose_node_for(request, ctx, &[])
+            .ok_or(ForwardError::NoHealthyUpstream)?;
+        tracing::info!(
+            "Forwarding {} to Node {} without retries",
+            request.method,
+            node.get_name()
+        );
+        let degraded = !node.is_healthy();
+        let mut response = node.call_rpc(request, ctx).await?;
+        response.meta.attempts = 1;
+        response.meta.degraded = degraded;
+        self.remember_tx_node(request, &response, node.get_name());
+        Ok(response)
+    }
+
+    /// The hedge delay for `request`, if hedging is on and the method may be hedged.
+    fn hedge_delay(&self, request: &RpcRequest) -> Option<Duration> {
+        let hedge = self.selection.hedge.as_ref()?;
+        let method = request.method.as_str();
+        (!method_class::is_write(method) && hedge.methods.iter().any(|m| m == method))
+            .then(|| Duration::from_millis(hedge.delay_ms))
+    }
+
+    /// Calls `node`, and if it has not answered within `delay`, also the next
+    /// eligible node, which is added to `tried`.
+    ///
+    /// Returns the node whose result is used: the first success, or the last
+    /// failure when both calls fail. A call still running when the other
+    /// succeeds is dropped.
+    async fn call_hedged(
+        &self,
+        node: &Arc<UpstreamNode>,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+        delay: Duration,
+        tried: &mut Vec<String>,
+        in_flight: &mut Vec<Arc<UpstreamNode>>,
+    ) -> (Arc<UpstreamNode>, Result<RpcResponse, ForwardError>) {
+        let primary = node.call_rpc(request, ctx);
+        tokio::pin!(primary);
+        tokio::select! {
+            result = &mut primary => return (Arc::clone(node), result),
+            _ = time::sleep(delay) => {}
+        }
+
+        let Some(hedge) = self.choose_node_for(request, ctx, tried) else {
+            return (Arc::clone(node), primary.await);
+        };
+        tried.push(hedge.get_name().to_string());
+        in_flight.push(Arc::clone(&hedge));
+        tracing::debug!(
+            "Node {} slower than {:?}, hedging to {}",
+            node.get_name(),
+            delay,
+            hedge.get_name()
+        );
+        let secondary = hedge.call_rpc(request, ctx);
+        tokio::pin!(secondary);
+
+        tokio::select! {
+            result = &mut primary => match result {
+                Ok(response) => (Arc::clone(node), Ok(response)),
+                Err(_) => {
+                    in_flight.retain(|n| !Arc::ptr_eq(n, node));
+                    (Arc::clone(&hedge), secondary.await)
+                }
+            },
+            result = &mut secondary => match result {
+                Ok(response) => (Arc::clone(&hedge), Ok(response)),
+                Err(_) => {
+                    in_flight.retain(|n| !Arc::ptr_eq(n, &hedge));
+                    (Arc::clone(node), primary.await)
+                }
+            },
+        }
+    }
+
+    /// Key under which a request's serving node is remembered, if the client
+    /// sent a retry key and repeat avoidance is enabled.
+    ///
+    /// Clients usually pick a fresh JSON-RPC id for every attempt, so a call is
+    /// identified by its caller, retry key, method and params instead.
+    fn client_retry_key(&self, request: &RpcRequest, ctx: &RequestContext) -> Option<String> {
+        self.selection.avoid_repeat_node_ms?;
+        let key = ctx.retry_key.as_deref()?;
+        Some(format!(
+            "{}:{}:{}:{}",
+            ctx.caller.scope(),
+            key,
+            request.method,
+            request.params
+        ))
+    }
+
+    /// The node that served `key` within the avoidance window, if any.
+    fn previous_node(&self, key: &str) -> Option<String> {
+        self.last_node_by_key.lock().peek(key).cloned()
+    }
+
+    /// Records the node that served `key`.
+    fn remember_node(&self, key: String, node: &str) {
+        self.last_node_by_key.lock().insert(key, node.to_string());
+    }
+
+    /// Sends the request to up to `k` healthy nodes at once and returns the
+    /// first success, dropping (and so cancelling) the calls still running.
+    async fn forward_fanout(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+        k: usize,
+    ) -> Result<RpcResponse, ForwardError> {
+        let mut chosen: Vec<String> = Vec::new();
+        let mut calls = Vec::new();
+        while chosen.len() < k {
+            let Some(node) = self.choose_node_for(request, ctx, &chosen) else {
+                break;
+            };
+            chosen.push(node.get_name().to_string());
+            calls.push(Box::pin(async move {
+                node.call_rpc(request, ctx).await.inspect_err(|e| {
+                    tracing::warn!("Fanout call to node {} failed: {}", node.get_name(), e)
+                })
+            }));
+        }
+        if calls.is_empty() {
+            return Err(ForwardError::NoHealthyUpstream);
+        }
+
+        tracing::debug!("Fanning request out to {}", chosen.join(", "));
+        let (mut response, _cancelled) = futures::future::select_ok(calls).await?;
+        response.meta.attempts = 1;
+        Ok(response)
+    }
+
+    /// Sends the request to every selectable node of the active tier and
+    /// returns the majority result.
+    ///
+    /// Results and JSON-RPC errors are tallied apart, so an error is never
+    /// taken for a result. Fails when fewer than a strict majority of the nodes
+    /// asked return the same result; when a majority returns the same JSON-RPC
+    /// error instead, that error is passed through. Transport failures count
+    /// toward neither.
+    async fn forward_quorum(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<RpcResponse, ForwardError> {
+        let required = self.required_capability(request);
+        let all = self.snapshot();
+        let selectable = self.eligibility(&all, &[]);
+        let nodes: Vec<_> = all
+            .iter()
+            .filter(|node| selectable(node))
+            .filter(|node| required.is_none_or(|required| node.has_capability(required)))
+            .cloned()
+            .collect();
+        if nodes.is_empty() {
+            return Err(ForwardError::NoHealthyUpstream);
+        }
+
+        let responses =
+            futures::future::join_all(nodes.iter().map(|node| node.call_rpc(request, ctx))).await;
+
+        // (result, votes, first response carrying it)
+        let mut results: Vec<(Option<serde_json::Value>, usize, RpcResponse)> = Vec::new();
+        // ((code, message), votes, first error carrying them)
+        let mut errors: Vec<((i32, String), usize, ForwardError)> = Vec::new();
+        for response in responses {
+            match response {
+                Ok(response) => match results
+                    .iter_mut()
+                    .find(|(result, _, _)| *result == response.result)
+                {
+                    Some((_, votes, _)) => *votes += 1,
+                    None => results.push((response.result.clone(), 1, response)),
+                },
+                Err(ForwardError::Rpc { error, node }) => {
+                    let kind = (error.code, error.message.clone());
+                    match errors.iter_mut().find(|(seen, _, _)| *seen == kind) {
+                        Some((_, votes, _)) => *votes += 1,
+                        None => errors.push((kind, 1, ForwardError::Rpc { error, node })),
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+
+        let needed = nodes.len() / 2 + 1;
+        let best = results.into_iter().max_by_key(|(_, votes, _)| *votes);
+        let best_votes = best.as_ref().map_or(0, |(_, votes, _)| *votes);
+        if let Some((_, votes, mut response)) = best
+            && votes >= needed
+        {
+            response.meta.attempts = nodes.len();
+            return Ok(response);
+        }
+        if let Some((_, _, error)) = errors.into_iter().find(|(_, votes, _)| *votes >= needed) {
+            return Err(error);
+        }
+        Err(format!("No quorum: {} of {} nodes agreed", best_votes, nodes.len()).into())
+    }
+
+    /// Looks up a node by its configured name.
+    pub fn node_by_name(&self, name: &str) -> Option<Arc<UpstreamNode>> {
+        self.nodes
+            .read()
+            .iter()
+            .find(|node| node.get_name() == name)
+            .cloned()
+    }
+
+    /// The current nodes, shared out so no lock is held while routing.
+    pub fn snapshot(&self) -> Arc<Vec<Arc<UpstreamNode>>> {
+        Arc::clone(&self.nodes.read())
+    }
+
+    /// Adds a node at runtime. It is selectable right away and joins the next
+    /// health check cycle.
+    ///
+    /// Fails if the name is taken or the node cannot be built.
+    pub fn add_node(&self, config: UpstreamConfig) -> Result<Arc<UpstreamNode>, String> {
+        let node = Arc::new(match &self.shared_client {
+            Some(shared) => UpstreamNode::with_shared_client(config, shared)?,
+            None => UpstreamNode::new(config)?,
+        });
+        let mut nodes = self.nodes.write();
+        if nodes.iter().any(|n| n.get_name() == node.get_name()) {
+            return Err(format!("Node {} already exists", node.get_name()));
+        }
+        Arc::make_mut(&mut nodes).push(Arc::clone(&node));
+        *self.current_weights.lock() = vec![0; nodes.len()];
+        tracing::info!("Node {} added: {}", node.get_name(), node.config.url);
+        Ok(node)
+    }
+
+    /// Removes a node at runtime, returning it if it existed.
+    ///
+    /// Requests already forwarded to it run to completion.
+    pub fn remove_node(&self, name: &str) -> Option<Arc<UpstreamNode>> {
+        let mut nodes = self.nodes.write();
+        let index = nodes.iter().position(|node| node.get_name() == name)?;
+        let node = Arc::make_mut(&mut nodes).remove(index);
+        *self.current_weights.lock() = vec![0; nodes.len()];
+        tracing::info!("Node {} removed", name);
+        Some(node)
+    }
+
+    /// Returns the next pseudo-random value (SplitMix64).
+    fn next_random(&self) -> u64 {
+        let mut z = self
+            .rng_state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Starts a background task that periodically checks the health of all nodes.
+    ///
+    /// It runs indefinitely, performing health checks on all nodes at regular intervals.
+    /// Each node's health check runs concurrently in its own task.
+    ///
+    /// # Behavior
+    ///
+    /// - Runs every `HealthCheckSettings::interval_ms`
+    /// - Spawns a separate task for each node's health check
+    /// - Skips a node whose previous check is still in flight
+    /// - Logs the health status of each node
+    /// - Continues running until the program terminates
+    pub fn start_health_checker(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval =
+                time::interval(Duration::from_millis(self.health_checks.interval_ms.max(1)));
+            let track_gas_price = self.selection.route_transactions_by_gas_price;
+            let track_peer_count = self.selection.weight_by_peer_count;
+            let verify_chain_id = self.chain_id_settings.verify_in_health_checks;
+            tracing::info!("Running health checks on all nodes...");
+
+            loop {
+                interval.tick().await;
+
+                for node in self.snapshot().iter() {
+                    let node = Arc::clone(node);
+                    let expected_chain_id = self
+                        .chain_id()
+                        .filter(|_| verify_chain_id || node.on_wrong_chain());
+                    let settings = self.health_checks.clone();
+                    tokio::spawn(async move {
+                        if let Some(expected) = expected_chain_id
+                            && !node.verify_chain_id(expected).await
+                        {
+                            return;
+                        }
+                        let Some(is_healthy) = node.check_health_exclusive(&settings).await else {
+                            return;
+                        };
+                        let status = if is_healthy { "HEALTHY" } else { "UNHEALTHY" };
+                        tracing::info!("Health check status for {}: {}", node.get_name(), status);
+                        if track_gas_price && is_healthy {
+                            node.refresh_gas_price().await;
+                        }
+                        if track_peer_count && is_healthy {
+                            node.refresh_peer_count().await;
+                        }
+                    });
+                }
+            }
+        });
+    }
+
+    /// Estimated time until the first unhealthy node may serve again, if any
+    /// node is unhealthy.
+    pub fn next_likely_recovery(&self) -> Option<Duration> {
+        self.snapshot()
+            .iter()
+            .filter_map(|node| node.cooldown_remaining())
+            .min()
+    }
+
+    /// Returns the current health status of all nodes.
+    ///
+    /// This method provides a snapshot of the health status of all registered
+    /// nodes, useful for monitoring and debugging.
+    pub fn get_nodes_status(&self) -> Vec<NodeStatus> {
+        self.nodes
+            .read()
+            .iter()
+            .map(|node| {
+                let condition = node.get_status();
+                let status = match condition {
+                    NodeCondition::Healthy => "HEALTHY",
+                    NodeCondition::Unhealthy => "UNHEALTHY",
+                    NodeCondition::HalfOpen => "HALF_OPEN",
+                };
+                let (successes, failures) = node.request_counts();
+                let (recent_successes, recent_failures) = node.recent_request_counts();
+                NodeStatus {
+                    name: node.get_name().to_string(),
+                    status,
+                    condition,
+                    labels: node.labels().clone(),
+                    successes,
+                    failures,
+                    recent_successes,
+                    recent_failures,
+                    consecutive_failures: node.get_consecutive_failures(),
+                    last_failure_unix_ms: node.last_failure_unix_ms(),
+                    latest_block: node.latest_block(),
+                    avg_latency_ms: node.average_latency_ms(),
+                    upgrading: node.is_upgrading(),
+                    tier: node.tier(),
+                    recovery_in_secs: node
+                        .cooldown_remaining()
+                        .map(|remaining| remaining.as_secs_f64()),
+                }
+            })
             .collect()
     }
 }
+
+/// Highest block height among the healthy, undrained `nodes`.
+fn head_of(nodes: &[Arc<UpstreamNode>]) -> Option<u64> {
+    nodes
+        .iter()
+        .filter(|node| !node.is_drained() && node.is_healthy())
+        .filter_map(|node| node.latest_block())
+        .max()
+}
+
+/// Cache of up to `capacity` keys and the node each was last sent to,
+/// forgetting a key `window_ms` after it was last recorded.
+fn node_memory(window_ms: Option<u64>, capacity: usize) -> LruCache<String, String> {
+    LruCache::with_expiry_duration_and_capacity(
+        Duration::from_millis(window_ms.unwrap_or_default()),
+        capacity,
+    )
+}
+
+/// Seeds the selection RNG from the process-wide hash randomness.
+fn entropy_seed() -> u64 {
+    use std::hash::{BuildHasher, RandomState};
+    RandomState::new().hash_one(Instant::now())
+}
+
+/// JSON-RPC error code for invalid method parameters.
+const INVALID_PARAMS_CODE: i32 = -32602;
+
+/// Upstream error messages, lowercased, that depend only on the request and
+/// chain state, so every node reports them the same way.
+const DETERMINISTIC_ERROR_MESSAGES: &[&str] = &[
+    "revert",
+    "insufficient funds",
+    "nonce too low",
+    "invalid argument",
+];
+
+/// Whether an upstream error is deterministic: a revert, invalid params or a
+/// transaction the chain state rules out, which any node would report the same
+/// way.
+fn is_deterministic_error(error: &RpcError) -> bool {
+    let message = error.message.to_ascii_lowercase();
+    error.code == 3
+        || error.code == INVALID_PARAMS_CODE
+        || DETERMINISTIC_ERROR_MESSAGES
+            .iter()
+            .any(|pattern| message.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::spawn_mock_upstream;
+    use crate::types::VersionMismatch;
+    use axum::{Json, Router, routing::post};
+    use std::collections::HashSet;
+
+    async fn spawn_delayed_upstream(delay: Duration, block: &'static str) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| async move {
+                time::sleep(delay).await;
+                Json(RpcResponse::success(req.id, serde_json::json!(block)))
+            }),
+        );
+        spawn_mock_upstream(app).await
+    }
+
+    fn config(name: &str, url: String) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            url,
+            ..Default::default()
+        }
+    }
+
+    fn block_number_request() -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempt_timeout_fails_over_within_deadline() {
+        let slow = spawn_delayed_upstream(Duration::from_secs(3), "0xslow").await;
+        let fast = spawn_delayed_upstream(Duration::ZERO, "0xfast").await;
+
+        let lb = LoadBalancer::new(&[config("Slow", slow), config("Fast", fast)])
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                deadline_ms: Some(2000),
+                attempt_timeout: Some(AttemptTimeout::FractionOfDeadline(0.25)),
+                ..Default::default()
+            });
+
+        let started = Instant::now();
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(response.result, Some(serde_json::json!("0xfast")));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_shared_client_keeps_per_node_timeouts() {
+        let slow = spawn_delayed_upstream(Duration::from_secs(2), "0xslow").await;
+        let fast = spawn_delayed_upstream(Duration::ZERO, "0xfast").await;
+        let slow = UpstreamConfig {
+            request_timeout_ms: Some(200),
+            ..config("Slow", slow)
+        };
+        let lb = LoadBalancer::new(&[slow, config("Fast", fast)])
+            .unwrap()
+            .with_shared_client()
+            .unwrap()
+            .with_retry_policy(retrying(2));
+
+        for _ in 0..2 {
+            let started = Instant::now();
+            let response = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.result, Some(serde_json::json!("0xfast")));
+            assert_eq!(response.meta.served_by.as_deref(), Some("Fast"));
+            assert!(started.elapsed() < Duration::from_secs(1));
+        }
+        assert!(lb.node_by_name("Slow").unwrap().request_counts().1 > 0);
+    }
+
+    #[tokio::test]
+    async fn test_shared_client_carries_node_options_and_rejects_conflicts() {
+        use axum::http::{HeaderMap, header};
+
+        let app = Router::new().route(
+            "/",
+            post(
+                |headers: HeaderMap, Json(req): Json<RpcRequest>| async move {
+                    let gzip = headers
+                        .get(header::ACCEPT_ENCODING)
+                        .is_some_and(|value| value.to_str().unwrap_or("").contains("gzip"));
+                    Json(RpcResponse::success(req.id, serde_json::json!(gzip)))
+                },
+            ),
+        );
+        let url = spawn_mock_upstream(app).await;
+
+        for accept_gzip in [false, true] {
+            let node = UpstreamConfig {
+                accept_gzip,
+                ..config("Node", url.clone())
+            };
+            let lb = LoadBalancer::new(&[node])
+                .unwrap()
+                .with_shared_client()
+                .unwrap();
+            let response = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.result, Some(serde_json::json!(accept_gzip)));
+        }
+
+        let http2 = UpstreamConfig {
+            http2: true,
+            ..config("Http2", url.clone())
+        };
+        let configs = [config("Plain", url.clone()), http2];
+        let err = LoadBalancer::new(&configs)
+            .unwrap()
+            .with_shared_client()
+            .err()
+            .unwrap();
+        assert!(err.contains("Http2"), "{}", err);
+
+        let redirecting = UpstreamConfig {
+            max_redirects: Some(3),
+            ..config("Redirecting", url.clone())
+        };
+        let err = LoadBalancer::new(&[redirecting])
+            .unwrap()
+            .with_shared_client()
+            .err()
+            .unwrap();
+        assert!(err.contains("redirect"), "{}", err);
+
+        let lb = LoadBalancer::new(&[config("Plain", url.clone())])
+            .unwrap()
+            .with_shared_client()
+            .unwrap();
+        let proxied = UpstreamConfig {
+            proxy: Some("http://127.0.0.1:3128".to_string()),
+            ..config("Proxied", url)
+        };
+        assert!(lb.add_node(proxied).is_err());
+    }
+
+    #[test]
+    fn test_attempt_timeout_clamped_to_remaining_deadline() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            deadline_ms: Some(10_000),
+            attempt_timeout: Some(AttemptTimeout::FixedMs(5000)),
+            ..Default::default()
+        };
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        let timeout = policy.next_attempt_timeout(Some(deadline)).unwrap();
+
+        assert!(timeout <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_seeded_selection_is_reproducible() {
+        let configs: Vec<_> = (1..=4)
+            .map(|i| {
+                config(
+                    &format!("Node {}", i),
+                    format!("http://localhost:{}", 8544 + i),
+                )
+            })
+            .collect();
+        let pick_sequence = |lb: &LoadBalancer| -> Vec<String> {
+            (0..8)
+                .map(|_| lb.choose_healthy_node(&[]).unwrap().get_name().to_string())
+                .collect()
+        };
+
+        let first = pick_sequence(&LoadBalancer::new_with_seed(&configs, 42).unwrap());
+        let second = pick_sequence(&LoadBalancer::new_with_seed(&configs, 42).unwrap());
+
+        assert_eq!(first, second);
+        // Selection is still round-robin, just from a seed-determined start.
+        assert_eq!(first[..4], first[4..]);
+    }
+
+    #[test]
+    fn test_last_resort_picks_node_closest_to_cooldown_expiry() {
+        let configs: Vec<_> = ["A", "B", "C"]
+            .iter()
+            .map(|name| config(name, "http://localhost:1".to_string()))
+            .collect();
+        let lb = LoadBalancer::new(&configs)
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                last_resort_when_all_cooling_down: true,
+                ..Default::default()
+            });
+
+        // B trips first, so its cooldown ends soonest.
+        for name in ["B", "C", "A"] {
+            let node = lb.node_by_name(name).unwrap();
+            for _ in 0..3 {
+                node.force_mark_failure();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let chosen = lb.choose_healthy_node(&[]).unwrap();
+        assert_eq!(chosen.get_name(), "B");
+        assert_eq!(
+            lb.choose_healthy_node(&["B".to_string()])
+                .unwrap()
+                .get_name(),
+            "C"
+        );
+    }
+
+    #[test]
+    fn test_weighted_round_robin_follows_weights_and_skips_unhealthy() {
+        let weighted = |name: &str, weight: u32| UpstreamConfig {
+            weight: Some(weight),
+            ..config(name, "http://localhost:1".to_string())
+        };
+        let lb =
+            LoadBalancer::new(&[weighted("Big", 5), weighted("B", 1), weighted("C", 1)]).unwrap();
+
+        let picks: Vec<String> = (0..7)
+            .map(|_| lb.choose_healthy_node(&[]).unwrap().get_name().to_string())
+            .collect();
+        assert_eq!(picks, ["Big", "Big", "B", "Big", "C", "Big", "Big"]);
+
+        for _ in 0..3 {
+            lb.snapshot()[0].force_mark_failure();
+        }
+        for _ in 0..4 {
+            assert_ne!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Big");
+        }
+    }
+
+    #[test]
+    fn test_equal_weights_keep_plain_round_robin() {
+        let weighted = |name: &str| UpstreamConfig {
+            weight: Some(3),
+            ..config(name, "http://localhost:1".to_string())
+        };
+        let lb = LoadBalancer::new(&[weighted("A"), weighted("B"), weighted("C")]).unwrap();
+
+        assert!(!lb.is_weighted(&lb.snapshot()));
+        let picks: Vec<String> = (0..4)
+            .map(|_| lb.choose_healthy_node(&[]).unwrap().get_name().to_string())
+            .collect();
+        assert_eq!(picks, ["A", "B", "C", "A"]);
+    }
+
+    #[test]
+    fn test_fallback_tier_serves_only_when_primary_tier_is_down() {
+        let tiered = |name: &str, tier: u8| UpstreamConfig {
+            tier,
+            ..config(name, "http://localhost:1".to_string())
+        };
+        let lb = LoadBalancer::new(&[
+            tiered("Public", 1),
+            tiered("Premium A", 0),
+            tiered("Premium B", 0),
+        ])
+        .unwrap();
+
+        for _ in 0..6 {
+            assert_ne!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Public");
+        }
+        assert_eq!(lb.active_tier(), Some(0));
+
+        // One premium node down keeps the primary tier active
+        for _ in 0..3 {
+            lb.node_by_name("Premium A").unwrap().force_mark_failure();
+        }
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Premium B");
+
+        for _ in 0..3 {
+            lb.node_by_name("Premium B").unwrap().force_mark_failure();
+        }
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Public");
+        assert_eq!(lb.active_tier(), Some(1));
+    }
+
+    #[test]
+    fn test_standby_serves_only_when_no_primary_is_healthy() {
+        let lb = LoadBalancer::new(&[
+            UpstreamConfig {
+                standby: true,
+                ..config("Standby", "http://localhost:1".to_string())
+            },
+            config("A", "http://localhost:2".to_string()),
+            config("B", "http://localhost:3".to_string()),
+        ])
+        .unwrap();
+
+        for _ in 0..3 {
+            lb.node_by_name("A").unwrap().force_mark_failure();
+        }
+        for _ in 0..4 {
+            assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "B");
+        }
+        // B already tried still holds the standby back: a primary is healthy
+        assert!(lb.choose_healthy_node(&["B".to_string()]).is_none());
+
+        for _ in 0..3 {
+            lb.node_by_name("B").unwrap().force_mark_failure();
+        }
+        for _ in 0..4 {
+            assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Standby");
+        }
+
+        let a = lb.node_by_name("A").unwrap();
+        a.force_cooldown_expiry();
+        assert_eq!(a.get_status(), NodeCondition::HalfOpen);
+        a.force_mark_success();
+        for _ in 0..4 {
+            assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "A");
+        }
+    }
+
+    #[test]
+    fn test_no_last_resort_by_default() {
+        let lb = LoadBalancer::new(&[config("A", "http://localhost:1".to_string())]).unwrap();
+        for _ in 0..3 {
+            lb.snapshot()[0].force_mark_failure();
+        }
+
+        assert!(lb.choose_healthy_node(&[]).is_none());
+    }
+
+    #[test]
+    fn test_preferred_labels_win_while_healthy() {
+        let labelled = |name: &str, region: &str| UpstreamConfig {
+            labels: HashMap::from([("region".to_string(), region.to_string())]),
+            ..config(name, "http://localhost:1".to_string())
+        };
+        let lb = LoadBalancer::new(&[
+            labelled("Far1", "us"),
+            labelled("Near", "eu"),
+            labelled("Far2", "us"),
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            preferred_labels: HashMap::from([("region".to_string(), "eu".to_string())]),
+            ..Default::default()
+        });
+
+        for _ in 0..4 {
+            assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Near");
+        }
+
+        // Without a healthy same-region node, fall back to the rest.
+        for _ in 0..3 {
+            lb.snapshot()[1].force_mark_failure();
+        }
+        assert_ne!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Near");
+    }
+
+    #[tokio::test]
+    async fn test_better_connected_node_gets_proportionally_more_traffic() {
+        let peers = |count: &'static str| {
+            Router::new().route(
+                "/",
+                post(move |Json(req): Json<RpcRequest>| async move {
+                    Json(RpcResponse::success(req.id, serde_json::json!(count)))
+                }),
+            )
+        };
+        let sparse = spawn_mock_upstream(peers("0x8")).await;
+        let dense = spawn_mock_upstream(peers("0x18")).await;
+        let lb = LoadBalancer::new(&[config("Sparse", sparse), config("Dense", dense)])
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                weight_by_peer_count: true,
+                ..Default::default()
+            });
+        for node in lb.snapshot().iter() {
+            node.refresh_peer_count().await;
+        }
+
+        let mut picks: HashMap<String, usize> = HashMap::new();
+        for _ in 0..32 {
+            let node = lb.choose_healthy_node(&[]).unwrap();
+            *picks.entry(node.get_name().to_string()).or_default() += 1;
+        }
+        assert_eq!(picks["Sparse"], 8);
+        assert_eq!(picks["Dense"], 24);
+    }
+
+    #[tokio::test]
+    async fn test_requests_route_by_capability_or_fail_clearly() {
+        let tagged = |name: &str, url: String, tags: &[&str]| UpstreamConfig {
+            capabilities: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..config(name, url)
+        };
+        let full = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let archive = spawn_delayed_upstream(Duration::ZERO, "0x2").await;
+        let lb = LoadBalancer::new(&[
+            tagged("Full", full, &["full"]),
+            tagged("Archive", archive, &["full", "archive"]),
+        ])
+        .unwrap();
+        let request = |method: &str, params: serde_json::Value| RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: serde_json::json!(1),
+        };
+
+        let historical = request("eth_getBalance", serde_json::json!(["0xabc", "earliest"]));
+        for _ in 0..3 {
+            let response = lb
+                .forward_request(&historical, &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Archive"));
+        }
+
+        let error = lb
+            .forward_request(
+                &request("trace_block", serde_json::json!(["0x1"])),
+                &RequestContext::internal(),
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("\"trace\" capability"), "{}", error);
+    }
+
+    async fn spawn_gas_price_upstream(gas_price: &'static str) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| async move {
+                let result = match req.method.as_str() {
+                    "eth_gasPrice" => gas_price,
+                    _ => "0xtxhash",
+                };
+                Json(RpcResponse::success(req.id, serde_json::json!(result)))
+            }),
+        );
+        spawn_mock_upstream(app).await
+    }
+
+    #[tokio::test]
+    async fn test_transactions_route_to_lowest_gas_price() {
+        let pricey = spawn_gas_price_upstream("0x3b9aca00").await;
+        let cheap = spawn_gas_price_upstream("0x77359400").await;
+        let cheapest = spawn_gas_price_upstream("0x1dcd6500").await;
+
+        let lb = LoadBalancer::new(&[
+            config("Pricey", pricey),
+            config("Cheapest", cheapest),
+            config("Cheap", cheap),
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            route_transactions_by_gas_price: true,
+            ..Default::default()
+        });
+        for node in lb.snapshot().iter() {
+            node.refresh_gas_price().await;
+        }
+
+        let tx = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_sendRawTransaction".to_string(),
+            params: serde_json::json!(["0xf86c"]),
+            id: serde_json::json!(1),
+        };
+        for _ in 0..3 {
+            let response = lb
+                .forward_request(&tx, &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Cheapest"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_result_fails_over_to_next_node() {
+        let broken = spawn_delayed_upstream(Duration::ZERO, "latest").await;
+        let good = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let validated = |name: &str, url: String| UpstreamConfig {
+            validate_results: true,
+            ..config(name, url)
+        };
+        let lb = LoadBalancer::new(&[validated("Broken", broken), validated("Good", good)])
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            });
+
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(response.result, Some(serde_json::json!("0x1")));
+        assert_eq!(response.meta.attempts, 2);
+        let broken = lb.node_by_name("Broken").unwrap();
+        assert_eq!(broken.get_consecutive_failures(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_null_receipt_is_only_trusted_for_unmined_transactions() {
+        // Serves `receipt` for receipts and `tx` for the transaction itself
+        async fn node(receipt: serde_json::Value, tx: serde_json::Value) -> String {
+            let app = Router::new().route(
+                "/",
+                post(move |Json(req): Json<RpcRequest>| async move {
+                    let result = if req.method == "eth_getTransactionReceipt" {
+                        receipt
+                    } else {
+                        tx
+                    };
+                    Json(RpcResponse::success(req.id, result))
+                }),
+            );
+            spawn_mock_upstream(app).await
+        }
+        let validated = |name: &str, url: String| UpstreamConfig {
+            validate_results: true,
+            ..config(name, url)
+        };
+        let mined = serde_json::json!({"hash": "0xabc", "blockHash": "0x1"});
+        let receipt = serde_json::json!({"status": "0x1"});
+        let lookup = RpcRequest {
+            method: "eth_getTransactionReceipt".to_string(),
+            params: serde_json::json!(["0xabc"]),
+            ..block_number_request()
+        };
+
+        // A node that knows the transaction was mined but has no receipt is broken
+        let lb = LoadBalancer::new(&[
+            validated(
+                "Missing",
+                node(serde_json::Value::Null, mined.clone()).await,
+            ),
+            validated("Good", node(receipt.clone(), mined).await),
+        ])
+        .unwrap()
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        });
+        let response = lb
+            .forward_request(&lookup, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(receipt));
+        assert_eq!(response.meta.attempts, 2);
+
+        // A pending transaction has no receipt yet
+        let pending = serde_json::json!({"hash": "0xabc", "blockHash": null});
+        let lb = LoadBalancer::new(&[validated(
+            "Node",
+            node(serde_json::Value::Null, pending).await,
+        )])
+        .unwrap();
+        let response = lb
+            .forward_request(&lookup, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert!(response.result.unwrap_or_default().is_null());
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_stops_retries_once_exhausted() {
+        let good = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let lb = LoadBalancer::new(&[
+            config("Dead", "http://127.0.0.1:1".to_string()),
+            config("Good", good),
+        ])
+        .unwrap()
+        .with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            budget: Some(RetryBudgetSettings {
+                ratio: 0.0,
+                window_secs: 60,
+                min_retries: 2,
+            }),
+            ..Default::default()
+        });
+        let request = block_number_request();
+        let ctx = RequestContext::internal();
+
+        // Every request starting on Dead needs a retry; the budget covers two.
+        for _ in 0..2 {
+            let response = lb.forward_request(&request, &ctx).await.unwrap();
+            assert_eq!(response.meta.attempts, 2);
+        }
+        assert_eq!(lb.retry_budget_remaining(), Some(0));
+
+        let err = lb
+            .forward_request(&request, &ctx)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("Retry budget exhausted"), "{}", err);
+    }
+
+    fn retrying(attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: attempts,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_sent_once_despite_retries_and_hedging() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let failing = || {
+            let calls = Arc::clone(&calls);
+            spawn_mock_upstream(Router::new().route(
+                "/",
+                post(move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async { axum::http::StatusCode::BAD_GATEWAY }
+                }),
+            ))
+        };
+        let lb = LoadBalancer::new(&[config("A", failing().await), config("B", failing().await)])
+            .unwrap()
+            .with_retry_policy(retrying(3))
+            .with_selection_settings(SelectionSettings {
+                hedge: Some(HedgeSettings {
+                    delay_ms: 0,
+                    methods: vec!["eth_sendRawTransaction".to_string()],
+                }),
+                read_fanout: Some(2),
+                ..Default::default()
+            });
+        let send = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_sendRawTransaction".to_string(),
+            params: serde_json::json!(["0x02f8"]),
+            id: serde_json::json!(1),
+        };
+
+        let err = lb
+            .forward_request(&send, &RequestContext::internal())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("502"), "{}", err);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Reads are still sent to both nodes
+        lb.forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_transport_failures_retry_on_each_healthy_node() {
+        let dead = |name: &str| config(name, "http://127.0.0.1:1".to_string());
+        let lb = LoadBalancer::new(&[dead("Dead1"), dead("Dead2")])
+            .unwrap()
+            .with_retry_policy(retrying(3));
+
+        let err = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.ends_with("(tried 2 nodes)"), "{}", err);
+        for node in lb.snapshot().iter() {
+            assert_eq!(node.get_consecutive_failures(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_hooks_rewrite_params_before_forwarding() {
+        struct Checksum;
+        impl RequestHook for Checksum {
+            fn apply(&self, request: &mut RpcRequest) {
+                request.params[0] = serde_json::json!("0xABC");
+            }
+        }
+
+        // Echoes the params it received, and reports them
+        let (seen, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let url = spawn_mock_upstream(Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| async move {
+                let _ = seen.send(req.params.clone());
+                Json(RpcResponse::success(req.id, req.params))
+            }),
+        ))
+        .await;
+        let lb = LoadBalancer::new(&[config("Node", url)])
+            .unwrap()
+            .with_request_hooks(vec![
+                Box::new(Checksum),
+                Box::new(crate::request_hooks::DefaultBlockTag::new("latest")),
+            ]);
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_getBalance".to_string(),
+            params: serde_json::json!(["0xabc"]),
+            id: serde_json::json!(1),
+        };
+
+        let response = lb
+            .forward_request(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.result,
+            Some(serde_json::json!(["0xABC", "latest"]))
+        );
+        received.recv().await.unwrap();
+
+        // Notifications are adjusted the same way
+        let notification = RpcRequest {
+            id: serde_json::Value::Null,
+            ..request
+        };
+        lb.forward_notification(&notification, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(
+            received.recv().await.unwrap(),
+            serde_json::json!(["0xABC", "latest"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_falls_back_on_transient_error_but_not_revert() {
+        let failing = |code: i32, message: &'static str| {
+            spawn_mock_upstream(Router::new().route(
+                "/",
+                post(move |Json(req): Json<RpcRequest>| async move {
+                    Json(RpcResponse::error(req.id, code, message.to_string()))
+                }),
+            ))
+        };
+        let estimate = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_estimateGas".to_string(),
+            params: serde_json::json!([{ "to": "0x0000000000000000000000000000000000000001" }]),
+            id: serde_json::json!(1),
+        };
+        let policy = RetryPolicy {
+            estimate_gas_fallback: true,
+            ..retrying(2)
+        };
+
+        let flaky = failing(-32000, "header not found").await;
+        let healthy = spawn_delayed_upstream(Duration::ZERO, "0x5208").await;
+        let lb = LoadBalancer::new_with_seed(&[config("A", flaky), config("B", healthy)], 0)
+            .unwrap()
+            .with_retry_policy(policy.clone());
+        // Whichever node a request starts on, B answers it
+        for _ in 0..2 {
+            let response = lb
+                .forward_request(&estimate, &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.result, Some(serde_json::json!("0x5208")));
+            assert_eq!(response.meta.served_by.as_deref(), Some("B"));
+        }
+
+        for (code, message) in [
+            (3, "execution reverted"),
+            (-32602, "missing value for required argument 0"),
+            (-32000, "insufficient funds for gas * price + value"),
+            (-32000, "nonce too low"),
+        ] {
+            let deterministic = failing(code, message).await;
+            let healthy = spawn_delayed_upstream(Duration::ZERO, "0x5208").await;
+            let lb =
+                LoadBalancer::new_with_seed(&[config("A", deterministic), config("B", healthy)], 0)
+                    .unwrap()
+                    .with_retry_policy(policy.clone());
+            let mut errors = Vec::new();
+            for _ in 0..2 {
+                let result = lb
+                    .forward_request(&estimate, &RequestContext::internal())
+                    .await;
+                errors.extend(result.err());
+            }
+
+            // The request starting on A returns the error instead of moving on
+            assert_eq!(errors.len(), 1, "{}", message);
+            assert!(
+                matches!(&errors[0], ForwardError::Rpc { error, node } if error.code == code && node == "A"),
+                "{}",
+                errors[0]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_are_not_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let reverting = spawn_mock_upstream(Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    Json(RpcResponse::error(
+                        req.id,
+                        3,
+                        "execution reverted".to_string(),
+                    ))
+                }
+            }),
+        ))
+        .await;
+        let other = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let lb = LoadBalancer::new_with_seed(&[config("A", reverting), config("B", other)], 0)
+            .unwrap()
+            .with_retry_policy(retrying(2));
+
+        let mut errors = Vec::new();
+        for _ in 0..2 {
+            let result = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await;
+            errors.extend(result.err());
+        }
+
+        // The request starting on A fails there instead of moving on to B
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(&errors[0], ForwardError::Rpc { error, node } if error.code == 3 && node == "A"),
+            "{}",
+            errors[0]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    fn fanout(k: usize) -> SelectionSettings {
+        SelectionSettings {
+            read_fanout: Some(k),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fanout_returns_fastest_success_without_waiting_for_slow_node() {
+        let slow = spawn_delayed_upstream(Duration::from_secs(3), "0xslow").await;
+        let fast = spawn_delayed_upstream(Duration::from_millis(50), "0xfast").await;
+        let lb = LoadBalancer::new(&[config("Slow", slow), config("Fast", fast)])
+            .unwrap()
+            .with_selection_settings(fanout(2));
+
+        let started = Instant::now();
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(response.result, Some(serde_json::json!("0xfast")));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // The slow call was dropped, not left to time out against the node
+        assert_eq!(lb.snapshot()[0].get_consecutive_failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_writes_are_never_fanned_out() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counting = || {
+            let calls = Arc::clone(&calls);
+            Router::new().route(
+                "/",
+                post(move |Json(req): Json<RpcRequest>| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    async move { Json(RpcResponse::success(req.id, serde_json::json!("0xtx"))) }
+                }),
+            )
+        };
+        let a = spawn_mock_upstream(counting()).await;
+        let b = spawn_mock_upstream(counting()).await;
+        let lb = LoadBalancer::new(&[config("A", a), config("B", b)])
+            .unwrap()
+            .with_selection_settings(fanout(2));
+        let request = RpcRequest {
+            method: "eth_sendRawTransaction".to_string(),
+            params: serde_json::json!(["0xf86c"]),
+            ..block_number_request()
+        };
+
+        lb.forward_request(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    fn with_consistency(consistency: Consistency) -> RequestContext {
+        RequestContext {
+            consistency,
+            ..RequestContext::internal()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fast_consistency_uses_round_robin() {
+        let behind = spawn_delayed_upstream(Duration::ZERO, "0x9").await;
+        let head = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let lb = LoadBalancer::new(&[config("Behind", behind), config("Head", head)]).unwrap();
+        let ctx = with_consistency(Consistency::Fast);
+
+        let mut served = Vec::new();
+        for _ in 0..2 {
+            let response = lb
+                .forward_request(&block_number_request(), &ctx)
+                .await
+                .unwrap();
+            served.push(response.meta.served_by.unwrap());
+        }
+        assert_eq!(served, ["Behind", "Head"]);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_consistency_routes_to_chain_head() {
+        let behind = spawn_delayed_upstream(Duration::ZERO, "0x9").await;
+        let head = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let lb = LoadBalancer::new(&[config("Behind", behind), config("Head", head)]).unwrap();
+        for node in lb.snapshot().iter() {
+            node.check_health().await;
+        }
+        let ctx = with_consistency(Consistency::Fresh);
+
+        for _ in 0..3 {
+            let response = lb
+                .forward_request(&block_number_request(), &ctx)
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Head"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nodes_lagging_beyond_threshold_are_skipped_but_stay_healthy() {
+        let lagging = spawn_delayed_upstream(Duration::ZERO, "0x5").await;
+        let close = spawn_delayed_upstream(Duration::ZERO, "0x9").await;
+        let head = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let lb = LoadBalancer::new(&[
+            config("Lagging", lagging),
+            config("Close", close),
+            config("Head", head),
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            max_block_lag: Some(2),
+            ..Default::default()
+        });
+        for node in lb.snapshot().iter() {
+            node.check_health().await;
+        }
+
+        for _ in 0..6 {
+            assert_ne!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Lagging");
+        }
+        assert!(lb.snapshot()[0].is_healthy());
+
+        let status = lb.get_nodes_status();
+        assert_eq!(status[0].latest_block, Some(5));
+        assert_eq!(status[2].latest_block, Some(10));
+    }
+
+    #[test]
+    fn test_status_estimates_recovery_with_backoff() {
+        let tripping = |name: &str| UpstreamConfig {
+            failure_threshold: Some(1),
+            cooldown_ms: Some(1_000),
+            max_cooldown_ms: Some(10_000),
+            ..config(name, "http://localhost:1".to_string())
+        };
+        let lb = LoadBalancer::new(&[tripping("Flapping"), tripping("Down")]).unwrap();
+        assert_eq!(lb.next_likely_recovery(), None);
+        let recovery = |name: &str| {
+            lb.get_nodes_status()
+                .into_iter()
+                .find(|status| status.name == name)
+                .and_then(|status| status.recovery_in_secs)
+        };
+
+        let down = lb.node_by_name("Down").unwrap();
+        down.force_mark_failure();
+        let first = recovery("Down").unwrap();
+        assert!(first > 0.9 && first <= 1.0);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(recovery("Down").unwrap() <= first - 0.05);
+
+        // Reopening right after the cooldown doubles it
+        let flapping = lb.node_by_name("Flapping").unwrap();
+        flapping.force_mark_failure();
+        flapping.force_cooldown_expiry();
+        assert_eq!(flapping.get_status(), NodeCondition::HalfOpen);
+        flapping.force_mark_failure();
+        let backed_off = recovery("Flapping").unwrap();
+        assert!(backed_off > 1.9 && backed_off <= 2.0);
+
+        // The soonest is the node with the shorter cooldown
+        let next = lb.next_likely_recovery().unwrap().as_secs_f64();
+        assert!(next < first && next >= recovery("Down").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_quorum_consistency_requires_majority() {
+        let a = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let b = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let c = spawn_delayed_upstream(Duration::ZERO, "0x9").await;
+        let ctx = with_consistency(Consistency::Quorum);
+
+        let lb = LoadBalancer::new(&[
+            config("A", a.clone()),
+            config("B", b),
+            config("C", c.clone()),
+        ])
+        .unwrap();
+        let response = lb
+            .forward_request(&block_number_request(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0xa")));
+
+        let split = LoadBalancer::new(&[config("A", a), config("C", c)]).unwrap();
+        let err = split
+            .forward_request(&block_number_request(), &ctx)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No quorum: 1 of 2 nodes agreed");
+    }
+
+    #[tokio::test]
+    async fn test_quorum_tallies_errors_apart_from_results() {
+        let failing = || {
+            spawn_mock_upstream(Router::new().route(
+                "/",
+                post(|Json(req): Json<RpcRequest>| async move {
+                    Json(RpcResponse::error(
+                        req.id,
+                        -32000,
+                        "header not found".to_string(),
+                    ))
+                }),
+            ))
+        };
+        let ok = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let ctx = with_consistency(Consistency::Quorum);
+
+        // A majority error is passed through as an error, not a result
+        let lb = LoadBalancer::new(&[
+            config("A", failing().await),
+            config("B", failing().await),
+            config("C", ok.clone()),
+        ])
+        .unwrap();
+        match lb.forward_request(&block_number_request(), &ctx).await {
+            Err(ForwardError::Rpc { error, .. }) => assert_eq!(error.code, -32000),
+            other => panic!(
+                "expected the agreed error, got {:?}",
+                other.map(|r| r.result)
+            ),
+        }
+
+        // Errors do not count as votes for a result
+        let lb = LoadBalancer::new(&[
+            config("A", failing().await),
+            config("B", ok.clone()),
+            config("C", spawn_delayed_upstream(Duration::ZERO, "0x9").await),
+        ])
+        .unwrap();
+        let err = lb
+            .forward_request(&block_number_request(), &ctx)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No quorum: 1 of 3 nodes agreed");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_tier_is_held_back_on_every_selection_path() {
+        let primary = spawn_delayed_upstream(Duration::ZERO, "0x9").await;
+        let backup = spawn_delayed_upstream(Duration::ZERO, "0xa").await;
+        let lb = LoadBalancer::new(&[
+            config("Primary", primary),
+            UpstreamConfig {
+                tier: 1,
+                ..config("Backup", backup)
+            },
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            session_affinity_ms: Some(60_000),
+            ..Default::default()
+        });
+        for node in lb.snapshot().iter() {
+            node.check_health().await;
+        }
+
+        // Backup is ahead of the chain, yet fresh reads stay on the primary tier
+        let fresh = with_consistency(Consistency::Fresh);
+        let response = lb
+            .forward_request(&block_number_request(), &fresh)
+            .await
+            .unwrap();
+        assert_eq!(response.meta.served_by.as_deref(), Some("Primary"));
+
+        for session in ["a", "b", "c", "d"] {
+            let ctx = RequestContext {
+                session_id: Some(session.to_string()),
+                ..RequestContext::internal()
+            };
+            let response = lb
+                .forward_request(&block_number_request(), &ctx)
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Primary"));
+        }
+
+        let quorum = with_consistency(Consistency::Quorum);
+        let response = lb
+            .forward_request(&block_number_request(), &quorum)
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0x9")));
+        assert_eq!(response.meta.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_result_and_error_together_penalizes_node_and_fails_over() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": req.id,
+                    "result": "0xbad",
+                    "error": {"code": -32000, "message": "confused"}
+                }))
+            }),
+        );
+        let confused = spawn_mock_upstream(app).await;
+        let good = spawn_delayed_upstream(Duration::ZERO, "0xgood").await;
+        let lb = LoadBalancer::new(&[config("Confused", confused), config("Good", good)])
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            });
+
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+
+        assert_eq!(response.result, Some(serde_json::json!("0xgood")));
+        let confused = lb.node_by_name("Confused").unwrap();
+        assert_eq!(confused.get_consecutive_failures(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_version_mismatch_is_normalized_or_rejected() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(serde_json::json!({ "jsonrpc": "1.0", "id": req.id, "result": "0xold" }))
+            }),
+        );
+        let legacy = spawn_mock_upstream(app).await;
+        let good = spawn_delayed_upstream(Duration::ZERO, "0xgood").await;
+        let with_policy = |policy: VersionMismatch| {
+            LoadBalancer::new(&[
+                UpstreamConfig {
+                    version_mismatch: policy,
+                    ..config("Legacy", legacy.clone())
+                },
+                config("Good", good.clone()),
+            ])
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            })
+        };
+
+        let lb = with_policy(VersionMismatch::Normalize);
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.meta.served_by.as_deref(), Some("Legacy"));
+        assert_eq!(response.jsonrpc, "2.0");
+        assert_eq!(response.result, Some(serde_json::json!("0xold")));
+
+        let lb = with_policy(VersionMismatch::Reject);
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.meta.served_by.as_deref(), Some("Good"));
+        let legacy = lb.node_by_name("Legacy").unwrap();
+        assert_eq!(legacy.get_consecutive_failures(), 1);
+    }
+
+    fn limited(name: &str, url: String, queue_timeout_ms: u64) -> UpstreamConfig {
+        UpstreamConfig {
+            max_concurrent_requests: Some(1),
+            queue: Some(crate::types::RequestQueue {
+                max_waiting: 4,
+                timeout_ms: queue_timeout_ms,
+            }),
+            ..config(name, url)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_request_proceeds_when_slot_frees() {
+        let slow = spawn_delayed_upstream(Duration::from_millis(200), "0x1").await;
+        let lb = Arc::new(LoadBalancer::new(&[limited("Slow", slow, 1000)]).unwrap());
+        let ctx = RequestContext::internal();
+
+        let busy = {
+            let lb = Arc::clone(&lb);
+            tokio::spawn(async move {
+                lb.forward_request(&block_number_request(), &RequestContext::internal())
+                    .await
+            })
+        };
+        time::sleep(Duration::from_millis(50)).await;
+
+        let started = Instant::now();
+        let response = lb
+            .forward_request(&block_number_request(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(response.meta.served_by.as_deref(), Some("Slow"));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        busy.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_leaves_the_queue() {
+        let slow = spawn_delayed_upstream(Duration::from_millis(200), "0x1").await;
+        let node = Arc::new(
+            UpstreamNode::new(UpstreamConfig {
+                queue: Some(crate::types::RequestQueue {
+                    max_waiting: 1,
+                    timeout_ms: 5000,
+                }),
+                ..limited("Slow", slow, 5000)
+            })
+            .unwrap(),
+        );
+        let busy = {
+            let node = Arc::clone(&node);
+            tokio::spawn(async move {
+                node.call_rpc(&block_number_request(), &RequestContext::internal())
+                    .await
+            })
+        };
+        time::sleep(Duration::from_millis(50)).await;
+
+        // Give up while queued behind the busy call
+        let cancelled = time::timeout(
+            Duration::from_millis(20),
+            node.call_rpc(&block_number_request(), &RequestContext::internal()),
+        )
+        .await;
+        assert!(cancelled.is_err());
+
+        // Its place in the queue is free again
+        let response = node
+            .call_rpc(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0x1")));
+        busy.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout_fails_over_without_penalty() {
+        let slow = spawn_delayed_upstream(Duration::from_millis(500), "0x1").await;
+        let fast = spawn_delayed_upstream(Duration::ZERO, "0x2").await;
+        let lb = LoadBalancer::new(&[limited("Slow", slow, 50), config("Fast", fast)])
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            });
+
+        // Occupy Slow's only slot without advancing the round-robin index.
+        let slow_node = lb.node_by_name("Slow").unwrap();
+        let busy = tokio::spawn(async move {
+            slow_node
+                .call_rpc(&block_number_request(), &RequestContext::internal())
+                .await
+        });
+        time::sleep(Duration::from_millis(50)).await;
+
+        let response = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.meta.served_by.as_deref(), Some("Fast"));
+        assert_eq!(
+            lb.node_by_name("Slow").unwrap().get_consecutive_failures(),
+            0
+        );
+        busy.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_bad_client_config_is_an_error_naming_the_node() {
+        let bad = UpstreamConfig {
+            proxy: Some("not a proxy url".to_string()),
+            ..config("Proxied", "http://localhost:1".to_string())
+        };
+
+        let err = LoadBalancer::new(&[config("Good", "http://localhost:2".to_string()), bad])
+            .err()
+            .expect("bad proxy should be rejected");
+        assert!(err.contains("Proxied"), "{}", err);
+        assert!(!err.contains("Good"), "{}", err);
+    }
+
+    #[test]
+    fn test_scheme_less_url_is_rejected_at_startup() {
+        for url in ["localhost:8545", "127.0.0.1:8545", "ws://localhost:8546"] {
+            let err = LoadBalancer::new(&[config("Bare", url.to_string())])
+                .err()
+                .expect("scheme-less url should be rejected");
+            assert!(err.contains("Bare") && err.contains("http://"), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_idempotency_key_routes_to_another_node() {
+        let a = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let b = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let lb = LoadBalancer::new(&[config("A", a), config("B", b)])
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                avoid_repeat_node_ms: Some(10_000),
+                ..Default::default()
+            });
+        let retried = RequestContext {
+            retry_key: Some("order-42".to_string()),
+            ..RequestContext::internal()
+        };
+
+        let first = lb
+            .forward_request(&block_number_request(), &retried)
+            .await
+            .unwrap();
+        // An unrelated request moves round robin back to the first node.
+        lb.forward_request(&block_number_request(), &RequestContext::internal())
+            .await
+            .unwrap();
+        // The retry carries a fresh JSON-RPC id, as clients usually send.
+        let retry = lb
+            .forward_request(
+                &RpcRequest {
+                    id: serde_json::json!(2),
+                    ..block_number_request()
+                },
+                &retried,
+            )
+            .await
+            .unwrap();
+
+        assert_ne!(first.meta.served_by, retry.meta.served_by);
+    }
+
+    #[test]
+    fn test_single_healthy_node_bypasses_the_strategy() {
+        let nodes = [
+            config("Down", "http://127.0.0.1:1".to_string()),
+            config("Up", "http://127.0.0.1:2".to_string()),
+        ];
+        let build = |always_run_strategy| {
+            let lb = LoadBalancer::new_with_seed(&nodes, 7)
+                .unwrap()
+                .with_selection_settings(SelectionSettings {
+                    least_response_time: true,
+                    always_run_strategy,
+                    ..Default::default()
+                });
+            for _ in 0..3 {
+                lb.snapshot()[0].force_mark_failure();
+            }
+            lb
+        };
+
+        // `choose_fastest` draws jitter from the RNG for every candidate, so
+        // an untouched RNG shows the strategy never ran
+        let lb = build(false);
+        let rng_before = lb.rng_state.load(Ordering::SeqCst);
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Up");
+        assert_eq!(lb.rng_state.load(Ordering::SeqCst), rng_before);
+
+        let lb = build(true);
+        let rng_before = lb.rng_state.load(Ordering::SeqCst);
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Up");
+        assert_ne!(lb.rng_state.load(Ordering::SeqCst), rng_before);
+    }
+
+    #[tokio::test]
+    async fn test_byte_fairness_prefers_the_node_with_fewer_recent_bytes() {
+        let heavy = spawn_delayed_upstream(Duration::ZERO, "0x1").await;
+        let light = spawn_delayed_upstream(Duration::ZERO, "0x2").await;
+        let lb = LoadBalancer::new(&[config("Heavy", heavy), config("Light", light)])
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                byte_fairness: true,
+                ..Default::default()
+            });
+        lb.snapshot()[0].force_record_bytes(50 * 1024 * 1024);
+
+        for _ in 0..10 {
+            let response = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Light"));
+        }
+        let status = lb.snapshot();
+        assert!(status[1].recent_bytes() > 0);
+        assert!(status[1].recent_bytes() < status[0].recent_bytes());
+
+        // Once the other node has transferred more, it is the one passed over
+        lb.snapshot()[1].force_record_bytes(100 * 1024 * 1024);
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "Heavy");
+    }
+
+    #[tokio::test]
+    async fn test_least_response_time_prefers_the_fastest_node() {
+        let slow = spawn_delayed_upstream(Duration::from_millis(60), "0x1").await;
+        let fast = spawn_delayed_upstream(Duration::ZERO, "0x2").await;
+        let lb = LoadBalancer::new_with_seed(&[config("Slow", slow), config("Fast", fast)], 7)
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                least_response_time: true,
+                ..Default::default()
+            });
+
+        // Both nodes are cold at first, so each gets tried before any is preferred
+        let mut served = Vec::new();
+        for _ in 0..2 {
+            let response = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await
+                .unwrap();
+            served.push(response.meta.served_by.unwrap());
+        }
+        served.sort();
+        assert_eq!(served, ["Fast", "Slow"]);
+
+        for _ in 0..10 {
+            let response = lb
+                .forward_request(&block_number_request(), &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.meta.served_by.as_deref(), Some("Fast"));
+        }
+
+        let status = lb.get_nodes_status();
+        let slow_ms = status[0].avg_latency_ms.unwrap();
+        let fast_ms = status[1].avg_latency_ms.unwrap();
+        assert!(
+            slow_ms >= 60.0 && fast_ms < slow_ms,
+            "{} vs {}",
+            slow_ms,
+            fast_ms
+        );
+    }
+
+    #[tokio::test]
+    async fn test_receipt_lookup_prefers_node_that_accepted_the_transaction() {
+        const TX_HASH: &str = "0xAbC0000000000000000000000000000000000000000000000000000000000001";
+        let upstream = || {
+            spawn_mock_upstream(Router::new().route(
+                "/",
+                post(|Json(req): Json<RpcRequest>| async move {
+                    let result = match req.method.as_str() {
+                        "eth_sendRawTransaction" => serde_json::json!(TX_HASH),
+                        _ => serde_json::json!({ "status": "0x1" }),
+                    };
+                    Json(RpcResponse::success(req.id, result))
+                }),
+            ))
+        };
+        let lb = LoadBalancer::new(&[
+            config("A", upstream().await),
+            config("B", upstream().await),
+            config("C", upstream().await),
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            tx_affinity_ms: Some(60_000),
+            ..Default::default()
+        });
+        let request = |method: &str, param: &str| RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params: serde_json::json!([param]),
+            id: serde_json::json!(1),
+        };
+
+        let broadcast = lb
+            .forward_request(
+                &request("eth_sendRawTransaction", "0x02f8"),
+                &RequestContext::internal(),
+            )
+            .await
+            .unwrap();
+        let accepted_by = broadcast.meta.served_by.unwrap();
+
+        for method in TX_LOOKUP_METHODS.iter().chain(TX_LOOKUP_METHODS) {
+            let response = lb
+                .forward_request(
+                    &request(method, &TX_HASH.to_lowercase()),
+                    &RequestContext::internal(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                response.meta.served_by.as_deref(),
+                Some(accepted_by.as_str())
+            );
+        }
+
+        // Unrelated hashes keep rotating
+        let mut served = HashSet::new();
+        for _ in 0..3 {
+            let response = lb
+                .forward_request(
+                    &request("eth_getTransactionReceipt", "0x01"),
+                    &RequestContext::internal(),
+                )
+                .await
+                .unwrap();
+            served.insert(response.meta.served_by.unwrap());
+        }
+        assert_eq!(served.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_session_sticks_to_one_node_and_fails_over() {
+        let lb = LoadBalancer::new(&[
+            config("A", spawn_delayed_upstream(Duration::ZERO, "0x1").await),
+            config("B", spawn_delayed_upstream(Duration::ZERO, "0x1").await),
+            config("C", spawn_delayed_upstream(Duration::ZERO, "0x1").await),
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            session_affinity_ms: Some(60_000),
+            ..Default::default()
+        });
+        let session = RequestContext {
+            session_id: Some("wallet-7".to_string()),
+            ..RequestContext::internal()
+        };
+        async fn served_by(lb: &LoadBalancer, ctx: &RequestContext) -> String {
+            lb.forward_request(&block_number_request(), ctx)
+                .await
+                .unwrap()
+                .meta
+                .served_by
+                .unwrap()
+        }
+
+        let first = served_by(&lb, &session).await;
+        for _ in 0..4 {
+            assert_eq!(served_by(&lb, &session).await, first);
+        }
+
+        lb.node_by_name(&first).unwrap().set_drained(true);
+        let second = served_by(&lb, &session).await;
+        assert_ne!(second, first);
+
+        // The session stays on its new node once the old one is back
+        lb.node_by_name(&first).unwrap().set_drained(false);
+        for _ in 0..3 {
+            assert_eq!(served_by(&lb, &session).await, second);
+        }
+
+        // Sessions are per caller: another client reusing the id is placed afresh
+        let other_caller = RequestContext {
+            caller: crate::types::Caller {
+                api_key: Some("other".to_string()),
+                ..Default::default()
+            },
+            ..session.clone()
+        };
+        assert_eq!(served_by(&lb, &other_caller).await, first);
+
+        // Without a session id requests keep rotating
+        let mut served = HashSet::new();
+        for _ in 0..3 {
+            served.insert(served_by(&lb, &RequestContext::internal()).await);
+        }
+        assert_eq!(served.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_chain_id_mismatch_aborts_or_quarantines_minority() {
+        let mut configs = Vec::new();
+        for (name, chain) in [("A", "0x1"), ("B", "0x1"), ("C", "0x5")] {
+            configs.push(config(
+                name,
+                spawn_delayed_upstream(Duration::ZERO, chain).await,
+            ));
+        }
+
+        let lb = LoadBalancer::new(&configs).unwrap();
+        let err = lb.verify_chain_ids().await.unwrap_err();
+        assert!(err.contains("A=1") && err.contains("C=5"), "{}", err);
+        assert_eq!(lb.chain_id(), None);
+
+        let lb = LoadBalancer::new(&configs)
+            .unwrap()
+            .with_chain_id_settings(ChainIdSettings {
+                on_mismatch: ChainIdMismatch::Quarantine,
+                ..Default::default()
+            });
+        lb.verify_chain_ids().await.unwrap();
+        assert_eq!(lb.chain_id(), Some(1));
+        let drained: Vec<bool> = lb.snapshot().iter().map(|n| n.is_drained()).collect();
+        assert_eq!(drained, vec![false, false, true]);
+
+        // A node that later switches chains is marked unhealthy
+        assert!(lb.snapshot()[0].verify_chain_id(1).await);
+        assert!(!lb.snapshot()[2].verify_chain_id(1).await);
+        assert_eq!(
+            lb.snapshot()[2].get_status(),
+            crate::upstream::NodeCondition::Unhealthy
+        );
+    }
+
+    #[tokio::test]
+    async fn test_configured_chain_id_mismatch_aborts_or_marks_nodes_unhealthy() {
+        let configs = [
+            config("A", spawn_delayed_upstream(Duration::ZERO, "0x1").await),
+            config("B", spawn_delayed_upstream(Duration::ZERO, "0x5").await),
+        ];
+        let expecting = |on_mismatch| ChainIdSettings {
+            expected: Some(5),
+            on_mismatch,
+            ..Default::default()
+        };
+
+        // The configured id wins even though no node agrees with a majority
+        let lb = LoadBalancer::new(&configs)
+            .unwrap()
+            .with_chain_id_settings(expecting(ChainIdMismatch::Abort));
+        let err = lb.verify_chain_ids().await.unwrap_err();
+        assert!(err.contains("configured chain id 5"), "{}", err);
+        assert!(err.contains("A=1"), "{}", err);
+
+        let lb = LoadBalancer::new(&configs)
+            .unwrap()
+            .with_chain_id_settings(expecting(ChainIdMismatch::MarkUnhealthy));
+        lb.verify_chain_ids().await.unwrap();
+        assert_eq!(lb.chain_id(), Some(5));
+        let status: Vec<NodeCondition> = lb.snapshot().iter().map(|n| n.get_status()).collect();
+        assert_eq!(
+            status,
+            vec![NodeCondition::Unhealthy, NodeCondition::Healthy]
+        );
+        assert!(lb.snapshot().iter().all(|n| !n.is_drained()));
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "B");
+
+        // The wrong-chain node stays out past its cooldown and a good probe
+        let wrong = lb.node_by_name("A").unwrap();
+        wrong.force_cooldown_expiry();
+        wrong.check_health().await;
+        assert_eq!(wrong.get_status(), NodeCondition::Unhealthy);
+        assert!(!wrong.verify_chain_id(5).await);
+        assert!(wrong.verify_chain_id(1).await);
+        assert!(!wrong.on_wrong_chain());
+
+        // With no node on the configured chain there is nothing to start with
+        let lb = LoadBalancer::new(&configs[..1])
+            .unwrap()
+            .with_chain_id_settings(expecting(ChainIdMismatch::MarkUnhealthy));
+        let err = lb.verify_chain_ids().await.unwrap_err();
+        assert!(
+            err.contains("No upstream serves configured chain id 5"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_reads_are_hedged_to_another_node() {
+        let slow = spawn_delayed_upstream(Duration::from_millis(600), "0xslow").await;
+        let fast = spawn_delayed_upstream(Duration::ZERO, "0xfast").await;
+        let lb = LoadBalancer::new(&[config("Slow", slow), config("Fast", fast)])
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                hedge: Some(HedgeSettings {
+                    delay_ms: 50,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        let ctx = RequestContext::internal();
+
+        let started = Instant::now();
+        let response = lb
+            .forward_request(&block_number_request(), &ctx)
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0xfast")));
+        assert_eq!(response.meta.attempts, 2);
+        assert!(started.elapsed() < Duration::from_millis(500));
+        // The dropped call on the slow node counts as neither outcome
+        let slow = lb.node_by_name("Slow").unwrap();
+        assert_eq!(slow.request_counts(), (0, 0));
+        assert_eq!(lb.node_by_name("Fast").unwrap().request_counts(), (1, 0));
+
+        // Writes wait for the node they were sent to
+        lb.next_index.store(0, Ordering::SeqCst);
+        let send = RpcRequest {
+            method: "eth_sendRawTransaction".to_string(),
+            params: serde_json::json!(["0x00"]),
+            ..block_number_request()
+        };
+        let response = lb.forward_request(&send, &ctx).await.unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0xslow")));
+        assert_eq!(slow.request_counts(), (1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_attempt_timeouts_are_charged_to_the_nodes_still_running() {
+        let failing = spawn_mock_upstream(Router::new().route(
+            "/",
+            post(|| async {
+                time::sleep(Duration::from_millis(100)).await;
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            }),
+        ))
+        .await;
+        let hung = spawn_delayed_upstream(Duration::from_secs(2), "0xhung").await;
+        let lb = LoadBalancer::new(&[config("Failing", failing), config("Hung", hung)])
+            .unwrap()
+            .with_selection_settings(SelectionSettings {
+                hedge: Some(HedgeSettings {
+                    delay_ms: 50,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .with_retry_policy(RetryPolicy {
+                max_attempts: 1,
+                attempt_timeout: Some(AttemptTimeout::FixedMs(300)),
+                ..Default::default()
+            });
+
+        let result = lb
+            .forward_request(&block_number_request(), &RequestContext::internal())
+            .await;
+        assert!(result.is_err());
+        // The primary failed on its own before the deadline; only the hedge
+        // was still running when the attempt timed out
+        let failing = lb.node_by_name("Failing").unwrap();
+        assert_eq!(failing.get_consecutive_failures(), 1);
+        let hung = lb.node_by_name("Hung").unwrap();
+        assert_eq!(hung.get_consecutive_failures(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_selection_trace_records_why_nodes_were_skipped() {
+        let url = spawn_delayed_upstream(Duration::from_millis(500), "0x1").await;
+        let traced = |name: &str| UpstreamConfig {
+            capabilities: HashSet::from(["trace".to_string()]),
+            ..config(name, url.clone())
+        };
+        let lb = LoadBalancer::new(&[
+            traced("Drained"),
+            traced("Cooling"),
+            config("Plain", url.clone()),
+            traced("Tried"),
+            traced("Chosen"),
+            UpstreamConfig {
+                max_concurrent_requests: Some(1),
+                ..traced("Busy")
+            },
+        ])
+        .unwrap()
+        .with_selection_settings(SelectionSettings {
+            record_selection_trace: true,
+            ..Default::default()
+        });
+        lb.node_by_name("Drained").unwrap().set_drained(true);
+        for _ in 0..3 {
+            lb.node_by_name("Cooling").unwrap().force_mark_failure();
+        }
+        let busy = lb.node_by_name("Busy").unwrap();
+        tokio::spawn(async move {
+            let _ = busy
+                .call_rpc(&block_number_request(), &RequestContext::internal())
+                .await;
+        });
+        time::sleep(Duration::from_millis(50)).await;
+
+        let request = RpcRequest {
+            method: "trace_block".to_string(),
+            ..block_number_request()
+        };
+        let chosen = lb
+            .choose_node_for(
+                &request,
+                &RequestContext::internal(),
+                &["Tried".to_string()],
+            )
+            .unwrap();
+        assert_eq!(chosen.get_name(), "Chosen");
+
+        let trace = lb.last_selection().unwrap();
+        assert_eq!(trace.method, "trace_block");
+        assert_eq!(trace.chosen.as_deref(), Some("Chosen"));
+        let reasons: Vec<(&str, SkipReason)> = trace
+            .skipped
+            .iter()
+            .map(|s| (s.node.as_str(), s.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("Drained", SkipReason::Drained),
+                ("Cooling", SkipReason::Cooldown),
+                ("Plain", SkipReason::CapabilityMismatch),
+                ("Tried", SkipReason::Excluded),
+                ("Busy", SkipReason::AtCapacity),
+            ]
+        );
+    }
+}
@@ -1,25 +1,94 @@
+mod admin;
+mod audit;
+mod auth;
 mod cache;
+mod cache_key;
+mod capability;
+mod circuit_state;
+mod config;
+mod field_filter;
+mod health_summary;
+mod idempotency;
 mod load_balancer;
+mod maintenance;
+mod method_class;
+mod method_filter;
+mod metrics;
+mod overload;
+mod param_defaults;
+mod plugins;
+mod prewarm;
+mod profiling;
+mod rate_limit;
+mod reorg;
+mod request_hooks;
+mod retry_budget;
+mod server;
+mod singleflight;
+mod slo;
+#[cfg(test)]
+mod test_util;
+mod trace_context;
+mod tx_dedup;
 mod types;
 mod upstream;
+mod upstream_override;
+mod ws_proxy;
 
+use audit::{AuditEntry, AuditLog};
+use auth::{Authenticator, Authorizer, KeyPolicyAuthorizer};
 use axum::{
     Json, Router,
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{ConnectInfo, Extension, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
 };
 use cache::Cache;
+use config::GatewayConfig;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use idempotency::IdempotencyStore;
 use load_balancer::LoadBalancer;
+use maintenance::MaintenanceScheduler;
+use metrics::{ErrorClass, RequestCounters, Timeseries};
+use overload::{AtCapacity, ConcurrencyLimiter, LagMonitor};
+use plugins::PluginHost;
+use rate_limit::RateLimiter;
+use singleflight::SingleFlight;
+use slo::SloTracker;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::SystemTime;
+use trace_context::TraceContext;
+use tracing::Instrument;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use types::{RpcRequest, RpcResponse, UpstreamConfig};
+use tx_dedup::TxDedup;
+use types::{
+    Caller, Consistency, ForwardError, RequestContext, RpcRequest, RpcResponse, UpstreamConfig,
+};
 
 #[derive(Clone)]
 struct AppState {
     load_balancer: Arc<LoadBalancer>,
     cache: Arc<Cache>,
+    config: Arc<GatewayConfig>,
+    inflight: Arc<SingleFlight<Result<RpcResponse, ForwardError>>>,
+    idempotent_inflight: Arc<SingleFlight<Outcome>>,
+    lag_monitor: Arc<LagMonitor>,
+    concurrency: Arc<ConcurrencyLimiter>,
+    in_flight: Arc<server::InFlightRequests>,
+    plugins: Arc<PluginHost>,
+    auth: Arc<Authenticator>,
+    authorizer: Arc<dyn Authorizer>,
+    rate_limiter: Arc<RateLimiter>,
+    tx_dedup: Arc<TxDedup>,
+    idempotency: Arc<IdempotencyStore>,
+    timeseries: Arc<Timeseries>,
+    request_counters: Arc<RequestCounters>,
+    slo: Arc<SloTracker>,
+    audit: Arc<AuditLog>,
+    logs_hub: Arc<ws_proxy::LogsHub>,
 }
 
 #[tokio::main]
@@ -34,107 +103,1022 @@ async fn main() {
 
     tracing::info!("Starting HA Gateway");
 
-    // Using local proxies to eth nodes.
-    let upstreams = vec![
-        UpstreamConfig {
-            name: "Node 1".to_string(),
-            url: "http://localhost:8545".to_string(),
-        },
-        UpstreamConfig {
-            name: "Node 2".to_string(),
-            url: "http://localhost:8546".to_string(),
-        },
-        UpstreamConfig {
-            name: "Node 3".to_string(),
-            url: "http://localhost:8547".to_string(),
-        },
-    ];
+    let Some(config_path) = config::config_path(
+        std::env::args().skip(1),
+        std::env::var(config::CONFIG_ENV_VAR).ok(),
+    ) else {
+        tracing::error!(
+            "No configuration file given: pass --config <path> or set {}",
+            config::CONFIG_ENV_VAR
+        );
+        std::process::exit(1);
+    };
+    let config = match config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let upstreams = config.upstreams.clone();
 
     tracing::info!("Configured {} upstream nodes", upstreams.len());
     for upstream in &upstreams {
         tracing::info!("  - {}: {}", upstream.name, upstream.url);
     }
 
-    // Create load balancer and start health checker
-    let load_balancer = Arc::new(LoadBalancer::new(&upstreams));
-    let cache = Arc::new(Cache::new());
+    let state = match build_state(config, &upstreams) {
+        Ok(state) => state,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(path) = &state.config.state_file {
+        match circuit_state::restore(&state.load_balancer, path) {
+            Ok(restored) => tracing::info!("Restored circuit state of {} nodes", restored),
+            Err(e) => tracing::warn!("{}, starting with fresh circuit state", e),
+        }
+    }
+
+    if let Err(e) = state.load_balancer.verify_chain_ids().await {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
 
     // Start background health checker
-    Arc::clone(&load_balancer).start_health_checker();
+    Arc::clone(&state.load_balancer).start_health_checker();
+    Arc::clone(&state.lag_monitor).start();
+    Arc::clone(&state.cache).start_summary_logger();
+    if state.cache.is_enabled() {
+        reorg::ReorgWatcher::new(Arc::clone(&state.load_balancer), Arc::clone(&state.cache)).start(
+            std::time::Duration::from_millis(state.config.health_checks.interval_ms),
+        );
+    }
+    prewarm::prewarm_cache(&state).await;
 
-    let state = AppState {
-        load_balancer: Arc::clone(&load_balancer),
-        cache,
-    };
+    if !state.config.maintenance_windows.is_empty() {
+        MaintenanceScheduler::new(
+            Arc::clone(&state.load_balancer),
+            state.config.maintenance_windows.clone(),
+        )
+        .start();
+    }
+
+    let in_flight = Arc::clone(&state.in_flight);
+    let server_settings = state.config.server.clone();
+    let load_balancer = Arc::clone(&state.load_balancer);
+    let state_file = state.config.state_file.clone();
 
     // Build router
     let app = Router::new()
-        .route("/", post(handle_rpc_request))
+        .route(
+            "/",
+            post(handle_rpc_request)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    auth::require_api_key,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit::enforce_rate_limit,
+                )),
+        )
+        .route(
+            "/ws",
+            get(ws_proxy::ws_handler)
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    auth::require_api_key,
+                ))
+                .layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit::enforce_rate_limit,
+                )),
+        )
         .route("/health", get(health_check))
+        .route("/healthz/summary", get(health_summary::summary_handler))
         .route("/status", get(status_check))
-        .with_state(state)
+        .route("/config", get(admin::effective_config))
+        .route("/admin/nodes", post(admin::add_node))
+        .route("/admin/nodes/{name}", delete(admin::remove_node))
+        .route("/admin/nodes/{name}/drain", post(admin::drain_node))
+        .route("/admin/nodes/{name}/reset", post(admin::reset_node))
+        .route(
+            "/admin/nodes/{name}/upgrading",
+            post(admin::start_upgrade).delete(admin::finish_upgrade),
+        )
+        .route("/debug/profile", get(profiling::profile_handler))
+        .route("/admin/cache/clear", post(admin::clear_cache))
+        .route("/debug/last-selection", get(admin::last_selection))
+        .route("/metrics", get(metrics::metrics_handler))
+        .route("/metrics/timeseries", get(metrics::timeseries_handler))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            server::read_request_body,
+        ))
+        // `read_request_body` enforces `max_body_bytes`; raise axum's 2 MB
+        // extractor default to match
+        .layer(axum::extract::DefaultBodyLimit::max(
+            server_settings.max_body_bytes,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            server::track_in_flight,
+        ))
+        .with_state(state);
+    let app = match server::with_cors(app, &server_settings.cors) {
+        Ok(app) => app,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let app = server::with_compression(app, &server_settings)
         .layer(tower_http::trace::TraceLayer::new_for_http());
 
-    // Start server
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080")
-        .await
-        .expect("Failed to bind to port 8080");
+    if let Err(e) = server::run(app, &server_settings, in_flight).await {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
 
-    tracing::info!("Listening on http://0.0.0.0:8080");
+    if let Some(path) = &state_file
+        && let Err(e) = circuit_state::save(&load_balancer, path)
+    {
+        tracing::error!("{}", e);
+    }
 
-    axum::serve(listener, app)
-        .await
-        .expect("Server failed to start");
+    tracing::info!("HA Gateway stopped");
+}
+
+/// Debug-mode header reporting where each response (or batch element) came from.
+const PROVENANCE_HEADER: &str = "x-cache-provenance";
+
+/// Header carrying the client's staleness tolerance for cached values, in ms.
+const MAX_AGE_HEADER: &str = "x-max-age";
+
+/// Content type of JSON-RPC responses unless `response_content_type` overrides it.
+const DEFAULT_RPC_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+/// Set when a response came from an unhealthy node used as a last resort.
+const DEGRADED_HEADER: &str = "x-degraded";
+
+/// JSON-RPC error code for requests no healthy upstream was available to serve
+/// ("resource unavailable" in EIP-1474).
+const NO_HEALTHY_UPSTREAM_CODE: i32 = -32002;
+
+/// Wires the load balancer and cache together from the resolved configuration.
+///
+/// Fails with a message naming the offending component (e.g. the upstream node)
+/// when part of the configuration cannot be used. `upstreams` is passed
+/// separately from `config.upstreams` so tests can wire nodes directly.
+fn build_state(config: GatewayConfig, upstreams: &[UpstreamConfig]) -> Result<AppState, String> {
+    let mut load_balancer = match config.selection_seed {
+        Some(seed) => LoadBalancer::new_with_seed(upstreams, seed)?,
+        None => LoadBalancer::new(upstreams)?,
+    };
+    if config.shared_http_client {
+        load_balancer = load_balancer.with_shared_client()?;
+    }
+    let load_balancer = load_balancer
+        .with_retry_policy(config.retry.clone())
+        .with_selection_settings(config.selection.clone())
+        .with_health_check_settings(config.health_checks.clone())
+        .with_chain_id_settings(config.chain_id.clone())
+        .with_request_hooks(request_hooks::from_settings(&config.request_hooks));
+
+    Ok(AppState {
+        load_balancer: Arc::new(load_balancer),
+        cache: Arc::new(Cache::new(config.cache.clone())),
+        inflight: Arc::new(SingleFlight::new()),
+        idempotent_inflight: Arc::new(SingleFlight::new()),
+        lag_monitor: Arc::new(LagMonitor::new(config.overload.clone())),
+        concurrency: Arc::new(ConcurrencyLimiter::new(&config.overload)),
+        in_flight: Arc::new(server::InFlightRequests::default()),
+        plugins: Arc::new(PluginHost::load(config.wasm_plugin.as_deref())?),
+        auth: Arc::new(Authenticator::new(&config.auth)),
+        authorizer: Arc::new(KeyPolicyAuthorizer::new(
+            &config.auth,
+            &config.method_filter,
+        )),
+        rate_limiter: Arc::new(RateLimiter::new(&config.rate_limit)),
+        tx_dedup: Arc::new(TxDedup::new(&config.tx_dedup)),
+        idempotency: Arc::new(IdempotencyStore::new(&config.idempotency)),
+        timeseries: Arc::new(Timeseries::new(&config.metrics)),
+        request_counters: Arc::new(RequestCounters::default()),
+        slo: Arc::new(SloTracker::new(&config.metrics.slo)),
+        audit: Arc::new(
+            AuditLog::new(&config.audit)
+                .map_err(|e| format!("Failed to open audit sink: {}", e))?,
+        ),
+        logs_hub: Arc::new(ws_proxy::LogsHub::default()),
+        config: Arc::new(config),
+    })
+}
+
+/// Oldest cached value the client accepts, if it set a limit.
+///
+/// `X-Max-Age` gives the tolerance in milliseconds; a cache bypass counts as a
+/// tolerance of zero. Unparseable values are ignored.
+fn max_cache_age(headers: &HeaderMap) -> Option<std::time::Duration> {
+    if bypass_cache_requested(headers) {
+        return Some(std::time::Duration::ZERO);
+    }
+    headers
+        .get(MAX_AGE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(std::time::Duration::from_millis)
+}
+
+/// Returns true when the client asked to skip cached results.
+///
+/// Accepts either `X-Bypass-Cache: true` or `Cache-Control: no-cache`.
+fn bypass_cache_requested(headers: &HeaderMap) -> bool {
+    let bypass_header = headers
+        .get("x-bypass-cache")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    let no_cache = headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.to_ascii_lowercase().contains("no-cache"));
+    bypass_header || no_cache
+}
+
+/// Selects the allowlisted upstream headers to pass through to the client.
+fn forwarded_headers(response: &RpcResponse, allowlist: &[String]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &response.meta.headers {
+        if !allowlist
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(name))
+        {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+/// Builds an ETag for scalar string results such as `eth_blockNumber`'s hex quantity.
+fn result_etag(result: &serde_json::Value) -> Option<HeaderValue> {
+    let value = result.as_str()?;
+    HeaderValue::from_str(&format!("\"{}\"", value)).ok()
+}
+
+/// Returns true when the client's `If-None-Match` already names the current result.
+///
+/// Quotes are optional, so pollers can simply echo the last value they saw.
+fn client_has_current_result(headers: &HeaderMap, result: &serde_json::Value) -> bool {
+    let Some(current) = result.as_str() else {
+        return false;
+    };
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"'))
+                .any(|tag| tag == current)
+        })
+}
+
+/// Where a response body came from, reported in debug mode.
+#[derive(Debug, Clone, PartialEq)]
+enum Provenance {
+    /// Served from the response cache.
+    Cache,
+    /// Fetched from the named upstream node.
+    Upstream(String),
+    /// Produced by the gateway itself (errors, invalid requests).
+    Gateway,
+}
+
+impl std::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Provenance::Cache => write!(f, "cache"),
+            Provenance::Upstream(node) => write!(f, "upstream:{}", node),
+            Provenance::Gateway => write!(f, "gateway"),
+        }
+    }
+}
+
+/// The result of processing one JSON-RPC request, standalone or within a batch.
+#[derive(Clone)]
+struct Outcome {
+    response: RpcResponse,
+    provenance: Provenance,
+
+    /// Whether the method is cacheable, which also enables ETag validation.
+    cacheable: bool,
+
+    /// Whether the request could not be served by any upstream.
+    failed: bool,
+}
+
+/// One serialized response of a batch, with what the response headers need.
+struct BatchElement {
+    json: Vec<u8>,
+    provenance: Provenance,
+    degraded: bool,
+}
+
+impl Outcome {
+    /// An error produced by the gateway without contacting an upstream.
+    fn gateway_error(response: RpcResponse) -> Self {
+        Self {
+            response,
+            provenance: Provenance::Gateway,
+            cacheable: false,
+            failed: true,
+        }
+    }
+
+    /// The answer for a request that got no result upstream.
+    ///
+    /// A JSON-RPC error from the upstream reaches the client unchanged; only
+    /// failures of the gateway itself are reported as internal errors.
+    fn forward_error(id: serde_json::Value, error: ForwardError) -> Self {
+        match error {
+            ForwardError::Rpc { error, node } => Self {
+                response: RpcResponse::from_error(id, error),
+                provenance: Provenance::Upstream(node),
+                cacheable: false,
+                failed: false,
+            },
+            ForwardError::NoHealthyUpstream => Self::gateway_error(RpcResponse::error(
+                id,
+                NO_HEALTHY_UPSTREAM_CODE,
+                "No healthy upstream available".to_string(),
+            )),
+            ForwardError::Internal(e) => Self::gateway_error(RpcResponse::error(
+                id,
+                -32603,
+                format!("Internal error: {}", e),
+            )),
+        }
+    }
+
+    /// Replaces the details of a gateway-side internal error, which name nodes
+    /// and their failures, with a generic message. Errors returned by an
+    /// upstream are left alone.
+    fn sanitize_error(&mut self) {
+        if !self.failed {
+            return;
+        }
+        if let Some(error) = self.response.error.as_mut()
+            && error.code == -32603
+        {
+            error.message = "Internal error".to_string();
+            error.data = None;
+        }
+    }
+
+    /// Whether the request failed because of the client or the server side.
+    fn error_class(&self) -> Option<ErrorClass> {
+        match &self.response.error {
+            Some(error) => Some(ErrorClass::of_rpc_error(error.code, self.failed)),
+            None if self.failed => Some(ErrorClass::Server),
+            None => None,
+        }
+    }
+}
+
+/// Builds the JSON-RPC "Invalid Request" error for a payload that is not a request object.
+fn invalid_request(reason: impl std::fmt::Display) -> RpcResponse {
+    RpcResponse::error(
+        serde_json::Value::Null,
+        -32600,
+        format!("Invalid Request: {}", reason),
+    )
 }
 
 async fn handle_rpc_request(
     State(state): State<AppState>,
-    Json(request): Json<RpcRequest>,
-) -> impl IntoResponse {
-    tracing::info!("Received RPC request: method={}", request.method);
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let request_id = trace_context::request_id_from_headers(&headers);
+    let slot = state.concurrency.acquire().await;
+    let pinned_node = upstream_override::pinned_node(
+        &state.config.upstream_override,
+        &headers,
+        SystemTime::now(),
+    );
+    let mut response = match (slot, Consistency::from_headers(&headers), pinned_node) {
+        (Err(AtCapacity), _, _) => {
+            tracing::warn!("Rejecting request: too many requests in flight");
+            state.request_counters.record(Some(ErrorClass::Server));
+            at_capacity_response()
+        }
+        (Ok(_), Err(e), _) => {
+            state.request_counters.record(Some(ErrorClass::Client));
+            (StatusCode::BAD_REQUEST, Json(invalid_request(e))).into_response()
+        }
+        (Ok(_), Ok(_), Err(reason)) => {
+            tracing::warn!("Rejecting upstream override: {}", reason);
+            state.request_counters.record(Some(ErrorClass::Client));
+            let body = RpcResponse::error(
+                serde_json::Value::Null,
+                auth::NOT_AUTHORIZED_CODE,
+                reason.to_string(),
+            );
+            (StatusCode::FORBIDDEN, Json(body)).into_response()
+        }
+        (Ok(_slot), Ok(consistency), Ok(pinned_node)) => {
+            let ctx = RequestContext {
+                trace: TraceContext::from_headers(&headers),
+                request_id: request_id.clone(),
+                consistency,
+                caller: Caller::from_headers(
+                    &headers,
+                    connect_info.map(|Extension(ConnectInfo(addr))| addr.ip()),
+                ),
+                retry_key: [types::IDEMPOTENCY_KEY_HEADER, types::REQUEST_ID_HEADER]
+                    .iter()
+                    .find_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+                    .map(str::to_string),
+                session_id: headers
+                    .get(types::SESSION_ID_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+                pinned_node,
+            };
+            let span = tracing::info_span!(
+                "rpc_request",
+                trace_id = %ctx.trace.trace_id,
+                request_id = %ctx.request_id
+            );
+            async {
+                match body {
+                    serde_json::Value::Array(items) => {
+                        handle_batch(&state, &headers, items, &ctx).await
+                    }
+                    single => handle_single_request(&state, &headers, single, &ctx).await,
+                }
+            }
+            .instrument(span)
+            .await
+        }
+    };
 
-    let cache_key = if request.method == "eth_blockNumber" {
-        Some(format!(
-            "{}:{}",
-            request.method,
-            serde_json::to_string(&request.params).unwrap_or_default()
-        ))
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(types::REQUEST_ID_HEADER, value);
+    }
+    // Bodyless responses such as 304 carry no content type to replace
+    if response.headers().contains_key(header::CONTENT_TYPE) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, rpc_content_type(&state.config));
+    }
+    response
+}
+
+/// 503 for a request turned away by the gateway-wide in-flight limit.
+fn at_capacity_response() -> Response {
+    let body = RpcResponse::error(
+        serde_json::Value::Null,
+        -32005,
+        "Gateway overloaded, try again later".to_string(),
+    );
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(1));
+    response
+}
+
+/// The content type sent with JSON-RPC responses.
+fn rpc_content_type(config: &GatewayConfig) -> HeaderValue {
+    config
+        .response_content_type
+        .as_deref()
+        .and_then(|value| HeaderValue::from_str(value).ok())
+        .unwrap_or(HeaderValue::from_static(DEFAULT_RPC_CONTENT_TYPE))
+}
+
+async fn handle_single_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: serde_json::Value,
+    ctx: &RequestContext,
+) -> Response {
+    let request: RpcRequest = match serde_json::from_value(body) {
+        Ok(request) => request,
+        Err(e) => {
+            state.request_counters.record(Some(ErrorClass::Client));
+            return (StatusCode::BAD_REQUEST, Json(invalid_request(e))).into_response();
+        }
+    };
+    if request.is_notification() {
+        dispatch_notification(state, headers, request, ctx).await;
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    let outcome = process_request(state, headers, &request, ctx).await;
+    let mut response_headers =
+        forwarded_headers(&outcome.response, &state.config.forward_response_headers);
+
+    if outcome.cacheable
+        && let Some(result) = &outcome.response.result
+        && let Some(etag) = result_etag(result)
+    {
+        response_headers.insert(header::ETAG, etag);
+        if outcome.provenance == Provenance::Cache && client_has_current_result(headers, result) {
+            return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+        }
+    }
+
+    if state.config.debug_headers
+        && let Ok(value) = HeaderValue::from_str(&outcome.provenance.to_string())
+    {
+        response_headers.insert(PROVENANCE_HEADER, value);
+    }
+
+    if outcome.response.meta.degraded {
+        response_headers.insert(DEGRADED_HEADER, HeaderValue::from_static("true"));
+    }
+
+    let status = if outcome.failed {
+        StatusCode::SERVICE_UNAVAILABLE
     } else {
-        None
+        StatusCode::OK
     };
+    (status, response_headers, Json(outcome.response)).into_response()
+}
+
+/// Handles a JSON-RPC batch, processing elements concurrently and answering in order.
+async fn handle_batch(
+    state: &AppState,
+    headers: &HeaderMap,
+    items: Vec<serde_json::Value>,
+    ctx: &RequestContext,
+) -> Response {
+    if items.is_empty() {
+        return (StatusCode::OK, Json(invalid_request("empty batch"))).into_response();
+    }
+
+    let methods = items
+        .iter()
+        .filter_map(|item| item.get("method").and_then(serde_json::Value::as_str));
+    if state.idempotency.has_conflicting_writes(headers, methods) {
+        state.request_counters.record(Some(ErrorClass::Client));
+        let error = invalid_request("batch carries more than one write under one idempotency key");
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
 
-    if let Some(ref key) = cache_key {
-        tracing::info!("checking key in cache {:?}",cache_key);
-        if let Some(cached_result) = state.cache.get(key) {
-            tracing::info!("Received cache result  {:?}",cached_result);
-            return (
-                StatusCode::OK,
-                Json(RpcResponse::success(request.id.clone(), cached_result)),
+    tracing::info!("Received RPC batch of {} requests", items.len());
+    // Elements are serialized as they complete, so the size limit trips
+    // before the whole batch is buffered and the unfinished calls are dropped
+    let limit = state.config.server.max_batch_response_bytes;
+    let count = items.len();
+    let mut pending: FuturesUnordered<_> = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| async move {
+            let outcome = match serde_json::from_value::<RpcRequest>(item) {
+                Ok(request) if request.is_notification() => {
+                    dispatch_notification(state, headers, request, ctx).await;
+                    None
+                }
+                Ok(request) => Some(process_request(state, headers, &request, ctx).await),
+                Err(e) => Some(Outcome::gateway_error(invalid_request(e))),
+            };
+            (index, outcome)
+        })
+        .collect();
+    let mut elements: Vec<Option<BatchElement>> = (0..count).map(|_| None).collect();
+    // Opening bracket, plus one separator or closing bracket per element
+    let mut size = 1;
+    let mut completed = 0;
+    while let Some((index, outcome)) = pending.next().await {
+        completed += 1;
+        let Some(outcome) = outcome else {
+            continue;
+        };
+        let json = match serde_json::to_vec(&outcome.response) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Failed to serialize batch response: {}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        size += json.len() + 1;
+        if let Some(limit) = limit
+            && size > limit
+        {
+            tracing::warn!(
+                "Batch response exceeded {} bytes after {} of {} elements",
+                limit,
+                completed,
+                count
+            );
+            let error = RpcResponse::error(
+                serde_json::Value::Null,
+                -32600,
+                format!("Batch response exceeds the limit of {} bytes", limit),
             );
+            return (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response();
+        }
+        elements[index] = Some(BatchElement {
+            json,
+            provenance: outcome.provenance,
+            degraded: outcome.response.meta.degraded,
+        });
+    }
+    let elements: Vec<BatchElement> = elements.into_iter().flatten().collect();
+    // A batch of notifications only gets no response at all
+    if elements.is_empty() {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+
+    let mut response_headers = HeaderMap::new();
+    if state.config.debug_headers {
+        let summary = elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| format!("{}={}", index, element.provenance))
+            .collect::<Vec<_>>()
+            .join(",");
+        if let Ok(value) = HeaderValue::from_str(&summary) {
+            response_headers.insert(PROVENANCE_HEADER, value);
+        }
+    }
+
+    if elements.iter().any(|e| e.degraded) {
+        response_headers.insert(DEGRADED_HEADER, HeaderValue::from_static("true"));
+    }
+
+    let mut body = Vec::with_capacity(size);
+    body.push(b'[');
+    for (index, element) in elements.iter().enumerate() {
+        if index > 0 {
+            body.push(b',');
+        }
+        body.extend_from_slice(&element.json);
+    }
+    body.push(b']');
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(DEFAULT_RPC_CONTENT_TYPE),
+    );
+    (StatusCode::OK, response_headers, body).into_response()
+}
+
+/// Forwards a notification; the client gets no response, so errors and
+/// authorization denials are only logged.
+///
+/// It is forwarded before the client is answered, so it holds the request's
+/// concurrency slot and counts as in flight during a shutdown drain. A
+/// transaction or idempotency key already served is not sent again, and
+/// every notification gets an audit entry like any other call.
+async fn dispatch_notification(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: RpcRequest,
+    ctx: &RequestContext,
+) {
+    let started = std::time::Instant::now();
+    let filled = param_defaults::apply(&state.config.param_defaults, &request);
+    let request = filled.as_ref().unwrap_or(&request);
+    let already_served = || {
+        let tx_hash = state.tx_dedup.key_for(&request.method, &request.params);
+        let key = state.idempotency.key_for(headers, &ctx.caller, request);
+        tx_hash.is_some_and(|hash| state.tx_dedup.get(&hash).is_some())
+            || key.is_some_and(|key| state.idempotency.get(&key).is_some())
+    };
+    let (node, error_class) = if auth::check_call(state.authorizer.as_ref(), &ctx.caller, request)
+        .await
+        .is_err()
+    {
+        (None, Some(ErrorClass::Client))
+    } else if already_served() {
+        tracing::info!(
+            "Not resending {} notification already served",
+            request.method
+        );
+        (None, None)
+    } else {
+        match state.load_balancer.forward_notification(request, ctx).await {
+            Ok(node) => (Some(node), None),
+            Err(_) => (None, Some(ErrorClass::Server)),
         }
+    };
+    state.request_counters.record(error_class);
+    state.request_counters.record_method(&request.method);
+    state.audit.record(&AuditEntry {
+        ctx,
+        method: &request.method,
+        params: &request.params,
+        node: node.as_deref(),
+        outcome: audit_outcome(error_class),
+        latency: started.elapsed(),
+    });
+}
+
+/// How a call with this error class is recorded in the audit trail.
+fn audit_outcome(error_class: Option<ErrorClass>) -> &'static str {
+    match error_class {
+        None => "ok",
+        Some(ErrorClass::Client) => "client_error",
+        Some(ErrorClass::Server) => "server_error",
     }
+}
 
-    // Forward to upstream
-    match state.load_balancer.forward_request(&request).await {
+/// Serves one request, recording it in the per-minute time series and audit trail.
+async fn process_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &RpcRequest,
+    ctx: &RequestContext,
+) -> Outcome {
+    let started = std::time::Instant::now();
+    let filled = param_defaults::apply(&state.config.param_defaults, request);
+    let request = filled.as_ref().unwrap_or(request);
+    let mut outcome = match auth::check_call(state.authorizer.as_ref(), &ctx.caller, request).await
+    {
+        Ok(()) => serve_idempotent(state, headers, request, ctx).await,
+        Err(response) => Outcome {
+            response,
+            provenance: Provenance::Gateway,
+            cacheable: false,
+            failed: false,
+        },
+    };
+    let latency = started.elapsed();
+    state
+        .timeseries
+        .record(latency, outcome.failed || outcome.response.error.is_some());
+    let error_class = outcome.error_class();
+    state.request_counters.record(error_class);
+    state.request_counters.record_method(&request.method);
+    state.slo.record(error_class);
+    state.audit.record(&AuditEntry {
+        ctx,
+        method: &request.method,
+        params: &request.params,
+        node: outcome.response.meta.served_by.as_deref(),
+        outcome: audit_outcome(error_class),
+        latency,
+    });
+    if !state.auth.verbose_errors(ctx.caller.api_key.as_deref()) {
+        outcome.sanitize_error();
+    }
+    if let Some(result) = outcome.response.result.as_mut() {
+        field_filter::apply(
+            &state.config.strip_result_fields,
+            &request.method,
+            field_filter::requested_fields(headers).as_deref(),
+            result,
+        );
+    }
+    outcome
+}
+
+/// Serves one request, replaying the earlier response for a repeated
+/// idempotency key.
+///
+/// The key is reserved while its first call is in flight, so a concurrent
+/// repeat waits for that call's response instead of forwarding again.
+async fn serve_idempotent(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &RpcRequest,
+    ctx: &RequestContext,
+) -> Outcome {
+    let Some(key) = state.idempotency.key_for(headers, &ctx.caller, request) else {
+        return serve_request(state, headers, request, ctx).await;
+    };
+    let replay = |mut response: RpcResponse| {
+        tracing::info!("Replaying response for repeated idempotency key");
+        response.id = request.id.clone();
+        Outcome {
+            response,
+            provenance: Provenance::Cache,
+            cacheable: false,
+            failed: false,
+        }
+    };
+
+    if let Some(response) = state.idempotency.get(&key) {
+        return replay(response);
+    }
+
+    let mut outcome = state
+        .idempotent_inflight
+        .run(
+            &key,
+            || async {
+                // A call that finished just before the reservation has recorded its response
+                if let Some(response) = state.idempotency.get(&key) {
+                    return replay(response);
+                }
+                let outcome = serve_request(state, headers, request, ctx).await;
+                if !outcome.failed {
+                    state
+                        .idempotency
+                        .record(key.clone(), outcome.response.clone());
+                }
+                outcome
+            },
+            || {
+                Outcome::gateway_error(RpcResponse::error(
+                    request.id.clone(),
+                    -32603,
+                    "Internal error: Call with the same idempotency key was cancelled".to_string(),
+                ))
+            },
+        )
+        .await;
+    outcome.response.id = request.id.clone();
+    outcome
+}
+
+/// Serves one request from the cache or an upstream node.
+async fn serve_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    request: &RpcRequest,
+    ctx: &RequestContext,
+) -> Outcome {
+    tracing::info!("Received RPC request: method={}", request.method);
+
+    if state.lag_monitor.should_shed(&request.method) {
+        tracing::warn!("Shedding {} request under overload", request.method);
+        return Outcome::gateway_error(RpcResponse::error(
+            request.id.clone(),
+            -32005,
+            "Gateway overloaded, try again later".to_string(),
+        ));
+    }
+
+    if request.method == "eth_chainId"
+        && let Some(chain_id) = state.load_balancer.chain_id()
+    {
+        return Outcome {
+            response: RpcResponse::success(
+                request.id.clone(),
+                serde_json::json!(format!("0x{:x}", chain_id)),
+            ),
+            provenance: Provenance::Gateway,
+            cacheable: false,
+            failed: false,
+        };
+    }
+
+    // Only `fast` reads of cacheable methods may share an in-flight call, and
+    // only those for a fixed block may be answered from cache; a pinned request
+    // must reach its node
+    let shared_read = ctx.consistency == Consistency::Fast && ctx.pinned_node.is_none();
+    let fixed_block = !cache_key::has_block_tag(&request.params);
+    let cache_ttl = state
+        .cache
+        .ttl_for(&request.method)
+        .filter(|_| shared_read && fixed_block);
+    let negative_ttl = state
+        .cache
+        .negative_ttl_for(&request.method)
+        .filter(|_| shared_read && fixed_block);
+    let inflight_key = state
+        .cache
+        .ttl_for(&request.method)
+        .or(state.cache.negative_ttl_for(&request.method))
+        .filter(|_| shared_read)
+        .map(|_| state.cache.key_for(&request.method, &request.params));
+    let cache_key = inflight_key.as_ref().filter(|_| fixed_block);
+
+    if let Some(key) = cache_key {
+        tracing::info!("checking key in cache {:?}", cache_key);
+        if let Some(cached_result) = state.cache.lookup(key, max_cache_age(headers)) {
+            tracing::info!("Received cache result  {:?}", cached_result);
+            return Outcome {
+                response: RpcResponse::success(request.id.clone(), cached_result),
+                provenance: Provenance::Cache,
+                cacheable: true,
+                failed: false,
+            };
+        }
+    }
+
+    let tx_hash = state.tx_dedup.key_for(&request.method, &request.params);
+    if let Some(hash) = &tx_hash
+        && let Some(mut response) = state.tx_dedup.get(hash)
+    {
+        tracing::info!(
+            "Replaying first response for resubmitted transaction {}",
+            hash
+        );
+        response.id = request.id.clone();
+        return Outcome {
+            response,
+            provenance: Provenance::Cache,
+            cacheable: false,
+            failed: false,
+        };
+    }
+
+    let upstream_request = match state.plugins.transform_request(request) {
+        Ok(transformed) => transformed,
+        Err(e) => {
+            tracing::error!("Request plugin failed: {}", e);
+            return Outcome::gateway_error(RpcResponse::error(
+                request.id.clone(),
+                -32603,
+                format!("Internal error: {}", e),
+            ));
+        }
+    };
+
+    // Forward to upstream, coalescing identical cacheable requests (and
+    // concurrent resubmissions of the same transaction) in flight
+    let forwarded = match inflight_key.as_ref().or(tx_hash.as_ref()) {
+        Some(key) => state
+            .inflight
+            .run(
+                key,
+                || state.load_balancer.forward_request(&upstream_request, ctx),
+                || Err("Coalesced upstream call was cancelled".to_string().into()),
+            )
+            .await
+            .map(|mut response| {
+                response.id = request.id.clone();
+                response
+            }),
+        None => {
+            state
+                .load_balancer
+                .forward_request(&upstream_request, ctx)
+                .await
+        }
+    }
+    .and_then(|response| {
+        state
+            .plugins
+            .transform_response(response)
+            .map_err(ForwardError::Internal)
+    });
+
+    match forwarded {
         Ok(response) => {
-            // Cache successful responses for cacheable methods
-            if let (Some(key), Some(result)) = (cache_key, &response.result) {
-                state.cache.put(key, result.clone());
+            if let Some(hash) = tx_hash {
+                state.tx_dedup.record(hash, response.clone());
+            }
+
+            // Cache successful responses for cacheable methods, unless they look
+            // wrong or we only got them after retrying past a misbehaving node.
+            // `null` results only go in with the method's negative TTL.
+            let result = response.result.as_ref().unwrap_or(&serde_json::Value::Null);
+            let ttl = if result.is_null() {
+                negative_ttl
+            } else {
+                cache_ttl.map(|ttl| {
+                    state
+                        .cache
+                        .hinted_ttl(&request.method, &response.meta.headers)
+                        .unwrap_or(ttl)
+                })
+            };
+            let well_formed = if result.is_null() {
+                cache::may_answer_null(&request.method)
+            } else {
+                cache::is_well_formed_result(&request.method, result)
+            };
+            if let (Some(key), Some(ttl)) = (cache_key, ttl) {
+                if response.meta.attempts > 1 {
+                    tracing::debug!("Not caching {}: obtained after retries", key);
+                } else if response.meta.degraded {
+                    tracing::debug!("Not caching {}: served by an unhealthy node", key);
+                } else if !well_formed {
+                    tracing::warn!("Not caching suspect result for {}: {}", key, result);
+                } else {
+                    state.cache.put_with_ttl(key.clone(), result.clone(), ttl);
+                }
             }
 
             tracing::info!("Successfully forwarded request");
-            (StatusCode::OK, Json(response))
+            let node = response.meta.served_by.clone().unwrap_or_default();
+            Outcome {
+                response,
+                provenance: Provenance::Upstream(node),
+                cacheable: cache_key.is_some(),
+                failed: false,
+            }
         }
         Err(e) => {
             tracing::error!("Failed to forward request: {}", e);
-            (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(RpcResponse::error(
-                    request.id.clone(),
-                    -32603,
-                    format!("Internal error: {}", e),
-                )),
-            )
+            Outcome::forward_error(request.id.clone(), e)
         }
     }
 }
@@ -146,15 +1130,1444 @@ async fn health_check() -> impl IntoResponse {
 
 /// Status check endpoint - returns status of all upstream nodes
 async fn status_check(State(state): State<AppState>) -> impl IntoResponse {
-    let nodes_status = state.load_balancer.get_nodes_status();
     let status_json = serde_json::json!({
-        "nodes": nodes_status.iter().map(|(name, status)| {
-            serde_json::json!({
-                "name": name,
-                "status": status
-            })
-        }).collect::<Vec<_>>()
+        "active_tier": state.load_balancer.active_tier(),
+        "next_likely_recovery_secs": state
+            .load_balancer
+            .next_likely_recovery()
+            .map(|remaining| remaining.as_secs_f64()),
+        "nodes": state.load_balancer.get_nodes_status()
     });
 
     (StatusCode::OK, Json(status_json))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::spawn_mock_upstream;
+    use std::sync::Mutex;
+
+    fn rpc_request(method: &str, params: serde_json::Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: serde_json::json!(1),
+        }
+    }
+
+    fn body(request: RpcRequest) -> Json<serde_json::Value> {
+        Json(serde_json::to_value(request).unwrap())
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn upstream(name: &str, url: String) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            url,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upstream_ttl_hint_overrides_configured_ttl() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                let ttl = if req.method == "eth_getBalance" {
+                    "60"
+                } else {
+                    "0"
+                };
+                (
+                    [("x-cache-ttl", ttl)],
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x10"))),
+                )
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            cache: cache::CacheSettings {
+                method_ttl_ms: std::collections::HashMap::from([
+                    ("eth_getBalance".to_string(), 1),
+                    ("eth_getTransactionCount".to_string(), 1),
+                ]),
+                ttl_hint_header: Some("X-Cache-TTL".to_string()),
+                ttl_hint_methods: vec!["eth_getBalance".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        for method in ["eth_getBalance", "eth_getTransactionCount"] {
+            let request = rpc_request(method, serde_json::json!(["0xabc", "0x1"]));
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), body(request)).await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+        // The hinted method outlives its configured 1ms; the other is not hinted
+        let key = |method| {
+            state
+                .cache
+                .key_for(method, &serde_json::json!(["0xabc", "0x1"]))
+        };
+        assert!(state.cache.get(&key("eth_getBalance")).is_some());
+        assert!(state.cache.get(&key("eth_getTransactionCount")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_allowlisted_upstream_headers_are_forwarded() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                (
+                    [
+                        ("x-provider-request-id", "req-123"),
+                        ("x-internal-shard", "7"),
+                        ("upgrade", "h2c"),
+                    ],
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x1"))),
+                )
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            forward_response_headers: vec![
+                "X-Provider-Request-Id".to_string(),
+                "Upgrade".to_string(),
+            ],
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            body(rpc_request("eth_chainId", serde_json::json!([]))),
+        )
+        .await;
+
+        let headers = response.headers();
+        assert_eq!(headers["x-provider-request-id"], "req-123");
+        assert!(headers.get("x-internal-shard").is_none());
+        assert!(headers.get(header::UPGRADE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_forwarded_without_a_response() {
+        let (seen_tx, mut seen_rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(req): Json<serde_json::Value>| {
+                let seen_tx = seen_tx.clone();
+                async move {
+                    let _ = seen_tx.send(req.clone());
+                    match req.get("id") {
+                        Some(id) => {
+                            Json(RpcResponse::success(id.clone(), serde_json::json!("0x1")))
+                                .into_response()
+                        }
+                        None => StatusCode::NO_CONTENT.into_response(),
+                    }
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        let notification = serde_json::json!({ "jsonrpc": "2.0", "method": "eth_subscribe_ping" });
+
+        let response = handle_rpc_request(
+            State(state.clone()),
+            None,
+            HeaderMap::new(),
+            Json(notification.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        // Forwarded before the client is answered
+        let forwarded = seen_rx.try_recv().unwrap();
+        assert_eq!(forwarded["method"], "eth_subscribe_ping");
+        assert!(forwarded.get("id").is_none());
+
+        // Only the call gets an answer in a mixed batch
+        let batch = serde_json::json!([
+            notification,
+            rpc_request("eth_blockNumber", serde_json::json!([])),
+        ]);
+        let response =
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), Json(batch)).await;
+        let json = json_body(response).await;
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        assert_eq!(json[0]["id"], 1);
+
+        let batch = serde_json::json!([{ "jsonrpc": "2.0", "method": "a", "id": null }]);
+        let response = handle_rpc_request(State(state), None, HeaderMap::new(), Json(batch)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_node_labels_and_failures() {
+        let node = UpstreamConfig {
+            labels: std::collections::HashMap::from([
+                ("region".to_string(), "us-east".to_string()),
+                ("provider".to_string(), "acme".to_string()),
+            ]),
+            ..upstream("Node", "http://localhost:1".to_string())
+        };
+        let state = build_state(GatewayConfig::default(), &[node]).unwrap();
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        process_request(
+            &state,
+            &HeaderMap::new(),
+            &request,
+            &RequestContext::internal(),
+        )
+        .await;
+
+        let json = json_body(status_check(State(state)).await.into_response()).await;
+
+        let node = &json["nodes"][0];
+        assert_eq!(node["name"], "Node");
+        assert_eq!(node["labels"]["region"], "us-east");
+        assert_eq!(node["labels"]["provider"], "acme");
+        assert_eq!(node["successes"], 0);
+        assert_eq!(node["failures"], 1);
+        assert_eq!(node["consecutive_failures"], 1);
+        assert!(node["last_failure_unix_ms"].as_u64().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_responses_use_configured_content_type() {
+        let state = build_state(GatewayConfig::default(), &[]).unwrap();
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let response =
+            handle_rpc_request(State(state), None, HeaderMap::new(), body(request)).await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/json; charset=utf-8"
+        );
+
+        let config = GatewayConfig {
+            response_content_type: Some("application/json-rpc".to_string()),
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            Json(serde_json::json!([])),
+        )
+        .await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/json-rpc"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_consistency_level_is_rejected() {
+        let state = build_state(GatewayConfig::default(), &[]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            types::CONSISTENCY_HEADER,
+            HeaderValue::from_static("eventual"),
+        );
+
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let response = handle_rpc_request(State(state), None, headers, body(request)).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let json = json_body(response).await;
+        assert_eq!(json["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_bypass_header_skips_cache_but_refreshes_it() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        state
+            .cache
+            .put("eth_blockNumber:[]".to_string(), serde_json::json!("0x1"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-bypass-cache", HeaderValue::from_static("true"));
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let response = handle_rpc_request(State(state.clone()), None, headers, body(request)).await;
+
+        assert_eq!(json_body(response).await["result"], "0x10");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(
+            state.cache.get("eth_blockNumber:[]"),
+            Some(serde_json::json!("0x10"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_block_number_returns_not_modified() {
+        let state = build_state(GatewayConfig::default(), &[]).unwrap();
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let key = format!("eth_blockNumber:{}", request.params);
+        state.cache.put(key.clone(), serde_json::json!("0x10"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"0x10\""));
+        let response =
+            handle_rpc_request(State(state.clone()), None, headers, body(request.clone())).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // The chain moved on; the poller gets the new value and a fresh ETag.
+        state.cache.put(key, serde_json::json!("0x11"));
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("0x10"));
+        let response = handle_rpc_request(State(state), None, headers, body(request)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"0x11\"");
+        let json = json_body(response).await;
+        assert_eq!(json["result"], "0x11");
+    }
+
+    #[tokio::test]
+    async fn test_traceparent_propagated_to_upstream() {
+        let seen = Arc::new(Mutex::new(None::<String>));
+        let captured = Arc::clone(&seen);
+        let app = Router::new().route(
+            "/",
+            post(move |headers: HeaderMap, Json(req): Json<RpcRequest>| {
+                let captured = Arc::clone(&captured);
+                async move {
+                    *captured.lock().unwrap() = headers
+                        .get("traceparent")
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+
+        handle_rpc_request(
+            State(state),
+            None,
+            headers,
+            body(rpc_request("eth_chainId", serde_json::json!([]))),
+        )
+        .await;
+
+        let outgoing = seen.lock().unwrap().clone().expect("no traceparent sent");
+        let parts: Vec<&str> = outgoing.split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "00");
+        assert_eq!(parts[1], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parts[2].len(), 16);
+        assert_ne!(parts[2], "00f067aa0ba902b7");
+        assert_eq!(parts[3], "01");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_forwarded_and_echoed() {
+        let seen = Arc::new(Mutex::new(Vec::<String>::new()));
+        let captured = Arc::clone(&seen);
+        let app = Router::new().route(
+            "/",
+            post(move |headers: HeaderMap, Json(req): Json<RpcRequest>| {
+                let captured = Arc::clone(&captured);
+                async move {
+                    if let Some(id) = headers.get("x-request-id").and_then(|v| v.to_str().ok()) {
+                        captured.lock().unwrap().push(id.to_string());
+                    }
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("client-123"));
+        let response = handle_rpc_request(
+            State(state.clone()),
+            None,
+            headers,
+            body(rpc_request("eth_chainId", serde_json::json!([]))),
+        )
+        .await;
+        assert_eq!(response.headers()["x-request-id"], "client-123");
+
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            body(rpc_request("eth_chainId", serde_json::json!([]))),
+        )
+        .await;
+        let generated = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(generated.len(), 36);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["client-123".to_string(), generated]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_limit_rejects_with_retry_after() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+                Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            overload: overload::OverloadSettings {
+                max_in_flight: 1,
+                in_flight_wait_ms: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let call = |state: AppState| {
+            handle_rpc_request(
+                State(state),
+                None,
+                HeaderMap::new(),
+                body(rpc_request("eth_getBalance", serde_json::json!(["0x1"]))),
+            )
+        };
+
+        let first = tokio::spawn(call(state.clone()));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let rejected = call(state.clone()).await;
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rejected.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(json_body(rejected).await["error"]["code"], -32005);
+
+        assert_eq!(first.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(call(state).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_configured_chain_id_is_answered_locally() {
+        let config = GatewayConfig {
+            chain_id: load_balancer::ChainIdSettings {
+                expected: Some(137),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Nothing listens here, so only a local answer can succeed
+        let upstreams = [upstream("Down", "http://127.0.0.1:1".to_string())];
+        let state = build_state(config, &upstreams).unwrap();
+
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            body(rpc_request("eth_chainId", serde_json::json!([]))),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["result"], "0x89");
+    }
+
+    #[tokio::test]
+    async fn test_batch_response_over_size_limit_is_rejected() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(
+                    req.id,
+                    serde_json::json!("0x".repeat(200)),
+                ))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let mut config = GatewayConfig::default();
+        config.server.max_batch_response_bytes = Some(1000);
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        let small = serde_json::json!([rpc_request("eth_call", serde_json::json!([]))]);
+        let response =
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), Json(small)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await.as_array().unwrap().len(), 1);
+
+        let large = serde_json::Value::Array(
+            (0..5)
+                .map(|_| serde_json::json!(rpc_request("eth_call", serde_json::json!([]))))
+                .collect(),
+        );
+        let response = handle_rpc_request(State(state), None, HeaderMap::new(), Json(large)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let json = json_body(response).await;
+        assert!(json.is_object(), "{}", json);
+        assert!(
+            json["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("1000 bytes")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_size_limit_drops_unfinished_elements() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                if req.method == "eth_call" {
+                    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                }
+                Json(RpcResponse::success(
+                    req.id,
+                    serde_json::json!("0x".repeat(200)),
+                ))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let mut config = GatewayConfig::default();
+        config.server.max_batch_response_bytes = Some(1000);
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        let mut batch = vec![serde_json::json!(rpc_request(
+            "eth_call",
+            serde_json::json!([])
+        ))];
+        batch.extend(
+            (0..5).map(|i| serde_json::json!(rpc_request("eth_getLogs", serde_json::json!([i])))),
+        );
+        let started = std::time::Instant::now();
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            Json(serde_json::Value::Array(batch)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        // The slow element was dropped, not awaited
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    async fn spawn_counting_upstream(calls: Arc<std::sync::atomic::AtomicUsize>) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| {
+                let calls = Arc::clone(&calls);
+                async move {
+                    calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x10")))
+                }
+            }),
+        );
+        spawn_mock_upstream(app).await
+    }
+
+    #[tokio::test]
+    async fn test_signed_upstream_override_pins_named_node() {
+        let first_calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let second_calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let first = spawn_counting_upstream(Arc::clone(&first_calls)).await;
+        let second = spawn_counting_upstream(Arc::clone(&second_calls)).await;
+        let config = GatewayConfig {
+            upstream_override: upstream_override::UpstreamOverrideSettings {
+                secret: Some("debug-secret".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(
+            config,
+            &[upstream("First", first), upstream("Second", second)],
+        )
+        .unwrap();
+        let now = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            upstream_override::UPSTREAM_HEADER,
+            HeaderValue::from_static("Second"),
+        );
+        headers.insert(upstream_override::TIMESTAMP_HEADER, HeaderValue::from(now));
+
+        // Without a signature the override is refused outright
+        let request = rpc_request("eth_getBlockByNumber", serde_json::json!(["0x1", false]));
+        let response = handle_rpc_request(
+            State(state.clone()),
+            None,
+            headers.clone(),
+            body(request.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            auth::NOT_AUTHORIZED_CODE
+        );
+
+        let signature = upstream_override::sign("debug-secret", "Second", now);
+        headers.insert(
+            upstream_override::SIGNATURE_HEADER,
+            HeaderValue::from_str(&signature).unwrap(),
+        );
+        for _ in 0..3 {
+            let response = handle_rpc_request(
+                State(state.clone()),
+                None,
+                headers.clone(),
+                body(request.clone()),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        assert_eq!(first_calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert_eq!(second_calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_rpc_errors_pass_through_and_outages_get_their_own_code() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                let mut response = RpcResponse::error(req.id, 3, "execution reverted".to_string());
+                if let Some(error) = response.error.as_mut() {
+                    error.data = Some(serde_json::json!("0x08c379a0"));
+                }
+                Json(response)
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        let request = rpc_request("eth_call", serde_json::json!([{}, "latest"]));
+
+        let outcome = process_request(
+            &state,
+            &HeaderMap::new(),
+            &request,
+            &RequestContext::internal(),
+        )
+        .await;
+        let error = outcome.response.error.as_ref().unwrap();
+        assert_eq!(error.code, 3);
+        assert_eq!(error.message, "execution reverted");
+        assert_eq!(error.data, Some(serde_json::json!("0x08c379a0")));
+        assert_eq!(outcome.error_class(), Some(ErrorClass::Client));
+
+        let empty = build_state(GatewayConfig::default(), &[]).unwrap();
+        let outcome = process_request(
+            &empty,
+            &HeaderMap::new(),
+            &request,
+            &RequestContext::internal(),
+        )
+        .await;
+        let error = outcome.response.error.as_ref().unwrap();
+        assert_eq!(error.code, NO_HEALTHY_UPSTREAM_CODE);
+        assert_eq!(outcome.error_class(), Some(ErrorClass::Server));
+    }
+
+    #[tokio::test]
+    async fn test_only_verbose_keys_see_internal_error_details() {
+        let config = GatewayConfig {
+            auth: auth::AuthSettings {
+                api_keys: vec!["debug-key".to_string(), "public-key".to_string()],
+                key_policies: std::collections::HashMap::from([(
+                    "debug-key".to_string(),
+                    auth::KeyPolicy {
+                        verbose_errors: true,
+                        ..Default::default()
+                    },
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dead = upstream("Dead", "http://127.0.0.1:1".to_string());
+        let state = build_state(config, &[dead]).unwrap();
+        let message_for = |key: &'static str| {
+            let state = state.clone();
+            async move {
+                let mut headers = HeaderMap::new();
+                headers.insert(auth::API_KEY_HEADER, HeaderValue::from_static(key));
+                let body = serde_json::json!(rpc_request("eth_chainId", serde_json::json!([])));
+                let response = handle_rpc_request(State(state), None, headers, Json(body)).await;
+                let json = json_body(response).await;
+                json["error"]["message"].as_str().unwrap().to_string()
+            }
+        };
+
+        let verbose = message_for("debug-key").await;
+        assert!(
+            verbose.starts_with("Internal error: Request failed"),
+            "{}",
+            verbose
+        );
+        assert!(verbose.contains("tried 1 node"), "{}", verbose);
+        assert_eq!(message_for("public-key").await, "Internal error");
+    }
+
+    #[tokio::test]
+    async fn test_custom_authorizer_denies_method_for_key() {
+        struct NoWritesForTrial;
+
+        impl Authorizer for NoWritesForTrial {
+            fn authorize<'a>(
+                &'a self,
+                request: auth::AuthorizationRequest<'a>,
+            ) -> futures::future::BoxFuture<'a, auth::Decision> {
+                let trial = request.caller.api_key.as_deref() == Some("trial");
+                let decision = if trial && request.call.method == "eth_sendRawTransaction" {
+                    auth::Decision::Deny("Trial keys cannot send transactions".to_string())
+                } else {
+                    auth::Decision::Allow
+                };
+                Box::pin(async move { decision })
+            }
+        }
+
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let mut state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        state.authorizer = Arc::new(NoWritesForTrial);
+        let ctx_for = |key: &str| RequestContext {
+            caller: Caller {
+                api_key: Some(key.to_string()),
+                ..Default::default()
+            },
+            ..RequestContext::internal()
+        };
+        let send = rpc_request("eth_sendRawTransaction", serde_json::json!(["0x02f8"]));
+        let headers = HeaderMap::new();
+
+        let denied = process_request(&state, &headers, &send, &ctx_for("trial")).await;
+        let error = denied.response.error.as_ref().unwrap();
+        assert_eq!(error.code, auth::NOT_AUTHORIZED_CODE);
+        assert_eq!(error.message, "Trial keys cannot send transactions");
+        assert_eq!(denied.error_class(), Some(ErrorClass::Client));
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        let read = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let allowed = process_request(&state, &headers, &read, &ctx_for("trial")).await;
+        assert_eq!(allowed.response.result, Some(serde_json::json!("0x10")));
+        let paid = process_request(&state, &headers, &send, &ctx_for("paid")).await;
+        assert!(paid.response.error.is_none());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_missing_param_is_filled_with_default_before_forwarding() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(req.id, req.params))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            param_defaults: std::collections::HashMap::from([(
+                "eth_getBlockByNumber".to_string(),
+                vec![serde_json::Value::Null, serde_json::json!(false)],
+            )]),
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let request = rpc_request("eth_getBlockByNumber", serde_json::json!(["0x10"]));
+
+        let outcome = process_request(
+            &state,
+            &HeaderMap::new(),
+            &request,
+            &RequestContext::internal(),
+        )
+        .await;
+
+        assert_eq!(
+            outcome.response.result,
+            Some(serde_json::json!(["0x10", false]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_policy_decides_which_methods_are_cached() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            cache: cache::CacheSettings {
+                cacheable_methods: vec![],
+                method_ttl_ms: std::collections::HashMap::from([(
+                    "eth_chainId".to_string(),
+                    3_600_000,
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let (headers, ctx) = (HeaderMap::new(), RequestContext::internal());
+
+        for method in [
+            "eth_chainId",
+            "eth_chainId",
+            "eth_blockNumber",
+            "eth_blockNumber",
+        ] {
+            let request = rpc_request(method, serde_json::json!([]));
+            process_request(&state, &headers, &request, &ctx).await;
+        }
+
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_single_request_and_batch_element_share_upstream_call() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        let single = serde_json::json!({
+            "jsonrpc": "2.0", "method": "eth_blockNumber", "params": [], "id": 1
+        });
+        let batch = serde_json::json!([{
+            "jsonrpc": "2.0", "method": "eth_blockNumber", "params": [], "id": 2
+        }]);
+
+        let single =
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), Json(single)).await;
+        assert_eq!(json_body(single).await["result"], "0x10");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let batch =
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), Json(batch)).await;
+        let batch = json_body(batch).await;
+        assert_eq!(batch[0]["result"], "0x10");
+        assert_eq!(batch[0]["id"], 2);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_configured_result_fields_are_stripped() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(
+                    req.id,
+                    serde_json::json!({
+                        "number": "0x10",
+                        "hash": "0xabc",
+                        "logsBloom": "0x00",
+                        "transactions": ["0x1", "0x2"]
+                    }),
+                ))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            strip_result_fields: std::collections::HashMap::from([(
+                "eth_getBlockByNumber".to_string(),
+                vec!["logsBloom".to_string(), "transactions".to_string()],
+            )]),
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            body(rpc_request(
+                "eth_getBlockByNumber",
+                serde_json::json!(["0x10", false]),
+            )),
+        )
+        .await;
+
+        let json = json_body(response).await;
+        assert_eq!(json["jsonrpc"], "2.0");
+        assert_eq!(json["id"], 1);
+        assert!(json.get("error").is_none());
+        assert_eq!(
+            json["result"],
+            serde_json::json!({"number": "0x10", "hash": "0xabc"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_tag_requests_are_not_cached() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            cache: cache::CacheSettings {
+                cacheable_methods: vec!["eth_getBalance".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let (headers, ctx) = (HeaderMap::new(), RequestContext::internal());
+
+        for block in ["latest", "latest", "0x10", "0x10"] {
+            let request = rpc_request("eth_getBalance", serde_json::json!(["0xabc", block]));
+            process_request(&state, &headers, &request, &ctx).await;
+        }
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        // Concurrent ones still share a single upstream call
+        let request = rpc_request("eth_getBalance", serde_json::json!(["0xabc", "latest"]));
+        tokio::join!(
+            process_request(&state, &headers, &request, &ctx),
+            process_request(&state, &headers, &request, &ctx),
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_only_plausible_first_attempt_results_are_cached() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(req.id, serde_json::json!("pending")))
+            }),
+        );
+        let suspect = spawn_mock_upstream(app).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", suspect)]).unwrap();
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let key = state.cache.key_for(&request.method, &request.params);
+        let (headers, ctx) = (HeaderMap::new(), RequestContext::internal());
+
+        process_request(&state, &headers, &request, &ctx).await;
+        assert!(state.cache.get(&key).is_none());
+
+        // A valid result is cached, but not when a retry was needed to get it.
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let valid = spawn_counting_upstream(calls).await;
+        let config = GatewayConfig {
+            retry: load_balancer::RetryPolicy {
+                max_attempts: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(
+            config,
+            &[
+                upstream("Dead", "http://127.0.0.1:1".to_string()),
+                upstream("Node", valid),
+            ],
+        )
+        .unwrap();
+
+        process_request(&state, &headers, &request, &ctx).await;
+        assert!(state.cache.get(&key).is_none());
+
+        state
+            .load_balancer
+            .node_by_name("Dead")
+            .unwrap()
+            .set_drained(true);
+        process_request(&state, &headers, &request, &ctx).await;
+        assert_eq!(state.cache.get(&key), Some(serde_json::json!("0x10")));
+    }
+
+    #[tokio::test]
+    async fn test_null_receipt_is_cached_briefly_then_real_one_served() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mined = Arc::new(AtomicBool::new(false));
+        let app = Router::new().route(
+            "/",
+            post({
+                let (calls, mined) = (Arc::clone(&calls), Arc::clone(&mined));
+                move |Json(req): Json<RpcRequest>| async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let receipt = if mined.load(Ordering::SeqCst) {
+                        serde_json::json!({"status": "0x1"})
+                    } else {
+                        serde_json::Value::Null
+                    };
+                    Json(RpcResponse::success(req.id, receipt))
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            cache: cache::CacheSettings {
+                negative_ttl_ms: std::collections::HashMap::from([(
+                    "eth_getTransactionReceipt".to_string(),
+                    300,
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let request = rpc_request("eth_getTransactionReceipt", serde_json::json!(["0xabc"]));
+        let (headers, ctx) = (HeaderMap::new(), RequestContext::internal());
+
+        for _ in 0..3 {
+            let outcome = process_request(&state, &headers, &request, &ctx).await;
+            assert!(outcome.response.result.unwrap_or_default().is_null());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Mined meanwhile: the null is served until its short TTL runs out
+        mined.store(true, Ordering::SeqCst);
+        let outcome = process_request(&state, &headers, &request, &ctx).await;
+        assert!(outcome.response.result.unwrap_or_default().is_null());
+
+        tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+        let outcome = process_request(&state, &headers, &request, &ctx).await;
+        assert_eq!(
+            outcome.response.result,
+            Some(serde_json::json!({"status": "0x1"}))
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // The method is not otherwise cacheable, so the receipt is not kept
+        process_request(&state, &headers, &request, &ctx).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_requests_differing_only_by_id_share_upstream_call() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+
+        let tasks: Vec<_> = (0..4)
+            .map(|id| {
+                let state = state.clone();
+                let mut request = rpc_request("eth_blockNumber", serde_json::json!([]));
+                request.id = serde_json::json!(id);
+                tokio::spawn(async move {
+                    handle_rpc_request(State(state), None, HeaderMap::new(), body(request)).await
+                })
+            })
+            .collect();
+
+        for (id, task) in tasks.into_iter().enumerate() {
+            let response = task.await.unwrap();
+            let json = json_body(response).await;
+            assert_eq!(json["id"], id);
+            assert_eq!(json["result"], "0x10");
+        }
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resubmitted_raw_transaction_is_broadcast_once() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            tx_dedup: tx_dedup::TxDedupSettings {
+                window_ms: Some(60_000),
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        for id in 1..=2 {
+            let mut request =
+                rpc_request("eth_sendRawTransaction", serde_json::json!(["0xf86c0a85"]));
+            request.id = serde_json::json!(id);
+            let response =
+                handle_rpc_request(State(state.clone()), None, HeaderMap::new(), body(request))
+                    .await;
+            let json = json_body(response).await;
+            assert_eq!(json["id"], id);
+            assert_eq!(json["result"], "0x10");
+        }
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_idempotency_key_replays_first_response() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            idempotency: idempotency::IdempotencySettings {
+                window_ms: Some(60_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let with_key = |key: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(types::IDEMPOTENCY_KEY_HEADER, HeaderValue::from_static(key));
+            headers
+        };
+        let request = rpc_request("eth_chainId", serde_json::json!([]));
+
+        let first = handle_rpc_request(
+            State(state.clone()),
+            None,
+            with_key("k1"),
+            body(request.clone()),
+        )
+        .await;
+        let repeat = handle_rpc_request(
+            State(state.clone()),
+            None,
+            with_key("k1"),
+            body(request.clone()),
+        )
+        .await;
+        assert_eq!(json_body(first).await, json_body(repeat).await);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        handle_rpc_request(State(state), None, with_key("k2"), body(request)).await;
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_prevents_rebroadcast_of_resigned_transaction() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            idempotency: idempotency::IdempotencySettings {
+                window_ms: Some(60_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            types::IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_static("order-7"),
+        );
+
+        let first = rpc_request("eth_sendRawTransaction", serde_json::json!(["0x02f8aa"]));
+        let mut resigned = rpc_request("eth_sendRawTransaction", serde_json::json!(["0x02f8bb"]));
+        resigned.id = serde_json::json!(2);
+        let first =
+            handle_rpc_request(State(state.clone()), None, headers.clone(), body(first)).await;
+        let repeat = handle_rpc_request(State(state), None, headers, body(resigned)).await;
+
+        let (first, repeat) = (json_body(first).await, json_body(repeat).await);
+        assert_eq!(repeat["result"], first["result"]);
+        assert_eq!(repeat["id"], 2);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_is_scoped_per_caller_and_reserved_in_flight() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            idempotency: idempotency::IdempotencySettings {
+                window_ms: Some(60_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let headers_for = |api_key: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                types::IDEMPOTENCY_KEY_HEADER,
+                HeaderValue::from_static("order-7"),
+            );
+            headers.insert(auth::API_KEY_HEADER, HeaderValue::from_static(api_key));
+            headers
+        };
+        let send = |api_key: &'static str, raw: &'static str| {
+            let state = state.clone();
+            let request = rpc_request("eth_sendRawTransaction", serde_json::json!([raw]));
+            tokio::spawn(async move {
+                handle_rpc_request(State(state), None, headers_for(api_key), body(request)).await
+            })
+        };
+
+        // A retry racing the first call shares its broadcast
+        let (first, retry) = (send("alice", "0x02f8aa"), send("alice", "0x02f8bb"));
+        let (first, retry) = (first.await.unwrap(), retry.await.unwrap());
+        assert_eq!(
+            json_body(first).await["result"],
+            json_body(retry).await["result"]
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Another client reusing the token still gets its own broadcast
+        send("bob", "0x02f8cc").await.unwrap();
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_several_writes_under_one_idempotency_key_is_rejected() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            idempotency: idempotency::IdempotencySettings {
+                window_ms: Some(60_000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            types::IDEMPOTENCY_KEY_HEADER,
+            HeaderValue::from_static("order-7"),
+        );
+        let batch = serde_json::json!([
+            rpc_request("eth_sendRawTransaction", serde_json::json!(["0x02f8aa"])),
+            rpc_request("eth_sendRawTransaction", serde_json::json!(["0x02f8bb"])),
+        ]);
+
+        let response = handle_rpc_request(State(state), None, headers, Json(batch)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_all_unhealthy_falls_back_with_degraded_header() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            selection: load_balancer::SelectionSettings {
+                last_resort_when_all_cooling_down: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(
+            config,
+            &[
+                upstream("Worse", "http://127.0.0.1:1".to_string()),
+                upstream("Node", url),
+            ],
+        )
+        .unwrap();
+        // Worse keeps failing once open, which pushes its cooldown further out
+        for (name, failures) in [("Node", 3), ("Worse", 5)] {
+            let node = state.load_balancer.node_by_name(name).unwrap();
+            for _ in 0..failures {
+                node.force_mark_failure();
+            }
+        }
+
+        let request = rpc_request("eth_blockNumber", serde_json::json!([]));
+        let response =
+            handle_rpc_request(State(state.clone()), None, HeaderMap::new(), body(request)).await;
+
+        assert_eq!(response.headers()[DEGRADED_HEADER], "true");
+        let json = json_body(response).await;
+        assert_eq!(json["result"], "0x10");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(state.cache.get("eth_blockNumber:[]").is_none());
+    }
+
+    #[test]
+    fn test_denied_method_counts_as_client_error() {
+        let counters = RequestCounters::default();
+
+        let denied = Outcome::gateway_error(RpcResponse::error(
+            serde_json::json!(1),
+            -32601,
+            "Method not found".to_string(),
+        ));
+        counters.record(denied.error_class());
+        assert_eq!(counters.errors(ErrorClass::Client), 1);
+        assert_eq!(counters.errors(ErrorClass::Server), 0);
+
+        let unavailable =
+            Outcome::forward_error(serde_json::json!(2), ForwardError::NoHealthyUpstream);
+        counters.record(unavailable.error_class());
+        assert_eq!(counters.errors(ErrorClass::Client), 1);
+        assert_eq!(counters.errors(ErrorClass::Server), 1);
+    }
+
+    #[tokio::test]
+    async fn test_empty_batch_returns_single_invalid_request_error() {
+        let state = build_state(GatewayConfig::default(), &[]).unwrap();
+
+        let response = handle_rpc_request(
+            State(state),
+            None,
+            HeaderMap::new(),
+            Json(serde_json::json!([])),
+        )
+        .await;
+
+        let json = json_body(response).await;
+        assert!(json.is_object(), "{}", json);
+        assert_eq!(json["error"]["code"], -32600);
+        assert_eq!(json["id"], serde_json::Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_batch_answers_each_element_in_order() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let state = build_state(GatewayConfig::default(), &[upstream("Node", url)]).unwrap();
+        state
+            .cache
+            .put("eth_blockNumber:[]".to_string(), serde_json::json!("0x10"));
+
+        let mut chain_id = rpc_request("eth_chainId", serde_json::json!([]));
+        chain_id.id = serde_json::json!(3);
+        let batch = serde_json::json!([
+            rpc_request("eth_blockNumber", serde_json::json!([])),
+            {"jsonrpc": "2.0", "id": 2},
+            chain_id,
+        ]);
+        let response = handle_rpc_request(State(state), None, HeaderMap::new(), Json(batch)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let json = json_body(response).await;
+        let responses = json.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["result"], "0x10");
+        assert_eq!(responses[1]["error"]["code"], -32600);
+        assert_eq!(responses[2]["id"], 3);
+        assert!(responses[2].get("result").is_some());
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_method_filter_blocks_denied_calls_within_a_batch() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            method_filter: method_filter::MethodFilterSettings {
+                deny: vec!["debug_*".to_string(), "personal_*".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+
+        let mut trace = rpc_request("debug_traceTransaction", serde_json::json!(["0xabc"]));
+        trace.id = serde_json::json!(2);
+        let batch = serde_json::json!([
+            rpc_request("eth_getBalance", serde_json::json!(["0x1", "0x10"])),
+            trace,
+        ]);
+        let response = handle_rpc_request(State(state), None, HeaderMap::new(), Json(batch)).await;
+
+        let json = json_body(response).await;
+        let responses = json.as_array().unwrap();
+        assert_eq!(responses[0]["result"], "0x10");
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[1]["error"]["message"], "Method not found");
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_per_element_provenance() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let config = GatewayConfig {
+            debug_headers: true,
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        state
+            .cache
+            .put("eth_blockNumber:[]".to_string(), serde_json::json!("0x10"));
+
+        let batch = serde_json::json!([
+            rpc_request("eth_blockNumber", serde_json::json!([])),
+            rpc_request("eth_chainId", serde_json::json!([])),
+        ]);
+        let response = handle_rpc_request(State(state), None, HeaderMap::new(), Json(batch)).await;
+
+        assert_eq!(
+            response.headers()[PROVENANCE_HEADER],
+            "0=cache,1=upstream:Node"
+        );
+        let json = json_body(response).await;
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_audit_record_written_per_request() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = spawn_counting_upstream(Arc::clone(&calls)).await;
+        let path =
+            std::env::temp_dir().join(format!("ha_gateway_audit_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = GatewayConfig {
+            audit: audit::AuditSettings {
+                sink: Some(audit::AuditSink::File {
+                    path: path.to_string_lossy().into_owned(),
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[upstream("Node", url)]).unwrap();
+        let client: SocketAddr = "192.0.2.7:40000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(auth::API_KEY_HEADER, HeaderValue::from_static("k1"));
+
+        let batch = serde_json::json!([
+            rpc_request("eth_chainId", serde_json::json!([])),
+            rpc_request("eth_getBalance", serde_json::json!(["0xabc", "latest"])),
+            { "jsonrpc": "2.0", "method": "eth_subscribe_ping" },
+        ]);
+        handle_rpc_request(
+            State(state),
+            Some(Extension(ConnectInfo(client))),
+            headers,
+            Json(batch),
+        )
+        .await;
+
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            lines = std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect();
+            if lines.len() == 3 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(lines.len(), 3);
+        let mut methods: Vec<_> = lines.iter().map(|r| r["method"].clone()).collect();
+        methods.sort_by_key(|m| m.to_string());
+        assert_eq!(
+            methods,
+            ["eth_chainId", "eth_getBalance", "eth_subscribe_ping"]
+        );
+        for record in &lines {
+            assert_eq!(record["client_ip"], "192.0.2.7");
+            assert_eq!(record["node"], "Node");
+            assert_eq!(record["outcome"], "ok");
+            assert!(record["api_key"].as_str().unwrap().starts_with("sha256:"));
+            assert!(record.get("params").is_none());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Scheduled maintenance windows.
+//!
+//! Operators can pre-schedule a window during which a node is drained, using
+//! the same drain override as manual maintenance. A background task re-evaluates
+//! the windows every `SCHEDULER_TICK` and drains or restores nodes accordingly.
+//!
+//! - Overlapping windows for the same node simply extend the drained period.
+//! - A node drained by an operator is never restored by the scheduler.
+//! - Draining ignores health, so an already-unhealthy node is drained as usual.
+
+use crate::load_balancer::LoadBalancer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the scheduler re-evaluates the configured windows.
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
+
+/// A period during which one node is taken out of rotation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    /// Name of the node to drain.
+    pub node: String,
+
+    /// Window start, in seconds since the Unix epoch (inclusive).
+    pub start_unix_secs: u64,
+
+    /// Window end, in seconds since the Unix epoch (exclusive).
+    pub end_unix_secs: u64,
+}
+
+impl MaintenanceWindow {
+    fn is_active(&self, now_unix_secs: u64) -> bool {
+        (self.start_unix_secs..self.end_unix_secs).contains(&now_unix_secs)
+    }
+}
+
+/// Applies maintenance windows to the load balancer's nodes.
+pub struct MaintenanceScheduler {
+    load_balancer: Arc<LoadBalancer>,
+    windows: Vec<MaintenanceWindow>,
+
+    /// Nodes this scheduler drained, so it only ever restores its own drains.
+    drained_by_schedule: HashSet<String>,
+}
+
+impl MaintenanceScheduler {
+    pub fn new(load_balancer: Arc<LoadBalancer>, windows: Vec<MaintenanceWindow>) -> Self {
+        for window in &windows {
+            if load_balancer.node_by_name(&window.node).is_none() {
+                tracing::warn!("Maintenance window references unknown node {}", window.node);
+            }
+        }
+        Self {
+            load_balancer,
+            windows,
+            drained_by_schedule: HashSet::new(),
+        }
+    }
+
+    /// Drains nodes entering a window and restores nodes leaving one.
+    fn apply(&mut self, now_unix_secs: u64) {
+        let nodes: HashSet<&str> = self.windows.iter().map(|w| w.node.as_str()).collect();
+        for name in nodes {
+            let Some(node) = self.load_balancer.node_by_name(name) else {
+                continue;
+            };
+            let in_window = self
+                .windows
+                .iter()
+                .any(|w| w.node == name && w.is_active(now_unix_secs));
+            let ours = self.drained_by_schedule.contains(name);
+
+            if in_window && !ours && !node.is_drained() {
+                tracing::info!("Maintenance window started for node {}", name);
+                node.set_drained(true);
+                self.drained_by_schedule.insert(name.to_string());
+            } else if !in_window && ours {
+                tracing::info!("Maintenance window ended for node {}", name);
+                node.set_drained(false);
+                self.drained_by_schedule.remove(name);
+            }
+        }
+    }
+
+    /// Runs the scheduler on a background task for the life of the process.
+    pub fn start(mut self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SCHEDULER_TICK);
+            loop {
+                interval.tick().await;
+                self.apply(unix_now_secs());
+            }
+        });
+    }
+}
+
+fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UpstreamConfig;
+
+    fn load_balancer() -> Arc<LoadBalancer> {
+        let configs: Vec<_> = ["A", "B"]
+            .iter()
+            .map(|name| UpstreamConfig {
+                name: name.to_string(),
+                url: "http://localhost:1".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        Arc::new(LoadBalancer::new(&configs).unwrap())
+    }
+
+    fn window(node: &str, start: u64, end: u64) -> MaintenanceWindow {
+        MaintenanceWindow {
+            node: node.to_string(),
+            start_unix_secs: start,
+            end_unix_secs: end,
+        }
+    }
+
+    #[test]
+    fn test_node_drained_then_restored_on_schedule() {
+        let lb = load_balancer();
+        let mut scheduler = MaintenanceScheduler::new(Arc::clone(&lb), vec![window("A", 100, 110)]);
+        let node = lb.node_by_name("A").unwrap();
+
+        scheduler.apply(99);
+        assert!(!node.is_drained());
+
+        scheduler.apply(100);
+        assert!(node.is_drained());
+        assert_eq!(lb.choose_healthy_node(&[]).unwrap().get_name(), "B");
+
+        scheduler.apply(110);
+        assert!(!node.is_drained());
+    }
+
+    #[test]
+    fn test_overlapping_windows_and_manual_drain() {
+        let lb = load_balancer();
+        let mut scheduler = MaintenanceScheduler::new(
+            Arc::clone(&lb),
+            vec![
+                window("A", 100, 110),
+                window("A", 105, 120),
+                window("B", 100, 110),
+            ],
+        );
+
+        // B was drained by an operator beforehand and must stay drained.
+        lb.node_by_name("B").unwrap().set_drained(true);
+
+        scheduler.apply(100);
+        scheduler.apply(112);
+        assert!(lb.node_by_name("A").unwrap().is_drained());
+        assert!(lb.node_by_name("B").unwrap().is_drained());
+
+        scheduler.apply(120);
+        assert!(!lb.node_by_name("A").unwrap().is_drained());
+        assert!(lb.node_by_name("B").unwrap().is_drained());
+    }
+}
@@ -0,0 +1,40 @@
+//! Read/write classification of JSON-RPC methods.
+//!
+//! Reads can safely be sent twice: retried on another node, hedged, fanned out
+//! or answered from cache. Writes (broadcasting or signing a transaction,
+//! submitting work) cannot: a second broadcast of a raw transaction to another
+//! node can race the first and confuse nonce and mempool tracking. Every
+//! feature that may duplicate or replay a call asks `is_write` first, so writes
+//! take a single attempt on a single node whatever the configuration says.
+
+/// Methods with side effects, which must reach exactly one node.
+const WRITE_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+    "eth_signTransaction",
+    "eth_sign",
+    "eth_submitWork",
+    "eth_submitHashrate",
+    "personal_sendTransaction",
+];
+
+/// Whether a method only reads state or has side effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodClass {
+    Read,
+    Write,
+}
+
+/// Classifies `method`; anything not known to have side effects is a read.
+pub fn classify(method: &str) -> MethodClass {
+    if WRITE_METHODS.contains(&method) {
+        MethodClass::Write
+    } else {
+        MethodClass::Read
+    }
+}
+
+/// Whether `method` has side effects and must never be duplicated or cached.
+pub fn is_write(method: &str) -> bool {
+    classify(method) == MethodClass::Write
+}
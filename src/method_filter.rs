@@ -0,0 +1,71 @@
+//! Gateway-wide allowlist and denylist of JSON-RPC methods.
+//!
+//! Lets a public deployment refuse dangerous or expensive methods before they
+//! reach any node. Patterns are exact method names or a prefix followed by
+//! `*`, e.g. `debug_*`:
+//!
+//! ```json
+//! "method_filter": { "deny": ["eth_sendTransaction", "personal_*", "debug_*", "admin_*"] }
+//! ```
+//!
+//! With a non-empty `allow`, only matching methods are served; `deny` is
+//! applied on top. Blocked calls get the same `-32601 Method not found` an
+//! unsupported method would, so probing cannot tell them apart. The filter is
+//! applied by the default `Authorizer`, so it covers every call of a batch,
+//! notifications (which are dropped) and WebSocket frames alike.
+
+use serde::{Deserialize, Serialize};
+
+/// JSON-RPC error code for an unknown method, returned for blocked ones too.
+pub const METHOD_NOT_FOUND_CODE: i32 = -32601;
+
+/// Methods the gateway serves at all.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MethodFilterSettings {
+    /// Method patterns served; empty allows every method not denied.
+    pub allow: Vec<String>,
+
+    /// Method patterns refused even when allowed.
+    pub deny: Vec<String>,
+}
+
+impl MethodFilterSettings {
+    /// Whether calls to `method` may be served.
+    pub fn permits(&self, method: &str) -> bool {
+        let matches = |patterns: &[String]| patterns.iter().any(|p| pattern_matches(p, method));
+        (self.allow.is_empty() || matches(&self.allow)) && !matches(&self.deny)
+    }
+}
+
+fn pattern_matches(pattern: &str, method: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => method.starts_with(prefix),
+        None => pattern == method,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards_and_allowlist() {
+        let deny = MethodFilterSettings {
+            deny: vec!["debug_*".to_string(), "eth_sendTransaction".to_string()],
+            ..Default::default()
+        };
+        assert!(!deny.permits("debug_traceTransaction"));
+        assert!(!deny.permits("eth_sendTransaction"));
+        assert!(deny.permits("eth_sendRawTransaction"));
+
+        let allow = MethodFilterSettings {
+            allow: vec!["eth_*".to_string(), "net_version".to_string()],
+            deny: vec!["eth_sign*".to_string()],
+        };
+        assert!(allow.permits("eth_call"));
+        assert!(allow.permits("net_version"));
+        assert!(!allow.permits("net_peerCount"));
+        assert!(!allow.permits("eth_signTransaction"));
+    }
+}
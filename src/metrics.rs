@@ -0,0 +1,582 @@
+//! Prometheus text-format metrics and per-minute request time series.
+//!
+//! Node gauges carry the node name plus every configured node label as label
+//! dimensions, so dashboards can slice by region, provider or tier.
+//!
+//! Request counters split errors into client-caused (bad requests, denied
+//! methods, failed auth) and server-caused (upstream failures, timeouts, no
+//! healthy nodes) so an error-rate SLO only tracks the latter. Requests are
+//! also counted per JSON-RPC method, per node outcome, and as cache hits versus
+//! misses. Method names come from clients, so only the first
+//! `MAX_TRACKED_METHODS` distinct ones get their own series; the rest share
+//! `method="other"`.
+//!
+//! For capacity planning, `Timeseries` keeps a ring buffer of per-minute
+//! aggregates (request count, error count, latency percentiles) served as JSON
+//! by `/metrics/timeseries`. Latencies go into a fixed histogram so each bucket
+//! stays the same size regardless of traffic; percentiles report the upper
+//! bound of the histogram bin they fall in.
+//!
+//! With an SLO target configured, rolling success ratios and error budget burn
+//! rates (see `slo`) are exported alongside.
+
+use crate::AppState;
+use crate::cache::CacheStats;
+use crate::load_balancer::NodeStatus;
+use crate::slo::{SloSettings, SloSnapshot};
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds (in milliseconds) of the latency histogram bins.
+const LATENCY_BOUNDS_MS: &[u64] = &[
+    1, 2, 5, 10, 20, 30, 50, 75, 100, 150, 200, 300, 500, 750, 1000, 1500, 2000, 3000, 5000,
+    10_000, 30_000, 60_000,
+];
+
+/// Distinct method names counted individually before falling back to `other`.
+const MAX_TRACKED_METHODS: usize = 256;
+
+/// JSON-RPC error codes that blame the caller rather than the gateway or upstream.
+const CLIENT_ERROR_CODES: &[i32] = &[-32700, -32600, -32601, -32602];
+
+/// Who caused a failed request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    /// Invalid or denied requests; not an outage.
+    Client,
+
+    /// Upstream failures, timeouts, overload or no healthy nodes.
+    Server,
+}
+
+impl ErrorClass {
+    /// Classifies a JSON-RPC error returned to the client.
+    ///
+    /// `gateway_failed` is set when the gateway could not get an answer from any
+    /// upstream, which is a server error unless the request itself was malformed.
+    pub fn of_rpc_error(code: i32, gateway_failed: bool) -> Self {
+        if CLIENT_ERROR_CODES.contains(&code) {
+            ErrorClass::Client
+        } else if gateway_failed || code == -32603 {
+            ErrorClass::Server
+        } else {
+            // Reverts, nonce errors and the like are about the caller's input
+            ErrorClass::Client
+        }
+    }
+
+    /// Classifies an HTTP-level rejection.
+    pub fn of_status(status: StatusCode) -> Self {
+        if status.is_client_error() {
+            ErrorClass::Client
+        } else {
+            ErrorClass::Server
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorClass::Client => "client",
+            ErrorClass::Server => "server",
+        }
+    }
+}
+
+/// Request totals, with errors broken down by class.
+#[derive(Default)]
+pub struct RequestCounters {
+    total: AtomicU64,
+    client_errors: AtomicU64,
+    server_errors: AtomicU64,
+    by_method: Mutex<HashMap<String, u64>>,
+}
+
+impl RequestCounters {
+    /// Counts one request, and its error class if it failed.
+    pub fn record(&self, error: Option<ErrorClass>) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        match error {
+            Some(ErrorClass::Client) => self.client_errors.fetch_add(1, Ordering::Relaxed),
+            Some(ErrorClass::Server) => self.server_errors.fetch_add(1, Ordering::Relaxed),
+            None => 0,
+        };
+    }
+
+    /// Counts one request for `method`.
+    pub fn record_method(&self, method: &str) {
+        let mut by_method = self.by_method.lock();
+        let key = if by_method.contains_key(method) || by_method.len() < MAX_TRACKED_METHODS {
+            method
+        } else {
+            "other"
+        };
+        *by_method.entry(key.to_string()).or_default() += 1;
+    }
+
+    pub fn errors(&self, class: ErrorClass) -> u64 {
+        match class {
+            ErrorClass::Client => self.client_errors.load(Ordering::Relaxed),
+            ErrorClass::Server => self.server_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Metrics configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsSettings {
+    /// Number of per-minute buckets kept for `/metrics/timeseries`.
+    pub timeseries_minutes: usize,
+
+    /// Availability SLO whose burn rate is exposed in `/metrics`.
+    pub slo: SloSettings,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self {
+            timeseries_minutes: 60,
+            slo: SloSettings::default(),
+        }
+    }
+}
+
+/// Aggregates for one wall-clock minute.
+struct MinuteBucket {
+    /// Minutes since the Unix epoch.
+    minute: u64,
+    count: u64,
+    errors: u64,
+    /// One counter per `LATENCY_BOUNDS_MS` bin, plus an overflow bin.
+    latency_bins: Vec<u64>,
+}
+
+impl MinuteBucket {
+    fn new(minute: u64) -> Self {
+        Self {
+            minute,
+            count: 0,
+            errors: 0,
+            latency_bins: vec![0; LATENCY_BOUNDS_MS.len() + 1],
+        }
+    }
+
+    /// Upper bound of the bin containing the `q` quantile, in milliseconds.
+    fn percentile_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bin, &n) in self.latency_bins.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                // The overflow bin reports the largest finite bound
+                let bound = LATENCY_BOUNDS_MS.get(bin).or(LATENCY_BOUNDS_MS.last());
+                return bound.copied();
+            }
+        }
+        LATENCY_BOUNDS_MS.last().copied()
+    }
+}
+
+/// One minute of aggregates as served by `/metrics/timeseries`.
+#[derive(Debug, Serialize)]
+pub struct MinuteSummary {
+    pub minute_start_unix: u64,
+    pub count: u64,
+    pub errors: u64,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+}
+
+/// Rolling per-minute request aggregates.
+pub struct Timeseries {
+    capacity: usize,
+    buckets: Mutex<VecDeque<MinuteBucket>>,
+}
+
+impl Timeseries {
+    pub fn new(settings: &MetricsSettings) -> Self {
+        Self {
+            capacity: settings.timeseries_minutes.max(1),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records one request in the current minute.
+    pub fn record(&self, latency: Duration, error: bool) {
+        let minute = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / 60;
+        self.record_at(minute, latency, error);
+    }
+
+    fn record_at(&self, minute: u64, latency: Duration, error: bool) {
+        let mut buckets = self.buckets.lock();
+        if buckets.back().is_none_or(|b| b.minute < minute) {
+            buckets.push_back(MinuteBucket::new(minute));
+            while buckets.len() > self.capacity {
+                buckets.pop_front();
+            }
+        }
+        // Late records for a minute that already rolled over land in the newest bucket
+        let Some(bucket) = buckets.back_mut() else {
+            return;
+        };
+
+        let ms = latency.as_millis() as u64;
+        let bin = LATENCY_BOUNDS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(LATENCY_BOUNDS_MS.len());
+        bucket.latency_bins[bin] += 1;
+        bucket.count += 1;
+        if error {
+            bucket.errors += 1;
+        }
+    }
+
+    /// Returns the retained minutes, oldest first.
+    pub fn snapshot(&self) -> Vec<MinuteSummary> {
+        self.buckets
+            .lock()
+            .iter()
+            .map(|bucket| MinuteSummary {
+                minute_start_unix: bucket.minute * 60,
+                count: bucket.count,
+                errors: bucket.errors,
+                p50_ms: bucket.percentile_ms(0.50),
+                p95_ms: bucket.percentile_ms(0.95),
+            })
+            .collect()
+    }
+}
+
+/// Serves the per-minute request aggregates as JSON.
+pub async fn timeseries_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(serde_json::json!({ "minutes": state.timeseries.snapshot() }))
+}
+
+/// Serves the current metrics in the Prometheus exposition format.
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let body = render(
+        &state.load_balancer.get_nodes_status(),
+        &state.request_counters,
+        state.cache.stats(),
+        state.load_balancer.retry_budget_remaining(),
+        state.slo.snapshot(),
+    );
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+pub(crate) fn render(
+    nodes: &[NodeStatus],
+    counters: &RequestCounters,
+    cache: CacheStats,
+    retry_budget_remaining: Option<u64>,
+    slo: Option<SloSnapshot>,
+) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP ha_gateway_requests_total JSON-RPC requests received.\n");
+    out.push_str("# TYPE ha_gateway_requests_total counter\n");
+    let _ = writeln!(
+        out,
+        "ha_gateway_requests_total {}",
+        counters.total.load(Ordering::Relaxed)
+    );
+    out.push_str("# HELP ha_gateway_request_errors_total Failed requests by error class.\n");
+    out.push_str("# TYPE ha_gateway_request_errors_total counter\n");
+    for class in [ErrorClass::Client, ErrorClass::Server] {
+        let _ = writeln!(
+            out,
+            "ha_gateway_request_errors_total{{class=\"{}\"}} {}",
+            class.label(),
+            counters.errors(class)
+        );
+    }
+
+    out.push_str("# HELP ha_gateway_method_requests_total JSON-RPC requests by method.\n");
+    out.push_str("# TYPE ha_gateway_method_requests_total counter\n");
+    let mut by_method: Vec<_> = counters
+        .by_method
+        .lock()
+        .iter()
+        .map(|(method, count)| (method.clone(), *count))
+        .collect();
+    by_method.sort();
+    for (method, count) in by_method {
+        let _ = writeln!(
+            out,
+            "ha_gateway_method_requests_total{{method=\"{}\"}} {}",
+            escape_value(&method),
+            count
+        );
+    }
+
+    out.push_str("# HELP ha_gateway_cache_lookups_total Response cache lookups by result.\n");
+    out.push_str("# TYPE ha_gateway_cache_lookups_total counter\n");
+    let _ = writeln!(
+        out,
+        "ha_gateway_cache_lookups_total{{result=\"hit\"}} {}",
+        cache.hits
+    );
+    let _ = writeln!(
+        out,
+        "ha_gateway_cache_lookups_total{{result=\"miss\"}} {}",
+        cache.misses
+    );
+
+    if let Some(remaining) = retry_budget_remaining {
+        out.push_str(
+            "# HELP ha_gateway_retry_budget_remaining Retries left in the rolling window.\n",
+        );
+        out.push_str("# TYPE ha_gateway_retry_budget_remaining gauge\n");
+        let _ = writeln!(out, "ha_gateway_retry_budget_remaining {}", remaining);
+    }
+
+    if let Some(slo) = slo {
+        out.push_str(
+            "# HELP ha_gateway_slo_success_ratio Requests without a server error, by window.\n",
+        );
+        out.push_str("# TYPE ha_gateway_slo_success_ratio gauge\n");
+        for window in &slo.windows {
+            let _ = writeln!(
+                out,
+                "ha_gateway_slo_success_ratio{{window=\"{}m\"}} {}",
+                window.minutes, window.success_ratio
+            );
+        }
+        out.push_str("# HELP ha_gateway_slo_burn_rate Error budget burn rate, by window.\n");
+        out.push_str("# TYPE ha_gateway_slo_burn_rate gauge\n");
+        for window in &slo.windows {
+            let _ = writeln!(
+                out,
+                "ha_gateway_slo_burn_rate{{window=\"{}m\"}} {}",
+                window.minutes, window.burn_rate
+            );
+        }
+        out.push_str(
+            "# HELP ha_gateway_slo_burn_rate_alerting Whether the burn rate alert is active.\n",
+        );
+        out.push_str("# TYPE ha_gateway_slo_burn_rate_alerting gauge\n");
+        let _ = writeln!(
+            out,
+            "ha_gateway_slo_burn_rate_alerting {}",
+            u8::from(slo.alerting)
+        );
+        out.push_str("# HELP ha_gateway_slo_burn_rate_alerts_total Burn rate alerts raised.\n");
+        out.push_str("# TYPE ha_gateway_slo_burn_rate_alerts_total counter\n");
+        let _ = writeln!(out, "ha_gateway_slo_burn_rate_alerts_total {}", slo.alerts);
+    }
+
+    out.push_str("# HELP ha_gateway_upstream_up Whether the upstream node is healthy.\n");
+    out.push_str("# TYPE ha_gateway_upstream_up gauge\n");
+    for node in nodes {
+        let up = u8::from(node.status == "HEALTHY");
+        let _ = writeln!(out, "ha_gateway_upstream_up{} {}", node_labels(node), up);
+    }
+
+    out.push_str(
+        "# HELP ha_gateway_upstream_requests_total Client requests sent to the node by outcome.\n",
+    );
+    out.push_str("# TYPE ha_gateway_upstream_requests_total counter\n");
+    for node in nodes {
+        // Reopen the node's label set to append the outcome
+        let labels = node_labels(node);
+        let labels = labels.strip_suffix('}').unwrap_or(&labels);
+        for (outcome, count) in [("success", node.successes), ("failure", node.failures)] {
+            let _ = writeln!(
+                out,
+                "ha_gateway_upstream_requests_total{},outcome=\"{}\"}} {}",
+                labels, outcome, count
+            );
+        }
+    }
+    out
+}
+
+/// Formats `{node="...",<label>="..."}` with labels in a stable order.
+fn node_labels(node: &NodeStatus) -> String {
+    let mut labels: Vec<_> = node.labels.iter().collect();
+    labels.sort();
+
+    let mut out = format!("{{node=\"{}\"", escape_value(&node.name));
+    for (key, value) in labels {
+        let key = sanitize_name(key);
+        if key == "node" {
+            // Never let a configured label shadow the node name
+            continue;
+        }
+        let _ = write!(out, ",{}=\"{}\"", key, escape_value(value));
+    }
+    out.push('}');
+    out
+}
+
+/// Maps a label key onto the `[a-zA-Z_][a-zA-Z0-9_]*` charset Prometheus accepts.
+fn sanitize_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn escape_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_node_labels_become_metric_label_dimensions() {
+        let nodes = vec![NodeStatus {
+            name: "Node1".to_string(),
+            status: "HEALTHY",
+            condition: crate::upstream::NodeCondition::Healthy,
+            labels: HashMap::from([
+                ("region".to_string(), "eu-west".to_string()),
+                ("provider".to_string(), "acme \"cloud\"".to_string()),
+                ("cost-tier".to_string(), "1".to_string()),
+            ]),
+            successes: 0,
+            failures: 0,
+            recent_successes: 0,
+            recent_failures: 0,
+            consecutive_failures: 0,
+            last_failure_unix_ms: None,
+            latest_block: None,
+            avg_latency_ms: None,
+            upgrading: false,
+            tier: 0,
+            recovery_in_secs: None,
+        }];
+
+        let output = render(
+            &nodes,
+            &RequestCounters::default(),
+            CacheStats::default(),
+            None,
+            None,
+        );
+
+        assert!(output.contains(
+            "ha_gateway_upstream_up{node=\"Node1\",cost_tier=\"1\",provider=\"acme \\\"cloud\\\"\",region=\"eu-west\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_render_reports_methods_node_outcomes_and_cache() {
+        let nodes = vec![NodeStatus {
+            name: "Node1".to_string(),
+            status: "HEALTHY",
+            condition: crate::upstream::NodeCondition::Healthy,
+            labels: HashMap::new(),
+            successes: 7,
+            failures: 2,
+            recent_successes: 7,
+            recent_failures: 2,
+            consecutive_failures: 0,
+            last_failure_unix_ms: None,
+            latest_block: None,
+            avg_latency_ms: None,
+            upgrading: false,
+            tier: 0,
+            recovery_in_secs: None,
+        }];
+        let counters = RequestCounters::default();
+        for method in ["eth_call", "eth_blockNumber", "eth_call"] {
+            counters.record(None);
+            counters.record_method(method);
+        }
+
+        let output = render(
+            &nodes,
+            &counters,
+            CacheStats {
+                hits: 4,
+                misses: 1,
+                ..Default::default()
+            },
+            None,
+            None,
+        );
+
+        for line in [
+            "ha_gateway_requests_total 3",
+            "ha_gateway_method_requests_total{method=\"eth_blockNumber\"} 1",
+            "ha_gateway_method_requests_total{method=\"eth_call\"} 2",
+            "ha_gateway_cache_lookups_total{result=\"hit\"} 4",
+            "ha_gateway_cache_lookups_total{result=\"miss\"} 1",
+            "ha_gateway_upstream_requests_total{node=\"Node1\",outcome=\"success\"} 7",
+            "ha_gateway_upstream_requests_total{node=\"Node1\",outcome=\"failure\"} 2",
+        ] {
+            assert!(output.contains(line), "missing {:?} in\n{}", line, output);
+        }
+    }
+
+    #[test]
+    fn test_method_series_are_capped() {
+        let counters = RequestCounters::default();
+        for i in 0..MAX_TRACKED_METHODS + 5 {
+            counters.record_method(&format!("m{}", i));
+        }
+        counters.record_method("m0");
+
+        let by_method = counters.by_method.lock();
+        assert_eq!(by_method.len(), MAX_TRACKED_METHODS + 1);
+        assert_eq!(by_method["other"], 5);
+        assert_eq!(by_method["m0"], 2);
+    }
+
+    #[test]
+    fn test_timeseries_buckets_roll_over_by_minute() {
+        let series = Timeseries::new(&MetricsSettings {
+            timeseries_minutes: 2,
+            ..Default::default()
+        });
+
+        for ms in [5, 8, 40, 90] {
+            series.record_at(100, Duration::from_millis(ms), false);
+        }
+        series.record_at(100, Duration::from_millis(900), true);
+        series.record_at(101, Duration::from_millis(3), false);
+
+        let minutes = series.snapshot();
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].minute_start_unix, 6000);
+        assert_eq!((minutes[0].count, minutes[0].errors), (5, 1));
+        assert_eq!(minutes[0].p50_ms, Some(50));
+        assert_eq!(minutes[0].p95_ms, Some(1000));
+        assert_eq!((minutes[1].count, minutes[1].errors), (1, 0));
+        assert_eq!(minutes[1].p50_ms, Some(5));
+
+        // Only the configured number of minutes is retained.
+        series.record_at(102, Duration::from_millis(1), false);
+        let minutes = series.snapshot();
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].minute_start_unix, 6060);
+    }
+}
@@ -0,0 +1,175 @@
+//! Load shedding driven by Tokio event-loop lag.
+//!
+//! A background task sleeps for a fixed interval and measures how late it
+//! wakes up. When the runtime is saturated, timers fire late; once the observed
+//! lag crosses `lag_threshold_ms` the gateway rejects low-priority requests
+//! with a 503 instead of letting latency spiral for everyone.
+//!
+//! Independently, `max_in_flight` caps how many requests the gateway handles
+//! at once, so a traffic spike cannot pile unbounded load onto the upstreams.
+//! A request over the cap waits up to `in_flight_wait_ms` for a slot and is
+//! otherwise answered with a 503 and `Retry-After`, pushing back on clients
+//! instead of letting the upstreams fail one after another.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+/// Overload shedding configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverloadSettings {
+    /// Enables the lag monitor and shedding.
+    pub enabled: bool,
+
+    /// How often the monitor samples event-loop lag.
+    pub sample_interval_ms: u64,
+
+    /// Lag above which low-priority requests are shed.
+    pub lag_threshold_ms: u64,
+
+    /// Methods that are never shed; everything else is low priority.
+    pub high_priority_methods: Vec<String>,
+
+    /// Requests handled at once across the gateway. 0 disables the limit.
+    pub max_in_flight: usize,
+
+    /// How long a request over `max_in_flight` waits for a slot before it is
+    /// rejected.
+    pub in_flight_wait_ms: u64,
+}
+
+impl Default for OverloadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_interval_ms: 100,
+            lag_threshold_ms: 200,
+            high_priority_methods: vec![
+                "eth_sendRawTransaction".to_string(),
+                "eth_sendTransaction".to_string(),
+            ],
+            max_in_flight: 0,
+            in_flight_wait_ms: 100,
+        }
+    }
+}
+
+/// Measures event-loop lag and decides whether to shed a request.
+pub struct LagMonitor {
+    settings: OverloadSettings,
+
+    /// Lag observed by the most recent sample, in milliseconds.
+    current_lag_ms: AtomicU64,
+}
+
+impl LagMonitor {
+    pub fn new(settings: OverloadSettings) -> Self {
+        Self {
+            settings,
+            current_lag_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts sampling lag on a background task (no-op when disabled).
+    pub fn start(self: Arc<Self>) {
+        if !self.settings.enabled {
+            return;
+        }
+        tokio::spawn(async move {
+            let interval = Duration::from_millis(self.settings.sample_interval_ms.max(1));
+            let mut expected = Instant::now() + interval;
+            loop {
+                tokio::time::sleep_until(expected).await;
+                let now = Instant::now();
+                let lag = now.saturating_duration_since(expected).as_millis() as u64;
+                let previous = self.current_lag_ms.swap(lag, Ordering::Relaxed);
+                let threshold = self.settings.lag_threshold_ms;
+                if lag > threshold && previous <= threshold {
+                    tracing::warn!("Event-loop lag {}ms exceeds threshold, shedding load", lag);
+                } else if lag <= threshold && previous > threshold {
+                    tracing::info!("Event-loop lag back to {}ms, no longer shedding", lag);
+                }
+                expected = now + interval;
+            }
+        });
+    }
+
+    /// Returns true if a request for `method` should be rejected right now.
+    pub fn should_shed(&self, method: &str) -> bool {
+        self.settings.enabled
+            && self.current_lag_ms.load(Ordering::Relaxed) > self.settings.lag_threshold_ms
+            && !self
+                .settings
+                .high_priority_methods
+                .iter()
+                .any(|m| m == method)
+    }
+}
+
+/// Returned when no in-flight slot freed up in time.
+#[derive(Debug)]
+pub struct AtCapacity;
+
+/// Gateway-wide limit on requests handled at once.
+pub struct ConcurrencyLimiter {
+    /// `None` when `max_in_flight` is 0.
+    slots: Option<Semaphore>,
+    wait: Duration,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(settings: &OverloadSettings) -> Self {
+        Self {
+            slots: (settings.max_in_flight > 0).then(|| Semaphore::new(settings.max_in_flight)),
+            wait: Duration::from_millis(settings.in_flight_wait_ms),
+        }
+    }
+
+    /// Takes a slot, waiting up to `in_flight_wait_ms` for one. The slot is
+    /// released when the returned permit is dropped; without a limit there is
+    /// no permit to hold.
+    pub async fn acquire(&self) -> Result<Option<SemaphorePermit<'_>>, AtCapacity> {
+        let Some(slots) = &self.slots else {
+            return Ok(None);
+        };
+        match tokio::time::timeout(self.wait, slots.acquire()).await {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            // The semaphore is never closed, so only the timeout can fail
+            _ => Err(AtCapacity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_blocked_runtime_triggers_shedding() {
+        let monitor = Arc::new(LagMonitor::new(OverloadSettings {
+            enabled: true,
+            sample_interval_ms: 10,
+            lag_threshold_ms: 50,
+            ..Default::default()
+        }));
+        Arc::clone(&monitor).start();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!monitor.should_shed("eth_call"));
+
+        // Hog the only runtime thread so the sampler wakes up late.
+        std::thread::sleep(Duration::from_millis(200));
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        tokio::task::yield_now().await;
+
+        assert!(monitor.should_shed("eth_call"));
+        assert!(!monitor.should_shed("eth_sendRawTransaction"));
+
+        // Once the runtime breathes again, shedding stops.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!monitor.should_shed("eth_call"));
+    }
+}
@@ -0,0 +1,113 @@
+//! Per-method default params filled in before forwarding.
+//!
+//! Some providers insist on params that the JSON-RPC spec makes optional, such
+//! as an explicit `fromBlock` in `eth_getLogs` or the `fullTransactions` flag of
+//! `eth_getBlockByNumber`. `param_defaults` maps a method to positional
+//! defaults: a missing position gets its default, and when both the default and
+//! the client's param are objects, missing keys are copied over. A `null`
+//! default leaves its position alone. Requests with by-name (object) params are
+//! forwarded untouched.
+//!
+//! ```json
+//! "param_defaults": {
+//!   "eth_getBlockByNumber": [null, false],
+//!   "eth_getLogs": [{ "fromBlock": "earliest" }]
+//! }
+//! ```
+
+use crate::types::RpcRequest;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Returns `request` with configured defaults filled in, or `None` if nothing
+/// was missing.
+pub fn apply(defaults: &HashMap<String, Vec<Value>>, request: &RpcRequest) -> Option<RpcRequest> {
+    let defaults = defaults.get(&request.method)?;
+    let mut params = match &request.params {
+        Value::Array(params) => params.clone(),
+        Value::Null => Vec::new(),
+        _ => return None,
+    };
+
+    let mut changed = false;
+    for (position, default) in defaults.iter().enumerate() {
+        if default.is_null() {
+            continue;
+        }
+        while params.len() < position {
+            params.push(Value::Null);
+        }
+        match params.get_mut(position) {
+            None => {
+                params.push(default.clone());
+                changed = true;
+            }
+            Some(Value::Object(param)) => {
+                let Value::Object(default) = default else {
+                    continue;
+                };
+                for (key, value) in default {
+                    if !param.contains_key(key) {
+                        param.insert(key.clone(), value.clone());
+                        changed = true;
+                    }
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    changed.then(|| RpcRequest {
+        params: Value::Array(params),
+        ..request.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(method: &str, params: Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: json!(1),
+        }
+    }
+
+    #[test]
+    fn test_missing_positions_and_object_keys_are_filled() {
+        let defaults = HashMap::from([
+            (
+                "eth_getBlockByNumber".to_string(),
+                vec![Value::Null, json!(false)],
+            ),
+            (
+                "eth_getLogs".to_string(),
+                vec![json!({ "fromBlock": "earliest" })],
+            ),
+        ]);
+
+        let filled = apply(
+            &defaults,
+            &request("eth_getBlockByNumber", json!(["latest"])),
+        );
+        assert_eq!(filled.unwrap().params, json!(["latest", false]));
+
+        let filled = apply(
+            &defaults,
+            &request("eth_getLogs", json!([{ "address": "0xabc" }])),
+        );
+        assert_eq!(
+            filled.unwrap().params,
+            json!([{ "address": "0xabc", "fromBlock": "earliest" }])
+        );
+
+        // Explicit client values win, and unlisted methods are untouched
+        let explicit = request("eth_getBlockByNumber", json!(["latest", true]));
+        assert!(apply(&defaults, &explicit).is_none());
+        assert!(apply(&defaults, &request("eth_call", json!([]))).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+//! Request/response transformation plugins loaded from WebAssembly.
+//!
+//! Available with the `wasm-plugins` cargo feature. A plugin is a WASM module
+//! (binary or WAT text) exporting:
+//!
+//! - `memory`: the linear memory used to exchange payloads
+//! - `alloc(len: i32) -> i32`: returns a pointer to `len` writable bytes
+//! - `transform_request(ptr: i32, len: i32) -> i64` (optional)
+//! - `transform_response(ptr: i32, len: i32) -> i64` (optional)
+//!
+//! Transform functions receive a serialized JSON-RPC request/response and return
+//! the transformed JSON packed as `(ptr << 32) | len`. Each call runs in a fresh
+//! instance so plugins cannot leak state between requests.
+//!
+//! Without the feature, `PluginHost` is a passthrough and configuring a plugin
+//! is a startup error.
+
+use crate::types::{RpcRequest, RpcResponse};
+use std::borrow::Cow;
+
+/// Applies the configured plugin (if any) around upstream forwarding.
+pub struct PluginHost {
+    #[cfg(feature = "wasm-plugins")]
+    plugin: Option<wasm::WasmPlugin>,
+}
+
+impl PluginHost {
+    /// Loads the plugin at `path`, or creates a passthrough host when `None`.
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        #[cfg(feature = "wasm-plugins")]
+        {
+            let plugin = path.map(wasm::WasmPlugin::load).transpose()?;
+            Ok(Self { plugin })
+        }
+
+        #[cfg(not(feature = "wasm-plugins"))]
+        match path {
+            Some(path) => Err(format!(
+                "WASM plugin {} configured but the gateway was built without the wasm-plugins feature",
+                path
+            )),
+            None => Ok(Self {}),
+        }
+    }
+
+    /// Runs the plugin's request transform, borrowing the input when there is none.
+    pub fn transform_request<'a>(
+        &self,
+        request: &'a RpcRequest,
+    ) -> Result<Cow<'a, RpcRequest>, String> {
+        #[cfg(feature = "wasm-plugins")]
+        if let Some(plugin) = &self.plugin {
+            let bytes = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+            if let Some(out) = plugin.call("transform_request", &bytes)? {
+                let transformed = serde_json::from_slice(&out)
+                    .map_err(|e| format!("Plugin returned an invalid request: {}", e))?;
+                return Ok(Cow::Owned(transformed));
+            }
+        }
+        Ok(Cow::Borrowed(request))
+    }
+
+    /// Runs the plugin's response transform, keeping the gateway-side metadata.
+    pub fn transform_response(&self, response: RpcResponse) -> Result<RpcResponse, String> {
+        #[cfg(feature = "wasm-plugins")]
+        if let Some(plugin) = &self.plugin {
+            let bytes = serde_json::to_vec(&response).map_err(|e| e.to_string())?;
+            if let Some(out) = plugin.call("transform_response", &bytes)? {
+                let mut transformed: RpcResponse = serde_json::from_slice(&out)
+                    .map_err(|e| format!("Plugin returned an invalid response: {}", e))?;
+                transformed.meta = response.meta;
+                return Ok(transformed);
+            }
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(feature = "wasm-plugins")]
+mod wasm {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    /// A compiled plugin module, instantiated afresh for each call.
+    pub struct WasmPlugin {
+        engine: Engine,
+        module: Module,
+    }
+
+    impl WasmPlugin {
+        pub fn load(path: &str) -> Result<Self, String> {
+            let engine = Engine::default();
+            let module = Module::from_file(&engine, path)
+                .map_err(|e| format!("Failed to load WASM plugin {}: {}", path, e))?;
+            tracing::info!("Loaded WASM plugin from {}", path);
+            Ok(Self { engine, module })
+        }
+
+        #[cfg(test)]
+        pub fn from_source(source: &str) -> Result<Self, String> {
+            let engine = Engine::default();
+            let module = Module::new(&engine, source).map_err(|e| e.to_string())?;
+            Ok(Self { engine, module })
+        }
+
+        /// Calls `export` with `input`, returning `None` if the plugin doesn't export it.
+        pub fn call(&self, export: &str, input: &[u8]) -> Result<Option<Vec<u8>>, String> {
+            let mut store = Store::new(&self.engine, ());
+            let instance = Instance::new(&mut store, &self.module, &[])
+                .map_err(|e| format!("Failed to instantiate WASM plugin: {}", e))?;
+            let Some(func) = instance.get_func(&mut store, export) else {
+                return Ok(None);
+            };
+            let func = func
+                .typed::<(i32, i32), i64>(&store)
+                .map_err(|e| format!("Plugin export {} has the wrong signature: {}", export, e))?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or("Plugin does not export memory")?;
+            let alloc = instance
+                .get_typed_func::<i32, i32>(&mut store, "alloc")
+                .map_err(|e| format!("Plugin does not export alloc: {}", e))?;
+
+            let len = i32::try_from(input.len()).map_err(|_| "Payload too large for plugin")?;
+            let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+            memory
+                .write(&mut store, ptr as u32 as usize, input)
+                .map_err(|e| e.to_string())?;
+
+            let packed = func
+                .call(&mut store, (ptr, len))
+                .map_err(|e| format!("Plugin {} trapped: {}", export, e))?;
+            let out_ptr = (packed as u64 >> 32) as usize;
+            let out_len = (packed as u64 & 0xFFFF_FFFF) as usize;
+            let mut output = vec![0u8; out_len];
+            memory
+                .read(&store, out_ptr, &mut output)
+                .map_err(|e| e.to_string())?;
+            Ok(Some(output))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "wasm-plugins"))]
+mod tests {
+    use super::*;
+
+    /// Echoes its input back unchanged.
+    const IDENTITY_PLUGIN: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (func $identity (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len))))
+          (export "transform_request" (func $identity))
+          (export "transform_response" (func $identity)))
+    "#;
+
+    #[test]
+    fn test_identity_plugin_round_trips_request_and_response() {
+        let host = PluginHost {
+            plugin: Some(wasm::WasmPlugin::from_source(IDENTITY_PLUGIN).unwrap()),
+        };
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_getBalance".to_string(),
+            params: serde_json::json!(["0xabc", "latest"]),
+            id: serde_json::json!(7),
+        };
+
+        let transformed = host.transform_request(&request).unwrap();
+        assert!(matches!(transformed, Cow::Owned(_)));
+        assert_eq!(transformed.method, "eth_getBalance");
+        assert_eq!(transformed.params, request.params);
+
+        let response = RpcResponse::success(serde_json::json!(7), serde_json::json!("0x10"));
+        let transformed = host.transform_response(response).unwrap();
+        assert_eq!(transformed.result, Some(serde_json::json!("0x10")));
+    }
+}
@@ -0,0 +1,99 @@
+//! Cache prewarming at startup.
+//!
+//! Configured requests are issued once through the normal request path before
+//! the gateway starts listening, so the first clients after a deploy hit a warm
+//! cache. Startup waits for every prewarm request to finish, so each one can
+//! delay listening by up to its request timeout; failures are logged and
+//! skipped rather than stopping the gateway.
+
+use crate::types::{RequestContext, RpcRequest};
+use crate::{AppState, process_request};
+use axum::http::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+/// A request issued at startup to populate the cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrewarmRequest {
+    pub method: String,
+
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+/// Runs every configured prewarm request, returning how many results were cached.
+pub async fn prewarm_cache(state: &AppState) -> usize {
+    let ctx = RequestContext::internal();
+    let headers = HeaderMap::new();
+    let mut warmed = 0;
+
+    for (index, entry) in state.config.prewarm.iter().enumerate() {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: entry.method.clone(),
+            params: entry.params.clone(),
+            id: serde_json::json!(index),
+        };
+
+        let outcome = process_request(state, &headers, &request, &ctx).await;
+        if let Some(error) = &outcome.response.error {
+            tracing::warn!("Prewarm of {} failed: {}", entry.method, error.message);
+        } else if !outcome.cacheable {
+            tracing::warn!("Prewarm of {} skipped: method is not cached", entry.method);
+        } else {
+            warmed += 1;
+        }
+    }
+
+    tracing::info!(
+        "Prewarmed {} of {} cache entries",
+        warmed,
+        state.config.prewarm.len()
+    );
+    warmed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_state;
+    use crate::config::GatewayConfig;
+    use crate::test_util::spawn_mock_upstream;
+    use crate::types::{RpcResponse, UpstreamConfig};
+    use axum::{Json, Router, routing::post};
+
+    #[tokio::test]
+    async fn test_prewarm_populates_cache() {
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(req.id, serde_json::json!("0x2a")))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let config = GatewayConfig {
+            prewarm: vec![
+                PrewarmRequest {
+                    method: "eth_blockNumber".to_string(),
+                    params: serde_json::json!([]),
+                },
+                PrewarmRequest {
+                    method: "eth_getBalance".to_string(),
+                    params: serde_json::json!(["0xabc", "latest"]),
+                },
+            ],
+            ..Default::default()
+        };
+        let node = UpstreamConfig {
+            name: "Node".to_string(),
+            url,
+            ..Default::default()
+        };
+        let state = build_state(config, &[node]).unwrap();
+
+        assert_eq!(prewarm_cache(&state).await, 1);
+        let key = state
+            .cache
+            .key_for("eth_blockNumber", &serde_json::json!([]));
+        assert_eq!(state.cache.get(&key), Some(serde_json::json!("0x2a")));
+    }
+}
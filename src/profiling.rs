@@ -0,0 +1,154 @@
+//! On-demand CPU profiling for diagnosing latency regressions.
+//!
+//! `GET /debug/profile?seconds=N` samples the process for `N` seconds and
+//! returns the profile in pprof's protobuf format, readable by
+//! `go tool pprof` and compatible viewers.
+//!
+//! Sampling needs the `profiling` cargo feature, and the endpoint additionally
+//! requires `debug_profiling` in the configuration plus the admin token, so a
+//! production build never profiles by accident.
+
+use crate::AppState;
+use crate::admin;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Profile length when `seconds` is not given.
+const DEFAULT_PROFILE_SECS: u64 = 10;
+
+/// Longest profile a single request may ask for.
+const MAX_PROFILE_SECS: u64 = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileParams {
+    seconds: Option<u64>,
+}
+
+/// Captures a CPU profile and returns it as a pprof protobuf.
+pub async fn profile_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ProfileParams>,
+) -> Response {
+    if let Err(rejection) = admin::authorize(&state, &headers) {
+        return rejection.into_response();
+    }
+    if !state.config.debug_profiling {
+        return (StatusCode::NOT_FOUND, "Profiling disabled").into_response();
+    }
+
+    let seconds = params
+        .seconds
+        .unwrap_or(DEFAULT_PROFILE_SECS)
+        .clamp(1, MAX_PROFILE_SECS);
+    tracing::info!("Capturing {}s CPU profile", seconds);
+
+    match capture(Duration::from_secs(seconds)).await {
+        Ok(profile) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            profile,
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("CPU profile failed: {}", e);
+            (StatusCode::NOT_IMPLEMENTED, e).into_response()
+        }
+    }
+}
+
+/// Samples the process for `duration` and encodes the result as pprof protobuf.
+#[cfg(feature = "profiling")]
+async fn capture(duration: Duration) -> Result<Vec<u8>, String> {
+    use pprof::protos::Message;
+
+    tokio::task::spawn_blocking(move || {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(100)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| format!("Failed to start profiler: {}", e))?;
+        std::thread::sleep(duration);
+        let profile = guard
+            .report()
+            .build()
+            .and_then(|report| report.pprof())
+            .map_err(|e| format!("Failed to build profile: {}", e))?;
+        Ok(profile.encode_to_vec())
+    })
+    .await
+    .map_err(|e| format!("Profiler task failed: {}", e))?
+}
+
+/// Stand-in profile for tests of builds without the profiler.
+#[cfg(all(test, not(feature = "profiling")))]
+async fn capture(_duration: Duration) -> Result<Vec<u8>, String> {
+    Ok(b"mock profile".to_vec())
+}
+
+#[cfg(all(not(test), not(feature = "profiling")))]
+async fn capture(_duration: Duration) -> Result<Vec<u8>, String> {
+    Err("CPU profiling requires building with the `profiling` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_state;
+    use crate::config::GatewayConfig;
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token).parse().unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_profile_endpoint_returns_profile_when_enabled() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            debug_profiling: true,
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+
+        let response = profile_handler(
+            State(state),
+            bearer("s3cret"),
+            Query(ProfileParams { seconds: Some(1) }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(!body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_profile_endpoint_is_off_by_default() {
+        let config = GatewayConfig {
+            admin_token: Some("s3cret".to_string()),
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+
+        let response = profile_handler(
+            State(state),
+            bearer("s3cret"),
+            Query(ProfileParams { seconds: Some(1) }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,282 @@
+//! Per-client token-bucket rate limiting keyed by IP address.
+//!
+//! Each client IP gets a bucket holding up to `burst` tokens that refills at
+//! `requests_per_second`; a request takes one token. A request finding the
+//! bucket empty is answered `429 Too Many Requests` with a JSON-RPC error body
+//! and a `Retry-After` header, before authentication or any upstream work.
+//!
+//! Behind a reverse proxy every connection comes from the proxy, so with
+//! `trust_forwarded_for` the first address in `X-Forwarded-For` is used
+//! instead. Only enable it when a trusted proxy sets that header, since clients
+//! can otherwise pick their own key.
+//!
+//! Buckets idle long enough to have refilled completely are indistinguishable
+//! from new ones, so they are swept periodically to bound memory.
+
+use crate::AppState;
+use crate::metrics::ErrorClass;
+use axum::{
+    Json,
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Header a reverse proxy uses to pass on the original client address.
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// How often idle buckets are swept.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rate limiter configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Sustained requests per second allowed per client IP. `None` disables
+    /// rate limiting.
+    pub requests_per_second: Option<f64>,
+
+    /// Requests a client may make in a burst. Defaults to one second's worth.
+    pub burst: Option<u32>,
+
+    /// Key clients on the first `X-Forwarded-For` address instead of the peer.
+    pub trust_forwarded_for: bool,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token buckets per client IP.
+pub struct RateLimiter {
+    rate: Option<f64>,
+    burst: f64,
+    trust_forwarded_for: bool,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    last_sweep: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        let rate = settings.requests_per_second.filter(|rate| *rate > 0.0);
+        let burst = match (settings.burst, rate) {
+            (Some(burst), _) => f64::from(burst.max(1)),
+            (None, Some(rate)) => rate.ceil(),
+            (None, None) => 0.0,
+        };
+        Self {
+            rate,
+            burst,
+            trust_forwarded_for: settings.trust_forwarded_for,
+            buckets: Mutex::new(HashMap::new()),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.rate.is_some()
+    }
+
+    /// Identifies the client a request is charged to.
+    fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        if self.trust_forwarded_for
+            && let Some(forwarded) = headers
+                .get(FORWARDED_FOR_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.split(',').next())
+                .and_then(|v| v.trim().parse().ok())
+        {
+            return Some(forwarded);
+        }
+        peer
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let Some(rate) = self.rate else {
+            return Ok(());
+        };
+        self.sweep_if_due(rate);
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+        let refill = now.duration_since(bucket.updated_at).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    fn sweep_if_due(&self, rate: f64) {
+        {
+            let mut last_sweep = self.last_sweep.lock();
+            if last_sweep.elapsed() < SWEEP_INTERVAL {
+                return;
+            }
+            *last_sweep = Instant::now();
+        }
+        self.sweep(rate);
+    }
+
+    /// Drops buckets that have refilled completely.
+    fn sweep(&self, rate: f64) {
+        let full_after = Duration::from_secs_f64(self.burst / rate);
+        let mut buckets = self.buckets.lock();
+        let before = buckets.len();
+        buckets.retain(|_, bucket| bucket.updated_at.elapsed() < full_after);
+        tracing::debug!("Swept {} idle rate limit buckets", before - buckets.len());
+    }
+}
+
+/// Middleware answering over-limit clients with `429 Too Many Requests`.
+pub async fn enforce_rate_limit(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.rate_limiter;
+    if !limiter.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let Some(ip) = limiter.client_ip(request.headers(), peer) else {
+        return next.run(request).await;
+    };
+
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            tracing::warn!("Rate limit exceeded for {}", ip);
+            state.request_counters.record(Some(ErrorClass::Client));
+            let body = serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32005, "message": "Rate limit exceeded" },
+                "id": null,
+            });
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+            let seconds = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_state;
+    use crate::config::GatewayConfig;
+    use axum::{Router, middleware, routing::post};
+
+    fn limiter(rate: f64, burst: u32) -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            requests_per_second: Some(rate),
+            burst: Some(burst),
+            trust_forwarded_for: true,
+        })
+    }
+
+    #[test]
+    fn test_bucket_allows_burst_then_limits_per_client() {
+        let limiter = limiter(1.0, 2);
+        let (a, b) = ("192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap());
+
+        assert!(limiter.check(a).is_ok());
+        assert!(limiter.check(a).is_ok());
+        let retry_after = limiter.check(a).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(1), "{:?}", retry_after);
+        assert!(limiter.check(b).is_ok());
+    }
+
+    #[test]
+    fn test_refilled_buckets_are_swept() {
+        let limiter = limiter(1000.0, 1);
+        limiter.check("192.0.2.1".parse().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+
+        limiter.sweep(1000.0);
+
+        assert!(limiter.buckets.lock().is_empty());
+    }
+
+    #[test]
+    fn test_forwarded_for_is_only_used_when_trusted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            FORWARDED_FOR_HEADER,
+            HeaderValue::from_static("203.0.113.9, 10.0.0.1"),
+        );
+        let peer = Some("10.0.0.1".parse().unwrap());
+
+        let trusted = limiter(1.0, 1);
+        assert_eq!(
+            trusted.client_ip(&headers, peer),
+            Some("203.0.113.9".parse().unwrap())
+        );
+        let untrusted = RateLimiter::new(&RateLimitSettings {
+            requests_per_second: Some(1.0),
+            ..Default::default()
+        });
+        assert_eq!(untrusted.client_ip(&headers, peer), peer);
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_request_gets_429_with_retry_after() {
+        let config = GatewayConfig {
+            rate_limit: RateLimitSettings {
+                requests_per_second: Some(0.5),
+                burst: Some(1),
+                trust_forwarded_for: true,
+            },
+            ..Default::default()
+        };
+        let state = build_state(config, &[]).unwrap();
+        let app = Router::new()
+            .route("/", post(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                enforce_rate_limit,
+            ))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let send = || {
+            client
+                .post(format!("http://{}/", addr))
+                .header(FORWARDED_FOR_HEADER, "203.0.113.9")
+                .send()
+        };
+
+        assert_eq!(send().await.unwrap().status(), StatusCode::OK);
+        let limited = send().await.unwrap();
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()[header::RETRY_AFTER], "2");
+        let body: serde_json::Value = limited.json().await.unwrap();
+        assert_eq!(body["error"]["code"], -32005);
+    }
+}
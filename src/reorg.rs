@@ -0,0 +1,181 @@
+//! Chain reorganization detection.
+//!
+//! After each health check interval the watcher reads the chain head, the
+//! highest block reported by a healthy node, and remembers the hash of the
+//! block at that height (`eth_getBlockByNumber`). It then re-reads the hash
+//! of the highest remembered height at or below the current head; a different
+//! hash means the nodes switched branches, so results cached from the
+//! abandoned branch may be wrong. The watcher then drops the cache entries of
+//! reorg-sensitive methods (see `Cache`) and keeps the rest.
+//!
+//! Comparing hashes catches reorgs that replace blocks at the same height,
+//! and a head that merely drops because the node at the tip went unhealthy,
+//! was drained or was removed is not mistaken for one: the blocks below it
+//! keep their hashes. A switch to a shorter branch is caught once that branch
+//! reaches a remembered height again; heights no node answers for are left
+//! unchecked. The watcher only runs when the cache is enabled.
+
+use crate::cache::Cache;
+use crate::load_balancer::LoadBalancer;
+use crate::types::{RequestContext, RpcRequest};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Block hashes remembered, one per observed head.
+const REMEMBERED_HEIGHTS: usize = 64;
+
+/// Watches the chain head and invalidates the cache on reorgs.
+pub struct ReorgWatcher {
+    load_balancer: Arc<LoadBalancer>,
+    cache: Arc<Cache>,
+
+    /// Hash of the block at each recently observed head, by height.
+    hashes: BTreeMap<u64, String>,
+}
+
+impl ReorgWatcher {
+    pub fn new(load_balancer: Arc<LoadBalancer>, cache: Arc<Cache>) -> Self {
+        Self {
+            load_balancer,
+            cache,
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Checks the highest remembered block at or below `head` against
+    /// `block_hash`, invalidating the cache if its hash changed, then
+    /// remembers the hash at `head`. Returns how many entries were dropped.
+    async fn observe<F, Fut>(&mut self, head: Option<u64>, block_hash: F) -> usize
+    where
+        F: Fn(u64) -> Fut,
+        Fut: Future<Output = Option<String>>,
+    {
+        let Some(head) = head else {
+            return 0;
+        };
+        let mut dropped = 0;
+        if let Some((&height, known)) = self.hashes.range(..=head).next_back()
+            && let Some(hash) = block_hash(height).await
+            && hash != *known
+        {
+            dropped = self.cache.invalidate_reorg_sensitive();
+            tracing::warn!(
+                "Block {} changed from {} to {}, dropped {} reorg-sensitive cache entries",
+                height,
+                known,
+                hash,
+                dropped
+            );
+            self.hashes.clear();
+        }
+
+        if let Some(hash) = block_hash(head).await {
+            self.hashes.insert(head, hash);
+            while self.hashes.len() > REMEMBERED_HEIGHTS {
+                self.hashes.pop_first();
+            }
+        }
+        dropped
+    }
+
+    /// Checks the head every `interval` on a background task for the life of
+    /// the process.
+    pub fn start(mut self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval.max(Duration::from_millis(1)));
+            loop {
+                interval.tick().await;
+                let load_balancer = Arc::clone(&self.load_balancer);
+                let head = load_balancer.chain_head();
+                self.observe(head, |height| block_hash(&load_balancer, height))
+                    .await;
+            }
+        });
+    }
+}
+
+/// Hash of the block at `height`, as the load balancer's pick of node reports
+/// it, or `None` if no node answers with one.
+async fn block_hash(load_balancer: &LoadBalancer, height: u64) -> Option<String> {
+    let request = RpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: "eth_getBlockByNumber".to_string(),
+        params: serde_json::json!([format!("{:#x}", height), false]),
+        id: serde_json::Value::String("reorg_check".to_string()),
+    };
+    let response = load_balancer
+        .forward_request(&request, &RequestContext::internal())
+        .await
+        .ok()?;
+    Some(response.result?.get("hash")?.as_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheSettings;
+    use crate::types::UpstreamConfig;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_reorg_drops_only_sensitive_entries() {
+        let load_balancer = LoadBalancer::new(&[UpstreamConfig {
+            name: "A".to_string(),
+            url: "http://localhost:1".to_string(),
+            ..Default::default()
+        }])
+        .unwrap();
+        let cache = Arc::new(Cache::new(CacheSettings {
+            cacheable_methods: vec![
+                "eth_chainId".to_string(),
+                "eth_getTransactionReceipt".to_string(),
+            ],
+            ..Default::default()
+        }));
+        let chain_id = cache.key_for("eth_chainId", &serde_json::json!([]));
+        let receipt = cache.key_for("eth_getTransactionReceipt", &serde_json::json!(["0xabc"]));
+        let fill = || {
+            cache.put(chain_id.clone(), serde_json::json!("0x1"));
+            cache.put(
+                receipt.clone(),
+                serde_json::json!({ "blockNumber": "0x64" }),
+            );
+        };
+        fill();
+        let mut watcher = ReorgWatcher::new(Arc::new(load_balancer), Arc::clone(&cache));
+        let chain: Mutex<HashMap<u64, &str>> = Mutex::new(HashMap::from([
+            (99, "0x99a"),
+            (100, "0x100a"),
+            (101, "0x101a"),
+        ]));
+        let hash_at = |height: u64| {
+            let hash = chain.lock().get(&height).map(|hash| hash.to_string());
+            async move { hash }
+        };
+
+        assert_eq!(watcher.observe(Some(100), hash_at).await, 0);
+        assert_eq!(watcher.observe(Some(101), hash_at).await, 0);
+
+        // The node at the tip went away: a lower head alone is no reorg
+        assert_eq!(watcher.observe(Some(100), hash_at).await, 0);
+        assert!(cache.get(&receipt).is_some());
+
+        // Block 100 replaced without the head moving
+        chain.lock().insert(100, "0x100b");
+        assert_eq!(watcher.observe(Some(100), hash_at).await, 1);
+        assert!(cache.get(&receipt).is_none());
+        assert_eq!(cache.get(&chain_id), Some(serde_json::json!("0x1")));
+
+        // A shorter branch is caught once it reaches a remembered height
+        fill();
+        assert_eq!(watcher.observe(Some(101), hash_at).await, 0);
+        chain.lock().insert(99, "0x99b");
+        chain.lock().remove(&101);
+        chain.lock().insert(100, "0x100c");
+        assert_eq!(watcher.observe(Some(99), hash_at).await, 0);
+        assert_eq!(watcher.observe(Some(100), hash_at).await, 1);
+    }
+}
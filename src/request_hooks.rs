@@ -0,0 +1,170 @@
+//! Ordered hooks that adjust a request right before it is forwarded upstream.
+//!
+//! Unlike the WASM plugins, hooks are plain Rust types implementing
+//! `RequestHook`, for small compatibility tweaks that some providers need. The
+//! load balancer runs them in order on every request it forwards, after
+//! caching and deduplication have already looked at the client's original
+//! request.
+//!
+//! Two hooks are built in and enabled from `request_hooks`:
+//!
+//! - `default_block_tag` appends a block tag to calls that omit their
+//!   optional block parameter, for providers that reject the short form. It
+//!   fills the parameter the way a `param_defaults` entry would.
+//! - `minimum_gas` raises a transaction object's `gas` to at least the given
+//!   amount in `eth_call` and `eth_estimateGas`.
+//!
+//! ```json
+//! "request_hooks": { "default_block_tag": "latest", "minimum_gas": 21000 }
+//! ```
+
+use crate::param_defaults;
+use crate::types::RpcRequest;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Mutates an outgoing request before it reaches an upstream node.
+pub trait RequestHook: Send + Sync {
+    fn apply(&self, request: &mut RpcRequest);
+}
+
+/// Built-in hooks to enable, in the order they run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestHookSettings {
+    /// Block tag appended to calls missing their block parameter, e.g. `latest`.
+    pub default_block_tag: Option<String>,
+
+    /// Lowest `gas` forwarded in `eth_call` and `eth_estimateGas` transactions.
+    pub minimum_gas: Option<u64>,
+}
+
+/// Builds the configured built-in hooks.
+pub fn from_settings(settings: &RequestHookSettings) -> Vec<Box<dyn RequestHook>> {
+    let mut hooks: Vec<Box<dyn RequestHook>> = Vec::new();
+    if let Some(tag) = &settings.default_block_tag {
+        hooks.push(Box::new(DefaultBlockTag::new(tag)));
+    }
+    if let Some(min) = settings.minimum_gas {
+        hooks.push(Box::new(MinimumGas { min }));
+    }
+    hooks
+}
+
+/// Position of the optional block parameter, per method.
+const BLOCK_PARAM_POSITIONS: &[(&str, usize)] = &[
+    ("eth_call", 1),
+    ("eth_estimateGas", 1),
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getTransactionCount", 1),
+    ("eth_getStorageAt", 2),
+];
+
+/// Fills in a block tag where positional params omit the block parameter.
+pub struct DefaultBlockTag {
+    /// `param_defaults` entries with the tag at each method's block position.
+    defaults: HashMap<String, Vec<Value>>,
+}
+
+impl DefaultBlockTag {
+    pub fn new(tag: &str) -> Self {
+        let defaults = BLOCK_PARAM_POSITIONS
+            .iter()
+            .map(|&(method, position)| {
+                let mut params = vec![Value::Null; position];
+                params.push(Value::String(tag.to_string()));
+                (method.to_string(), params)
+            })
+            .collect();
+        Self { defaults }
+    }
+}
+
+impl RequestHook for DefaultBlockTag {
+    fn apply(&self, request: &mut RpcRequest) {
+        if let Some(filled) = param_defaults::apply(&self.defaults, request) {
+            *request = filled;
+        }
+    }
+}
+
+/// Raises the transaction object's `gas` to at least `min`.
+///
+/// Transactions without a `gas` field are left for the node to estimate.
+pub struct MinimumGas {
+    pub min: u64,
+}
+
+impl RequestHook for MinimumGas {
+    fn apply(&self, request: &mut RpcRequest) {
+        if !matches!(request.method.as_str(), "eth_call" | "eth_estimateGas") {
+            return;
+        }
+        let Some(gas) = request.params.get_mut(0).and_then(|tx| tx.get_mut("gas")) else {
+            return;
+        };
+        let current = gas
+            .as_str()
+            .and_then(|hex| hex.strip_prefix("0x"))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+        if current.is_some_and(|current| current < self.min) {
+            *gas = Value::String(format!("0x{:x}", self.min));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, params: Value) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id: serde_json::json!(1),
+        }
+    }
+
+    #[test]
+    fn test_minimum_gas_only_raises_low_explicit_gas() {
+        let hook = MinimumGas { min: 50_000 };
+        let mut low = request(
+            "eth_call",
+            serde_json::json!([{ "gas": "0x5208" }, "latest"]),
+        );
+        let mut high = request("eth_call", serde_json::json!([{ "gas": "0x100000" }]));
+        let mut unset = request("eth_estimateGas", serde_json::json!([{ "to": "0x1" }]));
+
+        for request in [&mut low, &mut high, &mut unset] {
+            hook.apply(request);
+        }
+
+        assert_eq!(low.params[0]["gas"], "0xc350");
+        assert_eq!(high.params[0]["gas"], "0x100000");
+        assert!(unset.params[0].get("gas").is_none());
+    }
+
+    #[test]
+    fn test_default_block_tag_fills_only_a_missing_block_param() {
+        let hook = DefaultBlockTag::new("latest");
+        let mut short = request("eth_getBalance", serde_json::json!(["0xabc"]));
+        let mut explicit = request("eth_getBalance", serde_json::json!(["0xabc", "0x10"]));
+        let mut storage = request("eth_getStorageAt", serde_json::json!(["0xabc", "0x0"]));
+        let mut other = request("eth_blockNumber", serde_json::json!([]));
+
+        for request in [&mut short, &mut explicit, &mut storage, &mut other] {
+            hook.apply(request);
+        }
+
+        assert_eq!(short.params, serde_json::json!(["0xabc", "latest"]));
+        assert_eq!(explicit.params, serde_json::json!(["0xabc", "0x10"]));
+        assert_eq!(
+            storage.params,
+            serde_json::json!(["0xabc", "0x0", "latest"])
+        );
+        assert_eq!(other.params, serde_json::json!([]));
+    }
+}
@@ -0,0 +1,117 @@
+//! Global retry budget limiting failover amplification.
+//!
+//! During a partial outage, every request retrying across nodes multiplies the
+//! load on whatever is still standing. The budget caps retries at a fraction of
+//! the requests seen over a rolling window (plus a small floor so low-traffic
+//! gateways can still fail over). Once exhausted, requests fail fast instead of
+//! retrying.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Retry budget configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryBudgetSettings {
+    /// Retries allowed as a fraction of requests in the window (0.1 = 10%).
+    pub ratio: f64,
+
+    /// Length of the rolling window, in seconds.
+    pub window_secs: u64,
+
+    /// Retries always allowed per window regardless of traffic.
+    pub min_retries: u64,
+}
+
+impl Default for RetryBudgetSettings {
+    fn default() -> Self {
+        Self {
+            ratio: 0.1,
+            window_secs: 10,
+            min_retries: 10,
+        }
+    }
+}
+
+/// Per-second request and retry counts.
+struct Slot {
+    second: u64,
+    requests: u64,
+    retries: u64,
+}
+
+/// Tracks requests and retries over a rolling window of one-second slots.
+pub struct RetryBudget {
+    settings: RetryBudgetSettings,
+    started: Instant,
+    slots: Mutex<VecDeque<Slot>>,
+}
+
+impl RetryBudget {
+    pub fn new(settings: RetryBudgetSettings) -> Self {
+        Self {
+            settings,
+            started: Instant::now(),
+            slots: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Counts an incoming request towards the budget.
+    pub fn record_request(&self) {
+        let mut slots = self.current_slots();
+        if let Some(slot) = slots.back_mut() {
+            slot.requests += 1;
+        }
+    }
+
+    /// Takes one retry from the budget, returning false when it is exhausted.
+    pub fn try_acquire_retry(&self) -> bool {
+        let mut slots = self.current_slots();
+        let (requests, retries) = totals(&slots);
+        if retries >= self.allowed(requests) {
+            return false;
+        }
+        if let Some(slot) = slots.back_mut() {
+            slot.retries += 1;
+        }
+        true
+    }
+
+    /// Retries still available in the current window.
+    pub fn remaining(&self) -> u64 {
+        let slots = self.current_slots();
+        let (requests, retries) = totals(&slots);
+        self.allowed(requests).saturating_sub(retries)
+    }
+
+    fn allowed(&self, requests: u64) -> u64 {
+        let proportional = (requests as f64 * self.settings.ratio.max(0.0)) as u64;
+        proportional.max(self.settings.min_retries)
+    }
+
+    /// Locks the slots after dropping expired ones and opening the current second.
+    fn current_slots(&self) -> parking_lot::MutexGuard<'_, VecDeque<Slot>> {
+        let now = self.started.elapsed().as_secs();
+        let window = self.settings.window_secs.max(1);
+        let mut slots = self.slots.lock();
+        while slots.front().is_some_and(|s| s.second + window <= now) {
+            slots.pop_front();
+        }
+        if slots.back().is_none_or(|s| s.second < now) {
+            slots.push_back(Slot {
+                second: now,
+                requests: 0,
+                retries: 0,
+            });
+        }
+        slots
+    }
+}
+
+fn totals(slots: &VecDeque<Slot>) -> (u64, u64) {
+    slots
+        .iter()
+        .fold((0, 0), |(req, ret), s| (req + s.requests, ret + s.retries))
+}
@@ -0,0 +1,185 @@
+//! Coalescing of identical concurrent upstream calls.
+//!
+//! When several requests miss the cache for the same key at once, only the
+//! first (the leader) goes upstream; the rest wait for and share its result.
+//! Keys come from the cache key (method + params), never the JSON-RPC `id`, so
+//! pollers using different ids still share one upstream call. Batch elements
+//! are served by the same path as single requests, so a call inside a batch
+//! coalesces with an identical single request, or with an identical element of
+//! another batch.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use tokio::sync::broadcast;
+
+/// Tracks in-flight calls by key and fans their results out to waiters.
+pub struct SingleFlight<T: Clone> {
+    inflight: Mutex<HashMap<String, broadcast::Sender<T>>>,
+}
+
+/// Removes the leader's slot if it is dropped before publishing a result
+/// (e.g. the client disconnected), which wakes waiters with a closed channel.
+struct LeaderGuard<'a, T: Clone> {
+    flights: &'a SingleFlight<T>,
+    key: &'a str,
+}
+
+impl<T: Clone> Drop for LeaderGuard<'_, T> {
+    fn drop(&mut self) {
+        self.flights.inflight.lock().remove(self.key);
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Runs `call` unless an identical call is already in flight, in which case
+    /// its result is awaited and shared instead.
+    ///
+    /// `on_abandoned` produces the value returned to waiters if the leader is
+    /// cancelled before finishing.
+    pub async fn run<F, Fut>(&self, key: &str, call: F, on_abandoned: impl FnOnce() -> T) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let waiter = {
+            let mut inflight = self.inflight.lock();
+            match inflight.get(key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    let (sender, _) = broadcast::channel(1);
+                    inflight.insert(key.to_string(), sender);
+                    None
+                }
+            }
+        };
+
+        if let Some(mut receiver) = waiter {
+            tracing::debug!("Coalescing request onto in-flight call for {}", key);
+            return receiver.recv().await.unwrap_or_else(|_| on_abandoned());
+        }
+
+        let guard = LeaderGuard { flights: self, key };
+        let result = call().await;
+
+        // Publish under the lock so no waiter can subscribe after the send.
+        let mut inflight = self.inflight.lock();
+        if let Some(sender) = inflight.remove(key) {
+            let _ = sender.send(result.clone());
+        }
+        drop(inflight);
+        std::mem::forget(guard);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_execution() {
+        let flights = Arc::new(SingleFlight::<Result<u64, String>>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let flights = Arc::clone(&flights);
+                let calls = Arc::clone(&calls);
+                tokio::spawn(async move {
+                    flights
+                        .run(
+                            "eth_blockNumber:[]",
+                            || async {
+                                calls.fetch_add(1, Ordering::SeqCst);
+                                tokio::time::sleep(Duration::from_millis(100)).await;
+                                Ok(16)
+                            },
+                            || Err("abandoned".to_string()),
+                        )
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), Ok(16));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(flights.inflight.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_call_wakes_waiters_with_the_error() {
+        let flights = Arc::new(SingleFlight::<Result<u64, String>>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let flights = Arc::clone(&flights);
+                let calls = Arc::clone(&calls);
+                tokio::spawn(async move {
+                    flights
+                        .run(
+                            "eth_blockNumber:[]",
+                            || async {
+                                calls.fetch_add(1, Ordering::SeqCst);
+                                tokio::time::sleep(Duration::from_millis(100)).await;
+                                Err("upstream down".to_string())
+                            },
+                            || Err("abandoned".to_string()),
+                        )
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), Err("upstream down".to_string()));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(flights.inflight.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_leader_releases_waiters_and_slot() {
+        let flights = Arc::new(SingleFlight::<Result<u64, String>>::new());
+
+        let leader = {
+            let flights = Arc::clone(&flights);
+            tokio::spawn(async move {
+                flights
+                    .run("key", std::future::pending, || Err("abandoned".to_string()))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let waiter = {
+            let flights = Arc::clone(&flights);
+            tokio::spawn(async move {
+                flights
+                    .run("key", || async { Ok(1) }, || Err("abandoned".to_string()))
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        leader.abort();
+
+        let result = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("waiter hung after the leader was cancelled");
+        assert_eq!(result.unwrap(), Err("abandoned".to_string()));
+        assert!(flights.inflight.lock().is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+//! Availability SLO tracking: rolling success ratios and error-budget burn rate.
+//!
+//! With a `target` set (e.g. `0.999`), every served request is counted per
+//! wall-clock minute as good or bad. Only server errors are bad; client errors
+//! are the caller's fault and do not spend the error budget. For each of the
+//! configured windows the tracker reports the success ratio and the burn rate,
+//! the observed error ratio divided by the budgeted one (`1 - target`): a burn
+//! rate of 1 spends the budget exactly over the SLO period, 14.4 spends a
+//! 30-day budget in about two days.
+//!
+//! An alert is raised, as a structured `error` event on the `slo` target, when
+//! the burn rate reaches `alert_burn_rate` in every window at once. Pairing a
+//! short window with a long one keeps a brief spike from paging while still
+//! clearing soon after the errors stop. The alert is logged once per episode and
+//! cleared with an `info` event when any window drops below the threshold.
+
+use crate::metrics::ErrorClass;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// SLO configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SloSettings {
+    /// Availability objective as a fraction of requests, e.g. `0.999`. `None`
+    /// disables burn-rate tracking.
+    pub target: Option<f64>,
+
+    /// Windows, in minutes, over which success ratio and burn rate are computed.
+    pub windows_minutes: Vec<u64>,
+
+    /// Burn rate that must be reached in every window to raise an alert.
+    pub alert_burn_rate: f64,
+}
+
+impl Default for SloSettings {
+    fn default() -> Self {
+        Self {
+            target: None,
+            windows_minutes: vec![5, 60],
+            alert_burn_rate: 14.4,
+        }
+    }
+}
+
+/// Success ratio and burn rate over one window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowStats {
+    pub minutes: u64,
+    pub requests: u64,
+    pub success_ratio: f64,
+    pub burn_rate: f64,
+}
+
+/// Point-in-time SLO state as exposed in `/metrics`.
+pub struct SloSnapshot {
+    pub windows: Vec<WindowStats>,
+    pub alerting: bool,
+    pub alerts: u64,
+}
+
+struct MinuteCount {
+    /// Minutes since the Unix epoch.
+    minute: u64,
+    total: u64,
+    bad: u64,
+}
+
+/// Rolling per-minute good/bad counts and the burn-rate alert state.
+pub struct SloTracker {
+    /// Allowed error ratio, `1 - target`. `None` when tracking is disabled.
+    budget: Option<f64>,
+    windows: Vec<u64>,
+    alert_burn_rate: f64,
+    minutes: Mutex<VecDeque<MinuteCount>>,
+    alerting: Mutex<bool>,
+    alerts: AtomicU64,
+}
+
+impl SloTracker {
+    pub fn new(settings: &SloSettings) -> Self {
+        let budget = settings
+            .target
+            .filter(|target| (0.0..1.0).contains(target))
+            .map(|target| 1.0 - target);
+        let mut windows: Vec<u64> = settings
+            .windows_minutes
+            .iter()
+            .copied()
+            .filter(|w| *w > 0)
+            .collect();
+        windows.sort_unstable();
+        windows.dedup();
+        Self {
+            budget,
+            windows,
+            alert_burn_rate: settings.alert_burn_rate,
+            minutes: Mutex::new(VecDeque::new()),
+            alerting: Mutex::new(false),
+            alerts: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts one served request, failed if it ended in a server error.
+    pub fn record(&self, error: Option<ErrorClass>) {
+        if self.budget.is_none() {
+            return;
+        }
+        self.record_at(current_minute(), error == Some(ErrorClass::Server));
+    }
+
+    fn record_at(&self, minute: u64, bad: bool) {
+        {
+            let mut minutes = self.minutes.lock();
+            if minutes.back().is_none_or(|m| m.minute < minute) {
+                minutes.push_back(MinuteCount {
+                    minute,
+                    total: 0,
+                    bad: 0,
+                });
+                let longest = self.windows.last().copied().unwrap_or(1);
+                while minutes
+                    .front()
+                    .is_some_and(|m| m.minute + longest <= minute)
+                {
+                    minutes.pop_front();
+                }
+            }
+            // Late records for a minute that already rolled over land in the newest one
+            if let Some(current) = minutes.back_mut() {
+                current.total += 1;
+                current.bad += u64::from(bad);
+            }
+        }
+        self.evaluate(minute);
+    }
+
+    /// Stats for every window ending at `minute`; windows without traffic are skipped.
+    fn window_stats(&self, minute: u64) -> Vec<WindowStats> {
+        let Some(budget) = self.budget else {
+            return Vec::new();
+        };
+        let minutes = self.minutes.lock();
+        self.windows
+            .iter()
+            .filter_map(|&window| {
+                let (total, bad) = minutes
+                    .iter()
+                    .filter(|m| m.minute + window > minute)
+                    .fold((0, 0), |(total, bad), m| (total + m.total, bad + m.bad));
+                (total > 0).then(|| {
+                    let error_ratio = bad as f64 / total as f64;
+                    WindowStats {
+                        minutes: window,
+                        requests: total,
+                        success_ratio: 1.0 - error_ratio,
+                        burn_rate: error_ratio / budget,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Raises or clears the alert for the windows ending at `minute`.
+    fn evaluate(&self, minute: u64) {
+        let stats = self.window_stats(minute);
+        let burning = stats.len() == self.windows.len()
+            && stats.iter().all(|w| w.burn_rate >= self.alert_burn_rate);
+
+        let mut alerting = self.alerting.lock();
+        if burning == *alerting {
+            return;
+        }
+        *alerting = burning;
+        let summary: Vec<String> = stats
+            .iter()
+            .map(|w| format!("{}m={:.2}", w.minutes, w.burn_rate))
+            .collect();
+        if burning {
+            self.alerts.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                target: "slo",
+                burn_rates = %summary.join(","),
+                threshold = self.alert_burn_rate,
+                "SLO error budget burn rate alert"
+            );
+        } else {
+            tracing::info!(
+                target: "slo",
+                burn_rates = %summary.join(","),
+                "SLO error budget burn rate back below threshold"
+            );
+        }
+    }
+
+    /// Current state for `/metrics`, or `None` when tracking is disabled.
+    pub fn snapshot(&self) -> Option<SloSnapshot> {
+        self.budget?;
+        Some(SloSnapshot {
+            windows: self.window_stats(current_minute()),
+            alerting: *self.alerting.lock(),
+            alerts: self.alerts.load(Ordering::Relaxed),
+        })
+    }
+}
+
+fn current_minute() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / 60
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheStats;
+    use crate::metrics::{RequestCounters, render};
+
+    fn tracker() -> SloTracker {
+        SloTracker::new(&SloSettings {
+            target: Some(0.99),
+            windows_minutes: vec![1, 5],
+            alert_burn_rate: 10.0,
+        })
+    }
+
+    #[test]
+    fn test_error_spike_raises_burn_rate_and_fires_alert() {
+        let slo = tracker();
+        for minute in 100..104 {
+            for _ in 0..99 {
+                slo.record_at(minute, false);
+            }
+            slo.record_at(minute, true);
+        }
+        let steady = slo.window_stats(103);
+        assert_eq!(steady[0].requests, 100);
+        assert!((steady[0].burn_rate - 1.0).abs() < 1e-9);
+        assert!((steady[1].success_ratio - 0.99).abs() < 1e-9);
+        assert_eq!(slo.alerts.load(Ordering::Relaxed), 0);
+
+        // Every request fails: the short window burns at 100x right away, the
+        // long one only crosses 10x after enough errors to outweigh the history
+        for _ in 0..30 {
+            slo.record_at(104, true);
+        }
+        assert!(slo.window_stats(104)[1].burn_rate < 10.0);
+        assert!(!*slo.alerting.lock());
+        for _ in 0..30 {
+            slo.record_at(104, true);
+        }
+        let spike = slo.window_stats(104);
+        assert!((spike[0].burn_rate - 100.0).abs() < 1e-6);
+        assert!(spike[1].burn_rate >= 10.0, "{:?}", spike);
+        assert!(*slo.alerting.lock());
+        assert_eq!(slo.alerts.load(Ordering::Relaxed), 1);
+
+        // Recovery in the short window clears the alert
+        for _ in 0..1000 {
+            slo.record_at(105, false);
+        }
+        assert!(!*slo.alerting.lock());
+        assert_eq!(slo.alerts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_client_errors_do_not_spend_budget_and_old_minutes_expire() {
+        let slo = tracker();
+        slo.record(Some(ErrorClass::Client));
+        let minute = current_minute();
+        assert_eq!(slo.window_stats(minute)[0].burn_rate, 0.0);
+
+        slo.record_at(minute + 5, true);
+        let stats = slo.window_stats(minute + 5);
+        assert_eq!(stats[1].requests, 1);
+        assert_eq!(slo.minutes.lock().len(), 1);
+    }
+
+    #[test]
+    fn test_burn_rate_is_exposed_in_metrics() {
+        let slo = SloTracker::new(&SloSettings {
+            target: Some(0.5),
+            windows_minutes: vec![1, 5],
+            alert_burn_rate: 2.0,
+        });
+        slo.record_at(current_minute(), true);
+
+        let output = render(
+            &[],
+            &RequestCounters::default(),
+            CacheStats::default(),
+            None,
+            slo.snapshot(),
+        );
+
+        for line in [
+            "ha_gateway_slo_success_ratio{window=\"1m\"} 0",
+            "ha_gateway_slo_burn_rate{window=\"5m\"} 2",
+            "ha_gateway_slo_burn_rate_alerting 1",
+            "ha_gateway_slo_burn_rate_alerts_total 1",
+        ] {
+            assert!(output.contains(line), "missing {:?} in\n{}", line, output);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers shared by tests that need a live HTTP upstream.
+
+use axum::Router;
+
+/// Serves `app` on an ephemeral localhost port and returns its base URL.
+///
+/// The server runs on a background task for the rest of the test.
+pub async fn spawn_mock_upstream(app: Router) -> String {
+    spawn_mock_upstream_at("127.0.0.1:0", app).await
+}
+
+/// Like `spawn_mock_upstream`, bound to `addr` (e.g. `[::1]:0`).
+pub async fn spawn_mock_upstream_at(addr: &str, app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .expect("Failed to bind mock upstream");
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{}", addr)
+}
@@ -0,0 +1,203 @@
+//! W3C Trace Context (`traceparent`) propagation.
+//!
+//! The gateway continues an incoming trace when the client sends a valid
+//! `traceparent` header, or starts a new one otherwise. Every request forwarded
+//! upstream carries a `traceparent` naming the gateway's own span as parent, so
+//! upstreams that understand trace context can join the trace.
+//!
+//! Header format: `{version}-{trace-id}-{parent-id}-{flags}`, e.g.
+//! `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+//!
+//! Independently of tracing, every request has a request id: the client's
+//! `X-Request-Id` if it sent a usable one, or a new UUID. It is recorded on the
+//! request's log span, sent upstream as `X-Request-Id` and echoed back on the
+//! response, so a client report, the gateway logs and the upstream logs can be
+//! matched up.
+
+use crate::types::REQUEST_ID_HEADER;
+use axum::http::HeaderMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+/// Name of the W3C trace context header.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Longest client-supplied request id that is kept.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Trace identity of one request as it passes through the gateway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    /// 32 lowercase hex characters shared by every span in the trace.
+    pub trace_id: String,
+
+    /// 16 lowercase hex characters identifying the gateway's span.
+    pub span_id: String,
+
+    /// Trace flags (bit 0 = sampled).
+    pub flags: u8,
+}
+
+impl TraceContext {
+    /// Continues the trace from an incoming `traceparent`, or starts a new one.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(TRACEPARENT_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::parse_trace)
+            .map(|(trace_id, flags)| Self {
+                trace_id,
+                span_id: new_span_id(),
+                flags,
+            })
+            .unwrap_or_else(Self::new_root)
+    }
+
+    /// Starts a new sampled trace.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: format!("{:016x}{:016x}", random_u64(), random_u64()),
+            span_id: new_span_id(),
+            flags: 0x01,
+        }
+    }
+
+    /// Renders the `traceparent` value sent to upstreams.
+    pub fn to_header_value(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+
+    /// Extracts the trace id and flags from a `traceparent` value, rejecting
+    /// malformed or all-zero identifiers as the spec requires.
+    fn parse_trace(value: &str) -> Option<(String, u8)> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+
+        let valid = version.len() == 2
+            && is_lower_hex(version)
+            && version != "ff"
+            && trace_id.len() == 32
+            && is_lower_hex(trace_id)
+            && !trace_id.bytes().all(|b| b == b'0')
+            && parent_id.len() == 16
+            && is_lower_hex(parent_id)
+            && !parent_id.bytes().all(|b| b == b'0')
+            && flags.len() == 2;
+        // Version 00 has exactly four fields; later versions may append more.
+        if !valid || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        Some((trace_id.to_string(), flags))
+    }
+}
+
+/// The client's `X-Request-Id`, or a new random UUID if it sent none.
+///
+/// Ids that are empty, longer than `MAX_REQUEST_ID_LEN` or not visible ASCII
+/// are replaced too, since the id ends up in headers and log lines.
+pub fn request_id_from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map_or_else(new_request_id, str::to_string)
+}
+
+/// A random (version 4) UUID.
+pub fn new_request_id() -> String {
+    let high = (random_u64() & !0xf000) | 0x4000;
+    let low = (random_u64() & !(0xc000 << 48)) | (0x8000 << 48);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn new_span_id() -> String {
+    // A zero span id is invalid, so avoid it.
+    format!("{:016x}", random_u64().max(1))
+}
+
+/// Returns a random 64-bit value from std's per-process SipHash keys.
+///
+/// Not cryptographically secure, but ample for trace and span identifiers.
+pub fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INCOMING: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT_HEADER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_continues_incoming_trace_with_new_span() {
+        let ctx = TraceContext::from_headers(&headers_with(INCOMING));
+
+        assert_eq!(ctx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_ne!(ctx.span_id, "00f067aa0ba902b7");
+        assert_eq!(ctx.flags, 0x01);
+    }
+
+    #[test]
+    fn test_starts_new_trace_when_header_missing_or_invalid() {
+        for headers in [
+            HeaderMap::new(),
+            headers_with("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            headers_with("garbage"),
+        ] {
+            let ctx = TraceContext::from_headers(&headers);
+            assert_eq!(ctx.trace_id.len(), 32);
+            assert_ne!(ctx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+            assert!(TraceContext::parse_trace(&ctx.to_header_value()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_request_id_is_kept_or_generated() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, "req-42".parse().unwrap());
+        assert_eq!(request_id_from_headers(&headers), "req-42");
+
+        headers.insert(REQUEST_ID_HEADER, "a b".parse().unwrap());
+        for headers in [HeaderMap::new(), headers] {
+            let id = request_id_from_headers(&headers);
+            let groups: Vec<usize> = id.split('-').map(str::len).collect();
+            assert_eq!(groups, vec![8, 4, 4, 4, 12], "{}", id);
+            assert_eq!(&id[14..15], "4");
+            assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"), "{}", id);
+        }
+        assert_ne!(new_request_id(), new_request_id());
+    }
+}
@@ -0,0 +1,108 @@
+//! Short-lived deduplication of `eth_sendRawTransaction` submissions.
+//!
+//! Clients that retry a submission re-broadcast the same signed transaction and
+//! get "already known" errors back. With `window_ms` set, the first response for
+//! a given transaction hash is remembered and returned for repeat submissions
+//! within the window instead of re-broadcasting.
+
+use crate::types::RpcResponse;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tiny_keccak::{Hasher, Keccak};
+
+const SEND_RAW_TRANSACTION: &str = "eth_sendRawTransaction";
+
+/// Transaction deduplication configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TxDedupSettings {
+    /// How long a submission's response is replayed. `None` disables deduplication.
+    pub window_ms: Option<u64>,
+}
+
+/// Remembers recent `eth_sendRawTransaction` responses by transaction hash.
+pub struct TxDedup {
+    window: Option<Duration>,
+    seen: Mutex<HashMap<String, (Instant, RpcResponse)>>,
+}
+
+impl TxDedup {
+    pub fn new(settings: &TxDedupSettings) -> Self {
+        Self {
+            window: settings.window_ms.map(Duration::from_millis),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the transaction hash to deduplicate on, if this request qualifies.
+    pub fn key_for(&self, method: &str, params: &serde_json::Value) -> Option<String> {
+        if self.window.is_none() || method != SEND_RAW_TRANSACTION {
+            return None;
+        }
+        let raw = params.get(0)?.as_str()?;
+        let bytes = decode_hex(raw.strip_prefix("0x").unwrap_or(raw))?;
+        Some(keccak256_hex(&bytes))
+    }
+
+    /// Returns the response recorded for `tx_hash` if still within the window.
+    pub fn get(&self, tx_hash: &str) -> Option<RpcResponse> {
+        let window = self.window?;
+        let seen = self.seen.lock();
+        let (at, response) = seen.get(tx_hash)?;
+        (at.elapsed() < window).then(|| response.clone())
+    }
+
+    /// Records the first response for `tx_hash`, pruning expired entries.
+    pub fn record(&self, tx_hash: String, response: RpcResponse) {
+        let Some(window) = self.window else {
+            return;
+        };
+        let mut seen = self.seen.lock();
+        seen.retain(|_, (at, _)| at.elapsed() < window);
+        seen.entry(tx_hash).or_insert((Instant::now(), response));
+    }
+}
+
+/// Hex-encoded Keccak-256 digest, i.e. the Ethereum transaction hash of raw bytes.
+fn keccak256_hex(bytes: &[u8]) -> String {
+    let mut hasher = Keccak::v256();
+    hasher.update(bytes);
+    let mut digest = [0u8; 32];
+    hasher.finalize(&mut digest);
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", hex)
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tx_hash_is_keccak_of_raw_bytes() {
+        let dedup = TxDedup::new(&TxDedupSettings {
+            window_ms: Some(1000),
+        });
+
+        assert_eq!(
+            dedup.key_for(SEND_RAW_TRANSACTION, &serde_json::json!(["0x"])),
+            Some("0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470".to_string())
+        );
+        assert_eq!(
+            dedup.key_for(SEND_RAW_TRANSACTION, &serde_json::json!(["0xzz"])),
+            None
+        );
+        assert_eq!(dedup.key_for("eth_call", &serde_json::json!(["0x"])), None);
+    }
+}
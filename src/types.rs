@@ -1,5 +1,7 @@
+use crate::trace_context::TraceContext;
 use serde::{Deserialize, Serialize};
-
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 
 /// Eth client rpc request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,9 +14,17 @@ pub struct RpcRequest {
     #[serde(default)]
     pub params: serde_json::Value,
 
+    /// Absent (or `null`) for a notification, which gets no response.
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub id: serde_json::Value,
 }
 
+impl RpcRequest {
+    /// Whether this is a notification, which the client expects no response to.
+    pub fn is_notification(&self) -> bool {
+        self.id.is_null()
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RpcResponse {
@@ -27,6 +37,26 @@ pub struct RpcResponse {
     pub error: Option<RpcError>,
 
     pub id: serde_json::Value,
+
+    /// Gateway-side details about how the response was obtained. Never serialized.
+    #[serde(skip)]
+    pub meta: ResponseMeta,
+}
+
+/// Transport-level metadata captured alongside an upstream response.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMeta {
+    /// End-to-end upstream response headers (hop-by-hop headers already removed).
+    pub headers: Vec<(String, String)>,
+
+    /// Name of the upstream node that produced the response.
+    pub served_by: Option<String>,
+
+    /// Number of forwarding attempts it took to obtain the response.
+    pub attempts: usize,
+
+    /// Served by an unhealthy node because no healthy one was available.
+    pub degraded: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,25 +76,499 @@ impl RpcResponse {
             result: Some(result),
             error: None,
             id,
+            meta: ResponseMeta::default(),
         }
     }
 
     pub fn error(id: serde_json::Value, code: i32, message: String) -> Self {
-        Self {
-            jsonrpc: "2.0".to_string(),
-            result: None,
-            error: Some(RpcError {
+        Self::from_error(
+            id,
+            RpcError {
                 code,
                 message,
                 data: None,
-            }),
+            },
+        )
+    }
+
+    pub fn from_error(id: serde_json::Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(error),
             id,
+            meta: ResponseMeta::default(),
         }
     }
 }
 
+/// Why a request could not be answered with a result.
 #[derive(Debug, Clone)]
+pub enum ForwardError {
+    /// The upstream `node` answered with a JSON-RPC error. Such errors are
+    /// deterministic, so they are passed through to the client as they are
+    /// rather than retried on another node.
+    Rpc { error: RpcError, node: String },
+
+    /// No healthy upstream node was available to send the request to.
+    NoHealthyUpstream,
+
+    /// Transport failures, timeouts, protocol violations and other failures
+    /// of the gateway itself.
+    Internal(String),
+}
+
+impl std::fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForwardError::Rpc { error, node } => write!(
+                f,
+                "RPC error {} from node {}: {}",
+                error.code, node, error.message
+            ),
+            ForwardError::NoHealthyUpstream => f.write_str("No healthy nodes available"),
+            ForwardError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<String> for ForwardError {
+    fn from(message: String) -> Self {
+        ForwardError::Internal(message)
+    }
+}
+
+/// One upstream node, as listed under `upstreams` in the configuration file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct UpstreamConfig {
     pub name: String,
     pub url: String,
+
+    /// `ws://` or `wss://` endpoint used to proxy `/ws` clients. Nodes without
+    /// one only serve HTTP.
+    pub ws_url: Option<String>,
+
+    /// Optional HTTP basic-auth credentials sent in the `Authorization` header.
+    ///
+    /// Prefer this over embedding `user:pass@` in the URL, which breaks as soon as
+    /// the password contains reserved characters such as `:` or `@`.
+    pub basic_auth: Option<BasicAuth>,
+
+    /// Log requests that most likely had to open a fresh upstream connection.
+    pub log_new_connections: bool,
+
+    /// Custom probe used by health checks instead of `health_checks.probe`
+    /// (`eth_blockNumber` by default).
+    ///
+    /// Useful for non-Ethereum JSON-RPC services. Any response without an
+    /// `error` counts as healthy.
+    pub health_check: Option<HealthCheckRequest>,
+
+    /// Consecutive successful probes that close this node's open circuit.
+    /// Defaults to `health_checks.recovery_probes`.
+    pub recovery_probes: Option<usize>,
+
+    /// How failed client requests open this node's circuit.
+    pub circuit_breaker: BreakerMode,
+
+    /// What to do with responses whose `jsonrpc` member is not `"2.0"`.
+    pub version_mismatch: VersionMismatch,
+
+    /// Check that results of well-known methods have the expected shape (e.g.
+    /// a hex quantity for `eth_blockNumber`). A malformed result counts as a
+    /// failed request, so it trips the circuit breaker and is retried on
+    /// another node. A `null` receipt only passes if the node does not report
+    /// the transaction as mined.
+    pub validate_results: bool,
+
+    /// Consecutive failed requests that open the circuit. Defaults to 3.
+    pub failure_threshold: Option<usize>,
+
+    /// How long an open circuit stays open before the node turns half-open,
+    /// in milliseconds. Defaults to 60 seconds.
+    ///
+    /// Doubles each time the circuit reopens without the node having stayed
+    /// healthy for as long as its last cooldown, up to `max_cooldown_ms`.
+    pub cooldown_ms: Option<u64>,
+
+    /// Upper bound for the backed-off cooldown, in milliseconds. Defaults to
+    /// 10 minutes.
+    pub max_cooldown_ms: Option<u64>,
+
+    /// Gentler breaker settings while an operator has flagged the node as
+    /// upgrading.
+    pub upgrade: UpgradeTolerance,
+
+    /// Timeout for each RPC request to this node, in milliseconds. Defaults
+    /// to 5 seconds.
+    pub request_timeout_ms: Option<u64>,
+
+    /// Consecutive failed health probes that mark the node unhealthy.
+    ///
+    /// Counted separately from failed client requests, so a lenient value lets
+    /// a node that serves traffic fine ride out a few missed probes. `None`
+    /// uses the same threshold as request failures.
+    pub probe_failure_threshold: Option<usize>,
+
+    /// Optional HMAC signing of request bodies for private endpoints.
+    pub request_signing: Option<RequestSigning>,
+
+    /// Proxy URL for all requests to this node, e.g. `http://proxy:3128`.
+    pub proxy: Option<String>,
+
+    /// Ask for gzip-compressed responses and decompress them.
+    pub accept_gzip: bool,
+
+    /// Largest response body accepted from this node, counted after
+    /// decompression so a small gzip bomb cannot expand without bound.
+    /// Larger responses fail like any other upstream error. Defaults to 64 MiB.
+    pub max_response_bytes: Option<usize>,
+
+    /// Send small requests immediately instead of batching them into fewer
+    /// packets (`TCP_NODELAY`). Defaults to true.
+    pub tcp_nodelay: Option<bool>,
+
+    /// Interval of TCP keepalive probes on idle connections, in milliseconds,
+    /// so dead connections are noticed before a request is sent on them.
+    /// Unset leaves keepalive off.
+    pub tcp_keepalive_ms: Option<u64>,
+
+    /// Idle connections kept open to this node for reuse. Unset keeps
+    /// reqwest's default, which does not limit them.
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// How long an idle pooled connection is kept before it is closed, in
+    /// milliseconds. Defaults to 90 seconds.
+    pub pool_idle_timeout_ms: Option<u64>,
+
+    /// Talk HTTP/2 to this node from the start (prior knowledge), multiplexing
+    /// all requests over few connections. The node must accept HTTP/2 without
+    /// an upgrade; leave unset otherwise.
+    pub http2: bool,
+
+    /// Redirects followed per request before failing. Defaults to 10.
+    pub max_redirects: Option<usize>,
+
+    /// Hosts besides the node's own that redirects may lead to, as they
+    /// appear in URLs (e.g. `rpc.example.com`, `[::1]`). Redirects elsewhere
+    /// fail the request.
+    pub redirect_allowed_hosts: Vec<String>,
+
+    /// Relative share of traffic for weighted round-robin. Unset counts as 1,
+    /// as does 0; equal weights everywhere give plain round-robin.
+    pub weight: Option<u32>,
+
+    /// Fallback tier, lowest first. Only the lowest tier with a healthy node
+    /// takes traffic; higher tiers serve when every node below is down.
+    pub tier: u8,
+
+    /// Break-glass backup: never selected while any non-standby node is
+    /// healthy, whatever its tier, and promoted only once none is.
+    pub standby: bool,
+
+    /// Maximum requests in flight to this node at once. `None` is unlimited.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Lets requests wait briefly for a slot when the node is at its
+    /// concurrency limit, instead of failing over immediately.
+    pub queue: Option<RequestQueue>,
+
+    /// Free-form metadata such as `region`, `provider` or `tier`.
+    ///
+    /// Reported in `/status` and as label dimensions in `/metrics`, and matched
+    /// against `SelectionSettings::preferred_labels`.
+    pub labels: HashMap<String, String>,
+
+    /// Capability tags such as `archive`, `full` or `trace`, used to route
+    /// requests that only some nodes can serve.
+    pub capabilities: HashSet<String>,
+}
+
+/// Hash function used for HMAC request signatures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+/// Per-node HMAC signing of the serialized request body.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestSigning {
+    /// Shared secret; never logged.
+    pub secret: String,
+
+    pub algorithm: SigningAlgorithm,
+
+    /// Header carrying the lowercase hex signature.
+    pub header: String,
+}
+
+impl Default for RequestSigning {
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            algorithm: SigningAlgorithm::default(),
+            header: "X-Signature".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Debug for RequestSigning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestSigning")
+            .field("secret", &"<redacted>")
+            .field("algorithm", &self.algorithm)
+            .field("header", &self.header)
+            .finish()
+    }
+}
+
+impl RequestSigning {
+    /// Computes the lowercase hex HMAC of `body` with the shared secret.
+    pub fn sign(&self, body: &[u8]) -> String {
+        let algorithm = match self.algorithm {
+            SigningAlgorithm::HmacSha256 => ring::hmac::HMAC_SHA256,
+            SigningAlgorithm::HmacSha512 => ring::hmac::HMAC_SHA512,
+        };
+        let key = ring::hmac::Key::new(algorithm, self.secret.as_bytes());
+        ring::hmac::sign(&key, body)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Handling of upstream responses that declare a JSON-RPC version other than 2.0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionMismatch {
+    /// Pass the response on unchanged.
+    #[default]
+    Forward,
+
+    /// Rewrite `jsonrpc` to `"2.0"` for clients that validate it strictly.
+    Normalize,
+
+    /// Treat the response as a protocol violation and fail over.
+    Reject,
+}
+
+/// Rule deciding when failed client requests open a node's circuit.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum BreakerMode {
+    /// Open after a run of consecutive failures.
+    #[default]
+    ConsecutiveFailures,
+
+    /// Open once more than `max_error_percent` of the last `window` requests
+    /// failed. Nothing trips before `min_requests` outcomes are in the window,
+    /// so a handful of early errors cannot open the circuit.
+    ErrorRate {
+        window: usize,
+        max_error_percent: f64,
+        min_requests: usize,
+    },
+}
+
+/// Bounded wait queue in front of a node's concurrency limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestQueue {
+    /// Requests allowed to wait at once; further ones fail over immediately.
+    pub max_waiting: usize,
+
+    /// How long a request waits for a slot before failing over.
+    pub timeout_ms: u64,
+}
+
+/// Breaker behavior for a node flagged as upgrading through the admin API.
+///
+/// A rolling restart makes a node fail for a while on purpose, so failures in
+/// the window count against a higher threshold, the cooldown does not back
+/// off, and opening the circuit is logged as expected rather than as an error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpgradeTolerance {
+    /// Consecutive failed requests or probes that open the circuit while
+    /// upgrading. Never lower than the node's regular thresholds.
+    pub failure_threshold: usize,
+
+    /// Cooldown while upgrading, in milliseconds. Defaults to `cooldown_ms`.
+    pub cooldown_ms: Option<u64>,
+
+    /// How long the upgrading flag lasts unless cleared sooner, in milliseconds.
+    pub window_ms: u64,
+}
+
+impl Default for UpgradeTolerance {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 10,
+            cooldown_ms: None,
+            window_ms: 15 * 60 * 1000,
+        }
+    }
+}
+
+/// JSON-RPC call issued by the health checker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckRequest {
+    pub method: String,
+    pub params: serde_json::Value,
+}
+
+impl Default for HealthCheckRequest {
+    fn default() -> Self {
+        Self {
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+        }
+    }
+}
+
+/// Credentials for upstreams sitting behind HTTP basic-auth.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BasicAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Client-chosen key identifying a logical request across client retries.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "x-idempotency-key";
+
+/// Request identifier, chosen by the client or generated by the gateway,
+/// forwarded upstream and echoed back. Some clients reuse it when retrying.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Client-chosen key that keeps a session's requests on one node.
+pub const SESSION_ID_HEADER: &str = "x-session-id";
+
+/// Header letting clients pick a read consistency level per request.
+pub const CONSISTENCY_HEADER: &str = "x-consistency";
+
+/// How strongly a read must reflect the chain head.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Consistency {
+    /// Any healthy node.
+    #[default]
+    Fast,
+
+    /// A node at the highest block height seen by the health checker.
+    Fresh,
+
+    /// The result a majority of healthy nodes agree on.
+    Quorum,
+}
+
+impl Consistency {
+    /// Reads `X-Consistency`, defaulting to `Fast` when absent.
+    pub fn from_headers(headers: &axum::http::HeaderMap) -> Result<Self, String> {
+        let Some(value) = headers.get(CONSISTENCY_HEADER) else {
+            return Ok(Consistency::Fast);
+        };
+        match value
+            .to_str()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "fast" => Ok(Consistency::Fast),
+            "fresh" => Ok(Consistency::Fresh),
+            "quorum" => Ok(Consistency::Quorum),
+            other => Err(format!(
+                "unknown consistency level {:?}, expected fast, fresh or quorum",
+                other
+            )),
+        }
+    }
+}
+
+/// Who sent a request, as far as the gateway can tell.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// Address of the connecting client.
+    pub client_ip: Option<IpAddr>,
+
+    /// API key presented by the client.
+    pub api_key: Option<String>,
+}
+
+impl Caller {
+    /// Identity per-client state is partitioned by: the API key when the
+    /// client presented one, else its address.
+    pub fn scope(&self) -> String {
+        match (&self.api_key, self.client_ip) {
+            (Some(key), _) => format!("key:{}", key),
+            (None, Some(ip)) => format!("ip:{}", ip),
+            (None, None) => "anonymous".to_string(),
+        }
+    }
+
+    /// Identifies the client connecting from `client_ip` with these headers.
+    pub fn from_headers(headers: &axum::http::HeaderMap, client_ip: Option<IpAddr>) -> Self {
+        Self {
+            client_ip,
+            api_key: headers
+                .get(crate::auth::API_KEY_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// Per-request data threaded from the handler down to the upstream call.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Trace identity propagated to upstreams via `traceparent`.
+    pub trace: TraceContext,
+
+    /// Correlation id sent upstream as `X-Request-Id`.
+    pub request_id: String,
+
+    /// Requested read consistency.
+    pub consistency: Consistency,
+
+    /// Identity of the client, for auditing.
+    pub caller: Caller,
+
+    /// `X-Idempotency-Key` (or else `X-Request-Id`) sent by the client, used to
+    /// recognize the client's own retries.
+    pub retry_key: Option<String>,
+
+    /// `X-Session-Id` sent by the client, for session affinity.
+    pub session_id: Option<String>,
+
+    /// Node named by a verified `X-Upstream` override; only this node serves
+    /// the request.
+    pub pinned_node: Option<String>,
+}
+
+impl RequestContext {
+    /// Context for requests the gateway originates itself, such as health checks.
+    pub fn internal() -> Self {
+        Self {
+            trace: TraceContext::new_root(),
+            request_id: crate::trace_context::new_request_id(),
+            consistency: Consistency::Fast,
+            caller: Caller::default(),
+            retry_key: None,
+            session_id: None,
+            pinned_node: None,
+        }
+    }
 }
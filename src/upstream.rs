@@ -9,30 +9,120 @@
 //!
 //! The circuit breaker has three states:
 //! - **Healthy**: Node is operational and accepting requests
-//! - **Unhealthy**: Node has failed too many times and is temporarily disabled
-//! - **Cooldown**: After a cooldown period, unhealthy nodes can be retried
-use crate::types::{RpcRequest, RpcResponse, UpstreamConfig};
-use parking_lot::RwLock;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::{Duration, Instant};
-
-/// Maximum number of consecutive failures before opening the circuit.
+//! - **Unhealthy**: Node has failed too many times and is cooling down
+//! - **HalfOpen**: The cooldown is over, but the node stays out of rotation
+//!   until a successful probe closes the circuit again
+//!
+//! A failed probe while the circuit is open restarts the cooldown, so a
+//! flapping node never gets live traffic just because time has passed.
+//!
+//! Health-probe failures and client-request failures are counted separately,
+//! each against its own threshold, and either can open the circuit.
+//!
+//! Client-request failures open the circuit either after a run of consecutive
+//! failures (the default) or, in `error_rate` mode, when the share of failures
+//! over a sliding window of recent requests grows too high. The latter catches
+//! busy nodes whose intermittent errors never line up three in a row.
+//!
+//! # Concurrency Limits
+//!
+//! A node may cap its in-flight requests. With a queue configured, a request
+//! arriving at a saturated node waits up to the queue timeout for a slot before
+//! giving up so the load balancer can fail over. Saturation is not a health
+//! failure and never trips the circuit breaker.
+use crate::cache;
+use crate::load_balancer::HealthCheckSettings;
+use crate::trace_context::TRACEPARENT_HEADER;
+use crate::types::{
+    BreakerMode, ForwardError, REQUEST_ID_HEADER, RequestContext, RpcRequest, RpcResponse,
+    UpstreamConfig, VersionMismatch,
+};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+/// Default number of consecutive failures before opening the circuit.
 ///
 /// When a node reaches this threshold, it transitions to `NodeCondition::Unhealthy`
-/// and will not receive requests until the cooldown period expires.
-const MAX_CONSECUTIVE_FAILURES: usize = 3;
+/// and will not receive requests until the cooldown period expires. Overridden
+/// per node by `failure_threshold`.
+const DEFAULT_FAILURE_THRESHOLD: usize = 3;
 
-/// Duration a node must wait in unhealthy state before attempting recovery.
+/// Default duration a node must wait in unhealthy state before attempting recovery.
 ///
-/// After this cooldown period, the node becomes eligible for health checks
-/// and can potentially transition back to healthy state.
-const COOLDOWN_DURATION: Duration = Duration::from_secs(60);
+/// After this cooldown period, the node turns half-open and waits for a
+/// successful health check to transition back to healthy state. Overridden
+/// per node by `cooldown_ms`.
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
 
-/// Timeout duration for individual RPC requests.
+/// Default cap for the cooldown as it backs off on repeated reopening.
+/// Overridden per node by `max_cooldown_ms`.
+const DEFAULT_MAX_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// Default timeout for individual RPC requests.
 ///
 /// Requests exceeding this duration will be considered failed and contribute
-/// to the circuit breaker's failure count.
-const REQ_TIMEOUT: Duration = Duration::from_secs(5);
+/// to the circuit breaker's failure count. Overridden per node by
+/// `request_timeout_ms`.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default limit on a response body, after decompression. Overridden per node
+/// by `max_response_bytes`.
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Default limit on redirects followed per request, as in reqwest. Overridden
+/// per node by `max_redirects`.
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Weight of the newest sample in a node's moving average latency.
+const LATENCY_EWMA_ALPHA: f64 = 0.3;
+
+/// How many times the node's average latency a request may take to its
+/// response headers before it is guessed to have opened a new connection.
+const NEW_CONNECTION_LATENCY_RATIO: f64 = 3.0;
+
+/// Smallest excess over the average latency counted as connection setup, so
+/// jitter on sub-millisecond local nodes is not mistaken for a handshake.
+const NEW_CONNECTION_MIN_EXTRA_MS: f64 = 20.0;
+
+/// How far back a node's response bytes count toward `recent_bytes`.
+pub const RECENT_BYTES_WINDOW: Duration = Duration::from_secs(60);
+
+/// Granularity of the response byte accounting; samples within one bucket
+/// are summed, which bounds the history to a few dozen entries.
+const BYTES_BUCKET: Duration = Duration::from_secs(1);
+
+/// How far back a node's client requests count toward `recent_request_counts`.
+pub const RECENT_REQUESTS_WINDOW: Duration = Duration::from_secs(300);
+
+/// Granularity of the recent request accounting.
+const REQUESTS_BUCKET: Duration = Duration::from_secs(10);
+
+/// How long reqwest keeps an idle pooled connection before evicting it (library
+/// default). Overridden per node by `pool_idle_timeout_ms`.
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Upstream response headers that are never captured for forwarding.
+///
+/// Hop-by-hop headers describe the upstream connection only, and the entity
+/// headers are regenerated by the gateway for its own response body.
+pub const NON_FORWARDABLE_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+    "content-type",
+    "content-encoding",
+];
 
 /// Health status of an upstream RPC node.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -47,9 +137,14 @@ pub enum NodeCondition {
     /// Node has exceeded the failure threshold and is temporarily disabled.
     ///
     /// In this state, the node will not receive any traffic until the cooldown
-    /// period expires. After cooldown, a health check can transition the node
-    /// back to healthy state.
+    /// period expires, after which it turns half-open.
     Unhealthy,
+
+    /// Cooldown has expired and the node awaits a successful probe.
+    ///
+    /// The node is still kept out of rotation; a successful probe closes the
+    /// circuit and a failed one restarts the cooldown.
+    HalfOpen,
 }
 
 /// Represents a single upstream RPC node with circuit breaker logic.
@@ -62,8 +157,10 @@ pub enum NodeCondition {
 ///
 /// # Circuit Breaker Behavior
 ///
-/// - After `MAX_CONSECUTIVE_FAILURES` failures, the node transitions to unhealthy
-/// - Unhealthy nodes enter a cooldown period of `COOLDOWN_DURATION`
+/// - After `failure_threshold` failures, the node transitions to unhealthy
+/// - Unhealthy nodes enter a cooldown period of `cooldown_ms`, doubling (up to
+///   `max_cooldown_ms`) each time the circuit reopens before the node has
+///   stayed healthy for as long as its last cooldown
 /// - Successful requests reset the failure counter and restore health
 pub struct UpstreamNode {
     /// Configuration containing node name and URL.
@@ -75,8 +172,102 @@ pub struct UpstreamNode {
     /// Count of consecutive failures
     consecutive_failures: AtomicUsize,
 
+    /// Count of consecutive failed health probes, tracked apart from request failures.
+    consecutive_probe_failures: AtomicUsize,
+
+    /// Count of consecutive successful health probes, gating recovery.
+    consecutive_probe_successes: AtomicUsize,
+
+    /// Client requests to this node that succeeded and failed, since startup.
+    requests_succeeded: AtomicU64,
+    requests_failed: AtomicU64,
+
+    /// Wall-clock time of the latest failed request in Unix milliseconds, 0 if none.
+    last_failure_unix_ms: AtomicU64,
+
+    /// Recent request outcomes (`true` for a failure), kept in `error_rate` breaker mode.
+    recent_outcomes: Mutex<VecDeque<bool>>,
+
     /// HTTP client configured with timeout for making RPC requests.
     client: reqwest::Client,
+
+    /// Set while a health probe is running so overlapping probes are skipped.
+    health_check_in_progress: AtomicBool,
+
+    /// Number of requests estimated to have opened a new connection.
+    likely_new_connections: AtomicUsize,
+
+    /// Operator override that keeps the node out of rotation regardless of health.
+    drained: AtomicBool,
+
+    /// Set while the node reports a chain other than the gateway's, keeping
+    /// it unhealthy until its chain id checks out again.
+    wrong_chain: AtomicBool,
+
+    /// Until when an operator flagged the node as upgrading, softening its breaker.
+    upgrading_until: Mutex<Option<Instant>>,
+
+    /// Most recent `eth_gasPrice` reported by the node, in wei.
+    gas_price: Mutex<Option<u128>>,
+
+    /// Most recent `net_peerCount` reported by the node.
+    peer_count: Mutex<Option<u64>>,
+
+    /// Block height reported by the latest `eth_blockNumber` health probe.
+    latest_block: Mutex<Option<u64>>,
+
+    /// Exponentially weighted moving average of successful client request
+    /// latencies, in milliseconds. `None` until the first sample.
+    latency_ewma_ms: Mutex<Option<f64>>,
+
+    /// Response body bytes received, summed per `BYTES_BUCKET`, oldest first.
+    recent_bytes: Mutex<VecDeque<(Instant, u64)>>,
+
+    /// Client requests that succeeded and failed, counted per
+    /// `REQUESTS_BUCKET`, oldest first.
+    recent_requests: Mutex<VecDeque<(Instant, u64, u64)>>,
+
+    /// In-flight request slots, present when `max_concurrent_requests` is set.
+    slots: Option<Semaphore>,
+
+    /// Requests currently waiting in the queue for a slot.
+    queued: AtomicUsize,
+}
+
+/// A node's circuit breaker state, as saved across restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CircuitSnapshot {
+    /// The circuit was open (unhealthy or half-open).
+    pub open: bool,
+
+    /// Cooldown left when the state was saved, in milliseconds.
+    pub cooldown_remaining_ms: u64,
+
+    /// Times the circuit had reopened in a row. Non-zero marks a flapping
+    /// node, whose next cooldown is longer.
+    pub cooldown_attempts: u32,
+
+    /// Taken out of rotation by an operator or chain id quarantine.
+    pub drained: bool,
+}
+
+/// Clears the in-progress flag when a health probe finishes, even on panic.
+struct HealthCheckGuard<'a>(&'a AtomicBool);
+
+impl Drop for HealthCheckGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Counts a request as waiting in a node's queue until dropped, so callers
+/// cancelled mid-wait leave the count right.
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 /// Internal state of a node state
@@ -88,6 +279,12 @@ struct NodeState {
     /// Timestamp of the last failure, used to calculate cooldown expiration.
     /// `None` indicates the node has never failed or has fully recovered.
     last_failure_time: Option<Instant>,
+
+    /// Times the circuit reopened in a row, each doubling the cooldown.
+    cooldown_attempts: u32,
+
+    /// When the node last recovered, to tell a sustained recovery from a blip.
+    healthy_since: Option<Instant>,
 }
 
 impl UpstreamNode {
@@ -99,234 +296,1395 @@ impl UpstreamNode {
     /// # Arguments
     ///
     /// * `config` - Configuration containing the node's name and URL
-    /// 
-    pub fn new(config: UpstreamConfig) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(REQ_TIMEOUT)
+    ///
+    /// Fails, naming the node, if its URL is not an absolute `http(s)://` URL
+    /// (or its `ws_url` not a `ws(s)://` one) or its HTTP client cannot be
+    /// built (e.g. an invalid proxy URL).
+    pub fn new(config: UpstreamConfig) -> Result<Self, String> {
+        validate_urls(&config)?;
+        let client = ClientOptions::of(&config)
+            .builder(&format!("node {}", config.name))?
+            .timeout(request_timeout(&config))
+            .redirect(redirect_policy(&config))
             .build()
-            .expect("Failed to create HTTP client");
+            .map_err(|e| {
+                format!(
+                    "Failed to create HTTP client for node {}: {}",
+                    config.name, e
+                )
+            })?;
+        Ok(Self::with_client(config, client))
+    }
+
+    /// Creates a node sending its requests through `shared`, a client shared
+    /// with other nodes. The node's timeout still applies per request.
+    ///
+    /// Fails, naming the node, if it sets redirect options (the shared client
+    /// follows no redirects) or client-level options that differ from the
+    /// shared client's, rather than silently dropping them.
+    pub fn with_shared_client(
+        config: UpstreamConfig,
+        shared: &SharedClient,
+    ) -> Result<Self, String> {
+        validate_urls(&config)?;
+        if config.max_redirects.is_some_and(|limit| limit > 0)
+            || !config.redirect_allowed_hosts.is_empty()
+        {
+            return Err(format!(
+                "Node {} sets redirect options, which a shared HTTP client cannot honor",
+                config.name
+            ));
+        }
+        if ClientOptions::of(&config) != shared.options {
+            return Err(format!(
+                "Node {} sets client options that differ from the shared HTTP client's",
+                config.name
+            ));
+        }
+        Ok(Self::with_client(config, shared.client.clone()))
+    }
+
+    fn with_client(config: UpstreamConfig, client: reqwest::Client) -> Self {
+        let slots = config.max_concurrent_requests.map(Semaphore::new);
 
         Self {
             config,
             status: RwLock::new(NodeState {
                 health_status: NodeCondition::Healthy,
                 last_failure_time: None,
+                cooldown_attempts: 0,
+                healthy_since: None,
             }),
             consecutive_failures: AtomicUsize::new(0),
+            consecutive_probe_failures: AtomicUsize::new(0),
+            consecutive_probe_successes: AtomicUsize::new(0),
+            requests_succeeded: AtomicU64::new(0),
+            requests_failed: AtomicU64::new(0),
+            last_failure_unix_ms: AtomicU64::new(0),
+            recent_outcomes: Mutex::new(VecDeque::new()),
             client,
+            health_check_in_progress: AtomicBool::new(false),
+            likely_new_connections: AtomicUsize::new(0),
+            drained: AtomicBool::new(false),
+            wrong_chain: AtomicBool::new(false),
+            upgrading_until: Mutex::new(None),
+            gas_price: Mutex::new(None),
+            peer_count: Mutex::new(None),
+            latest_block: Mutex::new(None),
+            latency_ewma_ms: Mutex::new(None),
+            recent_bytes: Mutex::new(VecDeque::new()),
+            recent_requests: Mutex::new(VecDeque::new()),
+            slots,
+            queued: AtomicUsize::new(0),
         }
     }
 
     /// Checks if the node is currently healthy and ready to accept requests.
     ///
-    /// A node is considered healthy if:
-    /// - Its status is `NodeCondition::Healthy`, OR
-    /// - Its status is `NodeCondition::Unhealthy` but the cooldown period has expired
-    ///
+    /// Only `NodeCondition::Healthy` nodes are; half-open nodes wait for a
+    /// successful probe rather than testing themselves on live traffic.
     pub fn is_healthy(&self) -> bool {
+        self.get_status() == NodeCondition::Healthy
+    }
+
+    /// Returns true if the node has been taken out of rotation by an operator.
+    pub fn is_drained(&self) -> bool {
+        self.drained.load(Ordering::SeqCst)
+    }
+
+    /// Takes the node out of rotation (`true`) or puts it back (`false`).
+    ///
+    /// In-flight requests are unaffected; only new selections skip the node.
+    pub fn set_drained(&self, drained: bool) {
+        if self.drained.swap(drained, Ordering::SeqCst) != drained {
+            let action = if drained { "drained" } else { "restored" };
+            tracing::info!("Node {} {}", self.config.name, action);
+        }
+    }
+
+    /// Returns true while the node is flagged as upgrading.
+    pub fn is_upgrading(&self) -> bool {
+        self.upgrading_until
+            .lock()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Flags the node as upgrading for the configured window (`true`), or
+    /// clears the flag (`false`).
+    ///
+    /// While flagged, the breaker tolerates `upgrade.failure_threshold`
+    /// failures and uses the upgrade cooldown without backing off.
+    pub fn set_upgrading(&self, upgrading: bool) {
+        let window = Duration::from_millis(self.config.upgrade.window_ms);
+        *self.upgrading_until.lock() = upgrading.then(|| Instant::now() + window);
+        if upgrading {
+            tracing::info!(
+                "Node {} flagged as upgrading for {:?}",
+                self.config.name,
+                window
+            );
+        } else {
+            tracing::info!("Node {} no longer upgrading", self.config.name);
+        }
+    }
+
+    /// Closes the circuit and forgets past failures, for an operator who has
+    /// fixed the node and does not want to wait out the cooldown.
+    pub fn reset(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        self.consecutive_probe_failures.store(0, Ordering::SeqCst);
+        self.consecutive_probe_successes.store(0, Ordering::SeqCst);
+        self.recent_outcomes.lock().clear();
+        let mut state = self.status.write();
+        state.health_status = NodeCondition::Healthy;
+        state.last_failure_time = None;
+        state.cooldown_attempts = 0;
+        state.healthy_since = Some(Instant::now());
+        tracing::info!("Node {} reset and marked HEALTHY", self.config.name);
+    }
+
+    /// Captures the circuit state worth carrying over a restart.
+    pub fn circuit_snapshot(&self) -> CircuitSnapshot {
+        let open = self.get_status() != NodeCondition::Healthy;
+        let remaining = self.cooldown_remaining().unwrap_or_default();
+        CircuitSnapshot {
+            open,
+            cooldown_remaining_ms: remaining.as_millis() as u64,
+            cooldown_attempts: self.status.read().cooldown_attempts,
+            drained: self.is_drained(),
+        }
+    }
+
+    /// Re-applies a saved circuit state.
+    ///
+    /// An open circuit resumes its remaining cooldown and still needs a
+    /// successful probe to close. A flapping node keeps its backoff until it
+    /// has stayed healthy for a full cooldown.
+    pub fn restore_circuit(&self, snapshot: &CircuitSnapshot) {
+        let mut state = self.status.write();
+        state.cooldown_attempts = snapshot.cooldown_attempts;
+        if snapshot.open {
+            let elapsed = self
+                .cooldown(snapshot.cooldown_attempts)
+                .saturating_sub(Duration::from_millis(snapshot.cooldown_remaining_ms));
+            state.health_status = NodeCondition::Unhealthy;
+            state.last_failure_time = Instant::now().checked_sub(elapsed);
+            state.healthy_since = None;
+        } else if snapshot.cooldown_attempts > 0 {
+            state.healthy_since = Some(Instant::now());
+        }
+        drop(state);
+        self.set_drained(snapshot.drained);
+    }
+
+    /// Whether every in-flight slot is taken and there is no queue to wait in,
+    /// so a request sent now would be refused.
+    pub fn at_capacity(&self) -> bool {
+        self.config.queue.is_none()
+            && self
+                .slots
+                .as_ref()
+                .is_some_and(|slots| slots.available_permits() == 0)
+    }
+
+    /// Returns how long until an unhealthy node's cooldown expires.
+    ///
+    /// `None` for healthy nodes; `Some(Duration::ZERO)` once the cooldown is over.
+    pub fn cooldown_remaining(&self) -> Option<Duration> {
         let state = self.status.read();
         match state.health_status {
-            NodeCondition::Healthy => true,
-            NodeCondition::Unhealthy => {
-                // Check if cooldown period has expired
-                if let Some(last_failure) = state.last_failure_time {
-                    if Instant::now().duration_since(last_failure) >= COOLDOWN_DURATION {
-                        tracing::info!(
-                            "Node {} cooldown period expired, allowing retry",
-                            self.config.name
-                        );
-                        return true;
-                    }
-                }
-                false
-            }
+            NodeCondition::Healthy => None,
+            NodeCondition::HalfOpen => Some(Duration::ZERO),
+            NodeCondition::Unhealthy => Some(
+                state
+                    .last_failure_time
+                    .map(|at| {
+                        self.cooldown(state.cooldown_attempts)
+                            .saturating_sub(at.elapsed())
+                    })
+                    .unwrap_or(Duration::ZERO),
+            ),
         }
     }
 
-    /// Performs an active health check by calling `eth_blockNumber`.
+    /// Test helper, probes with the built-in health check defaults.
+    #[cfg(test)]
     pub async fn check_health(&self) -> bool {
+        self.check_health_with(&HealthCheckSettings::default())
+            .await
+    }
+
+    /// Performs an active health check by calling the node's configured probe,
+    /// falling back to the deployment-wide one in `defaults`.
+    pub async fn check_health_with(&self, defaults: &HealthCheckSettings) -> bool {
+        let probe = self
+            .config
+            .health_check
+            .clone()
+            .unwrap_or_else(|| defaults.probe.clone());
         let request = RpcRequest {
             jsonrpc: "2.0".to_string(),
-            method: "eth_blockNumber".to_string(),
-            params: serde_json::Value::Array(vec![]),
+            method: probe.method,
+            params: probe.params,
             id: serde_json::Value::String("health_check".to_string()),
         };
 
-        match self.call_rpc_internal(&request).await {
-            Ok(_) => {
-                self.record_success();
+        match self
+            .call_rpc_internal(&request, &RequestContext::internal())
+            .await
+        {
+            Ok(response) => {
+                if request.method == "eth_blockNumber" {
+                    *self.latest_block.lock() = response
+                        .result
+                        .as_ref()
+                        .and_then(|v| v.as_str())
+                        .and_then(|s| s.strip_prefix("0x"))
+                        .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+                }
+                self.record_probe_success(
+                    self.config
+                        .recovery_probes
+                        .unwrap_or(defaults.recovery_probes),
+                );
                 true
             }
             Err(e) => {
                 tracing::warn!("Health check failed for node {}: {}", self.config.name, e);
-                self.record_failure();
+                self.record_probe_failure();
                 false
             }
         }
     }
 
-    /// Calls the upstream RPC node with the given request.
-    pub async fn call_rpc(&self, request: &RpcRequest) -> Result<RpcResponse, String> {
-        self.call_rpc_internal(request).await.map_err(|e| {
-            self.record_failure();
-            e
-        })
-    }
+    /// Polls `eth_gasPrice` and remembers the result for gas-price routing.
+    ///
+    /// Failures only clear the stored price; health is tracked by `check_health_with`.
+    pub async fn refresh_gas_price(&self) {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_gasPrice".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::Value::String("gas_price".to_string()),
+        };
 
-    async fn call_rpc_internal(&self, request: &RpcRequest) -> Result<RpcResponse, String> {
-        let response = self
-            .client
-            .post(&self.config.url)
-            .json(request)
-            .send()
+        let price = match self
+            .call_rpc_internal(&request, &RequestContext::internal())
             .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        {
+            Ok(response) => response
+                .result
+                .as_ref()
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.strip_prefix("0x"))
+                .and_then(|hex| u128::from_str_radix(hex, 16).ok()),
+            Err(e) => {
+                tracing::debug!("Gas price poll failed for node {}: {}", self.config.name, e);
+                None
+            }
+        };
+        *self.gas_price.lock() = price;
+    }
 
-        if !response.status().is_success() {
-            return Err(format!("HTTP error: {}", response.status()));
-        }
+    /// Polls `net_peerCount` and remembers the result for peer-count weighting.
+    ///
+    /// Failures only clear the stored count; health is tracked by `check_health_with`.
+    pub async fn refresh_peer_count(&self) {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "net_peerCount".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::Value::String("peer_count".to_string()),
+        };
 
-        let rpc_response: RpcResponse = response
-            .json()
+        let peers = match self
+            .call_rpc_internal(&request, &RequestContext::internal())
             .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-        if rpc_response.error.is_some() {
-            return Err(format!("RPC error: {:?}", rpc_response.error));
-        }
+        {
+            Ok(response) => response
+                .result
+                .as_ref()
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.strip_prefix("0x"))
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok()),
+            Err(e) => {
+                tracing::debug!(
+                    "Peer count poll failed for node {}: {}",
+                    self.config.name,
+                    e
+                );
+                None
+            }
+        };
+        *self.peer_count.lock() = peers;
+    }
 
-        self.record_success();
-        Ok(rpc_response)
+    /// Returns the last peer count reported by this node, if known.
+    pub fn peer_count(&self) -> Option<u64> {
+        *self.peer_count.lock()
     }
 
-    
-    /// Records a successful request and potentially recovers the node.
-    ///
-    /// This method:
-    /// - Resets the consecutive failure counter to zero
-    /// - Transitions unhealthy nodes back to healthy state
-    /// - Clears the last failure timestamp
-    fn record_success(&self) {
-        let prev_failures = self.consecutive_failures.swap(0, Ordering::SeqCst);
-        let mut state = self.status.write();
-        if state.health_status == NodeCondition::Unhealthy {
-            tracing::info!("Node {} recovered and marked HEALTHY", self.config.name);
-            state.health_status = NodeCondition::Healthy;
-            state.last_failure_time = None;
-        } else if prev_failures > 0 {
-            tracing::debug!(
-                "Node {} success, reset failure count from {}",
-                self.config.name,
-                prev_failures
-            );
-        }
+    /// Asks the node which chain it serves via `eth_chainId`.
+    pub async fn fetch_chain_id(&self) -> Result<u64, ForwardError> {
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_chainId".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::Value::String("chain_id".to_string()),
+        };
+
+        let response = self
+            .call_rpc_internal(&request, &RequestContext::internal())
+            .await?;
+        response
+            .result
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .and_then(|s| s.strip_prefix("0x"))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| ForwardError::Internal("invalid eth_chainId result".to_string()))
     }
 
-    /// Records a failed request and potentially opens the circuit.
+    /// Checks that the node still serves chain `expected`, marking it unhealthy
+    /// if it reports another one and clearing an earlier mark if it does not.
     ///
-    /// This method:
-    /// - Increments the consecutive failure counter atomically
-    /// - Transitions to unhealthy state after reaching the threshold
-    /// - Records the failure timestamp for cooldown tracking
-    fn record_failure(&self) {
-        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
-        tracing::warn!("Node {} failure #{} recorded", self.config.name, failures);
-        if failures >= MAX_CONSECUTIVE_FAILURES {
-            let mut state = self.status.write();
-            if state.health_status == NodeCondition::Healthy {
-                tracing::error!(
-                    "Node {} reached {} consecutive failures, marking UNHEALTHY",
-                    self.config.name,
-                    failures
-                );
-                state.health_status = NodeCondition::Unhealthy;
-                state.last_failure_time = Some(Instant::now());
+    /// Returns false only on a mismatch; a node that cannot be asked is left to
+    /// the regular health probe.
+    pub async fn verify_chain_id(&self, expected: u64) -> bool {
+        match self.fetch_chain_id().await {
+            Ok(chain_id) if chain_id != expected => {
+                self.mark_wrong_chain(chain_id, expected);
+                false
+            }
+            Ok(_) => {
+                if self.wrong_chain.swap(false, Ordering::SeqCst) {
+                    tracing::info!(
+                        "Node {} serves chain id {} again",
+                        self.config.name,
+                        expected
+                    );
+                }
+                true
+            }
+            Err(e) => {
+                tracing::debug!("Chain id check failed for node {}: {}", self.config.name, e);
+                true
             }
         }
     }
 
-    pub fn get_name(&self) -> &str {
-        &self.config.name
+    /// Marks the node unhealthy for serving chain `chain_id` instead of `expected`.
+    ///
+    /// Unlike other failures this outlasts the cooldown: the node stays
+    /// unhealthy until `verify_chain_id` sees the expected chain.
+    pub fn mark_wrong_chain(&self, chain_id: u64, expected: u64) {
+        tracing::error!(
+            "Node {} reports chain id {}, expected {}",
+            self.config.name,
+            chain_id,
+            expected
+        );
+        self.wrong_chain.store(true, Ordering::SeqCst);
+        self.open_circuit(1, "chain id mismatch");
     }
 
-    /// Returns the current health status of this node.
-    ///
-    /// # Returns
-    ///
-    /// The current `NodeCondition` (Healthy or Unhealthy)
-    pub fn get_status(&self) -> NodeCondition {
-        self.status.read().health_status
+    /// Returns true while the node is marked as serving the wrong chain.
+    pub fn on_wrong_chain(&self) -> bool {
+        self.wrong_chain.load(Ordering::SeqCst)
     }
 
-    // Test helper
-    #[cfg(test)]
-    pub fn get_consecutive_failures(&self) -> usize {
-        self.consecutive_failures.load(Ordering::SeqCst)
+    /// Returns the block height seen by the last health probe, if known.
+    pub fn latest_block(&self) -> Option<u64> {
+        *self.latest_block.lock()
     }
 
-    /// Test helper, allows testing circuit breaker logic.
-    #[cfg(test)]
-    pub fn force_mark_failure(&self) {
-        self.record_failure();
+    /// Returns the moving average latency of successful requests, if any were made.
+    pub fn average_latency_ms(&self) -> Option<f64> {
+        *self.latency_ewma_ms.lock()
     }
 
-    /// Test helper, allows testing recovery logic.
-    #[cfg(test)]
-    pub fn force_mark_success(&self) {
-        self.record_success();
+    fn record_latency(&self, latency: Duration) {
+        let sample = latency.as_secs_f64() * 1000.0;
+        let mut average = self.latency_ewma_ms.lock();
+        *average = Some(match *average {
+            Some(previous) => previous + LATENCY_EWMA_ALPHA * (sample - previous),
+            None => sample,
+        });
     }
-}
 
+    /// Response body bytes received from this node within `RECENT_BYTES_WINDOW`.
+    pub fn recent_bytes(&self) -> u64 {
+        self.recent_bytes
+            .lock()
+            .iter()
+            .filter(|(started, _)| started.elapsed() <= RECENT_BYTES_WINDOW)
+            .map(|(_, bytes)| bytes)
+            .sum()
+    }
 
+    fn record_bytes(&self, bytes: u64) {
+        let now = Instant::now();
+        let mut buckets = self.recent_bytes.lock();
+        match buckets.back_mut() {
+            Some((started, total)) if now.duration_since(*started) < BYTES_BUCKET => {
+                *total += bytes
+            }
+            _ => buckets.push_back((now, bytes)),
+        }
+        while buckets
+            .front()
+            .is_some_and(|(started, _)| now.duration_since(*started) > RECENT_BYTES_WINDOW)
+        {
+            buckets.pop_front();
+        }
+    }
 
+    /// Client requests that succeeded and failed on this node within
+    /// `RECENT_REQUESTS_WINDOW`.
+    pub fn recent_request_counts(&self) -> (u64, u64) {
+        self.recent_requests
+            .lock()
+            .iter()
+            .filter(|(started, _, _)| started.elapsed() <= RECENT_REQUESTS_WINDOW)
+            .fold((0, 0), |(ok, failed), (_, s, f)| (ok + s, failed + f))
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn record_request_outcome(&self, failed: bool) {
+        let now = Instant::now();
+        let mut buckets = self.recent_requests.lock();
+        if buckets
+            .back()
+            .is_none_or(|(started, _, _)| now.duration_since(*started) >= REQUESTS_BUCKET)
+        {
+            buckets.push_back((now, 0, 0));
+        }
+        if let Some((_, succeeded, failures)) = buckets.back_mut() {
+            *if failed { failures } else { succeeded } += 1;
+        }
+        while buckets
+            .front()
+            .is_some_and(|(started, _, _)| now.duration_since(*started) > RECENT_REQUESTS_WINDOW)
+        {
+            buckets.pop_front();
+        }
+    }
 
-    fn create_test_node(name: &str) -> UpstreamNode {
-        UpstreamNode::new(UpstreamConfig {
-            name: name.to_string(),
-            url: "http://invalid-test-url:9999".to_string(),
-        })
+    /// Returns the last gas price reported by this node, if known.
+    pub fn gas_price(&self) -> Option<u128> {
+        *self.gas_price.lock()
     }
 
+    /// Runs `check_health_with` unless a previous probe for this node is still running.
+    ///
+    /// Returns `None` when the probe was skipped because one is already in flight.
+    pub async fn check_health_exclusive(&self, defaults: &HealthCheckSettings) -> Option<bool> {
+        if self
+            .health_check_in_progress
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            tracing::warn!(
+                "Health check for node {} still running, skipping this tick",
+                self.config.name
+            );
+            return None;
+        }
 
-    #[test]
-    fn test_single_failure_keeps_node_healthy() {
-        let node = create_test_node("TestNode");
-        node.force_mark_failure();
-        assert_eq!(node.get_status(), NodeCondition::Healthy);
-        assert!(node.is_healthy());
-        assert_eq!(node.get_consecutive_failures(), 1);
+        let _guard = HealthCheckGuard(&self.health_check_in_progress);
+        Some(self.check_health_with(defaults).await)
     }
 
-    #[test]
-    fn test_three_failures_opens_circuit() {
-        let node = create_test_node("TestNode");
+    /// Calls the upstream RPC node with the given request.
+    ///
+    /// Waits for a concurrency slot first if the node is limited; failing to get
+    /// one is reported as an error without counting against the node's health.
+    /// A JSON-RPC error answer shows the node is up, so only transport failures,
+    /// timeouts and malformed responses count as failures.
+    pub async fn call_rpc(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<RpcResponse, ForwardError> {
+        let _slot = self.acquire_slot().await?;
+        let started = Instant::now();
+        let result = self.call_rpc_internal(request, ctx).await;
+        match &result {
+            Ok(_) | Err(ForwardError::Rpc { .. }) => {
+                self.record_latency(started.elapsed());
+                self.record_success()
+            }
+            Err(_) => self.record_failure(),
+        }
+        result
+    }
 
-        // Balancer shall break the circuit here.
-        node.force_mark_failure();
-        node.force_mark_failure();
-        node.force_mark_failure();
-        
-        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
-        assert!(!node.is_healthy());
-        assert_eq!(node.get_consecutive_failures(), 3);
+    /// Sends a JSON-RPC notification, checking only the HTTP status.
+    ///
+    /// Upstreams answer notifications with an empty body, if at all, so there
+    /// is nothing to parse, and the outcome does not count toward the circuit
+    /// breaker.
+    pub async fn notify(&self, request: &RpcRequest, ctx: &RequestContext) -> Result<(), String> {
+        let _slot = self.acquire_slot().await?;
+        let response = self
+            .build_request(request, ctx)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()));
+        }
+        Ok(())
     }
 
-    #[test]
+    /// Takes an in-flight slot, queueing for one if configured.
+    ///
+    /// Returns `None` when the node has no concurrency limit.
+    async fn acquire_slot(&self) -> Result<Option<SemaphorePermit<'_>>, String> {
+        let Some(slots) = &self.slots else {
+            return Ok(None);
+        };
+        if let Ok(permit) = slots.try_acquire() {
+            return Ok(Some(permit));
+        }
+
+        let Some(queue) = &self.config.queue else {
+            return Err(format!("Node {} at concurrency limit", self.config.name));
+        };
+        let waiting = self.queued.fetch_add(1, Ordering::SeqCst);
+        let _queued = QueuedGuard(&self.queued);
+        if waiting >= queue.max_waiting {
+            return Err(format!("Node {} queue is full", self.config.name));
+        }
+
+        let timeout = Duration::from_millis(queue.timeout_ms);
+        let waited = tokio::time::timeout(timeout, slots.acquire()).await;
+        match waited {
+            Ok(Ok(permit)) => Ok(Some(permit)),
+            Ok(Err(_)) => Err(format!("Node {} is shutting down", self.config.name)),
+            Err(_) => Err(format!(
+                "Node {} had no free slot within {:?}",
+                self.config.name, timeout
+            )),
+        }
+    }
+
+    /// Builds the outgoing HTTP request, attaching basic-auth credentials and an
+    /// HMAC body signature if configured, plus the trace context from `ctx`.
+    ///
+    /// reqwest base64-encodes `username:password` itself, so reserved characters in
+    /// the password need no escaping. The body is serialized here, rather than via
+    /// `.json()`, so the signature covers exactly the bytes sent.
+    fn build_request(&self, request: &RpcRequest, ctx: &RequestContext) -> reqwest::RequestBuilder {
+        let body = serde_json::to_vec(request).unwrap_or_default();
+        let mut builder = self
+            .client
+            .post(&self.config.url)
+            .timeout(request_timeout(&self.config))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TRACEPARENT_HEADER, ctx.trace.to_header_value())
+            .header(REQUEST_ID_HEADER, &ctx.request_id);
+        if let Some(signing) = &self.config.request_signing {
+            builder = builder.header(signing.header.as_str(), signing.sign(&body));
+        }
+        if let Some(auth) = &self.config.basic_auth {
+            builder = builder.basic_auth(&auth.username, Some(&auth.password));
+        }
+        builder.body(body)
+    }
+
+    /// Guesses whether a request that took `to_headers` to its response headers
+    /// had to open a new connection.
+    ///
+    /// reqwest exposes no pool hooks, so this compares against the node's
+    /// average latency: a request with no average yet is cold, and one far
+    /// slower than the average most likely paid for a TCP and TLS handshake.
+    /// A node that is merely slow for a moment is flagged too.
+    fn likely_new_connection(&self, to_headers: Duration) -> bool {
+        let elapsed = to_headers.as_secs_f64() * 1000.0;
+        self.average_latency_ms().is_none_or(|average| {
+            elapsed > average * NEW_CONNECTION_LATENCY_RATIO
+                && elapsed - average > NEW_CONNECTION_MIN_EXTRA_MS
+        })
+    }
+
+    async fn call_rpc_internal(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+    ) -> Result<RpcResponse, ForwardError> {
+        let started = Instant::now();
+        let response = self
+            .build_request(request, ctx)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        if self.likely_new_connection(started.elapsed()) {
+            self.likely_new_connections.fetch_add(1, Ordering::Relaxed);
+            if self.config.log_new_connections {
+                tracing::info!(
+                    "Node {} request likely opened a new connection ({}ms to response headers)",
+                    self.config.name,
+                    started.elapsed().as_millis()
+                );
+            }
+        }
+
+        if !response.status().is_success() {
+            return Err(format!("HTTP error: {}", response.status()).into());
+        }
+
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !NON_FORWARDABLE_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+
+        let body = self.read_body(response).await?;
+        self.record_bytes(body.len() as u64);
+        let mut rpc_response: RpcResponse = serde_json::from_slice(&body)
+            .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+        // JSON-RPC 2.0 forbids both members; trusting either half would be a guess
+        if rpc_response.result.is_some() && rpc_response.error.is_some() {
+            tracing::warn!(
+                "Node {} violated JSON-RPC: response has both result and error",
+                self.config.name
+            );
+            return Err("Protocol violation: response has both result and error"
+                .to_string()
+                .into());
+        }
+        if rpc_response.jsonrpc != "2.0" {
+            match self.config.version_mismatch {
+                VersionMismatch::Forward => {}
+                VersionMismatch::Normalize => rpc_response.jsonrpc = "2.0".to_string(),
+                VersionMismatch::Reject => {
+                    tracing::warn!(
+                        "Node {} violated JSON-RPC: response declares version {:?}",
+                        self.config.name,
+                        rpc_response.jsonrpc
+                    );
+                    return Err(format!(
+                        "Protocol violation: response declares jsonrpc {:?}",
+                        rpc_response.jsonrpc
+                    )
+                    .into());
+                }
+            }
+        }
+        if let Some(error) = rpc_response.error.take() {
+            return Err(ForwardError::Rpc {
+                error,
+                node: self.config.name.clone(),
+            });
+        }
+        if self.config.validate_results {
+            let result = rpc_response
+                .result
+                .as_ref()
+                .unwrap_or(&serde_json::Value::Null);
+            if !cache::is_well_formed_result(&request.method, result)
+                && !self.confirms_null_receipt(request, ctx, result).await
+            {
+                tracing::warn!(
+                    "Node {} returned a malformed {} result ({} bytes)",
+                    self.config.name,
+                    request.method,
+                    result.to_string().len()
+                );
+                return Err(format!("Malformed result for {}", request.method).into());
+            }
+        }
+        rpc_response.meta.headers = headers;
+        rpc_response.meta.served_by = Some(self.config.name.clone());
+
+        Ok(rpc_response)
+    }
+
+    /// Whether a `null` receipt is genuine: the node does not know the
+    /// transaction as mined either. A node returning the mined transaction
+    /// without its receipt is missing data.
+    async fn confirms_null_receipt(
+        &self,
+        request: &RpcRequest,
+        ctx: &RequestContext,
+        result: &serde_json::Value,
+    ) -> bool {
+        if !result.is_null() || request.method != "eth_getTransactionReceipt" {
+            return false;
+        }
+        let lookup = RpcRequest {
+            method: "eth_getTransactionByHash".to_string(),
+            ..request.clone()
+        };
+        match Box::pin(self.call_rpc_internal(&lookup, ctx)).await {
+            Ok(response) => response
+                .result
+                .as_ref()
+                .and_then(|tx| tx.get("blockHash"))
+                .is_none_or(serde_json::Value::is_null),
+            Err(_) => false,
+        }
+    }
+
+    /// Reads a response body, giving up once it exceeds `max_response_bytes`.
+    ///
+    /// Chunks arrive already decompressed, so the limit bounds what a gzip
+    /// response expands to rather than what crossed the wire.
+    async fn read_body(&self, mut response: reqwest::Response) -> Result<Vec<u8>, String> {
+        let limit = self
+            .config
+            .max_response_bytes
+            .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES);
+        let too_large = || {
+            tracing::warn!(
+                "Node {} response exceeded {} bytes, aborting",
+                self.config.name,
+                limit
+            );
+            format!("Response exceeds the limit of {} bytes", limit)
+        };
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit as u64)
+        {
+            return Err(too_large());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?
+        {
+            if body.len() + chunk.len() > limit {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Records a successful request and potentially recovers the node.
+    ///
+    /// This method:
+    /// - Resets the consecutive failure counter to zero
+    /// - Transitions unhealthy nodes back to healthy state
+    /// - Clears the last failure timestamp
+    fn record_success(&self) {
+        let prev_failures = self.consecutive_failures.swap(0, Ordering::SeqCst);
+        self.requests_succeeded.fetch_add(1, Ordering::Relaxed);
+        self.record_request_outcome(false);
+        self.note_outcome(false);
+        self.recover(prev_failures);
+    }
+
+    /// Records a successful health probe, resetting only the probe failure counter.
+    ///
+    /// An open circuit closes once `required` probes in a row have succeeded.
+    fn record_probe_success(&self, required: usize) {
+        let prev_failures = self.consecutive_probe_failures.swap(0, Ordering::SeqCst);
+        let successes = self
+            .consecutive_probe_successes
+            .fetch_add(1, Ordering::SeqCst)
+            + 1;
+        if successes < required && self.get_status() != NodeCondition::Healthy {
+            tracing::info!(
+                "Node {} passed {} of {} probes needed to recover",
+                self.config.name,
+                successes,
+                required
+            );
+            return;
+        }
+        self.recover(prev_failures);
+    }
+
+    fn recover(&self, prev_failures: usize) {
+        let mut state = self.status.write();
+        if state.health_status != NodeCondition::Healthy {
+            tracing::info!("Node {} recovered and marked HEALTHY", self.config.name);
+            state.health_status = NodeCondition::Healthy;
+            state.last_failure_time = None;
+            state.healthy_since = Some(Instant::now());
+            // Start the error rate afresh so old failures cannot re-trip the node
+            self.recent_outcomes.lock().clear();
+        } else if prev_failures > 0 {
+            tracing::debug!(
+                "Node {} success, reset failure count from {}",
+                self.config.name,
+                prev_failures
+            );
+        }
+    }
+
+    /// Consecutive failed requests that open this node's circuit.
+    fn failure_threshold(&self) -> usize {
+        let threshold = self
+            .config
+            .failure_threshold
+            .unwrap_or(DEFAULT_FAILURE_THRESHOLD);
+        self.soften_threshold(threshold)
+    }
+
+    /// Consecutive failed health probes that open this node's circuit.
+    fn probe_failure_threshold(&self) -> usize {
+        let threshold = self.config.probe_failure_threshold.unwrap_or(
+            self.config
+                .failure_threshold
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD),
+        );
+        self.soften_threshold(threshold)
+    }
+
+    /// Raises `threshold` to the upgrade threshold while the node is upgrading.
+    fn soften_threshold(&self, threshold: usize) -> usize {
+        if self.is_upgrading() {
+            threshold.max(self.config.upgrade.failure_threshold)
+        } else {
+            threshold
+        }
+    }
+
+    /// How long this node's circuit stays open before a probe is allowed,
+    /// after it has reopened `attempts` times in a row.
+    fn cooldown(&self, attempts: u32) -> Duration {
+        let cooldown_ms = match self.config.upgrade.cooldown_ms {
+            Some(ms) if self.is_upgrading() => Some(ms),
+            _ => self.config.cooldown_ms,
+        };
+        let base = cooldown_ms.map_or(DEFAULT_COOLDOWN, Duration::from_millis);
+        let max = self
+            .config
+            .max_cooldown_ms
+            .map_or(DEFAULT_MAX_COOLDOWN, Duration::from_millis)
+            .max(base);
+        base.saturating_mul(2u32.saturating_pow(attempts)).min(max)
+    }
+
+    /// Records a failed request and potentially opens the circuit.
+    ///
+    /// This method:
+    /// - Increments the consecutive failure counter atomically, up to the
+    ///   failure threshold and not while the circuit is open, so it stays
+    ///   bounded for a node that is down for hours
+    /// - Transitions to unhealthy state after reaching the threshold
+    /// - Records the failure timestamp for cooldown tracking
+    pub fn record_failure(&self) {
+        let threshold = self.failure_threshold();
+        let circuit_open = self.status.read().health_status == NodeCondition::Unhealthy;
+        let failures =
+            match self
+                .consecutive_failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (!circuit_open && n < threshold).then_some(n + 1)
+                }) {
+                Ok(previous) => previous + 1,
+                Err(current) => current,
+            };
+        self.requests_failed.fetch_add(1, Ordering::Relaxed);
+        self.record_request_outcome(true);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        self.last_failure_unix_ms.store(now_ms, Ordering::Relaxed);
+        tracing::warn!("Node {} failure #{} recorded", self.config.name, failures);
+        match &self.config.circuit_breaker {
+            BreakerMode::ConsecutiveFailures => {
+                if failures >= self.failure_threshold() {
+                    self.open_circuit(failures, "consecutive failures");
+                }
+            }
+            BreakerMode::ErrorRate {
+                max_error_percent, ..
+            } => {
+                if let Some((failed, total)) = self.note_outcome(true)
+                    && failed as f64 * 100.0 > max_error_percent * total as f64
+                {
+                    self.open_circuit(failed, &format!("failures in the last {} requests", total));
+                }
+            }
+        }
+    }
+
+    /// Adds an outcome to the error-rate window, returning the failed and total
+    /// counts once the window holds enough samples to judge.
+    ///
+    /// Does nothing outside `error_rate` breaker mode.
+    fn note_outcome(&self, failed: bool) -> Option<(usize, usize)> {
+        let BreakerMode::ErrorRate {
+            window,
+            min_requests,
+            ..
+        } = self.config.circuit_breaker
+        else {
+            return None;
+        };
+        let mut outcomes = self.recent_outcomes.lock();
+        outcomes.push_back(failed);
+        while outcomes.len() > window.max(1) {
+            outcomes.pop_front();
+        }
+        let total = outcomes.len();
+        (total >= min_requests).then(|| (outcomes.iter().filter(|&&f| f).count(), total))
+    }
+
+    /// Records a failed health probe against the node's probe threshold.
+    ///
+    /// While the circuit is open any failed probe restarts the cooldown.
+    fn record_probe_failure(&self) {
+        self.consecutive_probe_successes.store(0, Ordering::SeqCst);
+        let failures = self
+            .consecutive_probe_failures
+            .fetch_add(1, Ordering::SeqCst)
+            + 1;
+        tracing::warn!(
+            "Node {} health probe failure #{} recorded",
+            self.config.name,
+            failures
+        );
+        if failures >= self.probe_failure_threshold() || self.get_status() != NodeCondition::Healthy
+        {
+            self.open_circuit(failures, "consecutive failed health probes");
+        }
+    }
+
+    /// Marks the node unhealthy, or restarts the cooldown if it already was.
+    ///
+    /// An upgrading node neither backs off its cooldown nor logs an error,
+    /// since its failures were announced.
+    fn open_circuit(&self, failures: usize, what: &str) {
+        let upgrading = self.is_upgrading();
+        self.consecutive_probe_successes.store(0, Ordering::SeqCst);
+        let mut state = self.status.write();
+        match state.health_status {
+            NodeCondition::Healthy | NodeCondition::HalfOpen if upgrading => {
+                tracing::info!(
+                    "Node {} reached {} {} while upgrading, out of rotation for {:?}",
+                    self.config.name,
+                    failures,
+                    what,
+                    self.cooldown(state.cooldown_attempts)
+                )
+            }
+            NodeCondition::Healthy => {
+                // Only a recovery that outlasted the last cooldown earns the
+                // base cooldown back; a node that fails again sooner backs off
+                let last_cooldown = self.cooldown(state.cooldown_attempts);
+                if state
+                    .healthy_since
+                    .is_none_or(|at| at.elapsed() >= last_cooldown)
+                {
+                    state.cooldown_attempts = 0;
+                } else {
+                    state.cooldown_attempts = state.cooldown_attempts.saturating_add(1);
+                }
+                tracing::error!(
+                    "Node {} reached {} {}, marking UNHEALTHY for {:?}",
+                    self.config.name,
+                    failures,
+                    what,
+                    self.cooldown(state.cooldown_attempts)
+                )
+            }
+            NodeCondition::HalfOpen => {
+                state.cooldown_attempts = state.cooldown_attempts.saturating_add(1);
+                tracing::warn!(
+                    "Node {} failed while HALF-OPEN, restarting cooldown of {:?}",
+                    self.config.name,
+                    self.cooldown(state.cooldown_attempts)
+                )
+            }
+            NodeCondition::Unhealthy => {}
+        }
+        state.health_status = NodeCondition::Unhealthy;
+        state.last_failure_time = Some(Instant::now());
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Returns this node's round-robin weight, at least 1.
+    pub fn weight(&self) -> u32 {
+        self.config.weight.unwrap_or(1).max(1)
+    }
+
+    /// Fallback tier this node belongs to; 0 is the primary tier.
+    pub fn tier(&self) -> u8 {
+        self.config.tier
+    }
+
+    /// Whether this node is a break-glass standby.
+    pub fn is_standby(&self) -> bool {
+        self.config.standby
+    }
+
+    /// Returns the labels configured for this node.
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.config.labels
+    }
+
+    /// Whether this node declares the given capability tag.
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.config.capabilities.contains(capability)
+    }
+
+    /// Whether this node carries every one of the given labels.
+    pub fn has_labels(&self, wanted: &HashMap<String, String>) -> bool {
+        wanted
+            .iter()
+            .all(|(key, value)| self.config.labels.get(key) == Some(value))
+    }
+
+    /// Returns the current health status of this node.
+    ///
+    /// # Returns
+    ///
+    /// The current `NodeCondition`. An unhealthy node whose cooldown has
+    /// expired is moved to `HalfOpen` here. A node on the wrong chain is
+    /// unhealthy regardless.
+    pub fn get_status(&self) -> NodeCondition {
+        if self.on_wrong_chain() {
+            return NodeCondition::Unhealthy;
+        }
+        let cooled_down = |state: &NodeState| {
+            state.health_status == NodeCondition::Unhealthy
+                && state
+                    .last_failure_time
+                    .is_none_or(|at| at.elapsed() >= self.cooldown(state.cooldown_attempts))
+        };
+        {
+            let state = self.status.read();
+            if !cooled_down(&state) {
+                return state.health_status;
+            }
+        }
+
+        let mut state = self.status.write();
+        if cooled_down(&state) {
+            tracing::info!(
+                "Node {} cooldown period expired, HALF-OPEN until a successful probe",
+                self.config.name
+            );
+            state.health_status = NodeCondition::HalfOpen;
+        }
+        state.health_status
+    }
+
+    /// Returns how many client requests succeeded and failed on this node.
+    pub fn request_counts(&self) -> (u64, u64) {
+        (
+            self.requests_succeeded.load(Ordering::Relaxed),
+            self.requests_failed.load(Ordering::Relaxed),
+        )
+    }
+
+    /// When a client request last failed on this node, in Unix milliseconds.
+    pub fn last_failure_unix_ms(&self) -> Option<u64> {
+        Some(self.last_failure_unix_ms.load(Ordering::Relaxed)).filter(|&ms| ms > 0)
+    }
+
+    /// Returns the number of failures since the last success.
+    pub fn get_consecutive_failures(&self) -> usize {
+        self.consecutive_failures.load(Ordering::SeqCst)
+    }
+
+    // Test helper
+    #[cfg(test)]
+    pub fn get_consecutive_probe_failures(&self) -> usize {
+        self.consecutive_probe_failures.load(Ordering::SeqCst)
+    }
+
+    // Test helper
+    #[cfg(test)]
+    pub fn get_likely_new_connections(&self) -> usize {
+        self.likely_new_connections.load(Ordering::Relaxed)
+    }
+
+    /// Test helper, counts response bytes as if a request had returned them.
+    #[cfg(test)]
+    pub fn force_record_bytes(&self, bytes: u64) {
+        self.record_bytes(bytes);
+    }
+
+    /// Test helper, allows testing circuit breaker logic.
+    #[cfg(test)]
+    pub fn force_mark_failure(&self) {
+        self.record_failure();
+    }
+
+    /// Test helper, allows testing recovery logic.
+    #[cfg(test)]
+    pub fn force_mark_success(&self) {
+        self.record_success();
+    }
+
+    /// Test helper, backdates the last failure so the cooldown is over.
+    #[cfg(test)]
+    pub fn force_cooldown_expiry(&self) {
+        let mut state = self.status.write();
+        state.last_failure_time =
+            Instant::now().checked_sub(self.cooldown(state.cooldown_attempts));
+    }
+}
+
+/// Checks that the node's URLs are absolute and use the expected schemes.
+fn validate_urls(config: &UpstreamConfig) -> Result<(), String> {
+    validate_url(&config.name, "url", &config.url, &["http", "https"])?;
+    if let Some(ws_url) = &config.ws_url {
+        validate_url(&config.name, "ws_url", ws_url, &["ws", "wss"])?;
+    }
+    Ok(())
+}
+
+/// How long a request to the node may take end to end.
+fn request_timeout(config: &UpstreamConfig) -> Duration {
+    config
+        .request_timeout_ms
+        .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_millis)
+}
+
+/// Client-level settings of a node: everything its HTTP client is built with
+/// besides the timeout and redirect policy. Nodes sharing one client must
+/// agree on them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientOptions {
+    pub proxy: Option<String>,
+    pub accept_gzip: bool,
+    pub tcp_nodelay: bool,
+    pub tcp_keepalive_ms: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Duration,
+    pub http2: bool,
+}
+
+impl ClientOptions {
+    /// The client-level settings `config` asks for, with defaults filled in.
+    pub fn of(config: &UpstreamConfig) -> Self {
+        Self {
+            proxy: config.proxy.clone(),
+            accept_gzip: config.accept_gzip,
+            tcp_nodelay: config.tcp_nodelay.unwrap_or(true),
+            tcp_keepalive_ms: config.tcp_keepalive_ms,
+            pool_max_idle_per_host: config.pool_max_idle_per_host,
+            pool_idle_timeout: pool_idle_timeout(config),
+            http2: config.http2,
+        }
+    }
+
+    /// A client builder with these settings applied. `owner` names the client
+    /// in errors, e.g. `node Alpha`.
+    fn builder(&self, owner: &str) -> Result<reqwest::ClientBuilder, String> {
+        let mut builder = reqwest::Client::builder()
+            .tcp_nodelay(self.tcp_nodelay)
+            .tcp_keepalive(self.tcp_keepalive_ms.map(Duration::from_millis))
+            .pool_idle_timeout(self.pool_idle_timeout);
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if self.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if !self.accept_gzip {
+            builder = builder.no_gzip();
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("Invalid proxy for {}: {}", owner, e))?;
+            builder = builder.proxy(proxy);
+        }
+        Ok(builder)
+    }
+}
+
+/// One HTTP client shared by several nodes, with the options it was built
+/// with. It follows no redirects, since their policy is per node.
+#[derive(Clone)]
+pub struct SharedClient {
+    client: reqwest::Client,
+    options: ClientOptions,
+}
+
+impl SharedClient {
+    pub fn new(options: ClientOptions) -> Result<Self, String> {
+        let client = options
+            .builder("shared HTTP client")?
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| format!("Failed to create shared HTTP client: {}", e))?;
+        Ok(Self { client, options })
+    }
+}
+
+/// How long the node's idle pooled connections are kept.
+fn pool_idle_timeout(config: &UpstreamConfig) -> Duration {
+    config
+        .pool_idle_timeout_ms
+        .map_or(DEFAULT_POOL_IDLE_TIMEOUT, Duration::from_millis)
+}
+
+/// Follows up to the node's `max_redirects`, only to its own host or one of
+/// `redirect_allowed_hosts`, logging the chain so far at each hop.
+fn redirect_policy(config: &UpstreamConfig) -> reqwest::redirect::Policy {
+    let node = config.name.clone();
+    let limit = config.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+    let mut allowed: Vec<String> = config
+        .redirect_allowed_hosts
+        .iter()
+        .map(|host| host.to_ascii_lowercase())
+        .collect();
+    if let Some(host) = reqwest::Url::parse(&config.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+    {
+        allowed.push(host);
+    }
+
+    reqwest::redirect::Policy::custom(move |attempt| {
+        // `previous` starts with the original URL, so its length is this hop's number
+        let hop = attempt.previous().len();
+        let chain: Vec<&str> = attempt
+            .previous()
+            .iter()
+            .chain([attempt.url()])
+            .map(reqwest::Url::as_str)
+            .collect();
+        tracing::debug!("Node {} redirect #{}: {}", node, hop, chain.join(" -> "));
+
+        let host = attempt
+            .url()
+            .host_str()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if hop > limit {
+            attempt.error(format!("more than {} redirects", limit))
+        } else if !allowed.contains(&host) {
+            attempt.error(format!("redirect to disallowed host {}", host))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// Checks that a node's `field` URL parses and uses one of `schemes`.
+///
+/// IPv6 hosts must be bracketed (`http://[::1]:8545`). An unbracketed one
+/// does not parse, and gets its own message rather than the scheme hint.
+fn validate_url(node: &str, field: &str, url: &str, schemes: &[&str]) -> Result<(), String> {
+    // `localhost:8545` parses with `localhost` as its scheme, so check the
+    // scheme itself rather than just parseability
+    let reason = match reqwest::Url::parse(url) {
+        Ok(parsed) if schemes.contains(&parsed.scheme()) => return Ok(()),
+        Err(_) if is_unbracketed_ipv6(url) => {
+            "IPv6 addresses must be in brackets, e.g. http://[::1]:8545".to_string()
+        }
+        _ => format!("must start with {}://", schemes.join(":// or ")),
+    };
+    Err(format!(
+        "Invalid {} for node {}: {:?} {}",
+        field, node, url, reason
+    ))
+}
+
+/// Whether the host part of `url` looks like a bare IPv6 address.
+fn is_unbracketed_ipv6(url: &str) -> bool {
+    let authority = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    !authority.contains('[') && authority.matches(':').count() > 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_node(name: &str) -> UpstreamNode {
+        UpstreamNode::new(UpstreamConfig {
+            name: name.to_string(),
+            url: "http://invalid-test-url:9999".to_string(),
+            failure_threshold: Some(3),
+            cooldown_ms: Some(60_000),
+            request_timeout_ms: Some(5_000),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_single_failure_keeps_node_healthy() {
+        let node = create_test_node("TestNode");
+        node.force_mark_failure();
+        assert_eq!(node.get_status(), NodeCondition::Healthy);
+        assert!(node.is_healthy());
+        assert_eq!(node.get_consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn test_three_failures_opens_circuit() {
+        let node = create_test_node("TestNode");
+
+        // Balancer shall break the circuit here.
+        node.force_mark_failure();
+        node.force_mark_failure();
+        node.force_mark_failure();
+
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+        assert!(!node.is_healthy());
+        assert_eq!(node.get_consecutive_failures(), 3);
+    }
+
+    #[test]
+    fn test_upgrading_node_tolerates_more_failures() {
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Upgrading".to_string(),
+            url: "http://invalid-test-url:9999".to_string(),
+            failure_threshold: Some(3),
+            upgrade: crate::types::UpgradeTolerance {
+                failure_threshold: 6,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .unwrap();
+        node.set_upgrading(true);
+
+        for _ in 0..5 {
+            node.force_mark_failure();
+        }
+        assert!(node.is_healthy());
+        node.force_mark_failure();
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+
+        // Back to the regular threshold once the flag is cleared
+        node.reset();
+        node.set_upgrading(false);
+        for _ in 0..3 {
+            node.force_mark_failure();
+        }
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+    }
+
+    #[test]
     fn test_success_resets_failure_count() {
         let node = create_test_node("TestNode");
-        
+
         // Two failures
         node.force_mark_failure();
         node.force_mark_failure();
         assert_eq!(node.get_consecutive_failures(), 2);
-        
+
         // Resets the counter
         node.force_mark_success();
-        
+
         assert_eq!(node.get_status(), NodeCondition::Healthy);
         assert!(node.is_healthy());
         assert_eq!(node.get_consecutive_failures(), 0);
@@ -335,16 +1693,16 @@ mod tests {
     #[test]
     fn test_circuit_recovery_after_success() {
         let node = create_test_node("TestNode");
-        
+
         // Open the circuit
         node.force_mark_failure();
         node.force_mark_failure();
         node.force_mark_failure();
         assert_eq!(node.get_status(), NodeCondition::Unhealthy);
-        
+
         // Success should close the circuit
         node.force_mark_success();
-        
+
         assert_eq!(node.get_status(), NodeCondition::Healthy);
         assert!(node.is_healthy());
         assert_eq!(node.get_consecutive_failures(), 0);
@@ -354,10 +1712,10 @@ mod tests {
     fn test_concurrent_failure_tracking() {
         use std::sync::Arc;
         use std::thread;
-        
+
         let node = Arc::new(create_test_node("TestNode"));
         let mut handles = vec![];
-        
+
         // Simulate concurrent failures from multiple threads
         for _ in 0..10 {
             let node_clone = Arc::clone(&node);
@@ -366,13 +1724,732 @@ mod tests {
             });
             handles.push(handle);
         }
-        
+
         for handle in handles {
             handle.join().unwrap();
         }
-        
-        // Should have exactly 10 failures
-        assert_eq!(node.get_consecutive_failures(), 10);
+
+        // Clamped at the threshold once the circuit opened
+        assert_eq!(node.get_consecutive_failures(), 3);
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+    }
+
+    #[test]
+    fn test_basic_auth_header_with_special_characters() {
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "AuthNode".to_string(),
+            url: "http://localhost:8545".to_string(),
+            basic_auth: Some(crate::types::BasicAuth {
+                username: "rpc-user".to_string(),
+                password: "p:ss@w0rd".to_string(),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+
+        let built = node
+            .build_request(&request, &RequestContext::internal())
+            .build()
+            .unwrap();
+
+        // base64("rpc-user:p:ss@w0rd")
+        assert_eq!(
+            built.headers()[reqwest::header::AUTHORIZATION],
+            "Basic cnBjLXVzZXI6cDpzc0B3MHJk"
+        );
+        assert_eq!(built.url().as_str(), "http://localhost:8545/");
+    }
+
+    #[test]
+    fn test_request_signature_header() {
+        use crate::types::{RequestSigning, SigningAlgorithm};
+
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+        let signed_node = |algorithm| {
+            UpstreamNode::new(UpstreamConfig {
+                name: "Private".to_string(),
+                url: "http://localhost:8545".to_string(),
+                request_signing: Some(RequestSigning {
+                    secret: "shared-s3cret".to_string(),
+                    algorithm,
+                    header: "X-Body-Signature".to_string(),
+                }),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let built = signed_node(SigningAlgorithm::HmacSha256)
+            .build_request(&request, &RequestContext::internal())
+            .build()
+            .unwrap();
+        assert_eq!(
+            built.body().unwrap().as_bytes().unwrap(),
+            br#"{"jsonrpc":"2.0","method":"eth_blockNumber","params":[],"id":1}"#
+        );
+        assert_eq!(
+            built.headers()["x-body-signature"],
+            "c45d420c4c9cfa642347c51ec01874f4c3ee92ea76444136047be16a55eb83e2"
+        );
+
+        let built = signed_node(SigningAlgorithm::HmacSha512)
+            .build_request(&request, &RequestContext::internal())
+            .build()
+            .unwrap();
+        assert!(
+            built.headers()["x-body-signature"]
+                .to_str()
+                .unwrap()
+                .starts_with("853da89d9ae447da3e65c53cc3973e94")
+        );
+        assert!(
+            !format!("{:?}", signed_node(SigningAlgorithm::HmacSha256).config).contains("s3cret")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_health_checks_are_skipped() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, routing::post};
+        use std::sync::Arc;
+
+        let probes = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&probes);
+        let app = Router::new().route(
+            "/",
+            post(move |Json(req): Json<RpcRequest>| {
+                let counter = Arc::clone(&counter);
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Slow".to_string(),
+            url,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let settings = HealthCheckSettings::default();
+
+        let (first, second) = tokio::join!(node.check_health_exclusive(&settings), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            node.check_health_exclusive(&settings).await
+        });
+
+        assert_eq!(first, Some(true));
+        assert_eq!(second, None);
+        assert_eq!(probes.load(Ordering::SeqCst), 1);
+
+        // Once the first probe finished, the next one runs normally.
+        assert_eq!(node.check_health_exclusive(&settings).await, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_cold_first_request_flagged_as_new_connection() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                if req.method == "eth_getLogs" {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+                Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+            }),
+        );
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Cold".to_string(),
+            url: spawn_mock_upstream(app).await,
+            log_new_connections: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+
+        node.call_rpc(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(node.get_likely_new_connections(), 1);
+
+        // A follow-up as fast as the average reuses the pooled connection.
+        node.call_rpc(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(node.get_likely_new_connections(), 1);
+
+        // Headers far slower than the average look like connection setup.
+        let slow = RpcRequest {
+            method: "eth_getLogs".to_string(),
+            ..request
+        };
+        node.call_rpc(&slow, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(node.get_likely_new_connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_custom_health_check_method() {
+        use crate::test_util::spawn_mock_upstream;
+        use crate::types::HealthCheckRequest;
+        use axum::{Json, Router, routing::post};
+
+        // A non-Ethereum service: only `status_ping` is a valid method.
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                if req.method == "status_ping" && req.params == serde_json::json!({"deep": true}) {
+                    Json(RpcResponse::success(req.id, serde_json::json!("pong")))
+                } else {
+                    Json(RpcResponse::error(
+                        req.id,
+                        -32601,
+                        "Method not found".to_string(),
+                    ))
+                }
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+
+        let custom = UpstreamNode::new(UpstreamConfig {
+            name: "Custom".to_string(),
+            url: url.clone(),
+            health_check: Some(HealthCheckRequest {
+                method: "status_ping".to_string(),
+                params: serde_json::json!({"deep": true}),
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        let default = UpstreamNode::new(UpstreamConfig {
+            name: "Default".to_string(),
+            url,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(custom.check_health().await);
+        assert!(!default.check_health().await);
+    }
+
+    #[tokio::test]
+    async fn test_probe_failures_use_their_own_threshold() {
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Probed".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            probe_failure_threshold: Some(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        for _ in 0..4 {
+            assert!(!node.check_health().await);
+        }
+        assert_eq!(node.get_consecutive_probe_failures(), 4);
+        assert_eq!(node.get_consecutive_failures(), 0);
+        assert_eq!(node.get_status(), NodeCondition::Healthy);
+
+        assert!(!node.check_health().await);
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+        assert_eq!(node.get_consecutive_failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cooled_down_node_stays_out_of_rotation_until_probe_succeeds() {
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Flapping".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            failure_threshold: Some(3),
+            cooldown_ms: Some(60_000),
+            ..Default::default()
+        })
+        .unwrap();
+        for _ in 0..3 {
+            node.force_mark_failure();
+        }
+
+        node.force_cooldown_expiry();
+        assert_eq!(node.get_status(), NodeCondition::HalfOpen);
+        assert!(!node.is_healthy());
+
+        // A failed probe restarts the cooldown from now.
+        assert!(!node.check_health().await);
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+        assert!(node.cooldown_remaining().unwrap() > Duration::from_secs(55));
+
+        node.force_cooldown_expiry();
+        assert_eq!(node.get_status(), NodeCondition::HalfOpen);
+        node.record_probe_success(1);
+        assert_eq!(node.get_status(), NodeCondition::Healthy);
+        assert!(node.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_recovery_needs_consecutive_successful_probes() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                if req.method == "net_version" {
+                    Json(RpcResponse::success(req.id, serde_json::json!("1")))
+                } else {
+                    Json(RpcResponse::error(
+                        req.id,
+                        -32601,
+                        "Method not found".to_string(),
+                    ))
+                }
+            }),
+        );
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Recovering".to_string(),
+            url: spawn_mock_upstream(app).await,
+            failure_threshold: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+        let settings = HealthCheckSettings {
+            probe: crate::types::HealthCheckRequest {
+                method: "net_version".to_string(),
+                params: serde_json::json!([]),
+            },
+            recovery_probes: 3,
+            ..Default::default()
+        };
+        node.force_mark_failure();
+        node.force_cooldown_expiry();
+
+        assert!(node.check_health_with(&settings).await);
+        assert!(node.check_health_with(&settings).await);
+        assert_eq!(node.get_status(), NodeCondition::HalfOpen);
+        assert!(node.check_health_with(&settings).await);
+        assert_eq!(node.get_status(), NodeCondition::Healthy);
+
+        // The default `eth_blockNumber` probe is not served by this upstream
+        assert!(!node.check_health().await);
+    }
+
+    #[test]
+    fn test_breaker_settings_are_per_node() {
+        let strict = UpstreamNode::new(UpstreamConfig {
+            name: "Strict".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            failure_threshold: Some(1),
+            cooldown_ms: Some(50),
+            request_timeout_ms: Some(250),
+            ..Default::default()
+        })
+        .unwrap();
+        let lenient = UpstreamNode::new(UpstreamConfig {
+            name: "Lenient".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            failure_threshold: Some(5),
+            ..Default::default()
+        })
+        .unwrap();
+
+        strict.force_mark_failure();
+        for _ in 0..4 {
+            lenient.force_mark_failure();
+        }
+        assert_eq!(strict.get_status(), NodeCondition::Unhealthy);
+        assert_eq!(lenient.get_status(), NodeCondition::Healthy);
+        assert!(strict.cooldown_remaining().unwrap() <= Duration::from_millis(50));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(strict.get_status(), NodeCondition::HalfOpen);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cooldown_backs_off_until_node_stays_healthy() {
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "Down".to_string(),
+            url: "http://127.0.0.1:1".to_string(),
+            failure_threshold: Some(1),
+            cooldown_ms: Some(1_000),
+            max_cooldown_ms: Some(3_000),
+            ..Default::default()
+        })
+        .unwrap();
+        let reopen = || {
+            node.force_cooldown_expiry();
+            assert_eq!(node.get_status(), NodeCondition::HalfOpen);
+            node.force_mark_failure();
+            node.cooldown_remaining().unwrap()
+        };
+
+        node.force_mark_failure();
+        assert_eq!(node.cooldown_remaining(), Some(Duration::from_secs(1)));
+        assert_eq!(reopen(), Duration::from_secs(2));
+        assert_eq!(reopen(), Duration::from_secs(3));
+        assert_eq!(reopen(), Duration::from_secs(3));
+
+        // A brief recovery keeps the backed-off cooldown
+        node.force_cooldown_expiry();
+        node.record_probe_success(1);
+        node.force_mark_failure();
+        assert_eq!(node.cooldown_remaining(), Some(Duration::from_secs(3)));
+
+        // Staying healthy for as long as the last cooldown resets it
+        node.force_cooldown_expiry();
+        node.record_probe_success(1);
+        tokio::time::advance(Duration::from_secs(3)).await;
+        node.force_mark_failure();
+        assert_eq!(node.cooldown_remaining(), Some(Duration::from_secs(1)));
+    }
+
+    fn error_rate_node(min_requests: usize) -> UpstreamNode {
+        UpstreamNode::new(UpstreamConfig {
+            name: "Busy".to_string(),
+            url: "http://invalid-test-url:9999".to_string(),
+            circuit_breaker: BreakerMode::ErrorRate {
+                window: 10,
+                max_error_percent: 30.0,
+                min_requests,
+            },
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_error_rate_breaker_trips_without_consecutive_failures() {
+        let node = error_rate_node(5);
+
+        // At most 30% of the window fails, never two in a row
+        let pattern = [
+            false, false, false, false, true, false, false, true, false, true,
+        ];
+        for failed in pattern {
+            if failed {
+                node.force_mark_failure();
+            } else {
+                node.force_mark_success();
+            }
+            assert_eq!(node.get_status(), NodeCondition::Healthy);
+        }
+
+        // The next failure makes it 4 of the last 10, over the 30% limit
+        node.force_mark_failure();
+        assert_eq!(node.get_status(), NodeCondition::Unhealthy);
+    }
+
+    #[test]
+    fn test_error_rate_breaker_waits_for_minimum_sample() {
+        let node = error_rate_node(8);
+
+        // 100% errors, and more than the consecutive threshold, but too few samples
+        for _ in 0..7 {
+            node.force_mark_failure();
+        }
+        assert_eq!(node.get_status(), NodeCondition::Healthy);
+
+        node.force_mark_failure();
         assert_eq!(node.get_status(), NodeCondition::Unhealthy);
     }
+
+    #[tokio::test]
+    async fn test_ipv6_upstream_is_dispatched_to() {
+        use crate::test_util::spawn_mock_upstream_at;
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new().route(
+            "/",
+            post(|Json(req): Json<RpcRequest>| async move {
+                Json(RpcResponse::success(req.id, serde_json::json!("0x6")))
+            }),
+        );
+        let url = spawn_mock_upstream_at("[::1]:0", app).await;
+        assert!(url.starts_with("http://[::1]:"), "{}", url);
+        let node = UpstreamNode::new(UpstreamConfig {
+            name: "V6".to_string(),
+            url,
+            ws_url: Some("ws://[::1]:8546".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+        let response = node
+            .call_rpc_internal(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0x6")));
+
+        let err = UpstreamNode::new(UpstreamConfig {
+            name: "Bare".to_string(),
+            url: "http://::1:8545".to_string(),
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(err.contains("in brackets"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_do_not_count_against_health() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, routing::post};
+
+        let app = Router::new()
+            .route(
+                "/",
+                post(|Json(req): Json<RpcRequest>| async move {
+                    Json(RpcResponse::error(
+                        req.id,
+                        -32000,
+                        "execution reverted".to_string(),
+                    ))
+                }),
+            )
+            .route("/garbage", post(|| async { "not json" }));
+        let url = spawn_mock_upstream(app).await;
+        let node = |url: String| {
+            UpstreamNode::new(UpstreamConfig {
+                name: "Node".to_string(),
+                url,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_call".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+
+        let reverting = node(url.clone());
+        for _ in 0..10 {
+            let err = reverting
+                .call_rpc(&request, &RequestContext::internal())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, ForwardError::Rpc { .. }), "{}", err);
+        }
+        assert_eq!(reverting.request_counts(), (10, 0));
+        assert_eq!(reverting.get_status(), NodeCondition::Healthy);
+
+        let malformed = node(format!("{}/garbage", url));
+        assert!(
+            malformed
+                .call_rpc(&request, &RequestContext::internal())
+                .await
+                .is_err()
+        );
+        assert_eq!(malformed.request_counts(), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_redirects_follow_limit_and_host_allowlist() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, response::Redirect, routing::post};
+
+        let app = Router::new()
+            .route("/once", post(|| async { Redirect::temporary("/final") }))
+            .route("/twice", post(|| async { Redirect::temporary("/once") }))
+            .route(
+                "/final",
+                post(|Json(req): Json<RpcRequest>| async move {
+                    Json(RpcResponse::success(req.id, serde_json::json!("0x1")))
+                }),
+            );
+        let base = spawn_mock_upstream(app).await;
+        let port = base.rsplit(':').next().unwrap().to_string();
+        let app = Router::new().route(
+            "/",
+            post(move || {
+                let target = format!("http://localhost:{}/final", port);
+                async move { Redirect::temporary(&target) }
+            }),
+        );
+        let elsewhere = spawn_mock_upstream(app).await;
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_blockNumber".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+        let node = |url: String, allowed: &[&str]| {
+            UpstreamNode::new(UpstreamConfig {
+                name: "Redirecting".to_string(),
+                url,
+                max_redirects: Some(1),
+                redirect_allowed_hosts: allowed.iter().map(|h| h.to_string()).collect(),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        let ctx = RequestContext::internal();
+
+        let within = node(format!("{}/once", base), &[]);
+        let response = within.call_rpc_internal(&request, &ctx).await.unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0x1")));
+
+        let beyond = node(format!("{}/twice", base), &[]);
+        assert!(beyond.call_rpc_internal(&request, &ctx).await.is_err());
+
+        // The mock redirects from 127.0.0.1 to localhost
+        let foreign = node(elsewhere.clone(), &[]);
+        assert!(foreign.call_rpc_internal(&request, &ctx).await.is_err());
+        let allowed = node(elsewhere, &["LOCALHOST"]);
+        assert!(allowed.call_rpc_internal(&request, &ctx).await.is_ok());
+    }
+
+    /// Gzip of a response whose result is `0x` followed by 100 000 zeros.
+    const GZIPPED_RESPONSE: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xed, 0xc8, 0x3b, 0x0e, 0x40,
+        0x40, 0x14, 0x00, 0xc0, 0xbb, 0xbc, 0x7a, 0x23, 0x4b, 0xb9, 0xd7, 0x41, 0x41, 0x04, 0xf1,
+        0x49, 0x24, 0xe2, 0xee, 0x1c, 0x43, 0x31, 0x53, 0xce, 0x1d, 0xe3, 0xbe, 0xcc, 0xdb, 0xda,
+        0x46, 0x89, 0xa6, 0xca, 0x91, 0x62, 0xe8, 0xa2, 0xd4, 0x29, 0xb6, 0x7e, 0x3f, 0xa7, 0xe3,
+        0xdb, 0x7c, 0x65, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe0, 0xb7, 0xe2, 0x79, 0x01, 0x65,
+        0x22, 0xc9, 0x10, 0xc6, 0x86, 0x01, 0x00,
+    ];
+
+    #[tokio::test]
+    async fn test_decompressed_size_limit_rejects_gzip_bomb() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Router, http::header, routing::post};
+
+        assert!(GZIPPED_RESPONSE.len() < 200);
+        let app = Router::new().route(
+            "/",
+            post(|| async {
+                (
+                    [
+                        (header::CONTENT_ENCODING, "gzip"),
+                        (header::CONTENT_TYPE, "application/json"),
+                    ],
+                    GZIPPED_RESPONSE,
+                )
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let node = |max_response_bytes: usize| {
+            UpstreamNode::new(UpstreamConfig {
+                name: "Gzip".to_string(),
+                url: url.clone(),
+                accept_gzip: true,
+                max_response_bytes: Some(max_response_bytes),
+                ..Default::default()
+            })
+            .unwrap()
+        };
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_getLogs".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+
+        let roomy = node(200_000);
+        let response = roomy
+            .call_rpc(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.result.unwrap().as_str().unwrap().len(), 100_002);
+
+        let strict = node(10_000);
+        let err = strict
+            .call_rpc(&request, &RequestContext::internal())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("limit of 10000 bytes"), "{}", err);
+        assert_eq!(strict.request_counts(), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_client_builds_with_tcp_options() {
+        use crate::test_util::spawn_mock_upstream;
+        use axum::{Json, Router, http::Version, routing::post};
+
+        // Answers with the HTTP version the request arrived over
+        let app = Router::new().route(
+            "/",
+            post(|version: Version, Json(req): Json<RpcRequest>| async move {
+                let result = if version == Version::HTTP_2 {
+                    "0x2"
+                } else {
+                    "0x1"
+                };
+                Json(RpcResponse::success(req.id, serde_json::json!(result)))
+            }),
+        );
+        let url = spawn_mock_upstream(app).await;
+        let request = RpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_chainId".to_string(),
+            params: serde_json::Value::Array(vec![]),
+            id: serde_json::json!(1),
+        };
+
+        for (nodelay, keepalive_ms, max_idle, idle_ms) in [
+            (Some(false), Some(30_000), Some(4), Some(5_000)),
+            (None, None, None, None),
+        ] {
+            let node = UpstreamNode::new(UpstreamConfig {
+                name: "Tcp".to_string(),
+                url: url.clone(),
+                tcp_nodelay: nodelay,
+                tcp_keepalive_ms: keepalive_ms,
+                pool_max_idle_per_host: max_idle,
+                pool_idle_timeout_ms: idle_ms,
+                ..Default::default()
+            })
+            .unwrap();
+            let response = node
+                .call_rpc(&request, &RequestContext::internal())
+                .await
+                .unwrap();
+            assert_eq!(response.result, Some(serde_json::json!("0x1")));
+        }
+
+        let http2 = UpstreamNode::new(UpstreamConfig {
+            name: "H2".to_string(),
+            url,
+            http2: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let response = http2
+            .call_rpc(&request, &RequestContext::internal())
+            .await
+            .unwrap();
+        assert_eq!(response.result, Some(serde_json::json!("0x2")));
+    }
 }
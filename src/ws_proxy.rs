@@ -0,0 +1,1428 @@
+//! WebSocket proxying on `/ws`, including `eth_subscribe` subscriptions.
+//!
+//! Each client connection is paired with a WebSocket to one healthy upstream,
+//! picked by the load balancer among nodes that have a `ws_url`. JSON-RPC
+//! frames are relayed in both directions as they are, so subscription
+//! notifications keep flowing for as long as both sides stay connected.
+//!
+//! # Reconnection
+//!
+//! If the upstream connection drops, the node is charged a failure and the
+//! session moves to another healthy node. Requests still awaiting an answer
+//! get an error, since the old node can no longer deliver it. Active
+//! subscriptions are re-created on the new node, and because that node hands
+//! out its own subscription ids, notifications and `eth_unsubscribe` calls are
+//! translated so the client keeps using the ids it was first given. When no
+//! other node is available, the client connection is closed with an error.
+//!
+//! # Authorization
+//!
+//! Every call in a client frame, batch elements included, is put to the
+//! `Authorizer` before the frame goes upstream, exactly as over HTTP. Rejected
+//! calls are answered by the gateway (`-32601` for methods the method filter
+//! blocks, `-32003` for calls the key policy denies) and removed from the
+//! frame; what is left is forwarded.
+//!
+//! # Logs Subscription Coalescing
+//!
+//! With `coalesce_logs` on, `eth_subscribe` calls for `logs` are not sent on
+//! the client's own upstream connection. A shared hub keeps one upstream
+//! subscription per distinct filter, keyed by the filter with addresses and
+//! topics lowercased and address lists sorted, and fans each notification
+//! out to every client subscribed to it under an id the gateway hands out.
+//! The upstream subscription is dropped with its last client, and re-created
+//! on another node if the hub's upstream connection drops. Calls inside a
+//! batch are not coalesced.
+//!
+//! # Keepalive
+//!
+//! An idle upstream connection can be dropped silently by a NAT or proxy on
+//! the way, leaving subscriptions that never fire again. With
+//! `keepalive_interval_ms` set, every upstream connection is pinged at that
+//! interval. A pong still missing `keepalive_timeout_ms` after its ping
+//! (checked at each ping, the interval by default) counts as a dropped
+//! connection: the node is charged a failure and the session reconnects as
+//! described above.
+
+use crate::AppState;
+use crate::auth;
+use crate::types::{Caller, RpcRequest};
+use crate::upstream::UpstreamNode;
+use axum::{
+    extract::{
+        ConnectInfo, Extension, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, RandomState};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, tungstenite};
+
+type UpstreamSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Pause before the logs hub retries an upstream after none could be reached.
+const HUB_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// WebSocket proxying behavior.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WsSettings {
+    /// Serve `logs` subscriptions from one shared upstream subscription per
+    /// distinct filter instead of one per client.
+    pub coalesce_logs: bool,
+
+    /// Ping upstream connections this often. `None` sends no pings.
+    pub keepalive_interval_ms: Option<u64>,
+
+    /// How long a pong may take before the connection is given up.
+    /// Defaults to `keepalive_interval_ms`.
+    pub keepalive_timeout_ms: Option<u64>,
+}
+
+/// Ping schedule of one upstream connection.
+struct Keepalive {
+    interval: Option<tokio::time::Interval>,
+    timeout: Duration,
+
+    /// When the oldest unanswered ping was sent.
+    awaiting_since: Option<Instant>,
+}
+
+impl Keepalive {
+    fn new(settings: &WsSettings) -> Self {
+        let interval = settings.keepalive_interval_ms.map(|ms| {
+            let period = Duration::from_millis(ms.max(1));
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+        let timeout = settings
+            .keepalive_timeout_ms
+            .or(settings.keepalive_interval_ms)
+            .map(Duration::from_millis)
+            .unwrap_or_default();
+        Self {
+            interval,
+            timeout,
+            awaiting_since: None,
+        }
+    }
+
+    /// Waits for the next ping, forever if keepalive is off.
+    async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Pings `upstream`, unless the previous ping went unanswered for too
+    /// long, which is reported as an error.
+    async fn ping(&mut self, upstream: &mut UpstreamSocket) -> Result<(), String> {
+        if let Some(since) = self.awaiting_since
+            && since.elapsed() >= self.timeout
+        {
+            return Err(format!("no pong for {:?}", since.elapsed()));
+        }
+        upstream
+            .send(tungstenite::Message::Ping(Vec::new().into()))
+            .await
+            .map_err(|e| e.to_string())?;
+        self.awaiting_since.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    fn pong(&mut self) {
+        self.awaiting_since = None;
+    }
+}
+
+/// Upgrades a client connection and proxies it to an upstream WebSocket.
+pub async fn ws_handler(
+    State(state): State<AppState>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let caller = Caller::from_headers(
+        &headers,
+        connect_info.map(|Extension(ConnectInfo(addr))| addr.ip()),
+    );
+    let logs = state.config.ws.coalesce_logs.then(|| HubClient {
+        hub: Arc::clone(&state.logs_hub),
+        subscriptions: HashSet::new(),
+    });
+    ws.on_upgrade(move |socket| {
+        Session {
+            caller,
+            logs,
+            ..Default::default()
+        }
+        .run(state, socket)
+    })
+}
+
+/// Why a proxied connection ended.
+enum Ended {
+    Client,
+    Upstream,
+}
+
+/// A subscription the client holds, as it must be re-created after failover.
+struct Subscription {
+    /// Subscription id the client was given.
+    client_id: String,
+
+    /// Params of the original `eth_subscribe` call.
+    params: Value,
+}
+
+/// State of one client connection across upstream reconnects.
+#[derive(Default)]
+struct Session {
+    /// The connected client, whose calls are authorized frame by frame.
+    caller: Caller,
+
+    /// Requests sent upstream and not yet answered, keyed by serialized id.
+    /// `eth_subscribe` calls keep their params.
+    pending: HashMap<String, Option<Value>>,
+
+    /// Live subscriptions, keyed by the current upstream's subscription id.
+    subscriptions: HashMap<String, Subscription>,
+
+    /// Subscriptions being re-created, keyed by the gateway's request id.
+    resubscribing: HashMap<String, Subscription>,
+
+    next_resubscribe_id: u64,
+
+    /// This client's `logs` subscriptions, when they are coalesced.
+    logs: Option<HubClient>,
+
+    /// Where the hub delivers this client's `logs` notifications.
+    logs_sink: Option<mpsc::UnboundedSender<String>>,
+}
+
+/// A client's subscriptions held by the logs hub, ended when it goes away.
+struct HubClient {
+    hub: Arc<LogsHub>,
+    subscriptions: HashSet<String>,
+}
+
+impl Drop for HubClient {
+    fn drop(&mut self) {
+        for client_id in self.subscriptions.drain() {
+            self.hub.unsubscribe(client_id);
+        }
+    }
+}
+
+impl Session {
+    async fn run(mut self, state: AppState, mut client: WebSocket) {
+        let (logs_sink, mut logs) = mpsc::unbounded_channel();
+        self.logs_sink = Some(logs_sink);
+        let mut exclude = Vec::new();
+        loop {
+            let Some((node, mut upstream)) = connect(&state, &mut exclude).await else {
+                tracing::error!("No healthy WebSocket upstream available, closing client");
+                let _ = client
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::ERROR,
+                        reason: "No healthy WebSocket upstream available".into(),
+                    })))
+                    .await;
+                return;
+            };
+            if self.resubscribe(&mut upstream).await.is_err() {
+                node.record_failure();
+                exclude = vec![node.get_name().to_string()];
+                continue;
+            }
+
+            match self
+                .proxy(&state, &mut client, &mut upstream, &mut logs)
+                .await
+            {
+                Ended::Client => {
+                    let _ = upstream.close(None).await;
+                    return;
+                }
+                Ended::Upstream => {
+                    tracing::warn!(
+                        "WebSocket upstream {} dropped, reconnecting",
+                        node.get_name()
+                    );
+                    node.record_failure();
+                    if self.fail_pending(&mut client).await.is_err() {
+                        return;
+                    }
+                    exclude = vec![node.get_name().to_string()];
+                }
+            }
+        }
+    }
+
+    /// Relays frames until either side goes away, along with the `logs`
+    /// notifications the hub delivers for this client.
+    async fn proxy(
+        &mut self,
+        state: &AppState,
+        client: &mut WebSocket,
+        upstream: &mut UpstreamSocket,
+        logs: &mut mpsc::UnboundedReceiver<String>,
+    ) -> Ended {
+        let mut keepalive = Keepalive::new(&state.config.ws);
+        loop {
+            tokio::select! {
+                _ = keepalive.tick() => {
+                    if let Err(e) = keepalive.ping(upstream).await {
+                        tracing::warn!("WebSocket upstream keepalive failed: {}", e);
+                        return Ended::Upstream;
+                    }
+                }
+                Some(text) = logs.recv() => {
+                    if client.send(Message::Text(text.into())).await.is_err() {
+                        return Ended::Client;
+                    }
+                }
+                message = client.recv() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        if let Some(ended) =
+                            self.relay_client_text(state, client, upstream, text.as_str()).await
+                        {
+                            return ended;
+                        }
+                    }
+                    // JSON sent as binary is screened like text; anything else
+                    // cannot be a call upstream would run
+                    Some(Ok(Message::Binary(data))) => match std::str::from_utf8(&data) {
+                        Ok(text) => {
+                            if let Some(ended) =
+                                self.relay_client_text(state, client, upstream, text).await
+                            {
+                                return ended;
+                            }
+                        }
+                        Err(_) => {
+                            if upstream.send(tungstenite::Message::Binary(data)).await.is_err() {
+                                return Ended::Upstream;
+                            }
+                        }
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ended::Client,
+                    // Pings are answered by the WebSocket layer itself
+                    Some(Ok(_)) => {}
+                },
+                message = upstream.next() => match message {
+                    Some(Ok(tungstenite::Message::Text(text))) => {
+                        if let Some(text) = self.upstream_frame(text.as_str())
+                            && client.send(Message::Text(text.into())).await.is_err()
+                        {
+                            return Ended::Client;
+                        }
+                    }
+                    Some(Ok(tungstenite::Message::Binary(data))) => {
+                        if client.send(Message::Binary(data)).await.is_err() {
+                            return Ended::Client;
+                        }
+                    }
+                    Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => {
+                        return Ended::Upstream;
+                    }
+                    Some(Ok(tungstenite::Message::Pong(_))) => keepalive.pong(),
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    /// Answers the rejected calls of a client frame and forwards the rest.
+    async fn relay_client_text(
+        &mut self,
+        state: &AppState,
+        client: &mut WebSocket,
+        upstream: &mut UpstreamSocket,
+        text: &str,
+    ) -> Option<Ended> {
+        let (forward, rejected) = screen(state, &self.caller, text).await;
+        if let Some(rejected) = rejected
+            && client.send(Message::Text(rejected.into())).await.is_err()
+        {
+            return Some(Ended::Client);
+        }
+        let forward = forward?;
+        if let Some(answer) = self.serve_from_hub(state, &forward).await {
+            return client
+                .send(Message::Text(answer.into()))
+                .await
+                .is_err()
+                .then_some(Ended::Client);
+        }
+        let forward = self.client_frame(&forward);
+        if upstream
+            .send(tungstenite::Message::text(forward))
+            .await
+            .is_err()
+        {
+            return Some(Ended::Upstream);
+        }
+        None
+    }
+
+    /// Answers a single `logs` subscribe, or an unsubscribe of one, through
+    /// the hub. Returns `None` for frames that go upstream as usual.
+    async fn serve_from_hub(&mut self, state: &AppState, text: &str) -> Option<String> {
+        let hub = Arc::clone(&self.logs.as_ref()?.hub);
+        let request: RpcRequest = serde_json::from_str(text).ok()?;
+        if request.is_notification() {
+            return None;
+        }
+        let result = match request.method.as_str() {
+            "eth_subscribe" if request.params.get(0)? == "logs" => {
+                let sink = self.logs_sink.clone()?;
+                hub.subscribe(state, request.params, sink)
+                    .await
+                    .map(|client_id| {
+                        if let Some(logs) = &mut self.logs {
+                            logs.subscriptions.insert(client_id.clone());
+                        }
+                        Value::String(client_id)
+                    })
+            }
+            "eth_unsubscribe" => {
+                let client_id = request.params.get(0)?.as_str()?;
+                if !self.logs.as_mut()?.subscriptions.remove(client_id) {
+                    return None;
+                }
+                hub.unsubscribe(client_id.to_string());
+                Ok(Value::Bool(true))
+            }
+            _ => return None,
+        };
+        let answer = match result {
+            Ok(result) => serde_json::json!({
+                "jsonrpc": "2.0", "id": request.id, "result": result,
+            }),
+            Err(message) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request.id,
+                "error": { "code": -32603, "message": format!("Internal error: {}", message) },
+            }),
+        };
+        Some(answer.to_string())
+    }
+
+    /// Tracks a client frame and rewrites subscription ids for the upstream.
+    fn client_frame(&mut self, text: &str) -> String {
+        let Ok(mut frame) = serde_json::from_str::<Value>(text) else {
+            return text.to_string();
+        };
+        let mut changed = false;
+        match &mut frame {
+            Value::Array(requests) => {
+                for request in requests {
+                    changed |= self.note_request(request);
+                }
+            }
+            request => changed = self.note_request(request),
+        }
+        if changed {
+            frame.to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Returns true if the request had to be rewritten.
+    fn note_request(&mut self, request: &mut Value) -> bool {
+        let Some(id) = request.get("id").filter(|id| !id.is_null()) else {
+            return false;
+        };
+        let id = id.to_string();
+        match request.get("method").and_then(Value::as_str) {
+            Some("eth_subscribe") => {
+                let params = request.get("params").cloned().unwrap_or(Value::Null);
+                self.pending.insert(id, Some(params));
+                false
+            }
+            Some("eth_unsubscribe") => {
+                self.pending.insert(id, None);
+                let Some(param) = request.pointer_mut("/params/0") else {
+                    return false;
+                };
+                let Some(upstream_id) = param.as_str().and_then(|client_id| {
+                    self.subscriptions
+                        .iter()
+                        .find(|(_, sub)| sub.client_id == client_id)
+                        .map(|(upstream_id, _)| upstream_id.clone())
+                }) else {
+                    return false;
+                };
+                self.subscriptions.remove(&upstream_id);
+                let changed = param.as_str() != Some(upstream_id.as_str());
+                *param = Value::String(upstream_id);
+                changed
+            }
+            _ => {
+                self.pending.insert(id, None);
+                false
+            }
+        }
+    }
+
+    /// Tracks an upstream frame and rewrites it for the client. Returns `None`
+    /// for frames only meant for the gateway.
+    fn upstream_frame(&mut self, text: &str) -> Option<String> {
+        let Ok(mut frame) = serde_json::from_str::<Value>(text) else {
+            return Some(text.to_string());
+        };
+
+        if frame.get("method").and_then(Value::as_str) == Some("eth_subscription") {
+            let param = frame.pointer_mut("/params/subscription")?;
+            let client_id = param
+                .as_str()
+                .and_then(|upstream_id| self.subscriptions.get(upstream_id))
+                .map(|sub| sub.client_id.clone());
+            return match client_id {
+                Some(client_id) if param.as_str() != Some(client_id.as_str()) => {
+                    *param = Value::String(client_id);
+                    Some(frame.to_string())
+                }
+                _ => Some(text.to_string()),
+            };
+        }
+
+        if let Some(id) = frame.get("id").map(Value::to_string)
+            && let Some(sub) = self.resubscribing.remove(&id)
+        {
+            match frame.get("result").and_then(Value::as_str) {
+                Some(upstream_id) => {
+                    self.subscriptions.insert(upstream_id.to_string(), sub);
+                }
+                None => tracing::warn!(
+                    "Failed to re-create subscription {}: {}",
+                    sub.client_id,
+                    text
+                ),
+            }
+            return None;
+        }
+
+        match &frame {
+            Value::Array(responses) => responses.iter().for_each(|r| self.note_response(r)),
+            response => self.note_response(response),
+        }
+        Some(text.to_string())
+    }
+
+    fn note_response(&mut self, response: &Value) {
+        let Some(id) = response.get("id").map(Value::to_string) else {
+            return;
+        };
+        if let Some(Some(params)) = self.pending.remove(&id)
+            && let Some(upstream_id) = response.get("result").and_then(Value::as_str)
+        {
+            self.subscriptions.insert(
+                upstream_id.to_string(),
+                Subscription {
+                    client_id: upstream_id.to_string(),
+                    params,
+                },
+            );
+        }
+    }
+
+    /// Re-creates every known subscription on a freshly connected upstream.
+    async fn resubscribe(
+        &mut self,
+        upstream: &mut UpstreamSocket,
+    ) -> Result<(), tungstenite::Error> {
+        let subscriptions: Vec<Subscription> = self
+            .subscriptions
+            .drain()
+            .map(|(_, sub)| sub)
+            .chain(self.resubscribing.drain().map(|(_, sub)| sub))
+            .collect();
+        for sub in subscriptions {
+            self.next_resubscribe_id += 1;
+            let id = Value::String(format!(
+                "ha_gateway-resubscribe-{}",
+                self.next_resubscribe_id
+            ));
+            let request = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "eth_subscribe",
+                "params": sub.params,
+            });
+            self.resubscribing.insert(id.to_string(), sub);
+            upstream
+                .send(tungstenite::Message::text(request.to_string()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Answers requests lost with a dropped upstream.
+    async fn fail_pending(&mut self, client: &mut WebSocket) -> Result<(), axum::Error> {
+        for (id, _) in self.pending.drain() {
+            let error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": serde_json::from_str::<Value>(&id).unwrap_or(Value::Null),
+                "error": { "code": -32603, "message": "Internal error: upstream connection lost" },
+            });
+            client.send(Message::Text(error.to_string().into())).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts each call of a client frame to the authorizer. Returns the frame to
+/// forward, unless nothing is left of it, and the answer to send the client
+/// for rejected calls, if any.
+async fn screen(state: &AppState, caller: &Caller, text: &str) -> (Option<String>, Option<String>) {
+    let Ok(frame) = serde_json::from_str::<Value>(text) else {
+        return (Some(text.to_string()), None);
+    };
+    let (calls, batch) = match frame {
+        Value::Array(calls) => (calls, true),
+        call => (vec![call], false),
+    };
+
+    let mut allowed = Vec::with_capacity(calls.len());
+    let mut rejections = Vec::new();
+    let mut rejected_any = false;
+    for call in calls {
+        // Malformed calls go upstream, which answers them as usual
+        let Ok(request) = serde_json::from_value::<RpcRequest>(call.clone()) else {
+            allowed.push(call);
+            continue;
+        };
+        match auth::check_call(state.authorizer.as_ref(), caller, &request).await {
+            Ok(()) => allowed.push(call),
+            Err(response) => {
+                rejected_any = true;
+                // Notifications get no answer, rejected or not
+                if !request.is_notification()
+                    && let Ok(response) = serde_json::to_value(response)
+                {
+                    rejections.push(response);
+                }
+            }
+        }
+    }
+    if !rejected_any {
+        return (Some(text.to_string()), None);
+    }
+
+    let pack = |mut values: Vec<Value>| match values.len() {
+        0 => None,
+        1 if !batch => values.pop().map(|value| value.to_string()),
+        _ => Some(Value::Array(values).to_string()),
+    };
+    (pack(allowed), pack(rejections))
+}
+
+/// Shares upstream `logs` subscriptions between clients with identical
+/// filters. Its upstream connection lives in a task started on first use.
+#[derive(Default)]
+pub struct LogsHub {
+    commands: Mutex<Option<mpsc::UnboundedSender<HubCommand>>>,
+}
+
+/// Answers a subscribe with the client's subscription id, or why it failed.
+type SubscribeReply = oneshot::Sender<Result<String, String>>;
+
+enum HubCommand {
+    Subscribe {
+        key: String,
+        params: Value,
+        sink: mpsc::UnboundedSender<String>,
+        reply: SubscribeReply,
+    },
+    Unsubscribe {
+        client_id: String,
+    },
+}
+
+impl LogsHub {
+    /// Subscribes `sink` to the logs `params` ask for, returning the
+    /// subscription id its notifications carry.
+    async fn subscribe(
+        &self,
+        state: &AppState,
+        params: Value,
+        sink: mpsc::UnboundedSender<String>,
+    ) -> Result<String, String> {
+        let (reply, answer) = oneshot::channel();
+        self.send(
+            state,
+            HubCommand::Subscribe {
+                key: logs_filter_key(&params),
+                params,
+                sink,
+                reply,
+            },
+        );
+        answer
+            .await
+            .unwrap_or_else(|_| Err("logs subscription hub stopped".to_string()))
+    }
+
+    /// Ends a subscription handed out by `subscribe`.
+    fn unsubscribe(&self, client_id: String) {
+        if let Some(commands) = self.commands.lock().as_ref() {
+            let _ = commands.send(HubCommand::Unsubscribe { client_id });
+        }
+    }
+
+    /// Hands `command` to the hub task, starting it if it is not running.
+    fn send(&self, state: &AppState, command: HubCommand) {
+        let mut commands = self.commands.lock();
+        let running = commands.as_ref().filter(|commands| !commands.is_closed());
+        let commands = match running {
+            Some(commands) => commands,
+            None => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                tokio::spawn(HubState::default().run(state.clone(), receiver));
+                commands.insert(sender)
+            }
+        };
+        let _ = commands.send(command);
+    }
+}
+
+/// One upstream `logs` subscription and the clients it serves.
+struct SharedLogs {
+    /// Params of the `eth_subscribe` call.
+    params: Value,
+
+    /// Id the upstream gave the subscription, once it has answered.
+    upstream_id: Option<String>,
+
+    /// Clients receiving the notifications, by the id they were given.
+    clients: HashMap<String, mpsc::UnboundedSender<String>>,
+
+    /// Clients waiting for the upstream to answer the subscribe.
+    waiting: Vec<(mpsc::UnboundedSender<String>, SubscribeReply)>,
+}
+
+/// State of the hub task.
+#[derive(Default)]
+struct HubState {
+    /// Shared subscriptions by normalized filter.
+    filters: HashMap<String, SharedLogs>,
+
+    /// Filter each pending `eth_subscribe` was sent for, by serialized id.
+    requests: HashMap<String, String>,
+
+    next_request_id: u64,
+}
+
+impl HubState {
+    async fn run(mut self, state: AppState, mut commands: mpsc::UnboundedReceiver<HubCommand>) {
+        let mut upstream: Option<(Arc<UpstreamNode>, UpstreamSocket)> = None;
+        let mut keepalive = Keepalive::new(&state.config.ws);
+        loop {
+            if upstream.is_none() && !self.filters.is_empty() {
+                upstream = self.connect(&state).await;
+                keepalive = Keepalive::new(&state.config.ws);
+            }
+            let retry = upstream.is_none() && !self.filters.is_empty();
+            tokio::select! {
+                command = commands.recv() => {
+                    let Some(command) = command else {
+                        return;
+                    };
+                    let socket = upstream.as_mut().map(|(_, socket)| socket);
+                    if self.handle(command, socket).await.is_err() {
+                        self.drop_upstream(&mut upstream);
+                    }
+                    if self.filters.is_empty()
+                        && let Some((_, mut socket)) = upstream.take()
+                    {
+                        let _ = socket.close(None).await;
+                    }
+                }
+                message = next_message(&mut upstream) => match message {
+                    Some(Ok(tungstenite::Message::Text(text))) => self.upstream_frame(text.as_str()),
+                    Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => {
+                        self.drop_upstream(&mut upstream);
+                    }
+                    Some(Ok(tungstenite::Message::Pong(_))) => keepalive.pong(),
+                    Some(Ok(_)) => {}
+                },
+                _ = keepalive.tick(), if upstream.is_some() => {
+                    if let Some((_, socket)) = upstream.as_mut()
+                        && let Err(e) = keepalive.ping(socket).await
+                    {
+                        tracing::warn!("Logs subscription upstream keepalive failed: {}", e);
+                        self.drop_upstream(&mut upstream);
+                    }
+                }
+                _ = tokio::time::sleep(HUB_RECONNECT_DELAY), if retry => {}
+            }
+        }
+    }
+
+    /// Connects to a node and subscribes to every filter on it. Clients
+    /// still waiting are failed if no node can be reached.
+    async fn connect(&mut self, state: &AppState) -> Option<(Arc<UpstreamNode>, UpstreamSocket)> {
+        let mut exclude = Vec::new();
+        while let Some((node, mut socket)) = connect(state, &mut exclude).await {
+            self.requests.clear();
+            let keys: Vec<String> = self.filters.keys().cloned().collect();
+            let mut sent = Ok(());
+            for key in keys {
+                sent = self.send_subscribe(key, &mut socket).await;
+                if sent.is_err() {
+                    break;
+                }
+            }
+            if sent.is_ok() {
+                return Some((node, socket));
+            }
+            node.record_failure();
+        }
+
+        tracing::error!("No healthy WebSocket upstream available for logs subscriptions");
+        self.filters.retain(|_, shared| {
+            for (_, reply) in shared.waiting.drain(..) {
+                let _ = reply.send(Err("no healthy WebSocket upstream available".to_string()));
+            }
+            !shared.clients.is_empty()
+        });
+        None
+    }
+
+    /// Forgets a lost upstream connection; its subscriptions are re-created
+    /// on the next one.
+    fn drop_upstream(&mut self, upstream: &mut Option<(Arc<UpstreamNode>, UpstreamSocket)>) {
+        if let Some((node, _)) = upstream.take() {
+            tracing::warn!(
+                "Logs subscription upstream {} dropped, reconnecting",
+                node.get_name()
+            );
+            node.record_failure();
+        }
+        self.requests.clear();
+        for shared in self.filters.values_mut() {
+            shared.upstream_id = None;
+        }
+    }
+
+    async fn handle(
+        &mut self,
+        command: HubCommand,
+        upstream: Option<&mut UpstreamSocket>,
+    ) -> Result<(), tungstenite::Error> {
+        match command {
+            HubCommand::Subscribe {
+                key,
+                params,
+                sink,
+                reply,
+            } => {
+                if let Some(shared) = self.filters.get_mut(&key) {
+                    if shared.upstream_id.is_some() {
+                        let client_id = new_subscription_id();
+                        shared.clients.insert(client_id.clone(), sink);
+                        let _ = reply.send(Ok(client_id));
+                    } else {
+                        shared.waiting.push((sink, reply));
+                    }
+                    return Ok(());
+                }
+                self.filters.insert(
+                    key.clone(),
+                    SharedLogs {
+                        params,
+                        upstream_id: None,
+                        clients: HashMap::new(),
+                        waiting: vec![(sink, reply)],
+                    },
+                );
+                // Without a connection, the next one subscribes
+                match upstream {
+                    Some(socket) => self.send_subscribe(key, socket).await,
+                    None => Ok(()),
+                }
+            }
+            HubCommand::Unsubscribe { client_id } => {
+                let Some((key, shared)) = self
+                    .filters
+                    .iter_mut()
+                    .find(|(_, shared)| shared.clients.contains_key(&client_id))
+                else {
+                    return Ok(());
+                };
+                shared.clients.remove(&client_id);
+                if !shared.clients.is_empty() || !shared.waiting.is_empty() {
+                    return Ok(());
+                }
+                let key = key.clone();
+                let upstream_id = self
+                    .filters
+                    .remove(&key)
+                    .and_then(|shared| shared.upstream_id);
+                match (upstream, upstream_id) {
+                    (Some(socket), Some(upstream_id)) => {
+                        self.next_request_id += 1;
+                        let request = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "id": format!("ha_gateway-logs-{}", self.next_request_id),
+                            "method": "eth_unsubscribe",
+                            "params": [upstream_id],
+                        });
+                        socket
+                            .send(tungstenite::Message::text(request.to_string()))
+                            .await
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    async fn send_subscribe(
+        &mut self,
+        key: String,
+        upstream: &mut UpstreamSocket,
+    ) -> Result<(), tungstenite::Error> {
+        let Some(shared) = self.filters.get(&key) else {
+            return Ok(());
+        };
+        self.next_request_id += 1;
+        let id = Value::String(format!("ha_gateway-logs-{}", self.next_request_id));
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "eth_subscribe",
+            "params": shared.params,
+        });
+        self.requests.insert(id.to_string(), key);
+        upstream
+            .send(tungstenite::Message::text(request.to_string()))
+            .await
+    }
+
+    /// Completes pending subscribes and fans notifications out to clients.
+    fn upstream_frame(&mut self, text: &str) {
+        let Ok(mut frame) = serde_json::from_str::<Value>(text) else {
+            return;
+        };
+
+        if frame.get("method").and_then(Value::as_str) == Some("eth_subscription") {
+            let Some(upstream_id) = frame
+                .pointer("/params/subscription")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+            else {
+                return;
+            };
+            let Some(shared) = self
+                .filters
+                .values_mut()
+                .find(|shared| shared.upstream_id.as_deref() == Some(upstream_id.as_str()))
+            else {
+                return;
+            };
+            shared.clients.retain(|client_id, sink| {
+                if let Some(param) = frame.pointer_mut("/params/subscription") {
+                    *param = Value::String(client_id.clone());
+                }
+                sink.send(frame.to_string()).is_ok()
+            });
+            return;
+        }
+
+        let Some(key) = frame
+            .get("id")
+            .and_then(|id| self.requests.remove(&id.to_string()))
+        else {
+            return;
+        };
+        let Some(shared) = self.filters.get_mut(&key) else {
+            return;
+        };
+        match frame.get("result").and_then(Value::as_str) {
+            Some(upstream_id) => {
+                shared.upstream_id = Some(upstream_id.to_string());
+                for (sink, reply) in shared.waiting.drain(..) {
+                    let client_id = new_subscription_id();
+                    if reply.send(Ok(client_id.clone())).is_ok() {
+                        shared.clients.insert(client_id, sink);
+                    }
+                }
+            }
+            None => {
+                tracing::warn!("Upstream refused logs subscription: {}", text);
+                let message = frame
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("subscription refused")
+                    .to_string();
+                if let Some(shared) = self.filters.remove(&key) {
+                    for (_, reply) in shared.waiting {
+                        let _ = reply.send(Err(message.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The next frame from `upstream`, or never if there is no connection.
+async fn next_message(
+    upstream: &mut Option<(Arc<UpstreamNode>, UpstreamSocket)>,
+) -> Option<Result<tungstenite::Message, tungstenite::Error>> {
+    match upstream {
+        Some((_, socket)) => socket.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// A fresh, unguessable subscription id in the usual `0x` hex form.
+fn new_subscription_id() -> String {
+    let state = RandomState::new();
+    format!("0x{:016x}{:016x}", state.hash_one(0u8), state.hash_one(1u8))
+}
+
+/// Key under which `logs` subscriptions share an upstream subscription: the
+/// params with addresses and topics lowercased, a single address written as
+/// a list and address lists sorted. Object keys are already in a canonical
+/// order.
+fn logs_filter_key(params: &Value) -> String {
+    let mut params = params.clone();
+    if let Some(filter) = params.get_mut(1).and_then(Value::as_object_mut) {
+        if let Some(address) = filter.get_mut("address") {
+            let mut addresses: Vec<Value> = match address.take() {
+                Value::Array(addresses) => addresses,
+                single => vec![single],
+            };
+            addresses.iter_mut().for_each(lowercase_strings);
+            addresses.sort_by_key(Value::to_string);
+            addresses.dedup();
+            *address = Value::Array(addresses);
+        }
+        if let Some(topics) = filter.get_mut("topics") {
+            lowercase_strings(topics);
+        }
+    }
+    params.to_string()
+}
+
+fn lowercase_strings(value: &mut Value) {
+    match value {
+        Value::String(text) => *text = text.to_ascii_lowercase(),
+        Value::Array(items) => items.iter_mut().for_each(lowercase_strings),
+        _ => {}
+    }
+}
+
+/// Connects to the next healthy node with a WebSocket URL, skipping (and
+/// adding to) `exclude` as nodes turn out to be unusable.
+async fn connect(
+    state: &AppState,
+    exclude: &mut Vec<String>,
+) -> Option<(Arc<UpstreamNode>, UpstreamSocket)> {
+    loop {
+        let node = state.load_balancer.choose_healthy_node(exclude)?;
+        exclude.push(node.get_name().to_string());
+        let Some(url) = node.config.ws_url.as_deref() else {
+            continue;
+        };
+        match tokio_tungstenite::connect_async(url).await {
+            Ok((socket, _)) => {
+                tracing::info!("Proxying WebSocket client to {}", node.get_name());
+                return Some((node, socket));
+            }
+            Err(e) => {
+                tracing::warn!("WebSocket connect to {} failed: {}", node.get_name(), e);
+                node.record_failure();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_state;
+    use crate::config::GatewayConfig;
+    use crate::test_util::spawn_mock_upstream;
+    use crate::types::UpstreamConfig;
+    use axum::{Router, routing::get};
+
+    /// Upstream that answers `eth_subscribe` with `subscription`, sends one
+    /// notification carrying `result`, then optionally hangs up.
+    async fn spawn_ws_upstream(
+        subscription: &'static str,
+        result: &'static str,
+        hang_up: bool,
+    ) -> String {
+        let app = Router::new().route(
+            "/ws",
+            get(move |ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(move |mut socket| async move {
+                    while let Some(Ok(Message::Text(text))) = socket.recv().await {
+                        let request: Value = serde_json::from_str(text.as_str()).unwrap();
+                        let reply = serde_json::json!({
+                            "jsonrpc": "2.0", "id": request["id"], "result": subscription,
+                        });
+                        let notification = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "method": "eth_subscription",
+                            "params": { "subscription": subscription, "result": result },
+                        });
+                        socket
+                            .send(Message::Text(reply.to_string().into()))
+                            .await
+                            .unwrap();
+                        socket
+                            .send(Message::Text(notification.to_string().into()))
+                            .await
+                            .unwrap();
+                        if hang_up {
+                            return;
+                        }
+                    }
+                })
+            }),
+        );
+        spawn_mock_upstream(app).await
+    }
+
+    fn ws_upstream(name: &str, url: String) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            ws_url: Some(format!("{}/ws", url.replacen("http", "ws", 1))),
+            url,
+            ..Default::default()
+        }
+    }
+
+    async fn next_json(socket: &mut UpstreamSocket) -> Value {
+        match socket.next().await {
+            Some(Ok(tungstenite::Message::Text(text))) => {
+                serde_json::from_str(text.as_str()).unwrap()
+            }
+            other => panic!("expected a text frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_survives_upstream_failover() {
+        let first = spawn_ws_upstream("0xaaa", "first", true).await;
+        let second = spawn_ws_upstream("0xbbb", "second", false).await;
+        let state = build_state(
+            GatewayConfig::default(),
+            &[ws_upstream("First", first), ws_upstream("Second", second)],
+        )
+        .unwrap();
+        let gateway = Router::new()
+            .route("/ws", get(ws_handler))
+            .with_state(state);
+        let gateway = spawn_mock_upstream(gateway).await.replacen("http", "ws", 1);
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("{}/ws", gateway))
+            .await
+            .unwrap();
+        let subscribe = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "eth_subscribe", "params": ["newHeads"],
+        });
+        client
+            .send(tungstenite::Message::text(subscribe.to_string()))
+            .await
+            .unwrap();
+
+        let reply = next_json(&mut client).await;
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["result"], "0xaaa");
+        let notification = next_json(&mut client).await;
+        assert_eq!(notification["params"]["result"], "first");
+
+        // After the first node hangs up, the subscription is re-created on the
+        // second one and its notifications arrive under the original id
+        let notification = next_json(&mut client).await;
+        assert_eq!(notification["params"]["result"], "second");
+        assert_eq!(notification["params"]["subscription"], "0xaaa");
+    }
+
+    #[tokio::test]
+    async fn test_frames_are_authorized_before_forwarding() {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        // Upstream answering each call with its method name, recording them
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let app = Router::new().route(
+            "/ws",
+            get({
+                let seen = Arc::clone(&seen);
+                move |ws: WebSocketUpgrade| async move {
+                    ws.on_upgrade(move |mut socket| async move {
+                        while let Some(Ok(Message::Text(text))) = socket.recv().await {
+                            let frame: Value = serde_json::from_str(text.as_str()).unwrap();
+                            let answer = |call: &Value| {
+                                seen.lock()
+                                    .push(call["method"].as_str().unwrap().to_string());
+                                serde_json::json!({
+                                    "jsonrpc": "2.0", "id": call["id"], "result": call["method"],
+                                })
+                            };
+                            let reply = match &frame {
+                                Value::Array(calls) => calls.iter().map(answer).collect(),
+                                call => answer(call),
+                            };
+                            socket
+                                .send(Message::Text(reply.to_string().into()))
+                                .await
+                                .unwrap();
+                        }
+                    })
+                }
+            }),
+        );
+        let upstream = spawn_mock_upstream(app).await;
+        let mut config = GatewayConfig::default();
+        config.method_filter.deny = vec!["debug_*".to_string()];
+        config.auth.key_policies.insert(
+            "reader".to_string(),
+            crate::auth::KeyPolicy {
+                allowed_methods: vec!["eth_blockNumber".to_string(), "debug_traceCall".to_string()],
+                ..Default::default()
+            },
+        );
+        let state = build_state(config, &[ws_upstream("Node", upstream)]).unwrap();
+        let gateway = Router::new()
+            .route("/ws", get(ws_handler))
+            .with_state(state);
+        let gateway = spawn_mock_upstream(gateway).await.replacen("http", "ws", 1);
+
+        let mut request = format!("{}/ws", gateway).into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(crate::auth::API_KEY_HEADER, "reader".parse().unwrap());
+        let (mut client, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let mut send = async |frame: Value| {
+            client
+                .send(tungstenite::Message::text(frame.to_string()))
+                .await
+                .unwrap();
+            next_json(&mut client).await
+        };
+
+        let hidden = send(serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "debug_traceCall", "params": [],
+        }))
+        .await;
+        assert_eq!(hidden["id"], 1);
+        assert_eq!(hidden["error"]["code"], -32601);
+
+        let denied = send(serde_json::json!({
+            "jsonrpc": "2.0", "id": 2, "method": "eth_sendRawTransaction", "params": ["0x01"],
+        }))
+        .await;
+        assert_eq!(denied["error"]["code"], -32003);
+
+        // In a batch only the rejected call is answered locally
+        let rejected = send(serde_json::json!([
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": 4, "method": "debug_traceCall", "params": [] },
+        ]))
+        .await;
+        assert_eq!(rejected[0]["id"], 4);
+        assert_eq!(rejected[0]["error"]["code"], -32601);
+        let forwarded = next_json(&mut client).await;
+        assert_eq!(forwarded[0]["id"], 3);
+        assert_eq!(forwarded[0]["result"], "eth_blockNumber");
+
+        assert_eq!(*seen.lock(), ["eth_blockNumber"]);
+    }
+
+    #[tokio::test]
+    async fn test_identical_logs_filters_share_one_upstream_subscription() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Upstream that answers the first subscribe, then streams logs
+        let subscribes = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/ws",
+            get({
+                let subscribes = Arc::clone(&subscribes);
+                move |ws: WebSocketUpgrade| async move {
+                    ws.on_upgrade(move |mut socket| async move {
+                        let Some(Ok(Message::Text(text))) = socket.recv().await else {
+                            return;
+                        };
+                        let request: Value = serde_json::from_str(text.as_str()).unwrap();
+                        assert_eq!(request["method"], "eth_subscribe");
+                        subscribes.fetch_add(1, Ordering::SeqCst);
+                        let reply = serde_json::json!({
+                            "jsonrpc": "2.0", "id": request["id"], "result": "0xlogs",
+                        });
+                        let _ = socket.send(Message::Text(reply.to_string().into())).await;
+                        loop {
+                            let log = serde_json::json!({
+                                "jsonrpc": "2.0",
+                                "method": "eth_subscription",
+                                "params": { "subscription": "0xlogs", "result": { "logIndex": "0x0" } },
+                            });
+                            if socket.send(Message::Text(log.to_string().into())).await.is_err() {
+                                return;
+                            }
+                            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                        }
+                    })
+                }
+            }),
+        );
+        let upstream = spawn_mock_upstream(app).await;
+        let mut config = GatewayConfig::default();
+        config.ws.coalesce_logs = true;
+        let state = build_state(config, &[ws_upstream("Node", upstream)]).unwrap();
+        let gateway = Router::new()
+            .route("/ws", get(ws_handler))
+            .with_state(state);
+        let gateway = spawn_mock_upstream(gateway).await.replacen("http", "ws", 1);
+
+        let filters = [
+            serde_json::json!({ "address": "0xABC", "topics": ["0xDDF"] }),
+            serde_json::json!({ "topics": ["0xddf"], "address": ["0xabc"] }),
+        ];
+        let mut ids = Vec::new();
+        let mut clients = Vec::new();
+        for (id, filter) in filters.into_iter().enumerate() {
+            let (mut client, _) = tokio_tungstenite::connect_async(format!("{}/ws", gateway))
+                .await
+                .unwrap();
+            let subscribe = serde_json::json!({
+                "jsonrpc": "2.0", "id": id, "method": "eth_subscribe", "params": ["logs", filter],
+            });
+            client
+                .send(tungstenite::Message::text(subscribe.to_string()))
+                .await
+                .unwrap();
+            let reply = next_json(&mut client).await;
+            assert_eq!(reply["id"], id);
+            let subscription = reply["result"].as_str().unwrap().to_string();
+
+            let log = next_json(&mut client).await;
+            assert_eq!(log["params"]["subscription"], subscription.as_str());
+            assert_eq!(log["params"]["result"]["logIndex"], "0x0");
+            ids.push(subscription);
+            clients.push(client);
+        }
+
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(subscribes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_pings_upstream_and_reconnects_without_pong() {
+        // Accepts the connection but never reads it, so pings go unanswered
+        let silent = Router::new().route(
+            "/ws",
+            get(|ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(|socket| async move {
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                    drop(socket);
+                })
+            }),
+        );
+        let silent = spawn_mock_upstream(silent).await;
+        // Greets each connection, then reports the pings it receives
+        let (pings_tx, mut pings) = mpsc::unbounded_channel();
+        let responsive = Router::new().route(
+            "/ws",
+            get(move |ws: WebSocketUpgrade| async move {
+                ws.on_upgrade(move |mut socket| async move {
+                    let hello = serde_json::json!({ "jsonrpc": "2.0", "method": "hello" });
+                    let _ = socket.send(Message::Text(hello.to_string().into())).await;
+                    while let Some(Ok(message)) = socket.recv().await {
+                        if let Message::Ping(_) = message {
+                            let _ = pings_tx.send(Instant::now());
+                        }
+                    }
+                })
+            }),
+        );
+        let responsive = spawn_mock_upstream(responsive).await;
+
+        let mut config = GatewayConfig::default();
+        config.ws.keepalive_interval_ms = Some(100);
+        let state = build_state(
+            config,
+            &[
+                ws_upstream("Silent", silent),
+                UpstreamConfig {
+                    tier: 1,
+                    ..ws_upstream("Responsive", responsive)
+                },
+            ],
+        )
+        .unwrap();
+        let load_balancer = Arc::clone(&state.load_balancer);
+        let gateway = Router::new()
+            .route("/ws", get(ws_handler))
+            .with_state(state);
+        let gateway = spawn_mock_upstream(gateway).await.replacen("http", "ws", 1);
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("{}/ws", gateway))
+            .await
+            .unwrap();
+        let hello = tokio::time::timeout(Duration::from_secs(5), next_json(&mut client))
+            .await
+            .unwrap();
+        assert_eq!(hello["method"], "hello");
+        let silent = load_balancer.node_by_name("Silent").unwrap();
+        assert!(silent.get_consecutive_failures() >= 1);
+
+        let wait = Duration::from_secs(5);
+        let first = tokio::time::timeout(wait, pings.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let second = tokio::time::timeout(wait, pings.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let gap = second - first;
+        assert!(gap >= Duration::from_millis(80), "{:?}", gap);
+        assert!(gap < Duration::from_millis(1000), "{:?}", gap);
+    }
+
+    #[test]
+    fn test_unsubscribe_is_translated_to_current_upstream_id() {
+        let mut session = Session::default();
+        session.subscriptions.insert(
+            "0xbbb".to_string(),
+            Subscription {
+                client_id: "0xaaa".to_string(),
+                params: serde_json::json!(["newHeads"]),
+            },
+        );
+
+        let forwarded = session.client_frame(
+            r#"{"jsonrpc":"2.0","id":7,"method":"eth_unsubscribe","params":["0xaaa"]}"#,
+        );
+
+        let forwarded: Value = serde_json::from_str(&forwarded).unwrap();
+        assert_eq!(forwarded["params"][0], "0xbbb");
+        assert!(session.subscriptions.is_empty());
+        assert!(session.pending.contains_key("7"));
+    }
+}